@@ -0,0 +1,339 @@
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+// Compiles the `content/` directory into the initial virtual filesystem.
+// Every directory may contain a `.meta` sidecar, see `content/.meta`.
+// Inode numbers are derived from each path, so they stay the same when
+// content is added and saved changes keep pointing at the right inodes.
+
+const CONTENT_DIR: &str = "content";
+const META_FILE: &str = ".meta";
+// `name.md.url` holding a URL becomes a file `name.md` fetched on first read
+const REMOTE_SUFFIX: &str = ".url";
+const ROOT_INO: u64 = 2;
+// Must stay below FIRST_DYNAMIC_INO in src/commands/filesystem.rs
+const INO_RANGE: (u64, u64) = (1000, 1_000_000);
+
+#[derive(Clone)]
+struct Meta {
+    owner: String,
+    group: String,
+    permissions: u16,
+    protected: bool,
+}
+
+impl Meta {
+    fn inherit(parent: &Meta, permissions: u16) -> Self {
+        Self {
+            owner: parent.owner.clone(),
+            group: parent.group.clone(),
+            permissions,
+            protected: false,
+        }
+    }
+
+    fn attributes(&self, mtime: f64) -> String {
+        format!(
+            "permissions: 0o{:o}, owner: {:?}.to_string(), group: {:?}.to_string(), protected: {}, times: Timestamps::at({:?})",
+            self.permissions, self.owner, self.group, self.protected, mtime
+        )
+    }
+}
+
+fn read_meta(dir: &Path) -> HashMap<String, Meta> {
+    let mut entries = HashMap::new();
+    let raw = match fs::read_to_string(dir.join(META_FILE)) {
+        Ok(raw) => raw,
+        Err(_) => return entries,
+    };
+
+    for (number, line) in raw.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 3 {
+            panic!(
+                "{}:{}: expected `<name> <owner>[:<group>] <mode> [protected]`",
+                dir.join(META_FILE).display(),
+                number + 1
+            );
+        }
+
+        let permissions = u16::from_str_radix(fields[2], 8).unwrap_or_else(|_| {
+            panic!(
+                "{}:{}: invalid mode '{}'",
+                dir.join(META_FILE).display(),
+                number + 1,
+                fields[2]
+            )
+        });
+        let (owner, group) = fields[1].split_once(':').unwrap_or((fields[1], fields[1]));
+
+        entries.insert(
+            fields[0].to_string(),
+            Meta {
+                owner: owner.to_string(),
+                group: group.to_string(),
+                permissions,
+                protected: fields.get(3) == Some(&"protected"),
+            },
+        );
+    }
+
+    entries
+}
+
+// When each path under `content/` last changed in git, in seconds since
+// the epoch. Directories count every change below them, and `latest` is
+// the newest commit of all.
+#[derive(Default)]
+struct CommitTimes {
+    paths: HashMap<String, u64>,
+    latest: Option<u64>,
+}
+
+impl CommitTimes {
+    // One `git log` for the whole tree, newest commit first, each as its
+    // time followed by the files it touched
+    fn load() -> Self {
+        let mut times = CommitTimes::default();
+        let output = Command::new("git")
+            .args(["-c", "core.quotePath=false", "log", "--name-only"])
+            .args(["--format=%ct", "--relative"])
+            .output();
+        let log = match output {
+            Ok(output) if output.status.success() => output.stdout,
+            _ => return times,
+        };
+
+        let mut time = None;
+        for line in String::from_utf8_lossy(&log).lines() {
+            if line.is_empty() {
+                continue;
+            }
+            if let Ok(seconds) = line.parse() {
+                times.latest.get_or_insert(seconds);
+                time = Some(seconds);
+                continue;
+            }
+            let time = match time {
+                Some(time) => time,
+                None => continue,
+            };
+            let mut path = Path::new(line);
+            loop {
+                times
+                    .paths
+                    .entry(path.to_string_lossy().into_owned())
+                    .or_insert(time);
+                match path.parent() {
+                    Some(parent) if parent != Path::new("") => path = parent,
+                    _ => break,
+                }
+            }
+        }
+        times
+    }
+
+    // Times come from the source rather than the checkout, which would give
+    // every clone different output: SOURCE_DATE_EPOCH if set, otherwise the
+    // last commit to change the path. Files git doesn't know yet get the
+    // latest commit, and a tree outside git the epoch itself.
+    fn modified(&self, path: &Path) -> f64 {
+        let seconds = env::var("SOURCE_DATE_EPOCH")
+            .ok()
+            .and_then(|epoch| epoch.trim().parse().ok())
+            .or_else(|| self.paths.get(&*path.to_string_lossy()).copied())
+            .or(self.latest)
+            .unwrap_or(0);
+        seconds as f64 * 1000.0
+    }
+}
+
+// Rebuilds when a commit changes which times git reports, that is
+// whenever HEAD or the branch it is on moves
+fn watch_git() {
+    let output = match Command::new("git")
+        .args(["rev-parse", "--git-dir"])
+        .output()
+    {
+        Ok(output) if output.status.success() => output.stdout,
+        _ => return,
+    };
+    let git_dir = Path::new(String::from_utf8_lossy(&output).trim()).to_path_buf();
+    let head = git_dir.join("HEAD");
+    println!("cargo:rerun-if-changed={}", head.display());
+
+    if let Some(branch) = fs::read_to_string(&head)
+        .ok()
+        .and_then(|head| Some(head.strip_prefix("ref: ")?.trim().to_string()))
+    {
+        for path in [git_dir.join(branch), git_dir.join("packed-refs")] {
+            if path.exists() {
+                println!("cargo:rerun-if-changed={}", path.display());
+            }
+        }
+    }
+}
+
+struct Inodes {
+    used: HashSet<u64>,
+}
+
+impl Inodes {
+    // FNV-1a of the path. On the rare collision the path is hashed again
+    // with a salt, which lands somewhere unrelated. Probing for the next
+    // free number instead would let one new path shift a whole run of
+    // existing inodes.
+    fn allocate(&mut self, path: &str) -> u64 {
+        let (start, end) = INO_RANGE;
+        let mut key = path.to_string();
+        for salt in 1.. {
+            let hash = key.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+                (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+            });
+            let ino = start + hash % (end - start);
+            if self.used.insert(ino) {
+                return ino;
+            }
+            key = format!("{}\0{}", path, salt);
+        }
+        unreachable!()
+    }
+}
+
+fn emit_directory(
+    dir: &Path,
+    ino: u64,
+    meta: &Meta,
+    times: &CommitTimes,
+    inodes: &mut Inodes,
+    out: &mut String,
+) {
+    let mut sidecar = read_meta(dir);
+
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .unwrap_or_else(|e| panic!("cannot read {}: {}", dir.display(), e))
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .filter(|name| name != META_FILE)
+        .collect();
+    names.sort();
+
+    let mut children = Vec::new();
+    let mut nodes = String::new();
+
+    for name in names {
+        let path = dir.join(&name);
+        let file_type = fs::symlink_metadata(&path).unwrap().file_type();
+        let default_permissions = if file_type.is_dir() { 0o755 } else { 0o644 };
+        let remote_name = name
+            .strip_suffix(REMOTE_SUFFIX)
+            .filter(|_| file_type.is_file());
+        let node_name = remote_name.unwrap_or(&name);
+        let child_meta = sidecar
+            .remove(node_name)
+            .unwrap_or_else(|| Meta::inherit(meta, default_permissions));
+        let mtime = times.modified(&path);
+        let vfs_path = path.with_file_name(node_name);
+        let vfs_path = vfs_path.strip_prefix(CONTENT_DIR).unwrap();
+        let child_ino = inodes.allocate(&format!("/{}", vfs_path.display()));
+        children.push(format!("({:?}.to_string(), {})", node_name, child_ino));
+
+        if file_type.is_dir() {
+            let own = read_meta(&path).remove(".").unwrap_or(child_meta);
+            emit_directory(&path, child_ino, &own, times, inodes, &mut nodes);
+            continue;
+        }
+
+        write!(nodes, "({}, ", child_ino).unwrap();
+
+        if file_type.is_symlink() {
+            let target = fs::read_link(&path).unwrap();
+            write!(
+                nodes,
+                "Node::Symlink {{ target: {:?}.to_string(), owner: {:?}.to_string(), group: {:?}.to_string(), times: Timestamps::at({:?}) }}",
+                target.to_string_lossy(),
+                child_meta.owner,
+                child_meta.group,
+                mtime
+            )
+            .unwrap();
+        } else if remote_name.is_some() {
+            let url = fs::read_to_string(&path).unwrap();
+            write!(
+                nodes,
+                "Node::File {{ content: Content::Text(String::new()), {}, url: Some({:?}.to_string()) }}",
+                child_meta.attributes(mtime),
+                url.trim()
+            )
+            .unwrap();
+        } else {
+            // Images and other files that aren't UTF-8 are embedded as bytes
+            let absolute = fs::canonicalize(&path).unwrap();
+            let content = if fs::read_to_string(&path).is_ok() {
+                format!("Content::Text(include_str!({:?}).to_string())", absolute)
+            } else {
+                format!(
+                    "Content::Binary {{ bytes: include_bytes!({:?}).to_vec() }}",
+                    absolute
+                )
+            };
+            write!(
+                nodes,
+                "Node::File {{ content: {}, {}, url: None }}",
+                content,
+                child_meta.attributes(mtime)
+            )
+            .unwrap();
+        }
+
+        nodes.push_str("),");
+    }
+
+    write!(
+        out,
+        "({}, Node::Directory {{ {}, children: HashMap::from([{}]) }}),{}",
+        ino,
+        meta.attributes(times.modified(dir)),
+        children.join(","),
+        nodes
+    )
+    .unwrap();
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed={}", CONTENT_DIR);
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+
+    let root = Path::new(CONTENT_DIR);
+    let meta = read_meta(root).remove(".").unwrap_or(Meta {
+        owner: "root".to_string(),
+        group: "root".to_string(),
+        permissions: 0o755,
+        protected: true,
+    });
+
+    let times = match env::var_os("SOURCE_DATE_EPOCH") {
+        Some(_) => CommitTimes::default(),
+        None => {
+            watch_git();
+            CommitTimes::load()
+        }
+    };
+    let mut inodes = Inodes {
+        used: HashSet::from([ROOT_INO]),
+    };
+    let mut out = String::from("Filesystem::from_nodes(vec![");
+    emit_directory(root, ROOT_INO, &meta, &times, &mut inodes, &mut out);
+    out.push_str("])");
+
+    let dest = Path::new(&env::var("OUT_DIR").unwrap()).join("content.rs");
+    fs::write(dest, out).unwrap();
+}
@@ -58,27 +58,39 @@ pub async fn logo(term: &Terminal) {
 }
 
 pub async fn login(term: &Terminal) {
-    let login_messages = vec![
-        ("Arch Linux 6.6.32-1-lts (tty1)", "", "green"),
-        ("", "", ""),
-        ("login: objz", "", "white"),
-        ("password: ", "", "white"),
-        ("", "", ""),
-        ("Last login: Mon May 27 13:59:36 2025", "", "white"),
-        ("Type 'help' for further information", "", "yellow"),
-        ("", "", ""),
+    login_banner(term).await;
+
+    // objz has no password, so login doesn't ask for one
+    term.add_line("login: objz", Some(LineOptions::new().with_typing(50)))
+        .await;
+    term.sleep(60).await;
+
+    motd(term, "Mon May 27 13:59:36 2025").await;
+}
+
+pub async fn login_banner(term: &Terminal) {
+    term.add_line(
+        "Arch Linux 6.6.32-1-lts (tty1)",
+        Some(LineOptions::new().with_color("green")),
+    )
+    .await;
+    term.sleep(60).await;
+    term.add_line("", None).await;
+    term.sleep(60).await;
+}
+
+pub async fn motd(term: &Terminal, last_login: &str) {
+    let last_login_line = format!("Last login: {}", last_login);
+    let motd_messages = vec![
+        ("", ""),
+        (last_login_line.as_str(), "white"),
+        ("Type 'help' for further information", "yellow"),
+        ("", ""),
     ];
 
-    for (msg, _status, color) in login_messages {
+    for (msg, color) in motd_messages {
         if msg.is_empty() {
             term.add_line("", None).await;
-        } else if msg.contains("login:") {
-            term.add_line(msg, Some(LineOptions::new().with_typing(50)))
-                .await;
-        } else if msg.contains("password:") {
-            let full = format!("{}••••••••", msg);
-            term.add_line(&full, Some(LineOptions::new().with_typing(50)))
-                .await;
         } else {
             term.add_line(msg, Some(LineOptions::new().with_color(color)))
                 .await;
@@ -0,0 +1,1391 @@
+use super::filesystem::{
+    self, follow, mode_letters, normalize_path, resolve_ino, Entry, Filesystem, FsError, Ino, Node,
+    CURRENT_PATH, FILESYSTEM,
+};
+use super::permissions::{self, EXECUTE, READ};
+use super::stream::{self, Output};
+use super::{system, text, users};
+use crate::utils::gzip;
+use std::collections::HashMap;
+
+const BLOCK: usize = 512;
+// Archives are padded to whole records of 20 blocks, as tar writes them
+const RECORD: usize = 20 * BLOCK;
+// GNU's extension for names that don't fit a ustar header
+const LONG_NAME: &str = "././@LongLink";
+
+// One archive member, gathered while the filesystem is locked. Owner ids
+// are looked up afterwards since that reads /etc/passwd.
+pub struct Member {
+    // Directories end with '/'
+    pub name: String,
+    pub entry: Entry,
+    pub mode: u16,
+    pub owner: String,
+    pub group: String,
+    pub mtime: f64,
+}
+
+impl Member {
+    fn type_flag(&self) -> u8 {
+        match self.entry {
+            Entry::File(_) => b'0',
+            Entry::HardLink(_) => b'1',
+            Entry::Symlink(_) => b'2',
+            Entry::Directory => b'5',
+        }
+    }
+
+    fn link(&self) -> &str {
+        match &self.entry {
+            Entry::Symlink(target) | Entry::HardLink(target) => target,
+            _ => "",
+        }
+    }
+
+    fn size(&self) -> usize {
+        match &self.entry {
+            Entry::File(bytes) => bytes.len(),
+            _ => 0,
+        }
+    }
+}
+
+fn uid(owner: &str) -> u32 {
+    users::find_user(owner).map_or(65534, |user| user.uid)
+}
+
+fn gid(group: &str) -> u32 {
+    users::find_group(group).map_or(65534, |group| group.gid)
+}
+
+// Operands after -C are relative to that directory
+fn locate(dir: Option<&str>, name: &str) -> String {
+    match dir {
+        Some(dir) if !name.starts_with('/') => format!("{}/{}", dir, name),
+        _ => name.to_string(),
+    }
+}
+
+fn lookup(name: &str) -> Result<Ino, FsError> {
+    let filesystem = FILESYSTEM.lock().unwrap();
+    let current_path = CURRENT_PATH.lock().unwrap();
+    resolve_ino(&filesystem, &normalize_path(name, &current_path), false)
+}
+
+pub struct Collected {
+    pub members: Vec<Member>,
+    // Without the command name in front
+    pub errors: Vec<String>,
+    // Whether a leading '/' was dropped from some name
+    pub stripped: bool,
+    // First name archived for each file with more than one link
+    seen: HashMap<Ino, String>,
+    archive: Option<Ino>,
+}
+
+fn collect(filesystem: &Filesystem, ino: Ino, name: String, found: &mut Collected) {
+    let node = match filesystem.get(ino) {
+        Some(node) => node,
+        None => return,
+    };
+    if found.archive == Some(ino) {
+        found.errors.push(stream::error(format!(
+            "{}: file is the archive; not dumped",
+            name
+        )));
+        return;
+    }
+
+    let entry = match node {
+        Node::Device { .. } => {
+            found
+                .errors
+                .push(stream::error(format!("{}: character device ignored", name)));
+            return;
+        }
+        Node::Directory { .. } => Entry::Directory,
+        Node::Symlink { target, .. } => Entry::Symlink(target.clone()),
+        Node::File { .. } => match found.seen.get(&ino) {
+            Some(first) => Entry::HardLink(first.clone()),
+            None => {
+                let bytes =
+                    permissions::check(node, READ).and_then(|_| node.bytes().ok_or(FsError::Io));
+                match bytes {
+                    Ok(bytes) => {
+                        if filesystem.link_count(ino) > 1 {
+                            found.seen.insert(ino, name.clone());
+                        }
+                        Entry::File(bytes)
+                    }
+                    Err(e) => {
+                        found
+                            .errors
+                            .push(stream::error(format!("{}: Cannot open: {}", name, e)));
+                        return;
+                    }
+                }
+            }
+        },
+    };
+
+    let is_directory = matches!(entry, Entry::Directory);
+    found.members.push(Member {
+        name: if is_directory && !name.ends_with('/') {
+            format!("{}/", name)
+        } else {
+            name.clone()
+        },
+        entry,
+        mode: node.permissions(),
+        owner: node.get_owner().to_string(),
+        group: node.get_group().to_string(),
+        mtime: node.times().mtime,
+    });
+
+    if let Node::Directory { children, .. } = node {
+        if let Err(e) = permissions::check(node, READ | EXECUTE) {
+            found
+                .errors
+                .push(stream::error(format!("{}: Cannot open: {}", name, e)));
+            return;
+        }
+        let mut entries: Vec<_> = children.iter().collect();
+        entries.sort();
+        for (child, ino) in entries {
+            let path = format!("{}/{}", name.trim_end_matches('/'), child);
+            collect(filesystem, *ino, path, found);
+        }
+    }
+}
+
+fn octal(field: &mut [u8], value: u64) {
+    let digits = format!("{:0width$o}", value, width = field.len() - 1);
+    let digits = &digits.as_bytes()[digits.len() - (field.len() - 1)..];
+    field[..digits.len()].copy_from_slice(digits);
+}
+
+fn copy(field: &mut [u8], value: &[u8]) {
+    let length = value.len().min(field.len());
+    field[..length].copy_from_slice(&value[..length]);
+}
+
+// ustar keeps up to 155 bytes of leading directories in a separate field
+fn split_name(name: &str) -> Option<(&str, &str)> {
+    if name.len() <= 100 {
+        return Some(("", name));
+    }
+    name.match_indices('/')
+        .map(|(i, _)| i)
+        .find(|&i| i <= 155 && name.len() - i - 1 <= 100 && i + 1 < name.len())
+        .map(|i| (&name[..i], &name[i + 1..]))
+}
+
+struct Header<'a> {
+    name: &'a str,
+    prefix: &'a str,
+    link: &'a str,
+    type_flag: u8,
+    mode: u16,
+    ids: (u32, u32),
+    size: usize,
+    mtime: u64,
+    owner: &'a str,
+    group: &'a str,
+}
+
+fn push_header(out: &mut Vec<u8>, header: &Header) {
+    let mut block = [0; BLOCK];
+    copy(&mut block[0..100], header.name.as_bytes());
+    octal(&mut block[100..108], header.mode as u64);
+    octal(&mut block[108..116], header.ids.0 as u64);
+    octal(&mut block[116..124], header.ids.1 as u64);
+    octal(&mut block[124..136], header.size as u64);
+    octal(&mut block[136..148], header.mtime);
+    block[156] = header.type_flag;
+    copy(&mut block[157..257], header.link.as_bytes());
+    copy(&mut block[257..265], b"ustar\x0000");
+    copy(&mut block[265..297], header.owner.as_bytes());
+    copy(&mut block[297..329], header.group.as_bytes());
+    copy(&mut block[345..500], header.prefix.as_bytes());
+
+    // Summed with the checksum field itself taken as spaces
+    block[148..156].copy_from_slice(b"        ");
+    let sum: u32 = block.iter().map(|&byte| byte as u32).sum();
+    copy(&mut block[148..156], format!("{:06o}\0 ", sum).as_bytes());
+    out.extend_from_slice(&block);
+}
+
+fn push_data(out: &mut Vec<u8>, data: &[u8]) {
+    out.extend_from_slice(data);
+    out.resize(out.len().next_multiple_of(BLOCK), 0);
+}
+
+// A GNU long name member, carrying a name or link target that's too long
+fn push_long(out: &mut Vec<u8>, type_flag: u8, value: &str) {
+    let mut data = value.as_bytes().to_vec();
+    data.push(0);
+    push_header(
+        out,
+        &Header {
+            name: LONG_NAME,
+            prefix: "",
+            link: "",
+            type_flag,
+            mode: 0o644,
+            ids: (0, 0),
+            size: data.len(),
+            mtime: 0,
+            owner: "root",
+            group: "root",
+        },
+    );
+    push_data(out, &data);
+}
+
+fn build(members: &[Member]) -> Vec<u8> {
+    let mut out = Vec::new();
+
+    for member in members {
+        let (prefix, name) = match split_name(&member.name) {
+            Some(split) => split,
+            None => {
+                push_long(&mut out, b'L', &member.name);
+                // The header keeps what fits of it for older readers
+                ("", member.name.as_str())
+            }
+        };
+        if member.link().len() > 100 {
+            push_long(&mut out, b'K', member.link());
+        }
+
+        push_header(
+            &mut out,
+            &Header {
+                name,
+                prefix,
+                link: member.link(),
+                type_flag: member.type_flag(),
+                mode: member.mode & 0o7777,
+                ids: (uid(&member.owner), gid(&member.group)),
+                size: member.size(),
+                mtime: (member.mtime / 1000.0).max(0.0) as u64,
+                owner: &member.owner,
+                group: &member.group,
+            },
+        );
+        if let Entry::File(bytes) = &member.entry {
+            push_data(&mut out, bytes);
+        }
+    }
+
+    // Two zero blocks end the archive
+    out.resize(out.len() + 2 * BLOCK, 0);
+    out.resize(out.len().next_multiple_of(RECORD), 0);
+    out
+}
+
+fn field(bytes: &[u8]) -> String {
+    let end = bytes
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+// Octal, or base-256 with the high bit set for values octal can't hold
+fn number(bytes: &[u8]) -> Option<u64> {
+    if bytes.first().is_some_and(|&byte| byte & 0x80 != 0) {
+        let first = (bytes[0] & 0x7f) as u64;
+        return Some(
+            bytes[1..]
+                .iter()
+                .fold(first, |n, &byte| n << 8 | byte as u64),
+        );
+    }
+    let text = std::str::from_utf8(bytes).ok()?;
+    match text.trim_matches(|c| c == '\0' || c == ' ') {
+        "" => Some(0),
+        digits => u64::from_str_radix(digits, 8).ok(),
+    }
+}
+
+fn checksum_matches(block: &[u8]) -> bool {
+    let expected = match number(&block[148..156]) {
+        Some(sum) => sum,
+        None => return false,
+    };
+    let sum = |signed: bool| -> i64 {
+        block
+            .iter()
+            .enumerate()
+            .map(|(i, &byte)| match i {
+                148..=155 => 32,
+                _ if signed => byte as i8 as i64,
+                _ => byte as i64,
+            })
+            .sum()
+    };
+    expected as i64 == sum(false) || expected as i64 == sum(true)
+}
+
+// pax records read `<length> <key>=<value>\n`
+fn pax_records(data: &[u8]) -> HashMap<String, String> {
+    let mut records = HashMap::new();
+    let mut rest = data;
+
+    while let Some(space) = rest.iter().position(|&byte| byte == b' ') {
+        let length: usize = match std::str::from_utf8(&rest[..space])
+            .ok()
+            .and_then(|length| length.parse().ok())
+        {
+            Some(length) if length > space && length <= rest.len() => length,
+            _ => break,
+        };
+        let record = String::from_utf8_lossy(&rest[space + 1..length]);
+        if let Some((key, value)) = record.trim_end_matches('\n').split_once('=') {
+            records.insert(key.to_string(), value.to_string());
+        }
+        rest = &rest[length..];
+    }
+
+    records
+}
+
+// An archive member as read back, with whatever a real tar may have put in
+struct Stored {
+    name: String,
+    link: String,
+    type_flag: u8,
+    mode: u16,
+    owner: String,
+    group: String,
+    size: u64,
+    mtime: f64,
+    data: Vec<u8>,
+}
+
+fn parse(data: &[u8]) -> Result<Vec<Stored>, String> {
+    let mut members = Vec::new();
+    let mut pos = 0;
+    let mut long_name = None;
+    let mut long_link = None;
+    let mut pax = HashMap::new();
+
+    while pos < data.len() {
+        let block = match data.get(pos..pos + BLOCK) {
+            Some(block) => block,
+            None if pos == 0 => {
+                return Err(stream::error("tar: This does not look like a tar archive"))
+            }
+            None => return Err(stream::error("tar: Unexpected EOF in archive")),
+        };
+        if block.iter().all(|&byte| byte == 0) {
+            break;
+        }
+        if !checksum_matches(block) {
+            return Err(if members.is_empty() && pos == 0 {
+                stream::error("tar: This does not look like a tar archive")
+            } else {
+                stream::error("tar: Skipping to next header")
+            });
+        }
+        pos += BLOCK;
+
+        let mut size = number(&block[124..136]).unwrap_or(0);
+        if let Some(pax_size) = pax.get("size").and_then(|size: &String| size.parse().ok()) {
+            size = pax_size;
+        }
+        let content = data
+            .get(pos..pos + size as usize)
+            .ok_or_else(|| stream::error("tar: Unexpected EOF in archive"))?;
+        pos += (size as usize).next_multiple_of(BLOCK);
+
+        let type_flag = block[156];
+        match type_flag {
+            b'L' => {
+                long_name = Some(field(content));
+                continue;
+            }
+            b'K' => {
+                long_link = Some(field(content));
+                continue;
+            }
+            b'x' => {
+                pax = pax_records(content);
+                continue;
+            }
+            b'g' => continue,
+            _ => {}
+        }
+
+        let mut name = field(&block[0..100]);
+        let prefix = field(&block[345..500]);
+        if &block[257..262] == b"ustar" && !prefix.is_empty() {
+            name = format!("{}/{}", prefix, name);
+        }
+        let mtime = pax
+            .get("mtime")
+            .and_then(|mtime| mtime.parse::<f64>().ok())
+            .unwrap_or_else(|| number(&block[136..148]).unwrap_or(0) as f64);
+
+        members.push(Stored {
+            name: pax
+                .remove("path")
+                .or_else(|| long_name.take())
+                .unwrap_or(name),
+            link: pax
+                .remove("linkpath")
+                .or_else(|| long_link.take())
+                .unwrap_or_else(|| field(&block[157..257])),
+            type_flag,
+            mode: number(&block[100..108]).unwrap_or(0) as u16 & 0o7777,
+            owner: field(&block[265..297]),
+            group: field(&block[297..329]),
+            size,
+            mtime: mtime * 1000.0,
+            data: content.to_vec(),
+        });
+        pax.clear();
+    }
+
+    Ok(members)
+}
+
+// Members named on the command line, along with everything below them
+fn selected(name: &str, patterns: &[&str]) -> bool {
+    let name = name.trim_end_matches('/');
+    patterns.is_empty()
+        || patterns.iter().any(|pattern| {
+            let pattern = pattern.trim_end_matches('/');
+            name == pattern
+                || name
+                    .strip_prefix(pattern)
+                    .is_some_and(|rest| rest.starts_with('/'))
+        })
+}
+
+fn list(members: &[Stored], verbose: bool) -> Vec<String> {
+    // Owner and size share a column that only ever grows, as in GNU tar
+    let mut width = 19;
+
+    members
+        .iter()
+        .map(|member| {
+            if !verbose {
+                return member.name.clone();
+            }
+            let file_type = match member.type_flag {
+                b'1' => 'h',
+                b'2' => 'l',
+                b'3' => 'c',
+                b'4' => 'b',
+                b'5' => 'd',
+                b'6' => 'p',
+                _ => '-',
+            };
+            let owner = format!("{}/{}", member.owner, member.group);
+            let size = member.size.to_string();
+            width = width.max(owner.len() + 1 + size.len());
+            let time = system::format_stat_time(member.mtime);
+            let name = match member.type_flag {
+                b'1' => format!("{} link to {}", member.name, member.link),
+                b'2' => format!("{} -> {}", member.name, member.link),
+                _ => member.name.clone(),
+            };
+            format!(
+                "{} {} {:>pad$} {} {}",
+                mode_letters(file_type, member.mode),
+                owner,
+                size,
+                &time[..16],
+                name,
+                pad = width - owner.len() - 1
+            )
+        })
+        .collect()
+}
+
+fn exists(name: &str) -> bool {
+    lookup(name).is_ok()
+}
+
+// Creates missing directories leading up to `name`, which archives made
+// from single files don't carry
+fn make_parents(dir: Option<&str>, name: &str) -> Result<(), FsError> {
+    let umask = permissions::current_umask();
+    let components: Vec<&str> = name.split('/').filter(|part| !part.is_empty()).collect();
+
+    for end in 1..components.len() {
+        let path = locate(dir, &components[..end].join("/"));
+        if !exists(&path) {
+            filesystem::install(&path, Entry::Directory, 0o777 & !umask, system::timestamp())?;
+        }
+    }
+    Ok(())
+}
+
+struct Extracted {
+    output: Vec<String>,
+    failed: bool,
+}
+
+fn extract(members: Vec<Stored>, dir: Option<&str>, verbose: bool) -> Extracted {
+    let mut result = Extracted {
+        output: Vec::new(),
+        failed: false,
+    };
+    let umask = if permissions::is_root() {
+        0
+    } else {
+        permissions::current_umask()
+    };
+    let mut stripped = false;
+    let mut directories = Vec::new();
+
+    for member in members {
+        let name = member.name.trim_start_matches('/');
+        if name.len() != member.name.len() && !stripped {
+            result
+                .output
+                .push(stream::error("tar: Removing leading `/' from member names"));
+            stripped = true;
+        }
+        if name.split('/').any(|part| part == "..") {
+            result.output.push(stream::error(format!(
+                "tar: {}: Member name contains '..'",
+                member.name
+            )));
+            result.failed = true;
+            continue;
+        }
+        if name.is_empty() {
+            continue;
+        }
+        if verbose {
+            result.output.push(member.name.clone());
+        }
+
+        let path = locate(dir, name.trim_end_matches('/'));
+        let mode = member.mode & !umask;
+        let (entry, action) = match member.type_flag {
+            b'0' | b'\0' | b'7' => (Entry::File(member.data), "Cannot open".to_string()),
+            b'1' => {
+                let target = locate(dir, member.link.trim_start_matches('/'));
+                let action = format!("Cannot hard link to '{}'", member.link);
+                (Entry::HardLink(target), action)
+            }
+            b'2' => {
+                let action = format!("Cannot create symlink to '{}'", member.link);
+                (Entry::Symlink(member.link), action)
+            }
+            // Kept searchable and writable until everything inside is in
+            b'5' => {
+                directories.push((path.clone(), name.to_string(), mode, member.mtime));
+                (Entry::Directory, "Cannot mkdir".to_string())
+            }
+            _ => {
+                result.output.push(stream::error(format!(
+                    "tar: {}: Cannot mknod: {}",
+                    name,
+                    FsError::NotPermitted
+                )));
+                result.failed = true;
+                continue;
+            }
+        };
+        let mode = match entry {
+            Entry::Directory => mode | 0o700,
+            _ => mode,
+        };
+        let mtime = member.mtime;
+
+        let installed =
+            make_parents(dir, name).and_then(|_| filesystem::install(&path, entry, mode, mtime));
+        if let Err(e) = installed {
+            result
+                .output
+                .push(stream::error(format!("tar: {}: {}: {}", name, action, e)));
+            result.failed = true;
+        }
+    }
+
+    // Extracting entries into a directory changed its mtime
+    for (path, name, mode, mtime) in directories.into_iter().rev() {
+        if let Err(e) = filesystem::install(&path, Entry::Directory, mode, mtime) {
+            result.output.push(stream::error(format!(
+                "tar: {}: Cannot change mode: {}",
+                name, e
+            )));
+            result.failed = true;
+        }
+    }
+
+    result
+}
+
+/// Everything at and below `operands`, which are relative to `dir` when
+/// given, leaving out the `archive` being written. Links aren't followed.
+pub fn gather(operands: &[&str], dir: Option<&str>, archive: Option<Ino>) -> Collected {
+    let mut found = Collected {
+        members: Vec::new(),
+        errors: Vec::new(),
+        stripped: false,
+        seen: HashMap::new(),
+        archive,
+    };
+    let filesystem = FILESYSTEM.lock().unwrap();
+    let current_path = CURRENT_PATH.lock().unwrap();
+
+    for &operand in operands {
+        let path = normalize_path(&locate(dir, operand), &current_path);
+        match resolve_ino(&filesystem, &path, false) {
+            Ok(ino) => {
+                let name = operand.trim_start_matches('/');
+                found.stripped |= name.len() != operand.len();
+                let name = if name.is_empty() { "." } else { name };
+                collect(&filesystem, ino, name.to_string(), &mut found);
+            }
+            Err(e) => found
+                .errors
+                .push(stream::error(format!("{}: Cannot stat: {}", operand, e))),
+        }
+    }
+
+    found
+}
+
+fn create(
+    archive: &str,
+    operands: &[&str],
+    dir: Option<&str>,
+    compress: bool,
+    verbose: bool,
+) -> (Vec<String>, bool) {
+    let mut output = Vec::new();
+    let archived = match archive {
+        "-" => None,
+        _ => lookup(archive).ok(),
+    };
+    let found = gather(operands, dir, archived);
+
+    if found.stripped {
+        output.push(stream::error("tar: Removing leading `/' from member names"));
+    }
+    if verbose {
+        output.extend(found.members.iter().map(|member| member.name.clone()));
+    }
+    let failed = !found.errors.is_empty();
+    output.extend(
+        found
+            .errors
+            .iter()
+            .map(|e| stream::error(format!("tar: {}", e))),
+    );
+
+    let mut bytes = build(&found.members);
+    if compress {
+        bytes = gzip::compress(&bytes, None, 0);
+    }
+    if let Err(e) = filesystem::write_bytes(archive, &bytes) {
+        output.push(stream::error(format!(
+            "tar: {}: Cannot open: {}",
+            archive, e
+        )));
+        output.push(stream::error("tar: Error is not recoverable: exiting now"));
+        return (output, false);
+    }
+    (output, failed)
+}
+
+// Rewrites long options and the old `tar czf archive` form, whose first
+// word is a bundle of flags without a dash, into plain short options
+fn expand(args: &[&str]) -> Result<Vec<String>, String> {
+    let mut expanded = Vec::new();
+    let mut rest = args.iter();
+
+    if let Some(first) = args.first().filter(|arg| !arg.starts_with('-')) {
+        rest.next();
+        for flag in first.chars() {
+            expanded.push(format!("-{}", flag));
+            if "fC".contains(flag) {
+                if let Some(value) = rest.next() {
+                    expanded.push(value.to_string());
+                }
+            }
+        }
+    }
+
+    for &arg in rest {
+        let long = match arg.strip_prefix("--") {
+            Some(long) if !long.is_empty() => long,
+            _ => {
+                expanded.push(arg.to_string());
+                continue;
+            }
+        };
+        let (name, value) = match long.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (long, None),
+        };
+        let flag = match name {
+            "create" => "-c",
+            "extract" | "get" => "-x",
+            "list" => "-t",
+            "gzip" | "gunzip" => "-z",
+            "verbose" => "-v",
+            "file" => "-f",
+            "directory" => "-C",
+            _ => {
+                return Err(stream::error(format!(
+                    "tar: unrecognized option '--{}'",
+                    name
+                )))
+            }
+        };
+        expanded.push(flag.to_string());
+        if let Some(value) = value {
+            expanded.push(value.to_string());
+        }
+    }
+
+    Ok(expanded)
+}
+
+pub fn tar(args: &[&str], stdin: Option<&[u8]>) -> String {
+    const USAGE: &str = "Try 'tar --help' or 'tar --usage' for more information.";
+
+    let expanded = match expand(args) {
+        Ok(expanded) => expanded,
+        Err(e) => return stream::error(format!("{}\n{}", e, USAGE)),
+    };
+    let expanded: Vec<&str> = expanded.iter().map(String::as_str).collect();
+    let parsed = match text::parse("tar", &expanded, "cxtzv", "fC") {
+        Ok(parsed) => parsed,
+        Err(e) => return stream::error(format!("{}\n{}", e, USAGE)),
+    };
+
+    let modes: Vec<char> = ['c', 'x', 't']
+        .iter()
+        .copied()
+        .filter(|&mode| parsed.has(mode))
+        .collect();
+    let mode = match modes[..] {
+        [mode] => mode,
+        [] => {
+            return stream::error(format!(
+                "tar: You must specify one of the '-Acdtrux', '--delete' or '--test-label' options\n{}",
+                USAGE
+            ))
+        }
+        _ => {
+            return stream::error(format!(
+                "tar: You may not specify more than one '-Acdtrux', '--delete' or  '--test-label' option\n{}",
+                USAGE
+            ))
+        }
+    };
+    let verbose = parsed.has('v');
+    let dir = parsed.value('C');
+
+    if let Some(dir) = dir {
+        let filesystem = FILESYSTEM.lock().unwrap();
+        let current_path = CURRENT_PATH.lock().unwrap();
+        let error = match follow(&filesystem, &normalize_path(dir, &current_path)) {
+            Ok(Node::Directory { .. }) => None,
+            Ok(_) => Some(FsError::NotADirectory),
+            Err(e) => Some(e),
+        };
+        if let Some(e) = error {
+            return stream::error(format!(
+                "tar: {}: Cannot open: {}\ntar: Error is not recoverable: exiting now",
+                dir, e
+            ));
+        }
+    }
+
+    // The terminal is the only place `-` could go
+    let archive = match parsed.value('f') {
+        Some("-") | None if mode == 'c' => {
+            return stream::error("tar: Refusing to write archive contents to terminal (missing -f option?)\ntar: Error is not recoverable: exiting now")
+        }
+        Some(archive) => archive,
+        None => {
+            return stream::error("tar: Refusing to read archive contents from terminal (missing -f option?)\ntar: Error is not recoverable: exiting now")
+        }
+    };
+
+    let (mut output, failed) = if mode == 'c' {
+        if parsed.operands.is_empty() {
+            return stream::error(format!(
+                "tar: Cowardly refusing to create an empty archive\n{}",
+                USAGE
+            ));
+        }
+        create(archive, &parsed.operands, dir, parsed.has('z'), verbose)
+    } else {
+        let mut data = match archive {
+            "-" => stdin.unwrap_or_default().to_vec(),
+            _ => match filesystem::read_bytes(archive) {
+                Ok(data) => data,
+                Err(e) => {
+                    return stream::error(format!(
+                        "tar: {}: Cannot open: {}\ntar: Error is not recoverable: exiting now",
+                        archive, e
+                    ))
+                }
+            },
+        };
+        // Compressed archives are recognised whether or not -z was given
+        if gzip::is_gzip(&data) {
+            data = match gzip::decompress(&data) {
+                Ok(member) => member.data,
+                Err(e) => {
+                    return stream::error(format!(
+                        "gzip: stdin: {}\ntar: Child returned status 1\ntar: Error is not recoverable: exiting now",
+                        e
+                    ))
+                }
+            };
+        } else if parsed.has('z') {
+            return stream::error("gzip: stdin: not in gzip format\ntar: Child returned status 1\ntar: Error is not recoverable: exiting now");
+        }
+
+        let (members, error) = match parse(&data) {
+            Ok(members) => (members, None),
+            Err(e) => (Vec::new(), Some(e)),
+        };
+        let patterns = &parsed.operands;
+        let mut missing: Vec<&str> = patterns
+            .iter()
+            .copied()
+            .filter(|pattern| !members.iter().any(|m| selected(&m.name, &[pattern])))
+            .collect();
+        let members: Vec<Stored> = members
+            .into_iter()
+            .filter(|member| selected(&member.name, patterns))
+            .collect();
+
+        let (mut output, mut failed) = if mode == 't' {
+            (list(&members, verbose), false)
+        } else {
+            let extracted = extract(members, dir, verbose);
+            (extracted.output, extracted.failed)
+        };
+        if let Some(e) = error {
+            output.push(e);
+            failed = true;
+        }
+        failed |= !missing.is_empty();
+        output.extend(
+            missing
+                .drain(..)
+                .map(|pattern| stream::error(format!("tar: {}: Not found in archive", pattern))),
+        );
+        (output, failed)
+    };
+
+    if failed {
+        output.push(stream::error(
+            "tar: Exiting with failure status due to previous errors",
+        ));
+    }
+    output.join("\n")
+}
+
+struct GzipOptions {
+    decompress: bool,
+    stdout: bool,
+    force: bool,
+    keep: bool,
+    list: bool,
+    test: bool,
+    verbose: bool,
+    no_name: bool,
+}
+
+// Compression ratio the way gzip reports it, counting only the compressed
+// data and not the header and trailer around it
+fn ratio(original: usize, compressed: usize) -> String {
+    let saved = if original == 0 {
+        0.0
+    } else {
+        100.0 * (original as f64 - compressed as f64) / original as f64
+    };
+    format!("{:5.1}%", saved)
+}
+
+fn overhead(name: Option<&str>) -> usize {
+    18 + name.map_or(0, |name| name.len() + 1)
+}
+
+// Mode and mtime of the file behind `name`, which must not be a directory
+fn source(name: &str) -> Result<(u16, f64), FsError> {
+    let filesystem = FILESYSTEM.lock().unwrap();
+    let current_path = CURRENT_PATH.lock().unwrap();
+    match follow(&filesystem, &normalize_path(name, &current_path))? {
+        Node::Directory { .. } => Err(FsError::IsADirectory),
+        node => Ok((node.permissions(), node.times().mtime)),
+    }
+}
+
+fn base_name(name: &str) -> &str {
+    name.rsplit('/').next().unwrap_or(name)
+}
+
+// The compressed-file suffix `name` ends with, if any
+fn suffix(name: &str) -> Option<&'static str> {
+    [".gz", "-gz", ".z", "-z", "_z", ".Z", ".tgz", ".taz"]
+        .iter()
+        .copied()
+        .find(|suffix| {
+            name.strip_suffix(*suffix)
+                .is_some_and(|stem| !stem.is_empty() && !stem.ends_with('/'))
+        })
+}
+
+// Where `name` decompresses to, None for names gzip wouldn't have made
+fn stripped_name(name: &str) -> Option<String> {
+    let suffix = suffix(name)?;
+    let stem = &name[..name.len() - suffix.len()];
+    match suffix {
+        ".tgz" | ".taz" => Some(format!("{}.tar", stem)),
+        _ => Some(stem.to_string()),
+    }
+}
+
+// Like gzip, compressed data only goes to the screen when forced to
+const NOT_TO_TERMINAL: &str = "gzip: compressed data not written to a terminal. Use -f to force compression.\nFor help, type: gzip -h";
+
+fn compress_file(name: &str, stdin: Option<&[u8]>, options: &GzipOptions, output: &mut Output) {
+    if name == "-" {
+        if !options.force && stream::is_terminal() {
+            output.line(stream::error(NOT_TO_TERMINAL));
+            return;
+        }
+        output.bytes(gzip::compress(stdin.unwrap_or_default(), None, 0));
+        return;
+    }
+
+    let (mode, mtime) = match source(name) {
+        Ok(found) => found,
+        Err(FsError::IsADirectory) => {
+            output.line(stream::error(format!(
+                "gzip: {} is a directory -- ignored",
+                name
+            )));
+            return;
+        }
+        Err(e) => {
+            output.line(stream::error(format!("gzip: {}: {}", name, e)));
+            return;
+        }
+    };
+    if let Some(suffix) = suffix(name).filter(|_| !options.stdout) {
+        output.line(stream::error(format!(
+            "gzip: {} already has {} suffix -- unchanged",
+            name, suffix
+        )));
+        return;
+    }
+    let data = match filesystem::read_bytes(name) {
+        Ok(data) => data,
+        Err(e) => {
+            output.line(stream::error(format!("gzip: {}: {}", name, e)));
+            return;
+        }
+    };
+
+    let original = Some(base_name(name)).filter(|_| !options.no_name);
+    let seconds = if options.no_name {
+        0
+    } else {
+        (mtime / 1000.0).max(0.0) as u32
+    };
+    let compressed = gzip::compress(&data, original, seconds);
+    let saved = ratio(data.len(), compressed.len() - overhead(original));
+
+    if options.stdout {
+        if !options.force && stream::is_terminal() {
+            output.line(stream::error(NOT_TO_TERMINAL));
+            return;
+        }
+        output.bytes(compressed);
+        return;
+    }
+
+    let target = format!("{}.gz", name);
+    write_result(
+        name,
+        &target,
+        compressed,
+        (mode, mtime),
+        &saved,
+        options,
+        output,
+    );
+}
+
+// Writes the compressed or decompressed file next to `name`, which goes
+// away afterwards unless -k was given
+fn write_result(
+    name: &str,
+    target: &str,
+    data: Vec<u8>,
+    (mode, mtime): (u16, f64),
+    saved: &str,
+    options: &GzipOptions,
+    output: &mut Output,
+) {
+    if !options.force && exists(target) {
+        output.line(stream::error(format!(
+            "gzip: {} already exists; not overwritten",
+            target
+        )));
+        return;
+    }
+    if let Err(e) = filesystem::install(target, Entry::File(data), mode, mtime) {
+        output.line(stream::error(format!("gzip: {}: {}", target, e)));
+        return;
+    }
+    if !options.keep {
+        if let Err(e) = filesystem::remove_file(name) {
+            output.line(stream::error(format!("gzip: {}: {}", name, e)));
+        }
+    }
+    if options.verbose {
+        let action = if options.keep {
+            "created"
+        } else {
+            "replaced with"
+        };
+        output.line(format!("{}:\t{} -- {} {}", name, saved, action, target));
+    }
+}
+
+fn decompress_file(name: &str, stdin: Option<&[u8]>, options: &GzipOptions, output: &mut Output) {
+    let (data, found) = if name == "-" {
+        (stdin.unwrap_or_default().to_vec(), None)
+    } else {
+        let found = match source(name) {
+            Ok(found) => found,
+            Err(FsError::IsADirectory) => {
+                output.line(stream::error(format!(
+                    "gzip: {} is a directory -- ignored",
+                    name
+                )));
+                return;
+            }
+            Err(e) => {
+                output.line(stream::error(format!("gzip: {}: {}", name, e)));
+                return;
+            }
+        };
+        match filesystem::read_bytes(name) {
+            Ok(data) => (data, Some(found)),
+            Err(e) => {
+                output.line(stream::error(format!("gzip: {}: {}", name, e)));
+                return;
+            }
+        }
+    };
+    let display = if name == "-" { "stdin" } else { name };
+
+    let target = stripped_name(name);
+    if target.is_none() && found.is_some() && !options.stdout && !options.test {
+        output.line(stream::error(format!(
+            "gzip: {}: unknown suffix -- ignored",
+            name
+        )));
+        return;
+    }
+    let member = match gzip::decompress(&data) {
+        Ok(member) => member,
+        Err(e) => {
+            output.line(stream::error(format!("gzip: {}: {}", display, e)));
+            return;
+        }
+    };
+
+    if options.test {
+        if options.verbose {
+            output.line(format!("{}:\t OK", display));
+        }
+        return;
+    }
+    if options.stdout || found.is_none() {
+        output.bytes(member.data);
+        return;
+    }
+
+    let (mode, mtime) = found.unwrap();
+    // The original mtime comes back from the header when it has one
+    let mtime = match member.mtime {
+        0 => mtime,
+        seconds => seconds as f64 * 1000.0,
+    };
+    let saved = ratio(
+        member.data.len(),
+        data.len() - overhead(member.name.as_deref()),
+    );
+    let target = target.unwrap();
+    write_result(
+        name,
+        &target,
+        member.data,
+        (mode, mtime),
+        &saved,
+        options,
+        output,
+    );
+}
+
+fn list_files(files: &[&str], stdin: Option<&[u8]>) -> Vec<String> {
+    let mut output = vec![format!(
+        "{:>19} {:>19}  ratio uncompressed_name",
+        "compressed", "uncompressed"
+    )];
+    let mut totals = (0, 0);
+    let mut listed = 0;
+
+    for &name in files {
+        let data = match name {
+            "-" => stdin.unwrap_or_default().to_vec(),
+            _ => match filesystem::read_bytes(name) {
+                Ok(data) => data,
+                Err(e) => {
+                    output.push(stream::error(format!("gzip: {}: {}", name, e)));
+                    continue;
+                }
+            },
+        };
+        let member = match gzip::decompress(&data) {
+            Ok(member) => member,
+            Err(e) => {
+                output.push(stream::error(format!("gzip: {}: {}", name, e)));
+                continue;
+            }
+        };
+
+        let uncompressed = member.data.len();
+        let compressed = data.len() - overhead(member.name.as_deref());
+        let display = stripped_name(name).unwrap_or_else(|| name.to_string());
+        output.push(format!(
+            "{:>19} {:>19} {} {}",
+            data.len(),
+            uncompressed,
+            ratio(uncompressed, compressed),
+            display
+        ));
+        totals = (totals.0 + data.len(), totals.1 + uncompressed);
+        listed += 1;
+    }
+
+    if listed > 1 {
+        output.push(format!(
+            "{:>19} {:>19} {} (totals)",
+            totals.0,
+            totals.1,
+            ratio(totals.1, totals.0.saturating_sub(18 * listed))
+        ));
+    }
+    if listed == 0 {
+        output.remove(0);
+    }
+    output
+}
+
+fn gzip_command(
+    cmd: &str,
+    args: &[&str],
+    stdin: Option<&[u8]>,
+    mut options: GzipOptions,
+) -> Output {
+    let mut short = Vec::new();
+    for &arg in args {
+        let flag = match arg.strip_prefix("--") {
+            Some("") | None => {
+                short.push(arg);
+                continue;
+            }
+            Some("stdout") | Some("to-stdout") => "-c",
+            Some("decompress") | Some("uncompress") => "-d",
+            Some("force") => "-f",
+            Some("keep") => "-k",
+            Some("list") => "-l",
+            Some("no-name") => "-n",
+            Some("name") => "-N",
+            Some("test") => "-t",
+            Some("verbose") => "-v",
+            Some("fast") => "-1",
+            Some("best") => "-9",
+            Some(name) => {
+                return stream::error(format!(
+                    "{}: unrecognized option '--{}'\nTry `{} --help' for more information.",
+                    cmd, name, cmd
+                ))
+                .into()
+            }
+        };
+        short.push(flag);
+    }
+
+    let parsed = match text::parse(cmd, &short, "cdfklnNtvq123456789", "") {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            return stream::error(format!("{}\nTry `{} --help' for more information.", e, cmd))
+                .into()
+        }
+    };
+    options.decompress |= parsed.has('d');
+    options.stdout |= parsed.has('c');
+    options.force |= parsed.has('f');
+    options.keep |= parsed.has('k');
+    options.list = parsed.has('l');
+    options.test = parsed.has('t');
+    options.verbose = parsed.has('v');
+    options.no_name = parsed.has('n');
+    // There is a single compression level, so -1 to -9 change nothing
+
+    let files = if parsed.operands.is_empty() {
+        vec!["-"]
+    } else {
+        parsed.operands
+    };
+    if options.list {
+        return list_files(&files, stdin).join("\n").into();
+    }
+
+    let mut output = Output::default();
+    for file in files {
+        if options.decompress || options.test {
+            decompress_file(file, stdin, &options, &mut output);
+        } else {
+            compress_file(file, stdin, &options, &mut output);
+        }
+    }
+    output
+}
+
+fn defaults() -> GzipOptions {
+    GzipOptions {
+        decompress: false,
+        stdout: false,
+        force: false,
+        keep: false,
+        list: false,
+        test: false,
+        verbose: false,
+        no_name: false,
+    }
+}
+
+pub fn gzip(args: &[&str], stdin: Option<&[u8]>) -> Output {
+    gzip_command("gzip", args, stdin, defaults())
+}
+
+pub fn gunzip(args: &[&str], stdin: Option<&[u8]>) -> Output {
+    let options = GzipOptions {
+        decompress: true,
+        ..defaults()
+    };
+    gzip_command("gunzip", args, stdin, options)
+}
+
+pub fn zcat(args: &[&str], stdin: Option<&[u8]>) -> Output {
+    let options = GzipOptions {
+        decompress: true,
+        stdout: true,
+        ..defaults()
+    };
+    gzip_command("zcat", args, stdin, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::{filesystem, CommandHandler};
+    use crate::testing;
+
+    #[test]
+    fn gzip_stdout_carries_raw_bytes() {
+        let _guard = testing::fresh();
+        let mut handler = CommandHandler::new();
+        handler.handle("head -c 16 /dev/urandom > /tmp/random");
+        let random = filesystem::read_bytes("/tmp/random").unwrap();
+
+        handler.handle("gzip -c /tmp/random > /tmp/random.gz");
+        handler.handle("gunzip -c /tmp/random.gz > /tmp/back");
+        assert_eq!(filesystem::read_bytes("/tmp/back").unwrap(), random);
+        assert_eq!(
+            handler.handle("gzip -c /tmp/random | gunzip | wc -c").0,
+            "16"
+        );
+    }
+
+    #[test]
+    fn gzip_refuses_the_terminal_unless_forced() {
+        let _guard = testing::fresh();
+        let mut handler = CommandHandler::new();
+        assert!(handler
+            .handle("gzip -c /etc/hostname")
+            .0
+            .starts_with("gzip: compressed data not written to a terminal"));
+        assert_eq!(handler.handle("echo hi | gzip -f | zcat").0, "hi");
+    }
+
+    #[test]
+    fn tar_keeps_links_modes_and_bytes() {
+        let _guard = testing::fresh();
+        let mut handler = CommandHandler::new();
+        let binary: Vec<u8> = (0..=255).cycle().take(3000).collect();
+        handler.handle("mkdir /tmp/src");
+        filesystem::write_bytes("/tmp/src/data", &binary).unwrap();
+        filesystem::write_file("/tmp/src/run", "#!/bin/sh\n").unwrap();
+        handler.handle("chmod 750 /tmp/src/run");
+        handler.handle("chmod 600 /tmp/src/data");
+        handler.handle("ln -s data /tmp/src/link");
+
+        for (create, extract) in &[("cf", "xf"), ("czf", "xzf")] {
+            handler.handle("rm -r /tmp/out");
+            handler.handle("mkdir /tmp/out");
+            handler.handle(&format!("tar {} /tmp/t -C /tmp src", create));
+            assert_eq!(
+                handler
+                    .handle(&format!("tar {} /tmp/t -C /tmp/out", extract))
+                    .0,
+                ""
+            );
+            assert_eq!(filesystem::read_bytes("/tmp/out/src/data").unwrap(), binary);
+            assert_eq!(
+                handler
+                    .handle(
+                        "stat -c '%a %F %N' /tmp/out/src/data /tmp/out/src/run /tmp/out/src/link"
+                    )
+                    .0,
+                "600 regular file '/tmp/out/src/data'\n\
+                 750 regular file '/tmp/out/src/run'\n\
+                 777 symbolic link '/tmp/out/src/link' -> 'data'"
+            );
+        }
+    }
+
+    #[test]
+    fn gunzip_rejects_a_bad_crc() {
+        let _guard = testing::fresh();
+        let mut handler = CommandHandler::new();
+        filesystem::write_file("/tmp/notes", "some notes\n").unwrap();
+        handler.handle("gzip /tmp/notes");
+        let mut data = filesystem::read_bytes("/tmp/notes.gz").unwrap();
+        let crc = data.len() - 8;
+        data[crc] ^= 0xff;
+        filesystem::write_bytes("/tmp/notes.gz", &data).unwrap();
+
+        assert_eq!(
+            handler.handle("gunzip /tmp/notes.gz").0,
+            "gzip: /tmp/notes.gz: invalid compressed data--crc error"
+        );
+        // The damaged file stays and nothing half-written appears
+        assert_eq!(filesystem::read_bytes("/tmp/notes.gz").unwrap(), data);
+        assert!(filesystem::read_file("/tmp/notes").is_err());
+    }
+}
@@ -0,0 +1,2130 @@
+use super::filesystem;
+use super::stream;
+use super::text;
+use regex_lite::Regex;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+const USAGE: &str = "Usage: awk [-F fs] [-v var=value] [-f progfile | 'prog'] [file ...]";
+
+// The terminal shares the page's only thread, so runaway loops are cut off
+const LOOP_LIMIT: usize = 1_000_000;
+
+const KEYWORDS: &[&str] = &[
+    "BEGIN", "END", "if", "else", "while", "for", "do", "in", "print", "printf", "next", "exit",
+    "delete", "break", "continue", "function", "return", "getline",
+];
+
+const BUILTINS: &[&str] = &[
+    "length", "substr", "index", "split", "sub", "gsub", "match", "sprintf", "tolower", "toupper",
+    "int", "sqrt", "exp", "log", "sin", "cos", "atan2",
+];
+
+// Longer operators first so `+=` wins over `+`
+const OPERATORS: &[&str] = &[
+    "+=", "-=", "*=", "/=", "%=", "^=", "==", "<=", ">=", "!=", "++", "--", "&&", "||", ">>", "!~",
+    "{", "}", "(", ")", "[", "]", ";", ",", "+", "-", "*", "/", "%", "^", "!", ">", "<", "|", "?",
+    ":", "~", "$", "=",
+];
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Str(String),
+    Regex(String),
+    Name(String),
+    Op(&'static str),
+    Newline,
+    Eof,
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Number(n) => number_to_string(*n),
+        Token::Str(s) => format!("\"{}\"", s),
+        Token::Regex(s) => format!("/{}/", s),
+        Token::Name(name) => name.clone(),
+        Token::Op(op) => op.to_string(),
+        Token::Newline => "newline".to_string(),
+        Token::Eof => "end of program".to_string(),
+    }
+}
+
+// A `/` after an operand divides, anywhere else it starts a regex
+fn regex_allowed(last: Option<&(Token, usize)>) -> bool {
+    match last.map(|(token, _)| token) {
+        None | Some(Token::Newline) => true,
+        Some(Token::Number(_)) | Some(Token::Str(_)) | Some(Token::Regex(_)) => false,
+        Some(Token::Name(name)) => KEYWORDS.contains(&name.as_str()),
+        Some(Token::Op(op)) => !matches!(*op, ")" | "]" | "$" | "++" | "--"),
+        Some(Token::Eof) => false,
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            ' ' | '\t' | '\r' => i += 1,
+            '\\' if chars.get(i + 1) == Some(&'\n') => {
+                i += 2;
+                line += 1;
+            }
+            '#' => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            }
+            '\n' => {
+                tokens.push((Token::Newline, line));
+                line += 1;
+                i += 1;
+            }
+            '"' => {
+                let mut text = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        Some('"') => break,
+                        Some('\\') => {
+                            i += 1;
+                            match chars.get(i) {
+                                Some('n') => text.push('\n'),
+                                Some('t') => text.push('\t'),
+                                Some('r') => text.push('\r'),
+                                Some(&c) => text.push(c),
+                                None => break,
+                            }
+                        }
+                        Some('\n') | None => {
+                            return Err(stream::error(format!(
+                                "awk: cmd. line:{}: unterminated string",
+                                line
+                            )))
+                        }
+                        Some(&c) => text.push(c),
+                    }
+                    i += 1;
+                }
+                tokens.push((Token::Str(text), line));
+                i += 1;
+            }
+            '/' if regex_allowed(tokens.last()) => {
+                let mut text = String::new();
+                let mut bracket = false;
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        Some('/') if !bracket => break,
+                        Some('\\') if chars.get(i + 1) == Some(&'/') => {
+                            text.push('/');
+                            i += 1;
+                        }
+                        Some('\\') if chars.get(i + 1).is_some() => {
+                            text.push('\\');
+                            text.push(chars[i + 1]);
+                            i += 1;
+                        }
+                        Some('\n') | None => {
+                            return Err(stream::error(format!(
+                                "awk: cmd. line:{}: unterminated regexp",
+                                line
+                            )))
+                        }
+                        Some(&c) => {
+                            match c {
+                                '[' => bracket = true,
+                                ']' => bracket = false,
+                                _ => {}
+                            }
+                            text.push(c);
+                        }
+                    }
+                    i += 1;
+                }
+                tokens.push((Token::Regex(text), line));
+                i += 1;
+            }
+            c if c.is_ascii_digit()
+                || (c == '.' && chars.get(i + 1).is_some_and(|c| c.is_ascii_digit())) =>
+            {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                if matches!(chars.get(i), Some('e') | Some('E')) {
+                    let mut end = i + 1;
+                    if matches!(chars.get(end), Some('+') | Some('-')) {
+                        end += 1;
+                    }
+                    if chars.get(end).is_some_and(|c| c.is_ascii_digit()) {
+                        i = end;
+                        while i < chars.len() && chars[i].is_ascii_digit() {
+                            i += 1;
+                        }
+                    }
+                }
+                let text: String = chars[start..i].iter().collect();
+                tokens.push((Token::Number(leading_number(&text)), line));
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                tokens.push((Token::Name(chars[start..i].iter().collect()), line));
+            }
+            _ => {
+                let op = OPERATORS.iter().find(|op| {
+                    op.chars()
+                        .enumerate()
+                        .all(|(j, c)| chars.get(i + j) == Some(&c))
+                });
+                match op {
+                    Some(op) => {
+                        tokens.push((Token::Op(op), line));
+                        i += op.len();
+                    }
+                    None => {
+                        return Err(stream::error(format!(
+                            "awk: cmd. line:{}: invalid char '{}' in expression",
+                            line, c
+                        )))
+                    }
+                }
+            }
+        }
+    }
+
+    tokens.push((Token::Eof, line));
+    Ok(tokens)
+}
+
+#[derive(Clone, Copy)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Pow,
+    Concat,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+enum Expr {
+    Num(f64),
+    Str(String),
+    // A bare regex matches against $0
+    Regex(String),
+    Var(String),
+    Field(Box<Expr>),
+    Index(String, Vec<Expr>),
+    // `(a, b)`, only meaningful before `in` or as print's arguments
+    Grouping(Vec<Expr>),
+    Assign(Box<Expr>, Option<Op>, Box<Expr>),
+    Cond(Box<Expr>, Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    Plus(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+    Match(bool, Box<Expr>, Box<Expr>),
+    In(Vec<Expr>, String),
+    Incr {
+        target: Box<Expr>,
+        delta: f64,
+        prefix: bool,
+    },
+    Call(String, Vec<Expr>),
+}
+
+enum Stmt {
+    Expr(Expr),
+    Print(Vec<Expr>),
+    Printf(Vec<Expr>),
+    If(Expr, Box<Stmt>, Option<Box<Stmt>>),
+    While(Expr, Box<Stmt>),
+    Do(Box<Stmt>, Expr),
+    For(Option<Expr>, Option<Expr>, Option<Expr>, Box<Stmt>),
+    ForIn(String, String, Box<Stmt>),
+    Block(Vec<Stmt>),
+    Delete(String, Option<Vec<Expr>>),
+    Next,
+    Exit(Option<Expr>),
+    Break,
+    Continue,
+}
+
+enum Pattern {
+    All,
+    Expr(Expr),
+    Range(Expr, Expr),
+}
+
+struct Rule {
+    pattern: Pattern,
+    // None prints the record
+    action: Option<Vec<Stmt>>,
+}
+
+struct Program {
+    begin: Vec<Stmt>,
+    rules: Vec<Rule>,
+    end: Vec<Stmt>,
+}
+
+fn is_lvalue(expr: &Expr) -> bool {
+    matches!(expr, Expr::Var(_) | Expr::Field(_) | Expr::Index(..))
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    // Inside print `>` would be a redirection, not a comparison
+    no_gt: bool,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos].0
+    }
+
+    fn peek_at(&self, offset: usize) -> &Token {
+        let index = (self.pos + offset).min(self.tokens.len() - 1);
+        &self.tokens[index].0
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.pos].0.clone();
+        if self.pos + 1 < self.tokens.len() {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn error(&self) -> String {
+        let (token, line) = &self.tokens[self.pos];
+        stream::error(format!(
+            "awk: cmd. line:{}: syntax error at {}",
+            line,
+            describe(token)
+        ))
+    }
+
+    fn unsupported(&self, what: &str) -> String {
+        let line = self.tokens[self.pos].1;
+        stream::error(format!(
+            "awk: cmd. line:{}: {} is not supported",
+            line, what
+        ))
+    }
+
+    fn is_op(&self, op: &str) -> bool {
+        matches!(self.peek(), Token::Op(o) if *o == op)
+    }
+
+    fn is_name(&self, name: &str) -> bool {
+        matches!(self.peek(), Token::Name(n) if n == name)
+    }
+
+    fn eat(&mut self, op: &str) -> bool {
+        let found = self.is_op(op);
+        if found {
+            self.advance();
+        }
+        found
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), String> {
+        if self.eat(op) {
+            Ok(())
+        } else {
+            Err(self.error())
+        }
+    }
+
+    fn skip_newlines(&mut self) {
+        while *self.peek() == Token::Newline {
+            self.advance();
+        }
+    }
+
+    fn skip_terminators(&mut self) {
+        while *self.peek() == Token::Newline || self.is_op(";") {
+            self.advance();
+        }
+    }
+
+    fn program(&mut self) -> Result<Program, String> {
+        let mut program = Program {
+            begin: Vec::new(),
+            rules: Vec::new(),
+            end: Vec::new(),
+        };
+
+        loop {
+            self.skip_terminators();
+            match self.peek() {
+                Token::Eof => return Ok(program),
+                Token::Name(name) if name == "BEGIN" => {
+                    self.advance();
+                    let block = self.block()?;
+                    program.begin.extend(block);
+                }
+                Token::Name(name) if name == "END" => {
+                    self.advance();
+                    let block = self.block()?;
+                    program.end.extend(block);
+                }
+                Token::Name(name) if name == "function" => {
+                    return Err(self.unsupported("user-defined function"))
+                }
+                Token::Op("{") => {
+                    let action = self.block()?;
+                    program.rules.push(Rule {
+                        pattern: Pattern::All,
+                        action: Some(action),
+                    });
+                }
+                _ => {
+                    let start = self.expr()?;
+                    let pattern = if self.eat(",") {
+                        self.skip_newlines();
+                        Pattern::Range(start, self.expr()?)
+                    } else {
+                        Pattern::Expr(start)
+                    };
+                    let action = if self.is_op("{") {
+                        Some(self.block()?)
+                    } else {
+                        None
+                    };
+                    program.rules.push(Rule { pattern, action });
+                }
+            }
+        }
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, String> {
+        self.expect("{")?;
+        let mut statements = Vec::new();
+        loop {
+            self.skip_terminators();
+            if self.eat("}") {
+                return Ok(statements);
+            }
+            if *self.peek() == Token::Eof {
+                return Err(self.error());
+            }
+            statements.push(self.statement()?);
+        }
+    }
+
+    // Simple statements end at `;`, a newline, or before `}`
+    fn end_simple(&mut self) -> Result<(), String> {
+        match self.peek() {
+            Token::Newline | Token::Op(";") => {
+                self.advance();
+                Ok(())
+            }
+            Token::Op("}") | Token::Eof => Ok(()),
+            _ => Err(self.error()),
+        }
+    }
+
+    fn body(&mut self) -> Result<Stmt, String> {
+        if self.eat(";") {
+            return Ok(Stmt::Block(Vec::new()));
+        }
+        self.skip_newlines();
+        self.statement()
+    }
+
+    fn condition(&mut self) -> Result<Expr, String> {
+        self.expect("(")?;
+        let condition = self.expr()?;
+        self.expect(")")?;
+        Ok(condition)
+    }
+
+    fn statement(&mut self) -> Result<Stmt, String> {
+        let keyword = match self.peek() {
+            Token::Op("{") => return Ok(Stmt::Block(self.block()?)),
+            Token::Op(";") => {
+                self.advance();
+                return Ok(Stmt::Block(Vec::new()));
+            }
+            Token::Name(name) if KEYWORDS.contains(&name.as_str()) => name.clone(),
+            _ => {
+                let expr = self.expr()?;
+                self.end_simple()?;
+                return Ok(Stmt::Expr(expr));
+            }
+        };
+
+        match keyword.as_str() {
+            "if" => {
+                self.advance();
+                let condition = self.condition()?;
+                let then = self.body()?;
+
+                let before = self.pos;
+                self.skip_terminators();
+                let otherwise = if self.is_name("else") {
+                    self.advance();
+                    self.skip_newlines();
+                    Some(Box::new(self.statement()?))
+                } else {
+                    self.pos = before;
+                    None
+                };
+                Ok(Stmt::If(condition, Box::new(then), otherwise))
+            }
+            "while" => {
+                self.advance();
+                let condition = self.condition()?;
+                Ok(Stmt::While(condition, Box::new(self.body()?)))
+            }
+            "do" => {
+                self.advance();
+                self.skip_newlines();
+                let body = self.statement()?;
+                self.skip_terminators();
+                if !self.is_name("while") {
+                    return Err(self.error());
+                }
+                self.advance();
+                let condition = self.condition()?;
+                self.end_simple()?;
+                Ok(Stmt::Do(Box::new(body), condition))
+            }
+            "for" => {
+                self.advance();
+                self.expect("(")?;
+
+                if let (
+                    Token::Name(var),
+                    Token::Name(keyword),
+                    Token::Name(array),
+                    Token::Op(")"),
+                ) = (
+                    self.peek(),
+                    self.peek_at(1),
+                    self.peek_at(2),
+                    self.peek_at(3),
+                ) {
+                    if keyword == "in" {
+                        let (var, array) = (var.clone(), array.clone());
+                        self.pos += 4;
+                        return Ok(Stmt::ForIn(var, array, Box::new(self.body()?)));
+                    }
+                }
+
+                let init = if self.is_op(";") {
+                    None
+                } else {
+                    Some(self.expr()?)
+                };
+                self.expect(";")?;
+                self.skip_newlines();
+                let condition = if self.is_op(";") {
+                    None
+                } else {
+                    Some(self.expr()?)
+                };
+                self.expect(";")?;
+                self.skip_newlines();
+                let step = if self.is_op(")") {
+                    None
+                } else {
+                    Some(self.expr()?)
+                };
+                self.expect(")")?;
+                Ok(Stmt::For(init, condition, step, Box::new(self.body()?)))
+            }
+            "delete" => {
+                self.advance();
+                let name = match self.advance() {
+                    Token::Name(name) => name,
+                    _ => return Err(self.error()),
+                };
+                let keys = if self.eat("[") {
+                    let keys = self.expr_list()?;
+                    self.expect("]")?;
+                    Some(keys)
+                } else {
+                    None
+                };
+                self.end_simple()?;
+                Ok(Stmt::Delete(name, keys))
+            }
+            "print" | "printf" => {
+                self.advance();
+                self.no_gt = true;
+                let args = match self.peek() {
+                    Token::Newline | Token::Eof | Token::Op(";") | Token::Op("}") => Vec::new(),
+                    _ => self.expr_list()?,
+                };
+                self.no_gt = false;
+
+                // `print (a, b)` is the same as `print a, b`
+                let mut args = args;
+                if let [Expr::Grouping(_)] = args.as_slice() {
+                    if let Some(Expr::Grouping(list)) = args.pop() {
+                        args = list;
+                    }
+                }
+                if self.is_op(">") || self.is_op(">>") || self.is_op("|") {
+                    return Err(self.unsupported("output redirection"));
+                }
+                self.end_simple()?;
+
+                if keyword == "print" {
+                    Ok(Stmt::Print(args))
+                } else if args.is_empty() {
+                    Err(self.error())
+                } else {
+                    Ok(Stmt::Printf(args))
+                }
+            }
+            "next" | "break" | "continue" => {
+                self.advance();
+                self.end_simple()?;
+                Ok(match keyword.as_str() {
+                    "next" => Stmt::Next,
+                    "break" => Stmt::Break,
+                    _ => Stmt::Continue,
+                })
+            }
+            "exit" => {
+                self.advance();
+                let status = match self.peek() {
+                    Token::Newline | Token::Eof | Token::Op(";") | Token::Op("}") => None,
+                    _ => Some(self.expr()?),
+                };
+                self.end_simple()?;
+                Ok(Stmt::Exit(status))
+            }
+            "getline" | "return" | "function" => Err(self.unsupported(&keyword)),
+            _ => Err(self.error()),
+        }
+    }
+
+    fn expr_list(&mut self) -> Result<Vec<Expr>, String> {
+        let mut list = vec![self.expr()?];
+        while self.eat(",") {
+            self.skip_newlines();
+            list.push(self.expr()?);
+        }
+        Ok(list)
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        let target = self.ternary()?;
+
+        let op = match self.peek() {
+            Token::Op("=") => None,
+            Token::Op("+=") => Some(Op::Add),
+            Token::Op("-=") => Some(Op::Sub),
+            Token::Op("*=") => Some(Op::Mul),
+            Token::Op("/=") => Some(Op::Div),
+            Token::Op("%=") => Some(Op::Mod),
+            Token::Op("^=") => Some(Op::Pow),
+            _ => return Ok(target),
+        };
+        if !is_lvalue(&target) {
+            return Err(self.error());
+        }
+        self.advance();
+        self.skip_newlines();
+        let value = self.expr()?;
+        Ok(Expr::Assign(Box::new(target), op, Box::new(value)))
+    }
+
+    fn ternary(&mut self) -> Result<Expr, String> {
+        let condition = self.or()?;
+        if !self.eat("?") {
+            return Ok(condition);
+        }
+        self.skip_newlines();
+        let then = self.expr()?;
+        self.skip_newlines();
+        self.expect(":")?;
+        self.skip_newlines();
+        let otherwise = self.ternary()?;
+        Ok(Expr::Cond(
+            Box::new(condition),
+            Box::new(then),
+            Box::new(otherwise),
+        ))
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut left = self.and()?;
+        while self.eat("||") {
+            self.skip_newlines();
+            left = Expr::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut left = self.membership()?;
+        while self.eat("&&") {
+            self.skip_newlines();
+            left = Expr::And(Box::new(left), Box::new(self.membership()?));
+        }
+        Ok(left)
+    }
+
+    fn membership(&mut self) -> Result<Expr, String> {
+        let mut left = self.matching()?;
+        while self.is_name("in") {
+            self.advance();
+            let array = match self.advance() {
+                Token::Name(name) => name,
+                _ => return Err(self.error()),
+            };
+            let keys = match left {
+                Expr::Grouping(keys) => keys,
+                key => vec![key],
+            };
+            left = Expr::In(keys, array);
+        }
+        Ok(left)
+    }
+
+    fn matching(&mut self) -> Result<Expr, String> {
+        let mut left = self.comparison()?;
+        loop {
+            let negated = match self.peek() {
+                Token::Op("~") => false,
+                Token::Op("!~") => true,
+                _ => return Ok(left),
+            };
+            self.advance();
+            let right = self.comparison()?;
+            left = Expr::Match(negated, Box::new(left), Box::new(right));
+        }
+    }
+
+    fn comparison(&mut self) -> Result<Expr, String> {
+        let left = self.concat()?;
+        let op = match self.peek() {
+            Token::Op("<") => Op::Lt,
+            Token::Op("<=") => Op::Le,
+            Token::Op(">") if !self.no_gt => Op::Gt,
+            Token::Op(">=") => Op::Ge,
+            Token::Op("==") => Op::Eq,
+            Token::Op("!=") => Op::Ne,
+            _ => return Ok(left),
+        };
+        self.advance();
+        let right = self.concat()?;
+        Ok(Expr::Binary(op, Box::new(left), Box::new(right)))
+    }
+
+    fn starts_concat(&self) -> bool {
+        match self.peek() {
+            Token::Number(_) | Token::Str(_) => true,
+            Token::Name(name) => !KEYWORDS.contains(&name.as_str()),
+            Token::Op(op) => matches!(*op, "$" | "("),
+            _ => false,
+        }
+    }
+
+    fn concat(&mut self) -> Result<Expr, String> {
+        let mut left = self.additive()?;
+        while self.starts_concat() {
+            let right = self.additive()?;
+            left = Expr::Binary(Op::Concat, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn additive(&mut self) -> Result<Expr, String> {
+        let mut left = self.multiplicative()?;
+        loop {
+            let op = match self.peek() {
+                Token::Op("+") => Op::Add,
+                Token::Op("-") => Op::Sub,
+                _ => return Ok(left),
+            };
+            self.advance();
+            let right = self.multiplicative()?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+    }
+
+    fn multiplicative(&mut self) -> Result<Expr, String> {
+        let mut left = self.unary()?;
+        loop {
+            let op = match self.peek() {
+                Token::Op("*") => Op::Mul,
+                Token::Op("/") => Op::Div,
+                Token::Op("%") => Op::Mod,
+                _ => return Ok(left),
+            };
+            self.advance();
+            let right = self.unary()?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat("!") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat("-") {
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        if self.eat("+") {
+            return Ok(Expr::Plus(Box::new(self.unary()?)));
+        }
+        self.power()
+    }
+
+    fn power(&mut self) -> Result<Expr, String> {
+        let base = self.postfix()?;
+        if self.eat("^") {
+            let exponent = self.unary()?;
+            return Ok(Expr::Binary(Op::Pow, Box::new(base), Box::new(exponent)));
+        }
+        Ok(base)
+    }
+
+    fn postfix(&mut self) -> Result<Expr, String> {
+        for (op, delta) in [("++", 1.0), ("--", -1.0)] {
+            if self.eat(op) {
+                let target = self.postfix()?;
+                if !is_lvalue(&target) {
+                    return Err(self.error());
+                }
+                return Ok(Expr::Incr {
+                    target: Box::new(target),
+                    delta,
+                    prefix: true,
+                });
+            }
+        }
+
+        let expr = self.primary()?;
+        if is_lvalue(&expr) {
+            for (op, delta) in [("++", 1.0), ("--", -1.0)] {
+                if self.eat(op) {
+                    return Ok(Expr::Incr {
+                        target: Box::new(expr),
+                        delta,
+                        prefix: false,
+                    });
+                }
+            }
+        }
+        Ok(expr)
+    }
+
+    // Parentheses, brackets and call arguments may use `>` even in print
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, String>,
+    ) -> Result<T, String> {
+        let no_gt = std::mem::replace(&mut self.no_gt, false);
+        let result = parse(self);
+        self.no_gt = no_gt;
+        result
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        let start = self.pos;
+        match self.advance() {
+            Token::Number(n) => Ok(Expr::Num(n)),
+            Token::Str(s) => Ok(Expr::Str(s)),
+            Token::Regex(s) => Ok(Expr::Regex(s)),
+            Token::Op("$") => {
+                let index = if self.is_op("++") || self.is_op("--") {
+                    self.postfix()?
+                } else {
+                    self.primary()?
+                };
+                Ok(Expr::Field(Box::new(index)))
+            }
+            Token::Op("(") => {
+                let mut list = self.nested(|parser| {
+                    let list = parser.expr_list()?;
+                    parser.expect(")")?;
+                    Ok(list)
+                })?;
+                if list.len() == 1 {
+                    Ok(list.pop().unwrap())
+                } else {
+                    Ok(Expr::Grouping(list))
+                }
+            }
+            Token::Name(name) if BUILTINS.contains(&name.as_str()) => {
+                // `length` alone means length($0)
+                if name == "length" && !self.is_op("(") {
+                    return Ok(Expr::Call(name, Vec::new()));
+                }
+                self.expect("(")?;
+                let args = self.nested(|parser| {
+                    let args = if parser.is_op(")") {
+                        Vec::new()
+                    } else {
+                        parser.expr_list()?
+                    };
+                    parser.expect(")")?;
+                    Ok(args)
+                })?;
+                Ok(Expr::Call(name, args))
+            }
+            Token::Name(name) if !KEYWORDS.contains(&name.as_str()) => {
+                if !self.eat("[") {
+                    return Ok(Expr::Var(name));
+                }
+                let keys = self.nested(|parser| {
+                    let keys = parser.expr_list()?;
+                    parser.expect("]")?;
+                    Ok(keys)
+                })?;
+                Ok(Expr::Index(name, keys))
+            }
+            _ => {
+                self.pos = start;
+                Err(self.error())
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+enum Value {
+    Uninit,
+    Num(f64),
+    Str(String),
+    // Input text, compared as a number when it looks like one
+    Strnum(String),
+}
+
+// Length of the numeric prefix of `text`, if it has any digits
+fn numeric_prefix(text: &str) -> usize {
+    let bytes = text.as_bytes();
+    let mut end = 0;
+    if matches!(bytes.first(), Some(b'+') | Some(b'-')) {
+        end += 1;
+    }
+
+    let mut digits = 0;
+    while end < bytes.len() && bytes[end].is_ascii_digit() {
+        end += 1;
+        digits += 1;
+    }
+    if end < bytes.len() && bytes[end] == b'.' {
+        end += 1;
+        while end < bytes.len() && bytes[end].is_ascii_digit() {
+            end += 1;
+            digits += 1;
+        }
+    }
+    if digits == 0 {
+        return 0;
+    }
+
+    if end < bytes.len() && (bytes[end] == b'e' || bytes[end] == b'E') {
+        let mut exponent = end + 1;
+        if matches!(bytes.get(exponent), Some(b'+') | Some(b'-')) {
+            exponent += 1;
+        }
+        if bytes.get(exponent).is_some_and(|b| b.is_ascii_digit()) {
+            end = exponent;
+            while end < bytes.len() && bytes[end].is_ascii_digit() {
+                end += 1;
+            }
+        }
+    }
+    end
+}
+
+fn leading_number(text: &str) -> f64 {
+    let text = text.trim_start();
+    text[..numeric_prefix(text)].parse().unwrap_or(0.0)
+}
+
+fn looks_numeric(text: &str) -> bool {
+    let text = text.trim();
+    !text.is_empty() && numeric_prefix(text) == text.len()
+}
+
+fn trim_zeros(text: &str) -> &str {
+    if text.contains('.') {
+        text.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        text
+    }
+}
+
+// Rust writes `1.5e2`, C writes `1.5e+02`
+fn c_exponent(text: &str, upper: bool) -> String {
+    let (mantissa, exponent) = text.split_once('e').unwrap_or((text, "0"));
+    let exponent: i32 = exponent.parse().unwrap_or(0);
+    let sign = if exponent < 0 { '-' } else { '+' };
+    let e = if upper { 'E' } else { 'e' };
+    format!("{}{}{}{:02}", mantissa, e, sign, exponent.abs())
+}
+
+fn format_g(x: f64, precision: usize, upper: bool) -> String {
+    let precision = precision.max(1);
+    if x == 0.0 {
+        return "0".to_string();
+    }
+
+    let scientific = format!("{:.*e}", precision - 1, x);
+    let exponent: i32 = scientific
+        .split_once('e')
+        .and_then(|(_, e)| e.parse().ok())
+        .unwrap_or(0);
+
+    if exponent < -4 || exponent >= precision as i32 {
+        let (mantissa, _) = scientific.split_once('e').unwrap();
+        c_exponent(&format!("{}e{}", trim_zeros(mantissa), exponent), upper)
+    } else {
+        let decimals = (precision as i32 - 1 - exponent) as usize;
+        trim_zeros(&format!("{:.*}", decimals, x)).to_string()
+    }
+}
+
+fn number_to_string(n: f64) -> String {
+    if n.is_nan() {
+        "nan".to_string()
+    } else if n.is_infinite() {
+        if n < 0.0 { "-inf" } else { "inf" }.to_string()
+    } else if n.fract() == 0.0 && n.abs() < 1e18 {
+        format!("{}", n as i64)
+    } else if n < 0.0 {
+        format!("-{}", format_g(-n, 6, false))
+    } else {
+        format_g(n, 6, false)
+    }
+}
+
+impl Value {
+    fn num(&self) -> f64 {
+        match self {
+            Value::Uninit => 0.0,
+            Value::Num(n) => *n,
+            Value::Str(s) | Value::Strnum(s) => leading_number(s),
+        }
+    }
+
+    fn string(&self) -> String {
+        match self {
+            Value::Uninit => String::new(),
+            Value::Num(n) => number_to_string(*n),
+            Value::Str(s) | Value::Strnum(s) => s.clone(),
+        }
+    }
+
+    fn is_numeric(&self) -> bool {
+        match self {
+            Value::Uninit | Value::Num(_) => true,
+            Value::Str(_) => false,
+            Value::Strnum(s) => looks_numeric(s),
+        }
+    }
+
+    fn truthy(&self) -> bool {
+        match self {
+            Value::Uninit => false,
+            Value::Num(n) => *n != 0.0,
+            Value::Str(s) => !s.is_empty(),
+            Value::Strnum(s) if looks_numeric(s) => leading_number(s) != 0.0,
+            Value::Strnum(s) => !s.is_empty(),
+        }
+    }
+}
+
+fn boolean(value: bool) -> Value {
+    Value::Num(if value { 1.0 } else { 0.0 })
+}
+
+fn pad(sign: &str, body: &str, width: usize, left: bool, zero: bool) -> String {
+    let length = sign.chars().count() + body.chars().count();
+    let fill = width.saturating_sub(length);
+    if left {
+        format!("{}{}{}", sign, body, " ".repeat(fill))
+    } else if zero {
+        format!("{}{}{}", sign, "0".repeat(fill), body)
+    } else {
+        format!("{}{}{}", " ".repeat(fill), sign, body)
+    }
+}
+
+// printf-style formatting shared by printf and sprintf
+fn sprintf(spec: &str, args: &[Value]) -> Result<String, String> {
+    let mut out = String::new();
+    let mut args = args.iter();
+    let mut chars = spec.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        if chars.peek() == Some(&'%') {
+            chars.next();
+            out.push('%');
+            continue;
+        }
+
+        let (mut left, mut zero, mut plus, mut space, mut alternate) =
+            (false, false, false, false, false);
+        while let Some(&flag) = chars.peek() {
+            match flag {
+                '-' => left = true,
+                '0' => zero = true,
+                '+' => plus = true,
+                ' ' => space = true,
+                '#' => alternate = true,
+                _ => break,
+            }
+            chars.next();
+        }
+
+        let mut number = |chars: &mut std::iter::Peekable<std::str::Chars>| -> Option<usize> {
+            if chars.peek() == Some(&'*') {
+                chars.next();
+                return Some(args.next().map_or(0.0, Value::num) as usize);
+            }
+            let mut digits = String::new();
+            while let Some(&d) = chars.peek().filter(|c| c.is_ascii_digit()) {
+                digits.push(d);
+                chars.next();
+            }
+            digits.parse().ok()
+        };
+        let width = number(&mut chars).unwrap_or(0);
+        let precision = if chars.peek() == Some(&'.') {
+            chars.next();
+            Some(number(&mut chars).unwrap_or(0))
+        } else {
+            None
+        };
+
+        let conversion = match chars.next() {
+            Some(conversion) => conversion,
+            None => {
+                out.push('%');
+                break;
+            }
+        };
+        if !"diouxXeEfFgGcs".contains(conversion) {
+            out.push('%');
+            out.push(conversion);
+            continue;
+        }
+        let arg = args.next().ok_or_else(|| {
+            stream::error("awk: fatal: not enough arguments to satisfy format string")
+        })?;
+
+        let sign_of = |negative: bool| {
+            if negative {
+                "-"
+            } else if plus {
+                "+"
+            } else if space {
+                " "
+            } else {
+                ""
+            }
+        };
+
+        let formatted = match conversion {
+            'd' | 'i' => {
+                let n = arg.num().trunc();
+                let mut digits = format!("{}", n.abs() as u64);
+                if let Some(precision) = precision {
+                    digits = format!("{:0>1$}", digits, precision);
+                }
+                let zero = zero && precision.is_none();
+                pad(sign_of(n < 0.0), &digits, width, left, zero)
+            }
+            'o' | 'u' | 'x' | 'X' => {
+                let n = arg.num().trunc() as i64 as u64;
+                let digits = match conversion {
+                    'o' => format!("{:o}", n),
+                    'u' => format!("{}", n),
+                    'x' => format!("{:x}", n),
+                    _ => format!("{:X}", n),
+                };
+                let prefix = match conversion {
+                    'x' if alternate && n != 0 => "0x",
+                    'X' if alternate && n != 0 => "0X",
+                    _ => "",
+                };
+                pad(prefix, &digits, width, left, zero)
+            }
+            'e' | 'E' | 'f' | 'F' | 'g' | 'G' => {
+                let n = arg.num();
+                let precision = precision.unwrap_or(6);
+                let body = if !n.is_finite() {
+                    if n.is_nan() { "nan" } else { "inf" }.to_string()
+                } else {
+                    match conversion {
+                        'e' | 'E' => {
+                            c_exponent(&format!("{:.*e}", precision, n.abs()), conversion == 'E')
+                        }
+                        'f' | 'F' => format!("{:.*}", precision, n.abs()),
+                        _ => format_g(n.abs(), precision, conversion == 'G'),
+                    }
+                };
+                pad(sign_of(n < 0.0), &body, width, left, zero && n.is_finite())
+            }
+            'c' => {
+                let c = match arg {
+                    Value::Num(n) => char::from_u32(*n as u32).map(String::from),
+                    _ => arg.string().chars().next().map(String::from),
+                };
+                pad("", &c.unwrap_or_default(), width, left, false)
+            }
+            _ => {
+                let mut s = arg.string();
+                if let Some(precision) = precision {
+                    s = s.chars().take(precision).collect();
+                }
+                pad("", &s, width, left, false)
+            }
+        };
+        out.push_str(&formatted);
+    }
+
+    Ok(out)
+}
+
+// Numeric keys first in numeric order, then the rest as strings
+fn compare_keys(a: &str, b: &str) -> Ordering {
+    match (looks_numeric(a), looks_numeric(b)) {
+        (true, true) => leading_number(a)
+            .partial_cmp(&leading_number(b))
+            .unwrap_or(Ordering::Equal),
+        (true, false) => Ordering::Less,
+        (false, true) => Ordering::Greater,
+        (false, false) => a.cmp(b),
+    }
+}
+
+#[derive(PartialEq)]
+enum Flow {
+    Normal,
+    Next,
+    Exit,
+    Break,
+    Continue,
+}
+
+struct Interpreter {
+    vars: HashMap<String, Value>,
+    arrays: HashMap<String, HashMap<String, Value>>,
+    record: String,
+    fields: Vec<String>,
+    regexes: HashMap<String, Regex>,
+    ranges: Vec<bool>,
+    output: String,
+    iterations: usize,
+}
+
+impl Interpreter {
+    fn new() -> Self {
+        let mut vars = HashMap::new();
+        for (name, value) in [
+            ("FS", " "),
+            ("OFS", " "),
+            ("ORS", "\n"),
+            ("SUBSEP", "\x1c"),
+            ("FILENAME", ""),
+        ] {
+            vars.insert(name.to_string(), Value::Str(value.to_string()));
+        }
+        for name in ["NR", "NF", "FNR", "RSTART"] {
+            vars.insert(name.to_string(), Value::Num(0.0));
+        }
+        vars.insert("RLENGTH".to_string(), Value::Num(-1.0));
+
+        Interpreter {
+            vars,
+            arrays: HashMap::new(),
+            record: String::new(),
+            fields: Vec::new(),
+            regexes: HashMap::new(),
+            ranges: Vec::new(),
+            output: String::new(),
+            iterations: 0,
+        }
+    }
+
+    fn var(&self, name: &str) -> String {
+        self.vars.get(name).map(Value::string).unwrap_or_default()
+    }
+
+    fn tick(&mut self) -> Result<(), String> {
+        self.iterations += 1;
+        if self.iterations > LOOP_LIMIT {
+            return Err(stream::error("awk: fatal: loop limit exceeded"));
+        }
+        Ok(())
+    }
+
+    fn regex(&mut self, source: &str) -> Result<&Regex, String> {
+        if !self.regexes.contains_key(source) {
+            let regex = Regex::new(source)
+                .map_err(|_| stream::error(format!("awk: fatal: invalid regexp: /{}/", source)))?;
+            self.regexes.insert(source.to_string(), regex);
+        }
+        Ok(&self.regexes[source])
+    }
+
+    // `" "` splits on runs of blanks, one other character literally,
+    // anything longer as a regex
+    fn split(&mut self, text: &str, separator: &str) -> Result<Vec<String>, String> {
+        if text.is_empty() {
+            return Ok(Vec::new());
+        }
+        if separator == " " {
+            return Ok(text.split_whitespace().map(str::to_string).collect());
+        }
+        let mut chars = separator.chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            if c != '\\' {
+                return Ok(text.split(c).map(str::to_string).collect());
+            }
+        }
+        Ok(self
+            .regex(separator)?
+            .split(text)
+            .map(str::to_string)
+            .collect())
+    }
+
+    fn set_record(&mut self, record: String) -> Result<(), String> {
+        let separator = self.var("FS");
+        self.fields = self.split(&record, &separator)?;
+        self.record = record;
+        self.vars
+            .insert("NF".to_string(), Value::Num(self.fields.len() as f64));
+        Ok(())
+    }
+
+    fn rebuild(&mut self) {
+        self.record = self.fields.join(&self.var("OFS"));
+        self.vars
+            .insert("NF".to_string(), Value::Num(self.fields.len() as f64));
+    }
+
+    fn field(&self, index: usize) -> String {
+        match index {
+            0 => self.record.clone(),
+            _ => self.fields.get(index - 1).cloned().unwrap_or_default(),
+        }
+    }
+
+    fn field_index(&mut self, expr: &Expr) -> Result<usize, String> {
+        let index = self.eval(expr)?.num();
+        if index < 0.0 {
+            return Err(stream::error(format!(
+                "awk: fatal: attempt to access field {}",
+                number_to_string(index)
+            )));
+        }
+        Ok(index as usize)
+    }
+
+    fn key(&mut self, keys: &[Expr]) -> Result<String, String> {
+        let mut parts = Vec::new();
+        for key in keys {
+            parts.push(self.eval(key)?.string());
+        }
+        Ok(parts.join(&self.var("SUBSEP")))
+    }
+
+    fn assign(&mut self, target: &Expr, value: Value) -> Result<(), String> {
+        match target {
+            Expr::Var(name) if name == "NF" => {
+                let count = value.num().max(0.0) as usize;
+                self.fields.resize(count, String::new());
+                self.rebuild();
+            }
+            Expr::Var(name) => {
+                self.vars.insert(name.clone(), value);
+            }
+            Expr::Field(index) => match self.field_index(index)? {
+                0 => self.set_record(value.string())?,
+                index => {
+                    if index > self.fields.len() {
+                        self.fields.resize(index, String::new());
+                    }
+                    self.fields[index - 1] = value.string();
+                    self.rebuild();
+                }
+            },
+            Expr::Index(name, keys) => {
+                let key = self.key(keys)?;
+                self.arrays
+                    .entry(name.clone())
+                    .or_default()
+                    .insert(key, value);
+            }
+            _ => unreachable!("assignment targets are checked by the parser"),
+        }
+        Ok(())
+    }
+
+    // Regex operands may be literals or any expression holding one
+    fn pattern(&mut self, expr: &Expr) -> Result<String, String> {
+        match expr {
+            Expr::Regex(source) => Ok(source.clone()),
+            _ => Ok(self.eval(expr)?.string()),
+        }
+    }
+
+    fn arithmetic(op: Op, a: f64, b: f64) -> Result<f64, String> {
+        Ok(match op {
+            Op::Add => a + b,
+            Op::Sub => a - b,
+            Op::Mul => a * b,
+            Op::Div if b == 0.0 => {
+                return Err(stream::error("awk: fatal: division by zero attempted"))
+            }
+            Op::Div => a / b,
+            Op::Mod if b == 0.0 => {
+                return Err(stream::error(
+                    "awk: fatal: division by zero attempted in `%'",
+                ))
+            }
+            Op::Mod => a % b,
+            _ => a.powf(b),
+        })
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Result<Value, String> {
+        Ok(match expr {
+            Expr::Num(n) => Value::Num(*n),
+            Expr::Str(s) => Value::Str(s.clone()),
+            Expr::Regex(source) => {
+                let record = self.record.clone();
+                boolean(self.regex(source)?.is_match(&record))
+            }
+            Expr::Var(name) => self.vars.get(name).cloned().unwrap_or(Value::Uninit),
+            Expr::Field(index) => {
+                let index = self.field_index(index)?;
+                Value::Strnum(self.field(index))
+            }
+            Expr::Index(name, keys) => {
+                let key = self.key(keys)?;
+                self.arrays
+                    .entry(name.clone())
+                    .or_default()
+                    .entry(key)
+                    .or_insert(Value::Uninit)
+                    .clone()
+            }
+            Expr::Grouping(list) => {
+                let key = self.key(list)?;
+                Value::Str(key)
+            }
+            Expr::Assign(target, op, value) => {
+                let mut value = self.eval(value)?;
+                if let Some(op) = op {
+                    let current = self.eval(target)?.num();
+                    value = Value::Num(Self::arithmetic(*op, current, value.num())?);
+                }
+                self.assign(target, value.clone())?;
+                value
+            }
+            Expr::Cond(condition, then, otherwise) => {
+                if self.eval(condition)?.truthy() {
+                    self.eval(then)?
+                } else {
+                    self.eval(otherwise)?
+                }
+            }
+            Expr::And(left, right) => {
+                boolean(self.eval(left)?.truthy() && self.eval(right)?.truthy())
+            }
+            Expr::Or(left, right) => {
+                boolean(self.eval(left)?.truthy() || self.eval(right)?.truthy())
+            }
+            Expr::Not(operand) => boolean(!self.eval(operand)?.truthy()),
+            Expr::Neg(operand) => Value::Num(-self.eval(operand)?.num()),
+            Expr::Plus(operand) => Value::Num(self.eval(operand)?.num()),
+            Expr::Binary(op, left, right) => {
+                let left = self.eval(left)?;
+                let right = self.eval(right)?;
+                let ordering = || {
+                    if left.is_numeric() && right.is_numeric() {
+                        left.num()
+                            .partial_cmp(&right.num())
+                            .unwrap_or(Ordering::Equal)
+                    } else {
+                        left.string().cmp(&right.string())
+                    }
+                };
+                match op {
+                    Op::Concat => Value::Str(left.string() + &right.string()),
+                    Op::Lt => boolean(ordering() == Ordering::Less),
+                    Op::Le => boolean(ordering() != Ordering::Greater),
+                    Op::Gt => boolean(ordering() == Ordering::Greater),
+                    Op::Ge => boolean(ordering() != Ordering::Less),
+                    Op::Eq => boolean(ordering() == Ordering::Equal),
+                    Op::Ne => boolean(ordering() != Ordering::Equal),
+                    _ => Value::Num(Self::arithmetic(*op, left.num(), right.num())?),
+                }
+            }
+            Expr::Match(negated, left, right) => {
+                let text = self.eval(left)?.string();
+                let source = self.pattern(right)?;
+                boolean(self.regex(&source)?.is_match(&text) != *negated)
+            }
+            Expr::In(keys, array) => {
+                let key = self.key(keys)?;
+                boolean(
+                    self.arrays
+                        .get(array)
+                        .is_some_and(|array| array.contains_key(&key)),
+                )
+            }
+            Expr::Incr {
+                target,
+                delta,
+                prefix,
+            } => {
+                let old = self.eval(target)?.num();
+                self.assign(target, Value::Num(old + delta))?;
+                Value::Num(if *prefix { old + delta } else { old })
+            }
+            Expr::Call(name, args) => self.call(name, args)?,
+        })
+    }
+
+    fn call(&mut self, name: &str, args: &[Expr]) -> Result<Value, String> {
+        let arity = match name {
+            "length" => 0..=1,
+            "split" | "substr" => 2..=3,
+            "sub" | "gsub" => 2..=3,
+            "index" | "match" | "atan2" => 2..=2,
+            "sprintf" => 1..=usize::MAX,
+            _ => 1..=1,
+        };
+        if !arity.contains(&args.len()) {
+            return Err(stream::error(format!(
+                "awk: fatal: {}: called with {} arguments",
+                name,
+                args.len()
+            )));
+        }
+
+        Ok(match name {
+            "length" => match args.first() {
+                None => Value::Num(self.record.chars().count() as f64),
+                Some(Expr::Var(array)) if self.arrays.contains_key(array) => {
+                    Value::Num(self.arrays[array].len() as f64)
+                }
+                Some(arg) => Value::Num(self.eval(arg)?.string().chars().count() as f64),
+            },
+            "substr" => {
+                let chars: Vec<char> = self.eval(&args[0])?.string().chars().collect();
+                let start = self.eval(&args[1])?.num().round();
+                let end = match args.get(2) {
+                    Some(length) => start + self.eval(length)?.num().round(),
+                    None => f64::INFINITY,
+                };
+                let from = start.max(1.0);
+                let to = end.min(chars.len() as f64 + 1.0);
+                if to <= from {
+                    Value::Str(String::new())
+                } else {
+                    Value::Str(chars[from as usize - 1..to as usize - 1].iter().collect())
+                }
+            }
+            "index" => {
+                let text = self.eval(&args[0])?.string();
+                let target = self.eval(&args[1])?.string();
+                let position = text
+                    .find(&target)
+                    .map_or(0, |byte| text[..byte].chars().count() + 1);
+                Value::Num(position as f64)
+            }
+            "split" => {
+                let array = match &args[1] {
+                    Expr::Var(array) => array.clone(),
+                    _ => {
+                        return Err(stream::error(
+                            "awk: fatal: split: second argument is not an array",
+                        ))
+                    }
+                };
+                let text = self.eval(&args[0])?.string();
+                let separator = match args.get(2) {
+                    Some(separator) => self.pattern(separator)?,
+                    None => self.var("FS"),
+                };
+                let parts = self.split(&text, &separator)?;
+                let count = parts.len();
+                self.arrays.insert(
+                    array,
+                    parts
+                        .into_iter()
+                        .enumerate()
+                        .map(|(i, part)| ((i + 1).to_string(), Value::Strnum(part)))
+                        .collect(),
+                );
+                Value::Num(count as f64)
+            }
+            "sub" | "gsub" => {
+                let source = self.pattern(&args[0])?;
+                let replacement = self.eval(&args[1])?.string();
+                let record = Expr::Field(Box::new(Expr::Num(0.0)));
+                let target = args.get(2).unwrap_or(&record);
+                if !is_lvalue(target) {
+                    return Err(stream::error(format!(
+                        "awk: fatal: {}: third argument is not assignable",
+                        name
+                    )));
+                }
+                let text = self.eval(target)?.string();
+
+                let regex = self.regex(&source)?;
+                let mut result = String::new();
+                let mut copied = 0;
+                let mut count = 0;
+                for found in regex.find_iter(&text) {
+                    result.push_str(&text[copied..found.start()]);
+                    // `&` is the match, `\&` a literal ampersand
+                    let mut chars = replacement.chars().peekable();
+                    while let Some(c) = chars.next() {
+                        match c {
+                            '\\' if matches!(chars.peek(), Some('&') | Some('\\')) => {
+                                result.push(chars.next().unwrap())
+                            }
+                            '&' => result.push_str(found.as_str()),
+                            c => result.push(c),
+                        }
+                    }
+                    copied = found.end();
+                    count += 1;
+                    if name == "sub" {
+                        break;
+                    }
+                }
+
+                if count > 0 {
+                    result.push_str(&text[copied..]);
+                    self.assign(target, Value::Str(result))?;
+                }
+                Value::Num(count as f64)
+            }
+            "match" => {
+                let text = self.eval(&args[0])?.string();
+                let source = self.pattern(&args[1])?;
+                let (start, length) = match self.regex(&source)?.find(&text) {
+                    Some(found) => (
+                        text[..found.start()].chars().count() as f64 + 1.0,
+                        found.as_str().chars().count() as f64,
+                    ),
+                    None => (0.0, -1.0),
+                };
+                self.vars.insert("RSTART".to_string(), Value::Num(start));
+                self.vars.insert("RLENGTH".to_string(), Value::Num(length));
+                Value::Num(start)
+            }
+            "sprintf" => {
+                let mut values = Vec::new();
+                for arg in args {
+                    values.push(self.eval(arg)?);
+                }
+                Value::Str(sprintf(&values[0].string(), &values[1..])?)
+            }
+            "tolower" => Value::Str(self.eval(&args[0])?.string().to_lowercase()),
+            "toupper" => Value::Str(self.eval(&args[0])?.string().to_uppercase()),
+            "atan2" => {
+                let y = self.eval(&args[0])?.num();
+                Value::Num(y.atan2(self.eval(&args[1])?.num()))
+            }
+            _ => {
+                let x = self.eval(&args[0])?.num();
+                Value::Num(match name {
+                    "int" => x.trunc(),
+                    "sqrt" => x.sqrt(),
+                    "exp" => x.exp(),
+                    "log" => x.ln(),
+                    "sin" => x.sin(),
+                    _ => x.cos(),
+                })
+            }
+        })
+    }
+
+    fn exec_block(&mut self, statements: &[Stmt]) -> Result<Flow, String> {
+        for statement in statements {
+            let flow = self.exec(statement)?;
+            if flow != Flow::Normal {
+                return Ok(flow);
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    // Runs a loop body and says whether the loop should go on, or how it
+    // has to be left
+    fn iterate(&mut self, body: &Stmt) -> Result<Option<Flow>, String> {
+        self.tick()?;
+        Ok(match self.exec(body)? {
+            Flow::Normal | Flow::Continue => None,
+            Flow::Break => Some(Flow::Normal),
+            flow => Some(flow),
+        })
+    }
+
+    fn exec(&mut self, statement: &Stmt) -> Result<Flow, String> {
+        match statement {
+            Stmt::Expr(expr) => {
+                self.eval(expr)?;
+            }
+            Stmt::Print(args) => {
+                let line = if args.is_empty() {
+                    self.record.clone()
+                } else {
+                    let mut parts = Vec::new();
+                    for arg in args {
+                        parts.push(self.eval(arg)?.string());
+                    }
+                    parts.join(&self.var("OFS"))
+                };
+                self.output.push_str(&line);
+                self.output.push_str(&self.var("ORS"));
+            }
+            Stmt::Printf(args) => {
+                let mut values = Vec::new();
+                for arg in args {
+                    values.push(self.eval(arg)?);
+                }
+                let text = sprintf(&values[0].string(), &values[1..])?;
+                self.output.push_str(&text);
+            }
+            Stmt::If(condition, then, otherwise) => {
+                if self.eval(condition)?.truthy() {
+                    return self.exec(then);
+                } else if let Some(otherwise) = otherwise {
+                    return self.exec(otherwise);
+                }
+            }
+            Stmt::While(condition, body) => {
+                while self.eval(condition)?.truthy() {
+                    if let Some(flow) = self.iterate(body)? {
+                        return Ok(flow);
+                    }
+                }
+            }
+            Stmt::Do(body, condition) => loop {
+                if let Some(flow) = self.iterate(body)? {
+                    return Ok(flow);
+                }
+                if !self.eval(condition)?.truthy() {
+                    break;
+                }
+            },
+            Stmt::For(init, condition, step, body) => {
+                if let Some(init) = init {
+                    self.eval(init)?;
+                }
+                loop {
+                    if let Some(condition) = condition {
+                        if !self.eval(condition)?.truthy() {
+                            break;
+                        }
+                    }
+                    if let Some(flow) = self.iterate(body)? {
+                        return Ok(flow);
+                    }
+                    if let Some(step) = step {
+                        self.eval(step)?;
+                    }
+                }
+            }
+            Stmt::ForIn(var, array, body) => {
+                let mut keys: Vec<String> = self
+                    .arrays
+                    .get(array)
+                    .map(|array| array.keys().cloned().collect())
+                    .unwrap_or_default();
+                keys.sort_by(|a, b| compare_keys(a, b));
+
+                for key in keys {
+                    self.vars.insert(var.clone(), Value::Strnum(key));
+                    if let Some(flow) = self.iterate(body)? {
+                        return Ok(flow);
+                    }
+                }
+            }
+            Stmt::Block(statements) => return self.exec_block(statements),
+            Stmt::Delete(array, keys) => match keys {
+                Some(keys) => {
+                    let key = self.key(keys)?;
+                    if let Some(array) = self.arrays.get_mut(array) {
+                        array.remove(&key);
+                    }
+                }
+                None => {
+                    self.arrays.remove(array);
+                }
+            },
+            Stmt::Next => return Ok(Flow::Next),
+            Stmt::Exit(status) => {
+                if let Some(status) = status {
+                    self.eval(status)?;
+                }
+                return Ok(Flow::Exit);
+            }
+            Stmt::Break => return Ok(Flow::Break),
+            Stmt::Continue => return Ok(Flow::Continue),
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn selects(&mut self, index: usize, pattern: &Pattern) -> Result<bool, String> {
+        Ok(match pattern {
+            Pattern::All => true,
+            Pattern::Expr(expr) => self.eval(expr)?.truthy(),
+            Pattern::Range(start, end) => {
+                if !self.ranges[index] {
+                    if !self.eval(start)?.truthy() {
+                        return Ok(false);
+                    }
+                    self.ranges[index] = true;
+                }
+                if self.eval(end)?.truthy() {
+                    self.ranges[index] = false;
+                }
+                true
+            }
+        })
+    }
+
+    fn process(
+        &mut self,
+        program: &Program,
+        files: &[&str],
+        stdin: Option<&str>,
+    ) -> Result<(), String> {
+        // exit in BEGIN or a rule skips the rest of the input, but END still runs
+        if self.exec_block(&program.begin)? != Flow::Exit
+            && (!program.rules.is_empty() || !program.end.is_empty())
+        {
+            self.ranges = vec![false; program.rules.len()];
+            'inputs: for input in text::read_inputs(files, stdin) {
+                let content = match &input.content {
+                    Ok(content) => content,
+                    Err(e) => {
+                        return Err(stream::error(format!(
+                            "awk: fatal: cannot open file `{}' for reading: {}",
+                            input.display_name(),
+                            e
+                        )))
+                    }
+                };
+                let filename = input.name.clone().unwrap_or_default();
+                self.vars
+                    .insert("FILENAME".to_string(), Value::Str(filename));
+                self.vars.insert("FNR".to_string(), Value::Num(0.0));
+
+                for line in content.lines() {
+                    for counter in ["NR", "FNR"] {
+                        let n = self.vars[counter].num();
+                        self.vars.insert(counter.to_string(), Value::Num(n + 1.0));
+                    }
+                    self.set_record(line.to_string())?;
+
+                    for (index, rule) in program.rules.iter().enumerate() {
+                        if !self.selects(index, &rule.pattern)? {
+                            continue;
+                        }
+                        let flow = match &rule.action {
+                            Some(action) => self.exec_block(action)?,
+                            None => self.exec(&Stmt::Print(Vec::new()))?,
+                        };
+                        match flow {
+                            Flow::Next => break,
+                            Flow::Exit => break 'inputs,
+                            _ => {}
+                        }
+                    }
+                }
+            }
+        }
+
+        self.exec_block(&program.end)?;
+        Ok(())
+    }
+}
+
+// Escapes in -F and -v values work as in string literals, `-F t` is a tab
+fn unescape(text: &str) -> String {
+    let mut out = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some(c) => out.push(c),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+pub fn awk(args: &[&str], stdin: Option<&str>) -> String {
+    let parsed = match text::parse("awk", args, "", "Fvf") {
+        Ok(parsed) => parsed,
+        Err(e) => return stream::error(format!("{}\n{}", e, USAGE)),
+    };
+
+    let sources = parsed.values('f');
+    let (source, files) = if !sources.is_empty() {
+        let mut program = Vec::new();
+        for file in sources {
+            match filesystem::read_file(file) {
+                Ok(content) => program.push(content),
+                Err(e) => {
+                    return stream::error(format!(
+                        "awk: fatal: can't open source file `{}' for reading: {}",
+                        file, e
+                    ))
+                }
+            }
+        }
+        (program.join("\n"), &parsed.operands[..])
+    } else {
+        match parsed.operands.split_first() {
+            Some((program, files)) => (program.to_string(), files),
+            None => return stream::error(USAGE),
+        }
+    };
+
+    let program = match tokenize(&source).and_then(|tokens| {
+        Parser {
+            tokens,
+            pos: 0,
+            no_gt: false,
+        }
+        .program()
+    }) {
+        Ok(program) => program,
+        Err(e) => return e,
+    };
+
+    let mut interpreter = Interpreter::new();
+    if let Some(separator) = parsed.value('F') {
+        let separator = match separator {
+            "t" => "\t".to_string(),
+            _ => unescape(separator),
+        };
+        interpreter
+            .vars
+            .insert("FS".to_string(), Value::Str(separator));
+    }
+    for assignment in parsed.values('v') {
+        match assignment.split_once('=') {
+            Some((name, value))
+                if !name.is_empty()
+                    && !name.starts_with(|c: char| c.is_ascii_digit())
+                    && name.chars().all(|c| c.is_alphanumeric() || c == '_') =>
+            {
+                interpreter
+                    .vars
+                    .insert(name.to_string(), Value::Strnum(unescape(value)));
+            }
+            _ => {
+                return stream::error(format!(
+                    "awk: fatal: `{}' argument to `-v' not in `var=value' form",
+                    assignment
+                ))
+            }
+        }
+    }
+
+    let result = interpreter.process(&program, files, stdin);
+    let mut output = interpreter.output;
+    if output.ends_with('\n') {
+        output.pop();
+    }
+    match result {
+        Ok(()) => output,
+        Err(e) if output.is_empty() => e,
+        Err(e) => format!("{}\n{}", output, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    const PEOPLE: &str = "alice:30:paris\nbob:25:rome\ncarol:35:paris\n";
+
+    #[test]
+    fn fields_split_on_f() {
+        let _guard = testing::fresh();
+        assert_eq!(awk(&["{ print $2 }"], Some("a  b\tc\n d e\n")), "b\ne");
+        assert_eq!(
+            awk(&["-F:", "{ print $1, $3 }"], Some(PEOPLE)),
+            "alice paris\nbob rome\ncarol paris"
+        );
+        assert_eq!(
+            awk(&["-F", ":", "{ print NF, $NF }"], Some("a:b:c\n")),
+            "3 c"
+        );
+        assert_eq!(awk(&["-F[0-9]+", "{ print $2 }"], Some("a12b345c\n")), "b");
+        assert_eq!(awk(&["-Ft", "{ print $2 }"], Some("a\tb\n")), "b");
+        assert_eq!(
+            awk(
+                &["-F:", "-v", "OFS=-", "{ $1 = $1; print }"],
+                Some("a:b:c\n")
+            ),
+            "a-b-c"
+        );
+    }
+
+    #[test]
+    fn begin_and_end_run_around_the_input() {
+        let _guard = testing::fresh();
+        assert_eq!(
+            awk(
+                &[
+                    "-F:",
+                    "BEGIN { print \"start\" } { sum += $2 } END { print sum, NR }"
+                ],
+                Some(PEOPLE)
+            ),
+            "start\n90 3"
+        );
+        assert_eq!(awk(&["BEGIN { print 1 + 2 * 3 }"], None), "7");
+        assert_eq!(awk(&["END { print $0 }"], Some("first\nlast\n")), "last");
+    }
+
+    #[test]
+    fn patterns_and_ranges_select_lines() {
+        let _guard = testing::fresh();
+        let lines = "one\ntwo\nthree\nfour\nfive\n";
+        assert_eq!(awk(&["/^t/"], Some(lines)), "two\nthree");
+        assert_eq!(awk(&["NR % 2 == 0"], Some(lines)), "two\nfour");
+        assert_eq!(
+            awk(&["-F:", "$2 > 28 { print $1 }"], Some(PEOPLE)),
+            "alice\ncarol"
+        );
+        assert_eq!(awk(&["/two/,/four/"], Some(lines)), "two\nthree\nfour");
+        assert_eq!(awk(&["NR == 4, 0"], Some(lines)), "four\nfive");
+        assert_eq!(awk(&["!/o/"], Some(lines)), "three\nfive");
+        assert_eq!(awk(&["$0 ~ /e$/ && length > 4"], Some(lines)), "three");
+    }
+
+    #[test]
+    fn arrays_are_associative() {
+        let _guard = testing::fresh();
+        let program = r#"{ n[$3]++ } END { print n["paris"], n["rome"], length(n) }"#;
+        assert_eq!(awk(&["-F:", program], Some(PEOPLE)), "2 1 2");
+        let program = r#"BEGIN { a["x"] = 1; delete a["x"]; print ("x" in a), length(a) }"#;
+        assert_eq!(awk(&[program], None), "0 0");
+        assert_eq!(
+            awk(&["{ seen[$0]++ } seen[$0] == 2"], Some("a\nb\na\na\n")),
+            "a"
+        );
+    }
+
+    #[test]
+    fn printf_formats_its_arguments() {
+        let _guard = testing::fresh();
+        assert_eq!(
+            awk(
+                &["-F:", "{ printf \"%-6s|%4d|%s\\n\", $1, $2, $3 }"],
+                Some("bob:25:rome\n")
+            ),
+            "bob   |  25|rome"
+        );
+        assert_eq!(
+            awk(
+                &["BEGIN { printf \"%.2f %x %o %c %5.1f%%\\n\", 3.14159, 255, 8, 65, 9.96 }"],
+                None
+            ),
+            "3.14 ff 10 A  10.0%"
+        );
+        assert_eq!(
+            awk(&["BEGIN { printf \"%s %s\\n\", \"only\" }"], None),
+            stream::error("awk: fatal: not enough arguments to satisfy format string")
+        );
+    }
+
+    #[test]
+    fn string_functions() {
+        let _guard = testing::fresh();
+        assert_eq!(
+            awk(&["{ print length($0), length() }"], Some("hello\n")),
+            "5 5"
+        );
+        let program = r#"BEGIN { s = "portfolio"; print substr(s, 5), substr(s, 1, 4) }"#;
+        assert_eq!(awk(&[program], None), "folio port");
+        let program = r#"BEGIN { n = split("a,b,,c", parts, ","); print n, parts[1], parts[4] }"#;
+        assert_eq!(awk(&[program], None), "4 a c");
+        assert_eq!(
+            awk(
+                &["{ n = gsub(/o/, \"0\"); print n, $0 }"],
+                Some("foo boo\n")
+            ),
+            "4 f00 b00"
+        );
+        assert_eq!(
+            awk(&["{ sub(/o+/, \"[&]\", $2); print }"], Some("foo boo\n")),
+            "foo b[oo]"
+        );
+        assert_eq!(
+            awk(
+                &["BEGIN { print toupper(\"abc\") index(\"abc\", \"c\") }"],
+                None
+            ),
+            "ABC3"
+        );
+    }
+
+    #[test]
+    fn parse_errors_name_the_line_and_token() {
+        let _guard = testing::fresh();
+        assert_eq!(
+            awk(&["{ print $1"], Some("a\n")),
+            stream::error("awk: cmd. line:1: syntax error at end of program")
+        );
+        assert_eq!(
+            awk(&["BEGIN {\nx = 1 +\n}"], None),
+            stream::error("awk: cmd. line:2: syntax error at newline")
+        );
+        assert_eq!(
+            awk(&["function f() { }"], None),
+            stream::error("awk: cmd. line:1: user-defined function is not supported")
+        );
+        assert_eq!(
+            awk(&["BEGIN { print 1 / 0 }"], None),
+            stream::error("awk: fatal: division by zero attempted")
+        );
+    }
+}
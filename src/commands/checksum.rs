@@ -0,0 +1,311 @@
+use super::filesystem;
+use super::stream;
+use super::text;
+use crate::utils::digest;
+
+#[derive(Clone, Copy)]
+enum Algorithm {
+    Md5,
+    Sha1,
+    Sha256,
+}
+
+impl Algorithm {
+    fn command(self) -> &'static str {
+        match self {
+            Algorithm::Md5 => "md5sum",
+            Algorithm::Sha1 => "sha1sum",
+            Algorithm::Sha256 => "sha256sum",
+        }
+    }
+
+    // As written in --tag lines and messages
+    fn label(self) -> &'static str {
+        match self {
+            Algorithm::Md5 => "MD5",
+            Algorithm::Sha1 => "SHA1",
+            Algorithm::Sha256 => "SHA256",
+        }
+    }
+
+    fn digest(self, bytes: &[u8]) -> String {
+        match self {
+            Algorithm::Md5 => digest::hex(&digest::md5(bytes)),
+            Algorithm::Sha1 => digest::hex(&digest::sha1(bytes)),
+            Algorithm::Sha256 => digest::hex(&digest::sha256(bytes)),
+        }
+    }
+
+    fn hex_len(self) -> usize {
+        match self {
+            Algorithm::Md5 => 32,
+            Algorithm::Sha1 => 40,
+            Algorithm::Sha256 => 64,
+        }
+    }
+}
+
+struct Options {
+    binary: bool,
+    tag: bool,
+    quiet: bool,
+    status: bool,
+    warn: bool,
+}
+
+fn read(name: &str, stdin: Option<&[u8]>) -> Result<Vec<u8>, filesystem::FsError> {
+    match name {
+        "-" => Ok(stdin.unwrap_or_default().to_vec()),
+        _ => filesystem::read_bytes(name),
+    }
+}
+
+// Either `<hash>  <name>`, `<hash> *<name>` or the --tag form
+// `<ALGO> (<name>) = <hash>`
+fn parse_line(algorithm: Algorithm, line: &str) -> Option<(String, String)> {
+    let tag = format!("{} (", algorithm.label());
+    let (hash, name) = match line.strip_prefix(&tag) {
+        Some(rest) => {
+            let (name, hash) = rest.rsplit_once(") = ")?;
+            (hash, name)
+        }
+        None => {
+            let (hash, rest) = line.split_once(' ')?;
+            let name = rest.strip_prefix(|c| c == ' ' || c == '*')?;
+            (hash, name)
+        }
+    };
+
+    let valid = hash.len() == algorithm.hex_len() && hash.chars().all(|c| c.is_ascii_hexdigit());
+    if valid && !name.is_empty() {
+        Some((hash.to_ascii_lowercase(), name.to_string()))
+    } else {
+        None
+    }
+}
+
+fn plural(count: usize, one: &str, many: &str) -> String {
+    format!("{} {}", count, if count == 1 { one } else { many })
+}
+
+fn check(algorithm: Algorithm, files: &[&str], stdin: Option<&[u8]>, options: &Options) -> String {
+    let cmd = algorithm.command();
+    let mut output = Vec::new();
+    let mut malformed = 0;
+    let mut unreadable = 0;
+    let mut mismatched = 0;
+
+    for &file in files {
+        let listing = match read(file, stdin) {
+            Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+            Err(e) => {
+                output.push(stream::error(format!("{}: {}: {}", cmd, file, e)));
+                continue;
+            }
+        };
+
+        let mut checked = 0;
+        let mut bad_lines = 0;
+        for (number, line) in listing.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (expected, name) = match parse_line(algorithm, line) {
+                Some(entry) => entry,
+                None => {
+                    bad_lines += 1;
+                    if options.warn {
+                        output.push(stream::error(format!(
+                            "{}: {}:{}: improperly formatted {} checksum line",
+                            cmd,
+                            file,
+                            number + 1,
+                            algorithm.label()
+                        )));
+                    }
+                    continue;
+                }
+            };
+            checked += 1;
+
+            match read(&name, stdin) {
+                Ok(bytes) if algorithm.digest(&bytes) == expected => {
+                    if !options.quiet {
+                        output.push(format!("{}: OK", name));
+                    }
+                }
+                Ok(_) => {
+                    mismatched += 1;
+                    output.push(format!("{}: FAILED", name));
+                }
+                Err(e) => {
+                    unreadable += 1;
+                    output.push(stream::error(format!("{}: {}: {}", cmd, name, e)));
+                    output.push(format!("{}: FAILED open or read", name));
+                }
+            }
+        }
+
+        // A file with nothing to check gets this instead of the warning
+        if checked == 0 {
+            output.push(stream::error(format!(
+                "{}: {}: no properly formatted {} checksum lines found",
+                cmd,
+                file,
+                algorithm.label()
+            )));
+        } else {
+            malformed += bad_lines;
+        }
+    }
+
+    // --status only has the exit code left, which this shell doesn't keep
+    if options.status {
+        return String::new();
+    }
+    if malformed > 0 {
+        output.push(stream::error(format!(
+            "{}: WARNING: {} improperly formatted",
+            cmd,
+            plural(malformed, "line is", "lines are")
+        )));
+    }
+    if unreadable > 0 {
+        output.push(stream::error(format!(
+            "{}: WARNING: {} could not be read",
+            cmd,
+            plural(unreadable, "listed file", "listed files")
+        )));
+    }
+    if mismatched > 0 {
+        output.push(stream::error(format!(
+            "{}: WARNING: {} did NOT match",
+            cmd,
+            plural(mismatched, "computed checksum", "computed checksums")
+        )));
+    }
+    output.join("\n")
+}
+
+fn checksum(algorithm: Algorithm, args: &[&str], stdin: Option<&[u8]>) -> String {
+    let cmd = algorithm.command();
+    let mut long = Vec::new();
+    let mut short = Vec::new();
+    for &arg in args {
+        match arg.strip_prefix("--") {
+            Some(name) if !name.is_empty() => long.push(name),
+            _ => short.push(arg),
+        }
+    }
+
+    let parsed = match text::parse(cmd, &short, "bctw", "") {
+        Ok(parsed) => parsed,
+        Err(e) => return e,
+    };
+    let mut options = Options {
+        binary: parsed.has('b'),
+        tag: false,
+        quiet: false,
+        status: false,
+        warn: parsed.has('w'),
+    };
+    let mut verify = parsed.has('c');
+    for name in long {
+        match name {
+            "binary" => options.binary = true,
+            "text" => options.binary = false,
+            "check" => verify = true,
+            "tag" => options.tag = true,
+            "quiet" => options.quiet = true,
+            "status" => options.status = true,
+            "warn" => options.warn = true,
+            "strict" => {}
+            _ => return stream::error(format!("{}: unrecognized option '--{}'", cmd, name)),
+        }
+    }
+
+    let files = if parsed.operands.is_empty() {
+        vec!["-"]
+    } else {
+        parsed.operands
+    };
+    if verify {
+        return check(algorithm, &files, stdin, &options);
+    }
+
+    files
+        .iter()
+        .map(|&file| match read(file, stdin) {
+            Ok(bytes) if options.tag => {
+                format!(
+                    "{} ({}) = {}",
+                    algorithm.label(),
+                    file,
+                    algorithm.digest(&bytes)
+                )
+            }
+            Ok(bytes) => {
+                let mode = if options.binary { '*' } else { ' ' };
+                format!("{} {}{}", algorithm.digest(&bytes), mode, file)
+            }
+            Err(e) => stream::error(format!("{}: {}: {}", cmd, file, e)),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub fn md5sum(args: &[&str], stdin: Option<&[u8]>) -> String {
+    checksum(Algorithm::Md5, args, stdin)
+}
+
+pub fn sha1sum(args: &[&str], stdin: Option<&[u8]>) -> String {
+    checksum(Algorithm::Sha1, args, stdin)
+}
+
+pub fn sha256sum(args: &[&str], stdin: Option<&[u8]>) -> String {
+    checksum(Algorithm::Sha256, args, stdin)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::CommandHandler;
+    use crate::testing;
+
+    const HELLO: &str = "5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03";
+
+    #[test]
+    fn check_reports_ok_failed_and_missing_files() {
+        let _guard = testing::fresh();
+        let mut handler = CommandHandler::new();
+        filesystem::write_file("/tmp/a", "hello\n").unwrap();
+        filesystem::write_file("/tmp/b", "hello\n").unwrap();
+        filesystem::write_file("/tmp/c", "hello\n").unwrap();
+
+        handler.handle("sha256sum /tmp/a /tmp/b /tmp/c > /tmp/sums");
+        assert_eq!(
+            filesystem::read_file("/tmp/sums").unwrap(),
+            format!("{0}  /tmp/a\n{0}  /tmp/b\n{0}  /tmp/c\n", HELLO)
+        );
+        filesystem::write_file("/tmp/b", "bye\n").unwrap();
+        filesystem::rm(&["/tmp/c"]);
+
+        assert_eq!(
+            handler.handle("sha256sum -c /tmp/sums").0,
+            "/tmp/a: OK\n\
+             /tmp/b: FAILED\n\
+             sha256sum: /tmp/c: No such file or directory\n\
+             /tmp/c: FAILED open or read\n\
+             sha256sum: WARNING: 1 listed file could not be read\n\
+             sha256sum: WARNING: 1 computed checksum did NOT match"
+        );
+        assert_eq!(
+            handler.handle("sha256sum -c --quiet /tmp/sums").0,
+            "/tmp/b: FAILED\n\
+             sha256sum: /tmp/c: No such file or directory\n\
+             /tmp/c: FAILED open or read\n\
+             sha256sum: WARNING: 1 listed file could not be read\n\
+             sha256sum: WARNING: 1 computed checksum did NOT match"
+        );
+    }
+}
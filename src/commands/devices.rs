@@ -0,0 +1,147 @@
+use super::filesystem::{Ino, Node, Timestamps};
+use crate::terminal::buffer;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+
+// /dev and the devices in it take the inodes right after the root
+pub const DEV_INO: Ino = 3;
+// What a read that doesn't ask for a length gets from an endless device,
+// like the single read(2) behind `cat`
+pub const BLOCK: usize = 4096;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Device {
+    Null,
+    Zero,
+    Random,
+    Urandom,
+    Tty,
+}
+
+impl Device {
+    // Major and minor numbers as Linux assigns them
+    pub fn numbers(self) -> (u32, u32) {
+        match self {
+            Device::Null => (1, 3),
+            Device::Zero => (1, 5),
+            Device::Random => (1, 8),
+            Device::Urandom => (1, 9),
+            Device::Tty => (5, 0),
+        }
+    }
+
+    // Endless devices give exactly `limit` bytes, the others end at once.
+    // Nothing is ever typed ahead on the terminal.
+    pub fn read(self, limit: usize) -> Vec<u8> {
+        match self {
+            Device::Null | Device::Tty => Vec::new(),
+            Device::Zero => vec![0; limit],
+            Device::Random | Device::Urandom => random_bytes(limit),
+        }
+    }
+
+    // Everything is accepted, only the terminal does something with it
+    pub fn write(self, bytes: &[u8]) {
+        if self == Device::Tty && !bytes.is_empty() {
+            let text = String::from_utf8_lossy(bytes);
+            buffer::add_output_lines(text.strip_suffix('\n').unwrap_or(&text), None);
+        }
+    }
+}
+
+fn random_bytes(len: usize) -> Vec<u8> {
+    let mut bytes = vec![0; len];
+    if cfg!(target_arch = "wasm32") {
+        if let Some(crypto) = web_sys::window().and_then(|window| window.crypto().ok()) {
+            // getRandomValues fills at most 64 KiB per call
+            for chunk in bytes.chunks_mut(65536) {
+                let _ = crypto.get_random_values_with_u8_array(chunk);
+            }
+        }
+    } else {
+        // Every RandomState gets fresh keys seeded by the OS
+        for chunk in bytes.chunks_mut(8) {
+            let value = RandomState::new().build_hasher().finish().to_le_bytes();
+            chunk.copy_from_slice(&value[..chunk.len()]);
+        }
+    }
+    bytes
+}
+
+// /dev followed by its devices, for the built-in filesystem
+pub fn nodes(times: Timestamps) -> Vec<(Ino, Node)> {
+    let devices = [
+        ("null", Device::Null),
+        ("zero", Device::Zero),
+        ("random", Device::Random),
+        ("urandom", Device::Urandom),
+        ("tty", Device::Tty),
+    ];
+
+    let mut nodes = Vec::new();
+    let mut children = HashMap::new();
+    for (ino, (name, device)) in (DEV_INO + 1..).zip(devices) {
+        let group = if device == Device::Tty { "tty" } else { "root" };
+        children.insert(name.to_string(), ino);
+        nodes.push((
+            ino,
+            Node::Device {
+                device,
+                permissions: 0o666,
+                owner: "root".to_string(),
+                group: group.to_string(),
+                times,
+            },
+        ));
+    }
+
+    nodes.insert(
+        0,
+        (
+            DEV_INO,
+            Node::Directory {
+                children,
+                permissions: 0o755,
+                owner: "root".to_string(),
+                group: "root".to_string(),
+                protected: true,
+                times,
+            },
+        ),
+    );
+    nodes
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::filesystem::{self, FsError};
+    use crate::commands::CommandHandler;
+    use crate::testing;
+
+    #[test]
+    fn endless_devices_give_what_is_asked_for() {
+        let _guard = testing::fresh();
+        let mut handler = CommandHandler::new();
+        assert_eq!(handler.handle("head -c 5 /dev/zero").0, "\0".repeat(5));
+        assert_eq!(filesystem::read_prefix("/dev/zero", 3).unwrap(), vec![0; 3]);
+        assert_eq!(filesystem::read_prefix("/dev/urandom", 8).unwrap().len(), 8);
+        assert_eq!(filesystem::read_bytes("/dev/null").unwrap(), b"");
+    }
+
+    #[test]
+    fn null_swallows_writes_and_stays() {
+        let _guard = testing::fresh();
+        let mut handler = CommandHandler::new();
+        filesystem::write_file("/tmp/note.txt", "hello\n").unwrap();
+        assert_eq!(handler.handle("cp /tmp/note.txt /dev/null").0, "");
+        assert_eq!(filesystem::write_bytes("/dev/null", b"gone"), Ok(()));
+        assert_eq!(filesystem::read_bytes("/dev/null").unwrap(), b"");
+
+        assert_eq!(
+            filesystem::remove_file("/dev/null"),
+            Err(FsError::NotPermitted)
+        );
+    }
+}
@@ -0,0 +1,873 @@
+use super::filesystem::{
+    self, normalize_path, resolve_ino, FsError, Node, CURRENT_PATH, FILESYSTEM,
+};
+use super::permissions::{self, EXECUTE, READ};
+use super::stream;
+use super::text;
+
+// Whole-line SGR colors, the terminal paints these and pipes drop them
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const CYAN: &str = "\x1b[36m";
+const RESET: &str = "\x1b[0m";
+
+const NO_NEWLINE: &str = "\\ No newline at end of file";
+
+fn paint(color: &str, line: &str) -> String {
+    format!("{}{}{}", color, line, RESET)
+}
+
+enum Entry {
+    File(String),
+    // Child names, sorted
+    Directory(Vec<String>),
+}
+
+fn load(name: &str) -> Result<Entry, String> {
+    let filesystem = FILESYSTEM.lock().unwrap();
+    let current_path = CURRENT_PATH.lock().unwrap();
+    let error = |e: FsError| stream::error(format!("diff: {}: {}", name, e));
+
+    let ino =
+        resolve_ino(&filesystem, &normalize_path(name, &current_path), true).map_err(error)?;
+    let node = filesystem.get(ino).unwrap();
+    match node {
+        // `diff /dev/null file` shows the whole file as added
+        Node::File { .. } | Node::Device { .. } => {
+            permissions::check(node, READ).map_err(error)?;
+            node.content()
+                .map(Entry::File)
+                .ok_or_else(|| error(FsError::Io))
+        }
+        Node::Directory { children, .. } => {
+            permissions::check(node, READ | EXECUTE).map_err(error)?;
+            let mut names: Vec<String> = children.keys().cloned().collect();
+            names.sort();
+            Ok(Entry::Directory(names))
+        }
+        Node::Symlink { .. } => Err(error(FsError::NotFound)),
+    }
+}
+
+// Lines keep their '\n' so a missing final newline counts as a change
+fn split_lines(content: &str) -> Vec<&str> {
+    content.split_inclusive('\n').collect()
+}
+
+// A run of changed lines: a[a_start..a_end] became b[b_start..b_end]
+struct Change {
+    a_start: usize,
+    a_end: usize,
+    b_start: usize,
+    b_end: usize,
+}
+
+// Longest common subsequence of the lines, after the shared prefix and
+// suffix are set aside
+fn changes(a: &[&str], b: &[&str]) -> Vec<Change> {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let a_mid = &a[prefix..a.len() - suffix];
+    let b_mid = &b[prefix..b.len() - suffix];
+
+    // lengths[i][j] is the LCS length of a_mid[i..] and b_mid[j..]
+    let mut lengths = vec![vec![0u32; b_mid.len() + 1]; a_mid.len() + 1];
+    for (i, x) in a_mid.iter().enumerate().rev() {
+        for (j, y) in b_mid.iter().enumerate().rev() {
+            lengths[i][j] = if x == y {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut changes: Vec<Change> = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a_mid.len() || j < b_mid.len() {
+        if i < a_mid.len() && j < b_mid.len() && a_mid[i] == b_mid[j] {
+            i += 1;
+            j += 1;
+            continue;
+        }
+
+        let (a_at, b_at) = (prefix + i, prefix + j);
+        if i < a_mid.len() && (j == b_mid.len() || lengths[i + 1][j] >= lengths[i][j + 1]) {
+            i += 1;
+        } else {
+            j += 1;
+        }
+
+        match changes.last_mut() {
+            Some(change) if change.a_end == a_at && change.b_end == b_at => {
+                change.a_end = prefix + i;
+                change.b_end = prefix + j;
+            }
+            _ => changes.push(Change {
+                a_start: a_at,
+                a_end: prefix + i,
+                b_start: b_at,
+                b_end: prefix + j,
+            }),
+        }
+    }
+
+    changes
+}
+
+fn push_line(out: &mut Vec<String>, marker: &str, line: &str, color: Option<&str>) {
+    let text = format!("{}{}", marker, line.strip_suffix('\n').unwrap_or(line));
+    out.push(match color {
+        Some(color) => paint(color, &text),
+        None => text,
+    });
+    if !line.ends_with('\n') {
+        out.push(NO_NEWLINE.to_string());
+    }
+}
+
+// The classic format: `2c2`, `< old`, `---`, `> new`
+fn normal(a: &[&str], b: &[&str], changes: &[Change], out: &mut Vec<String>) {
+    let range = |start: usize, end: usize| {
+        if end - start == 1 {
+            format!("{}", start + 1)
+        } else {
+            format!("{},{}", start + 1, end)
+        }
+    };
+
+    for change in changes {
+        let header = if change.a_start == change.a_end {
+            format!("{}a{}", change.a_start, range(change.b_start, change.b_end))
+        } else if change.b_start == change.b_end {
+            format!("{}d{}", range(change.a_start, change.a_end), change.b_start)
+        } else {
+            format!(
+                "{}c{}",
+                range(change.a_start, change.a_end),
+                range(change.b_start, change.b_end)
+            )
+        };
+        out.push(header);
+
+        for line in &a[change.a_start..change.a_end] {
+            push_line(out, "< ", line, Some(RED));
+        }
+        if change.a_start < change.a_end && change.b_start < change.b_end {
+            out.push("---".to_string());
+        }
+        for line in &b[change.b_start..change.b_end] {
+            push_line(out, "> ", line, Some(GREEN));
+        }
+    }
+}
+
+// `-1,3` style hunk ranges, an empty range names the line before it
+fn hunk_range(start: usize, end: usize) -> String {
+    match end - start {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        count => format!("{},{}", start + 1, count),
+    }
+}
+
+fn unified(a: &[&str], b: &[&str], changes: &[Change], context: usize, out: &mut Vec<String>) {
+    let mut index = 0;
+    while index < changes.len() {
+        // Changes whose context would touch share a hunk
+        let first = index;
+        while index + 1 < changes.len()
+            && changes[index + 1].a_start - changes[index].a_end <= 2 * context
+        {
+            index += 1;
+        }
+        let hunk = &changes[first..=index];
+        index += 1;
+
+        let (head, tail) = (&hunk[0], &hunk[hunk.len() - 1]);
+        let a_start = head.a_start.saturating_sub(context);
+        let a_end = (tail.a_end + context).min(a.len());
+        let b_start = head.b_start - (head.a_start - a_start);
+        let b_end = tail.b_end + (a_end - tail.a_end);
+
+        out.push(paint(
+            CYAN,
+            &format!(
+                "@@ -{} +{} @@",
+                hunk_range(a_start, a_end),
+                hunk_range(b_start, b_end)
+            ),
+        ));
+
+        let mut at = a_start;
+        for change in hunk {
+            for line in &a[at..change.a_start] {
+                push_line(out, " ", line, None);
+            }
+            for line in &a[change.a_start..change.a_end] {
+                push_line(out, "-", line, Some(RED));
+            }
+            for line in &b[change.b_start..change.b_end] {
+                push_line(out, "+", line, Some(GREEN));
+            }
+            at = change.a_end;
+        }
+        for line in &a[at..a_end] {
+            push_line(out, " ", line, None);
+        }
+    }
+}
+
+struct Options {
+    // The switches as given, repeated in `diff -r` headers
+    switches: String,
+    context: Option<usize>,
+    recursive: bool,
+    brief: bool,
+}
+
+fn diff_files(
+    a_name: &str,
+    a: &str,
+    b_name: &str,
+    b: &str,
+    options: &Options,
+    nested: bool,
+    out: &mut Vec<String>,
+) {
+    let (a_lines, b_lines) = (split_lines(a), split_lines(b));
+    let changes = changes(&a_lines, &b_lines);
+    if changes.is_empty() {
+        return;
+    }
+
+    if options.brief {
+        out.push(format!("Files {} and {} differ", a_name, b_name));
+        return;
+    }
+    if nested {
+        out.push(format!("diff {}{} {}", options.switches, a_name, b_name));
+    }
+    match options.context {
+        Some(context) => {
+            out.push(format!("--- {}", a_name));
+            out.push(format!("+++ {}", b_name));
+            unified(&a_lines, &b_lines, &changes, context, out);
+        }
+        None => normal(&a_lines, &b_lines, &changes, out),
+    }
+}
+
+fn join(dir: &str, name: &str) -> String {
+    format!("{}/{}", dir.trim_end_matches('/'), name)
+}
+
+fn diff_directories(
+    a_name: &str,
+    a_children: &[String],
+    b_name: &str,
+    b_children: &[String],
+    options: &Options,
+    out: &mut Vec<String>,
+) {
+    let mut names: Vec<&String> = a_children.iter().chain(b_children).collect();
+    names.sort();
+    names.dedup();
+
+    for name in names {
+        let (a_path, b_path) = (join(a_name, name), join(b_name, name));
+        if !b_children.contains(name) {
+            out.push(format!("Only in {}: {}", a_name, name));
+            continue;
+        }
+        if !a_children.contains(name) {
+            out.push(format!("Only in {}: {}", b_name, name));
+            continue;
+        }
+
+        match (load(&a_path), load(&b_path)) {
+            (Err(e), _) | (_, Err(e)) => out.push(e),
+            (Ok(Entry::File(a)), Ok(Entry::File(b))) => {
+                diff_files(&a_path, &a, &b_path, &b, options, true, out)
+            }
+            (Ok(Entry::Directory(a)), Ok(Entry::Directory(b))) => {
+                if options.recursive {
+                    diff_directories(&a_path, &a, &b_path, &b, options, out);
+                } else {
+                    out.push(format!("Common subdirectories: {} and {}", a_path, b_path));
+                }
+            }
+            (Ok(Entry::Directory(_)), Ok(Entry::File(_))) => out.push(format!(
+                "File {} is a directory while file {} is a regular file",
+                a_path, b_path
+            )),
+            (Ok(Entry::File(_)), Ok(Entry::Directory(_))) => out.push(format!(
+                "File {} is a regular file while file {} is a directory",
+                a_path, b_path
+            )),
+        }
+    }
+}
+
+pub fn diff(args: &[&str], stdin: Option<&str>) -> String {
+    let parsed = match text::parse("diff", args, "urq", "U") {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            return stream::error(format!(
+                "{}\ndiff: Try 'diff --help' for more information.",
+                e
+            ))
+        }
+    };
+
+    let context = match parsed.value('U') {
+        Some(lines) => match lines.parse() {
+            Ok(lines) => Some(lines),
+            Err(_) => return stream::error(format!("diff: invalid context length '{}'", lines)),
+        },
+        None if parsed.has('u') => Some(3),
+        None => None,
+    };
+    let switches: String = args
+        .iter()
+        .filter(|arg| arg.starts_with('-') && arg.len() > 1)
+        .map(|arg| format!("{} ", arg))
+        .collect();
+    let options = Options {
+        switches,
+        context,
+        recursive: parsed.has('r'),
+        brief: parsed.has('q'),
+    };
+
+    let (a_name, b_name) =
+        match parsed.operands[..] {
+            [a, b] => (a, b),
+            [] => return stream::error(
+                "diff: missing operand after 'diff'\ndiff: Try 'diff --help' for more information.",
+            ),
+            [a] => {
+                return stream::error(format!(
+                "diff: missing operand after '{}'\ndiff: Try 'diff --help' for more information.",
+                a
+            ))
+            }
+            [_, _, extra, ..] => {
+                return stream::error(format!(
+                    "diff: extra operand '{}'\ndiff: Try 'diff --help' for more information.",
+                    extra
+                ))
+            }
+        };
+
+    let open = |name: &str| match name {
+        "-" => Ok(Entry::File(stdin.unwrap_or_default().to_string())),
+        _ => load(name),
+    };
+    let (a, b) = match (open(a_name), open(b_name)) {
+        (Ok(a), Ok(b)) => (a, b),
+        (Err(e), _) | (_, Err(e)) => return e,
+    };
+
+    let mut out = Vec::new();
+    match (a, b) {
+        (Entry::File(a), Entry::File(b)) => {
+            diff_files(a_name, &a, b_name, &b, &options, false, &mut out)
+        }
+        (Entry::Directory(a), Entry::Directory(b)) => {
+            diff_directories(a_name, &a, b_name, &b, &options, &mut out)
+        }
+        // A file against a directory means the file of the same name in it
+        (Entry::File(a), Entry::Directory(_)) => {
+            let base = a_name.rsplit('/').next().unwrap_or(a_name);
+            let b_path = join(b_name, base);
+            match load(&b_path) {
+                Ok(Entry::File(b)) => {
+                    diff_files(a_name, &a, &b_path, &b, &options, false, &mut out)
+                }
+                Ok(Entry::Directory(_)) => out.push(format!(
+                    "File {} is a regular file while file {} is a directory",
+                    a_name, b_path
+                )),
+                Err(e) => out.push(e),
+            }
+        }
+        (Entry::Directory(_), Entry::File(b)) => {
+            let base = b_name.rsplit('/').next().unwrap_or(b_name);
+            let a_path = join(a_name, base);
+            match load(&a_path) {
+                Ok(Entry::File(a)) => {
+                    diff_files(&a_path, &a, b_name, &b, &options, false, &mut out)
+                }
+                Ok(Entry::Directory(_)) => out.push(format!(
+                    "File {} is a directory while file {} is a regular file",
+                    a_path, b_name
+                )),
+                Err(e) => out.push(e),
+            }
+        }
+    }
+
+    out.join("\n")
+}
+
+struct Hunk {
+    old_start: usize,
+    new_start: usize,
+    // ' ', '-' or '+' with the line, which keeps its '\n' unless the patch
+    // says there is none
+    lines: Vec<(char, String)>,
+}
+
+impl Hunk {
+    fn side(&self, kind: char) -> Vec<&str> {
+        self.lines
+            .iter()
+            .filter(|(k, _)| *k == ' ' || *k == kind)
+            .map(|(_, line)| line.as_str())
+            .collect()
+    }
+
+    fn unified(&self) -> String {
+        let mut text = format!(
+            "@@ -{},{} +{},{} @@\n",
+            self.old_start,
+            self.side('-').len(),
+            self.new_start,
+            self.side('+').len()
+        );
+        for (kind, line) in &self.lines {
+            text.push(*kind);
+            text.push_str(line);
+            if !line.ends_with('\n') {
+                text.push('\n');
+                text.push_str(NO_NEWLINE);
+                text.push('\n');
+            }
+        }
+        text
+    }
+}
+
+struct FilePatch {
+    old: String,
+    new: String,
+    hunks: Vec<Hunk>,
+    // Line of the `---` header, for messages
+    line: usize,
+}
+
+// `-3,2` gives (3, 2), a bare `-3` has one line
+fn parse_range(range: &str) -> Option<(usize, usize)> {
+    match range.split_once(',') {
+        Some((start, count)) => Some((start.parse().ok()?, count.parse().ok()?)),
+        None => Some((range.parse().ok()?, 1)),
+    }
+}
+
+fn parse_hunk_header(line: &str) -> Option<(usize, usize, usize, usize)> {
+    let mut words = line.split_whitespace().skip(1);
+    let old = parse_range(words.next()?.strip_prefix('-')?)?;
+    let new = parse_range(words.next()?.strip_prefix('+')?)?;
+    Some((old.0, old.1, new.0, new.1))
+}
+
+fn parse_patch(text: &str) -> Result<Vec<FilePatch>, String> {
+    let lines: Vec<&str> = text.lines().collect();
+    let name = |header: &str| {
+        header[4..]
+            .split('\t')
+            .next()
+            .unwrap_or("")
+            .trim()
+            .to_string()
+    };
+    let malformed = |i: usize| {
+        stream::error(format!(
+            "patch: **** malformed patch at line {}: {}",
+            i + 1,
+            lines[i]
+        ))
+    };
+    let mut patches = Vec::new();
+    let mut i = 0;
+
+    while i < lines.len() {
+        if !(lines[i].starts_with("--- ")
+            && lines.get(i + 1).is_some_and(|l| l.starts_with("+++ ")))
+        {
+            i += 1;
+            continue;
+        }
+
+        let mut patch = FilePatch {
+            old: name(lines[i]),
+            new: name(lines[i + 1]),
+            hunks: Vec::new(),
+            line: i + 1,
+        };
+        i += 2;
+
+        while i < lines.len() && lines[i].starts_with("@@ ") {
+            let (old_start, mut old_left, new_start, mut new_left) =
+                parse_hunk_header(lines[i]).ok_or_else(|| malformed(i))?;
+            let mut hunk = Hunk {
+                old_start,
+                new_start,
+                lines: Vec::new(),
+            };
+            i += 1;
+
+            while old_left > 0 || new_left > 0 {
+                let line = *lines
+                    .get(i)
+                    .ok_or_else(|| stream::error("patch: **** unexpected end of file in patch"))?;
+                // Some tools drop the space of an empty context line
+                let (kind, text) = match line.chars().next() {
+                    None => (' ', ""),
+                    Some(kind @ (' ' | '-' | '+')) => (kind, &line[1..]),
+                    Some(_) => return Err(malformed(i)),
+                };
+                if kind != '+' {
+                    old_left = old_left.checked_sub(1).ok_or_else(|| malformed(i))?;
+                }
+                if kind != '-' {
+                    new_left = new_left.checked_sub(1).ok_or_else(|| malformed(i))?;
+                }
+                hunk.lines.push((kind, format!("{}\n", text)));
+                i += 1;
+
+                if lines.get(i).is_some_and(|line| line.starts_with('\\')) {
+                    hunk.lines.last_mut().unwrap().1.pop();
+                    i += 1;
+                }
+            }
+            patch.hunks.push(hunk);
+        }
+        patches.push(patch);
+    }
+
+    if patches.is_empty() {
+        return Err(stream::error(
+            "patch: **** Only garbage was found in the patch input.",
+        ));
+    }
+    Ok(patches)
+}
+
+// Without -p only the file name is kept, -pN drops N leading components
+fn strip(name: &str, components: Option<usize>) -> String {
+    match components {
+        None => name.rsplit('/').next().unwrap_or(name).to_string(),
+        Some(count) => {
+            let parts: Vec<&str> = name.split('/').collect();
+            if count < parts.len() {
+                parts[count..].join("/")
+            } else {
+                name.to_string()
+            }
+        }
+    }
+}
+
+// Applies each hunk where its old lines are found, starting at the line it
+// names and moving outwards. Returns the new content, the messages and the
+// hunks that didn't fit.
+fn apply<'a>(
+    content: &str,
+    hunks: &'a [Hunk],
+    reverse: bool,
+) -> (String, Vec<String>, Vec<&'a Hunk>) {
+    let mut lines: Vec<String> = content.split_inclusive('\n').map(str::to_string).collect();
+    let mut messages = Vec::new();
+    let mut rejected = Vec::new();
+    let mut offset: isize = 0;
+    // How far the file has drifted from the patch so far, like GNU patch
+    // every hunk after a moved one reports it
+    let mut drift: isize = 0;
+    let (removed, added) = if reverse { ('+', '-') } else { ('-', '+') };
+
+    for (number, hunk) in hunks.iter().enumerate() {
+        let old = hunk.side(removed);
+        let new = hunk.side(added);
+        let start = if reverse {
+            hunk.new_start
+        } else {
+            hunk.old_start
+        };
+        // An empty old side names the line after which to insert
+        let base = if old.is_empty() {
+            start
+        } else {
+            start.saturating_sub(1)
+        };
+        let expected = (base as isize + offset).max(0) as usize;
+
+        let fits = |at: usize| {
+            at + old.len() <= lines.len()
+                && lines[at..at + old.len()]
+                    .iter()
+                    .zip(&old)
+                    .all(|(a, b)| a == b)
+        };
+        let found = (0..=lines.len()).find_map(|distance| {
+            Some(expected + distance)
+                .filter(|&at| fits(at))
+                .or_else(|| expected.checked_sub(distance).filter(|&at| fits(at)))
+        });
+
+        match found {
+            Some(at) => {
+                lines.splice(at..at + old.len(), new.iter().map(|line| line.to_string()));
+                let moved = at as isize - expected as isize;
+                drift += moved;
+                if drift != 0 {
+                    messages.push(format!(
+                        "Hunk #{} succeeded at {} (offset {} line{}).",
+                        number + 1,
+                        at + 1,
+                        drift,
+                        if drift.abs() == 1 { "" } else { "s" }
+                    ));
+                }
+                offset += moved + new.len() as isize - old.len() as isize;
+            }
+            None => {
+                messages.push(format!("Hunk #{} FAILED at {}.", number + 1, start));
+                rejected.push(hunk);
+            }
+        }
+    }
+
+    (lines.concat(), messages, rejected)
+}
+
+pub fn patch(args: &[&str], stdin: Option<&str>) -> String {
+    let parsed = match text::parse("patch", args, "R", "pi") {
+        Ok(parsed) => parsed,
+        Err(e) => return e,
+    };
+
+    let components = match parsed.value('p') {
+        Some(count) => match count.parse() {
+            Ok(count) => Some(count),
+            Err(_) => {
+                return stream::error(format!("patch: **** strip count {} is not a number", count))
+            }
+        },
+        None => None,
+    };
+    let reverse = parsed.has('R');
+
+    // patch [originalfile [patchfile]], or the patch from -i or stdin
+    let target = parsed.operands.first().copied();
+    let source = parsed
+        .value('i')
+        .or_else(|| parsed.operands.get(1).copied());
+    let text = match source {
+        Some(file) => match filesystem::read_file(file) {
+            Ok(text) => text,
+            Err(e) => {
+                return stream::error(format!(
+                    "patch: **** Can't open patch file {} : {}",
+                    file, e
+                ))
+            }
+        },
+        None => stdin.unwrap_or_default().to_string(),
+    };
+
+    let patches = match parse_patch(&text) {
+        Ok(patches) => patches,
+        Err(e) => return e,
+    };
+
+    let mut out = Vec::new();
+    for patch in &patches {
+        let (old, new) = if reverse {
+            (&patch.new, &patch.old)
+        } else {
+            (&patch.old, &patch.new)
+        };
+        let creating = old == "/dev/null";
+        let deleting = new == "/dev/null";
+
+        let name = match target {
+            Some(target) => target.to_string(),
+            None if creating => strip(new, components),
+            None => {
+                let candidates = [strip(old, components), strip(new, components)];
+                match candidates
+                    .iter()
+                    .find(|name| filesystem::read_file(name).is_ok())
+                {
+                    Some(name) => name.clone(),
+                    None => {
+                        out.push(format!(
+                            "can't find file to patch at input line {}",
+                            patch.line
+                        ));
+                        out.push("No file to patch.  Skipping patch.".to_string());
+                        continue;
+                    }
+                }
+            }
+        };
+
+        let content = match filesystem::read_file(&name) {
+            Ok(content) => content,
+            Err(FsError::NotFound) if creating => String::new(),
+            Err(e) => {
+                out.push(stream::error(format!(
+                    "patch: **** Can't open file {} : {}",
+                    name, e
+                )));
+                continue;
+            }
+        };
+
+        out.push(format!("patching file {}", name));
+        let (result, messages, rejected) = apply(&content, &patch.hunks, reverse);
+        out.extend(messages);
+
+        if deleting && result.is_empty() {
+            let removed = filesystem::rm(&[name.as_str()]);
+            if !removed.is_empty() {
+                out.push(removed);
+            }
+        } else if let Err(e) = filesystem::write_file(&name, &result) {
+            out.push(stream::error(format!(
+                "patch: **** Can't write file {} : {}",
+                name, e
+            )));
+            continue;
+        }
+
+        if !rejected.is_empty() {
+            let reject = format!("{}.rej", name);
+            let mut text = format!("--- {}\n+++ {}\n", patch.old, patch.new);
+            for hunk in &rejected {
+                text.push_str(&hunk.unified());
+            }
+            out.push(format!(
+                "{} out of {} hunk{} FAILED -- saving rejects to file {}",
+                rejected.len(),
+                patch.hunks.len(),
+                if patch.hunks.len() == 1 { "" } else { "s" },
+                reject
+            ));
+            if let Err(e) = filesystem::write_file(&reject, &text) {
+                out.push(stream::error(format!(
+                    "patch: **** Can't write file {} : {}",
+                    reject, e
+                )));
+            }
+        }
+    }
+
+    out.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::CommandHandler;
+    use crate::testing;
+
+    const OLD: &str = "one\ntwo\nthree\nfour\nfive\nsix\nseven\neight\nnine\nten\n";
+    const NEW: &str = "one\nTWO\nthree\nfour\nfive\nsix\nseven\neight\nnine\nten\neleven\n";
+
+    #[test]
+    fn diff_u_output_patches_back() {
+        let _guard = testing::fresh();
+        let mut handler = CommandHandler::new();
+        filesystem::write_file("/tmp/old", OLD).unwrap();
+        filesystem::write_file("/tmp/new", NEW).unwrap();
+
+        handler.handle("diff -u /tmp/old /tmp/new > /tmp/change.diff");
+        assert_eq!(
+            filesystem::read_file("/tmp/change.diff").unwrap(),
+            "--- /tmp/old\n+++ /tmp/new\n@@ -1,5 +1,5 @@\n one\n-two\n+TWO\n three\n four\n five\n\
+             @@ -8,3 +8,4 @@\n eight\n nine\n ten\n+eleven\n"
+        );
+
+        assert_eq!(
+            handler.handle("patch /tmp/old /tmp/change.diff").0,
+            "patching file /tmp/old"
+        );
+        assert_eq!(filesystem::read_file("/tmp/old").unwrap(), NEW);
+        assert_eq!(handler.handle("diff /tmp/old /tmp/new").0, "");
+
+        assert_eq!(
+            handler.handle("patch -R /tmp/old < /tmp/change.diff").0,
+            "patching file /tmp/old"
+        );
+        assert_eq!(filesystem::read_file("/tmp/old").unwrap(), OLD);
+    }
+
+    #[test]
+    fn hunks_that_moved_or_no_longer_fit() {
+        let _guard = testing::fresh();
+        let mut handler = CommandHandler::new();
+        filesystem::write_file("/tmp/old", OLD).unwrap();
+        filesystem::write_file("/tmp/new", NEW).unwrap();
+        handler.handle("diff -u /tmp/old /tmp/new > /tmp/change.diff");
+
+        // Two lines added on top move both hunks down
+        filesystem::write_file("/tmp/moved", &format!("zero\nzero\n{}", OLD)).unwrap();
+        assert_eq!(
+            handler.handle("patch /tmp/moved /tmp/change.diff").0,
+            "patching file /tmp/moved\nHunk #1 succeeded at 3 (offset 2 lines).\n\
+             Hunk #2 succeeded at 10 (offset 2 lines)."
+        );
+
+        // Its second hunk's context is gone
+        filesystem::write_file("/tmp/edited", &OLD.replace("nine", "NINE")).unwrap();
+        assert_eq!(
+            handler.handle("patch /tmp/edited /tmp/change.diff").0,
+            "patching file /tmp/edited\nHunk #2 FAILED at 8.\n\
+             1 out of 2 hunks FAILED -- saving rejects to file /tmp/edited.rej"
+        );
+        let edited = filesystem::read_file("/tmp/edited").unwrap();
+        assert!(edited.starts_with("one\nTWO\n"));
+        assert_eq!(
+            filesystem::read_file("/tmp/edited.rej").unwrap(),
+            "--- /tmp/old\n+++ /tmp/new\n@@ -8,3 +8,4 @@\n eight\n nine\n ten\n+eleven\n"
+        );
+    }
+
+    #[test]
+    fn diff_r_walks_both_trees() {
+        let _guard = testing::fresh();
+        let mut handler = CommandHandler::new();
+        for dir in ["/tmp/a", "/tmp/a/sub", "/tmp/b", "/tmp/b/sub"].iter() {
+            handler.handle(&format!("mkdir {}", dir));
+        }
+        filesystem::write_file("/tmp/a/same", "x\n").unwrap();
+        filesystem::write_file("/tmp/b/same", "x\n").unwrap();
+        filesystem::write_file("/tmp/a/only", "a\n").unwrap();
+        filesystem::write_file("/tmp/b/sub/new", "b\n").unwrap();
+        filesystem::write_file("/tmp/a/sub/file", "old\n").unwrap();
+        filesystem::write_file("/tmp/b/sub/file", "new\n").unwrap();
+
+        assert_eq!(
+            diff(&["/tmp/a", "/tmp/b"], None),
+            "Only in /tmp/a: only\nCommon subdirectories: /tmp/a/sub and /tmp/b/sub"
+        );
+        assert_eq!(
+            stream::strip_colors(&diff(&["-r", "/tmp/a", "/tmp/b"], None)),
+            "Only in /tmp/a: only\ndiff -r /tmp/a/sub/file /tmp/b/sub/file\n1c1\n< old\n---\n> new\n\
+             Only in /tmp/b/sub: new"
+        );
+        assert_eq!(
+            diff(&["-rq", "/tmp/a", "/tmp/b"], None),
+            "Only in /tmp/a: only\nFiles /tmp/a/sub/file and /tmp/b/sub/file differ\n\
+             Only in /tmp/b/sub: new"
+        );
+    }
+}
@@ -0,0 +1,641 @@
+use super::filesystem;
+use super::stream::{self, Output};
+use super::text;
+use crate::utils::base64;
+
+// A length limit reads no more of each file than it needs, which is what
+// lets the dumps stop on an endless device like /dev/urandom
+fn read_file(file: &str, limit: Option<usize>) -> Result<Vec<u8>, filesystem::FsError> {
+    match limit {
+        Some(limit) => filesystem::read_prefix(file, limit),
+        None => filesystem::read_bytes(file),
+    }
+}
+
+// Every operand back to back, `-` or no operand at all reads stdin
+fn read_all(
+    cmd: &str,
+    files: &[&str],
+    stdin: Option<&[u8]>,
+    limit: Option<usize>,
+) -> (Vec<u8>, Vec<String>) {
+    if files.is_empty() {
+        return (stdin.unwrap_or_default().to_vec(), Vec::new());
+    }
+
+    let mut data = Vec::new();
+    let mut errors = Vec::new();
+    for &file in files {
+        match file {
+            "-" => data.extend(stdin.unwrap_or_default().to_vec()),
+            _ => match read_file(file, limit) {
+                Ok(bytes) => data.extend(bytes),
+                Err(e) => errors.push(stream::error(format!("{}: {}: {}", cmd, file, e))),
+            },
+        }
+    }
+    (data, errors)
+}
+
+// Offsets and lengths, in decimal or with a 0x prefix in hex
+fn number(value: &str) -> Option<usize> {
+    match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+// Applies a skip and a length limit to the input
+fn window(data: &[u8], skip: usize, length: Option<usize>) -> &[u8] {
+    let data = data.get(skip..).unwrap_or_default();
+    match length {
+        Some(length) => &data[..length.min(data.len())],
+        None => data,
+    }
+}
+
+fn printable(byte: u8) -> char {
+    if (0x20..0x7f).contains(&byte) {
+        byte as char
+    } else {
+        '.'
+    }
+}
+
+// One line per `width` bytes. With `squeeze`, lines repeating the one
+// before collapse into a single `*`.
+fn dump_lines(
+    data: &[u8],
+    start: usize,
+    width: usize,
+    squeeze: bool,
+    format: impl Fn(usize, &[u8]) -> String,
+) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut previous: Option<&[u8]> = None;
+    let mut starred = false;
+
+    for (index, chunk) in data.chunks(width).enumerate() {
+        if squeeze && chunk.len() == width && previous == Some(chunk) {
+            if !starred {
+                lines.push("*".to_string());
+                starred = true;
+            }
+            continue;
+        }
+        starred = false;
+        previous = Some(chunk);
+        lines.push(format(start + index * width, chunk));
+    }
+
+    lines
+}
+
+fn hex_digits(bytes: &[u8], upper: bool) -> String {
+    bytes
+        .iter()
+        .map(|byte| {
+            if upper {
+                format!("{:02X}", byte)
+            } else {
+                format!("{:02x}", byte)
+            }
+        })
+        .collect()
+}
+
+// Turns a dump back into bytes. Plain dumps are one run of hex digits,
+// regular ones put each line's bytes at the offset before the colon.
+fn xxd_reverse(input: &str, plain: bool) -> Result<Vec<u8>, String> {
+    let pairs = |hex: &str| -> Vec<u8> {
+        let digits: Vec<u8> = hex
+            .chars()
+            .filter_map(|c| c.to_digit(16))
+            .map(|digit| digit as u8)
+            .collect();
+        digits
+            .chunks_exact(2)
+            .map(|pair| pair[0] << 4 | pair[1])
+            .collect()
+    };
+
+    if plain {
+        return Ok(pairs(input));
+    }
+
+    let mut data = Vec::new();
+    for line in input.lines().filter(|line| !line.trim().is_empty()) {
+        let (offset, rest) = line
+            .split_once(':')
+            .ok_or_else(|| stream::error("xxd: sorry, cannot seek backwards."))?;
+        let offset = usize::from_str_radix(offset.trim(), 16)
+            .map_err(|_| stream::error("xxd: sorry, cannot seek backwards."))?;
+        // The text column starts after two spaces
+        let hex = rest.trim_start().split("  ").next().unwrap_or_default();
+        let bytes = pairs(hex);
+        if data.len() < offset + bytes.len() {
+            data.resize(offset + bytes.len(), 0);
+        }
+        data[offset..offset + bytes.len()].copy_from_slice(&bytes);
+    }
+    Ok(data)
+}
+
+pub fn xxd(args: &[&str], stdin: Option<&[u8]>) -> Output {
+    let parsed = match text::parse("xxd", args, "pru", "cgls") {
+        Ok(parsed) => parsed,
+        Err(e) => return e.into(),
+    };
+    let option = |flag: char, default: usize| match parsed.value(flag) {
+        Some(value) => {
+            number(value).ok_or_else(|| stream::error(format!("xxd: invalid number '{}'", value)))
+        }
+        None => Ok(default),
+    };
+    let plain = parsed.has('p');
+    let (columns, group, skip) = match (
+        option('c', if plain { 30 } else { 16 }),
+        option('g', 2),
+        option('s', 0),
+    ) {
+        (Ok(columns), Ok(group), Ok(skip)) => (columns.max(1), group, skip),
+        (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => return e.into(),
+    };
+    let length = match parsed.value('l').map(number) {
+        Some(None) => {
+            return stream::error(format!(
+                "xxd: invalid number '{}'",
+                parsed.value('l').unwrap()
+            ))
+            .into()
+        }
+        Some(length) => length,
+        None => None,
+    };
+    let (input, output) = match parsed.operands[..] {
+        [] => (None, None),
+        [input] => (Some(input), None),
+        [input, output] => (Some(input), Some(output)),
+        _ => return stream::error("Usage: xxd [options] [infile [outfile]]").into(),
+    };
+
+    // Reversing reads everything, a dump only up to the end of its window
+    let limit = length
+        .filter(|_| !parsed.has('r'))
+        .map(|length| skip.saturating_add(length));
+    let data = match input {
+        None | Some("-") => stdin.unwrap_or_default().to_vec(),
+        Some(file) => match read_file(file, limit) {
+            Ok(bytes) => bytes,
+            Err(e) => return stream::error(format!("xxd: {}: {}", file, e)).into(),
+        },
+    };
+
+    let result = if parsed.has('r') {
+        match xxd_reverse(&String::from_utf8_lossy(&data), plain) {
+            Ok(bytes) => bytes,
+            Err(e) => return e.into(),
+        }
+    } else {
+        let data = window(&data, skip, length);
+        let upper = parsed.has('u');
+        let lines = if plain {
+            dump_lines(data, 0, columns, false, |_, chunk| hex_digits(chunk, upper))
+        } else {
+            let groups = if group == 0 {
+                1
+            } else {
+                columns.div_ceil(group)
+            };
+            let hex_width = columns * 2 + groups - 1;
+            dump_lines(data, skip, columns, false, |offset, chunk| {
+                let hex = match group {
+                    0 => hex_digits(chunk, upper),
+                    _ => chunk
+                        .chunks(group)
+                        .map(|bytes| hex_digits(bytes, upper))
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                };
+                let text: String = chunk.iter().map(|&byte| printable(byte)).collect();
+                format!("{:08x}: {:<w$}  {}", offset, hex, text, w = hex_width)
+            })
+        };
+        let mut text = lines.join("\n");
+        if output.is_some() && !text.is_empty() {
+            text.push('\n');
+        }
+        text.into_bytes()
+    };
+
+    let mut dumped = Output::default();
+    match output {
+        Some(file) => {
+            if let Err(e) = filesystem::write_bytes(file, &result) {
+                dumped.line(stream::error(format!("xxd: {}: {}", file, e)));
+            }
+        }
+        None if parsed.has('r') => dumped.bytes(result),
+        None => return String::from_utf8_lossy(&result).into_owned().into(),
+    }
+    dumped
+}
+
+pub fn hexdump(args: &[&str], stdin: Option<&[u8]>) -> String {
+    let parsed = match text::parse("hexdump", args, "Cv", "ns") {
+        Ok(parsed) => parsed,
+        Err(e) => return e,
+    };
+    let skip = match parsed.value('s').map(number) {
+        Some(Some(skip)) => skip,
+        Some(None) => {
+            return stream::error(format!(
+                "hexdump: bad skip value '{}'",
+                parsed.value('s').unwrap()
+            ))
+        }
+        None => 0,
+    };
+    let length = match parsed.value('n').map(number) {
+        Some(None) => {
+            return stream::error(format!(
+                "hexdump: bad length value '{}'",
+                parsed.value('n').unwrap()
+            ))
+        }
+        Some(length) => length,
+        None => None,
+    };
+
+    let limit = length.map(|length| skip.saturating_add(length));
+    let (data, mut output) = read_all("hexdump", &parsed.operands, stdin, limit);
+    let data = window(&data, skip, length);
+    if data.is_empty() {
+        return output.join("\n");
+    }
+    let squeeze = !parsed.has('v');
+
+    if parsed.has('C') {
+        output.extend(dump_lines(data, skip, 16, squeeze, |offset, chunk| {
+            let mut hex = String::new();
+            for (index, byte) in chunk.iter().enumerate() {
+                if index == 8 {
+                    hex.push(' ');
+                }
+                hex.push_str(&format!("{:02x} ", byte));
+            }
+            let text: String = chunk.iter().map(|&byte| printable(byte)).collect();
+            format!("{:08x}  {:<49} |{}|", offset, hex, text)
+        }));
+        output.push(format!("{:08x}", skip + data.len()));
+    } else {
+        // Two-byte little-endian words, an odd last byte padded with zero
+        output.extend(dump_lines(data, skip, 16, squeeze, |offset, chunk| {
+            let words: Vec<String> = chunk
+                .chunks(2)
+                .map(|pair| {
+                    let word = pair[0] as u16 | (*pair.get(1).unwrap_or(&0) as u16) << 8;
+                    format!("{:04x}", word)
+                })
+                .collect();
+            format!("{:07x} {}", offset, words.join(" "))
+        }));
+        output.push(format!("{:07x}", skip + data.len()));
+    }
+
+    output.join("\n")
+}
+
+#[derive(Clone, Copy)]
+enum OdType {
+    Named,
+    Char,
+    Signed(usize),
+    Unsigned(usize),
+    Octal(usize),
+    Hex(usize),
+}
+
+impl OdType {
+    fn parse(spec: &str) -> Option<Vec<OdType>> {
+        let mut types = Vec::new();
+        let mut chars = spec.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            let mut digits = String::new();
+            while let Some(digit) = chars.peek().filter(|c| c.is_ascii_digit()) {
+                digits.push(*digit);
+                chars.next();
+            }
+            let size = match digits.as_str() {
+                "" => 4,
+                "1" | "2" | "4" | "8" => digits.parse().unwrap(),
+                _ => return None,
+            };
+            types.push(match c {
+                'a' if digits.is_empty() => OdType::Named,
+                'c' if digits.is_empty() => OdType::Char,
+                'd' => OdType::Signed(size),
+                'u' => OdType::Unsigned(size),
+                'o' => OdType::Octal(size),
+                'x' => OdType::Hex(size),
+                _ => return None,
+            });
+        }
+
+        Some(types)
+    }
+
+    fn size(self) -> usize {
+        match self {
+            OdType::Named | OdType::Char => 1,
+            OdType::Signed(size)
+            | OdType::Unsigned(size)
+            | OdType::Octal(size)
+            | OdType::Hex(size) => size,
+        }
+    }
+
+    // Wide enough for the largest value of the size
+    fn width(self) -> usize {
+        match self {
+            OdType::Named | OdType::Char => 3,
+            OdType::Signed(size) => [4, 6, 11, 20][size.trailing_zeros() as usize],
+            OdType::Unsigned(size) => [3, 5, 10, 20][size.trailing_zeros() as usize],
+            OdType::Octal(size) => [3, 6, 11, 22][size.trailing_zeros() as usize],
+            OdType::Hex(size) => size * 2,
+        }
+    }
+
+    fn format(self, bytes: &[u8]) -> String {
+        const NAMES: [&str; 33] = [
+            "nul", "soh", "stx", "etx", "eot", "enq", "ack", "bel", "bs", "ht", "nl", "vt", "ff",
+            "cr", "so", "si", "dle", "dc1", "dc2", "dc3", "dc4", "nak", "syn", "etb", "can", "em",
+            "sub", "esc", "fs", "gs", "rs", "us", "sp",
+        ];
+
+        // Little-endian, a short last value is padded with zero bytes
+        let value = bytes
+            .iter()
+            .rev()
+            .fold(0u64, |value, &byte| value << 8 | byte as u64);
+        let signed = |size: usize| {
+            let shift = 64 - size * 8;
+            ((value << shift) as i64) >> shift
+        };
+
+        match self {
+            OdType::Named => match bytes[0] & 0x7f {
+                byte @ 0..=0x20 => NAMES[byte as usize].to_string(),
+                0x7f => "del".to_string(),
+                byte => (byte as char).to_string(),
+            },
+            OdType::Char => match bytes[0] {
+                0 => "\\0".to_string(),
+                7 => "\\a".to_string(),
+                8 => "\\b".to_string(),
+                9 => "\\t".to_string(),
+                10 => "\\n".to_string(),
+                11 => "\\v".to_string(),
+                12 => "\\f".to_string(),
+                13 => "\\r".to_string(),
+                byte @ 0x20..=0x7e => (byte as char).to_string(),
+                byte => format!("{:03o}", byte),
+            },
+            OdType::Signed(size) => signed(size).to_string(),
+            OdType::Unsigned(_) => value.to_string(),
+            OdType::Octal(size) => format!("{:0w$o}", value, w = OdType::Octal(size).width()),
+            OdType::Hex(size) => format!("{:0w$x}", value, w = size * 2),
+        }
+    }
+}
+
+pub fn od(args: &[&str], stdin: Option<&[u8]>) -> String {
+    let parsed = match text::parse("od", args, "bcdovx", "AjNt") {
+        Ok(parsed) => parsed,
+        Err(e) => return e,
+    };
+
+    // The traditional flags are shorthands for -t
+    let mut types: Vec<OdType> = [
+        ('b', OdType::Octal(1)),
+        ('c', OdType::Char),
+        ('d', OdType::Unsigned(2)),
+        ('o', OdType::Octal(2)),
+        ('x', OdType::Hex(2)),
+    ]
+    .iter()
+    .filter(|(flag, _)| parsed.has(*flag))
+    .map(|(_, kind)| *kind)
+    .collect();
+    for spec in parsed.values('t') {
+        match OdType::parse(spec) {
+            Some(parsed) => types.extend(parsed),
+            None => return stream::error(format!("od: invalid type string '{}'", spec)),
+        }
+    }
+    if types.is_empty() {
+        types.push(OdType::Octal(2));
+    }
+
+    let address = |offset: usize| match parsed.value('A') {
+        Some("d") => format!("{:07}", offset),
+        Some("x") => format!("{:06x}", offset),
+        Some("n") => String::new(),
+        _ => format!("{:07o}", offset),
+    };
+    if let Some(radix) = parsed
+        .value('A')
+        .filter(|radix| !["d", "o", "x", "n"].contains(radix))
+    {
+        return stream::error(format!("od: invalid output address radix '{}'", radix));
+    }
+    let skip = match parsed.value('j').map(number) {
+        Some(Some(skip)) => skip,
+        Some(None) => {
+            return stream::error(format!("od: invalid skip '{}'", parsed.value('j').unwrap()))
+        }
+        None => 0,
+    };
+    let length = match parsed.value('N').map(number) {
+        Some(None) => {
+            return stream::error(format!(
+                "od: invalid count '{}'",
+                parsed.value('N').unwrap()
+            ))
+        }
+        Some(length) => length,
+        None => None,
+    };
+
+    let limit = length.map(|length| skip.saturating_add(length));
+    let (data, mut output) = read_all("od", &parsed.operands, stdin, limit);
+    let data = window(&data, skip, length);
+    let padding = " ".repeat(address(0).len());
+
+    output.extend(dump_lines(
+        data,
+        skip,
+        16,
+        !parsed.has('v'),
+        |offset, chunk| {
+            types
+                .iter()
+                .enumerate()
+                .map(|(index, kind)| {
+                    let fields: String = chunk
+                        .chunks(kind.size())
+                        .map(|bytes| format!(" {:>w$}", kind.format(bytes), w = kind.width()))
+                        .collect();
+                    let prefix = if index == 0 {
+                        address(offset)
+                    } else {
+                        padding.clone()
+                    };
+                    format!("{}{}", prefix, fields)
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        },
+    ));
+    if parsed.value('A') != Some("n") {
+        output.push(address(skip + data.len()));
+    }
+
+    output.join("\n")
+}
+
+pub fn base64(args: &[&str], stdin: Option<&[u8]>) -> Output {
+    let parsed = match text::parse("base64", args, "di", "w") {
+        Ok(parsed) => parsed,
+        Err(e) => return e.into(),
+    };
+    if parsed.operands.len() > 1 {
+        return stream::error(format!("base64: extra operand '{}'", parsed.operands[1])).into();
+    }
+    let wrap = match parsed.value('w').map(str::parse::<usize>) {
+        Some(Ok(wrap)) => wrap,
+        Some(Err(_)) => {
+            return stream::error(format!(
+                "base64: invalid wrap size: '{}'",
+                parsed.value('w').unwrap()
+            ))
+            .into()
+        }
+        None => 76,
+    };
+
+    let (data, errors) = read_all("base64", &parsed.operands, stdin, None);
+    if !errors.is_empty() {
+        return errors.join("\n").into();
+    }
+
+    if parsed.has('d') {
+        let text = String::from_utf8_lossy(&data);
+        // -i drops anything outside the alphabet instead of failing
+        let text: String = if parsed.has('i') {
+            text.chars()
+                .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '/' | '='))
+                .collect()
+        } else {
+            text.into_owned()
+        };
+        let mut output = Output::default();
+        match base64::decode(&text) {
+            Some(bytes) => output.bytes(bytes),
+            None => output.line(stream::error("base64: invalid input")),
+        }
+        return output;
+    }
+
+    let encoded = base64::encode(&data);
+    if wrap == 0 {
+        return encoded.into();
+    }
+    encoded
+        .as_bytes()
+        .chunks(wrap)
+        .map(|line| String::from_utf8_lossy(line).into_owned())
+        .collect::<Vec<_>>()
+        .join("\n")
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::CommandHandler;
+    use crate::testing;
+
+    // Every byte value, then some that aren't valid UTF-8 together
+    fn binary() -> Vec<u8> {
+        let mut bytes: Vec<u8> = (0..=255).collect();
+        bytes.extend_from_slice(b"\xc3\x28\xff\xfe\0\0\x80");
+        bytes
+    }
+
+    #[test]
+    fn xxd_reverses_its_own_dump() {
+        let _guard = testing::fresh();
+        let mut handler = CommandHandler::new();
+        filesystem::write_bytes("/tmp/bin", &binary()).unwrap();
+
+        handler.handle("xxd /tmp/bin > /tmp/dump");
+        let dump = filesystem::read_file("/tmp/dump").unwrap();
+        assert!(dump
+            .starts_with("00000000: 0001 0203 0405 0607 0809 0a0b 0c0d 0e0f  ................\n"));
+        assert!(
+            dump.contains("00000060: 6061 6263 6465 6667 6869 6a6b 6c6d 6e6f  `abcdefghijklmno\n")
+        );
+
+        handler.handle("xxd -r /tmp/dump > /tmp/back");
+        assert_eq!(filesystem::read_bytes("/tmp/back").unwrap(), binary());
+        handler.handle("xxd -p /tmp/bin | xxd -r -p > /tmp/plain");
+        assert_eq!(filesystem::read_bytes("/tmp/plain").unwrap(), binary());
+    }
+
+    #[test]
+    fn base64_decodes_back_to_the_same_bytes() {
+        let _guard = testing::fresh();
+        let mut handler = CommandHandler::new();
+        filesystem::write_bytes("/tmp/bin", &binary()).unwrap();
+
+        handler.handle("base64 /tmp/bin > /tmp/encoded");
+        let encoded = filesystem::read_file("/tmp/encoded").unwrap();
+        assert!(encoded.lines().all(|line| line.len() <= 76));
+        handler.handle("base64 -d /tmp/encoded > /tmp/decoded");
+        assert_eq!(filesystem::read_bytes("/tmp/decoded").unwrap(), binary());
+
+        assert_eq!(
+            base64(&["-d"], Some(b"AP8A/w==\n")).into_bytes(),
+            vec![0, 255, 0, 255]
+        );
+        assert_eq!(
+            base64(&["-d"], Some(b"not base64!")).into_text(),
+            "base64: invalid input"
+        );
+        assert_eq!(
+            base64(&["-di"], Some(b"aGk*\n")).into_bytes(),
+            b"hi".to_vec()
+        );
+    }
+
+    #[test]
+    fn od_c_shows_characters_and_escapes() {
+        let _guard = testing::fresh();
+        assert_eq!(
+            od(&["-c"], Some(b"hi\n\t\0\x7f\xff")),
+            "0000000   h   i  \\n  \\t  \\0 177 377\n0000007"
+        );
+        assert_eq!(od(&["-An", "-c"], Some(b"ab")), "   a   b");
+        // Repeated lines collapse unless -v
+        let zeros = [0u8; 48];
+        assert_eq!(
+            od(&["-c"], Some(&zeros[..])),
+            "0000000  \\0  \\0  \\0  \\0  \\0  \\0  \\0  \\0  \\0  \\0  \\0  \\0  \\0  \\0  \\0  \\0\n*\n0000060"
+        );
+    }
+}
@@ -1,24 +1,173 @@
+use crate::commands::devices::{self, Device};
+use crate::commands::permissions::{self, EXECUTE, READ, WRITE};
+use crate::commands::stream::{self, Output};
+use crate::commands::{procfs, system, text, users};
+use crate::utils::{base64, fetch, storage};
 use lazy_static::lazy_static;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::ops::Range;
 use std::sync::Mutex;
 
-#[derive(Clone, Debug)]
+const STORAGE_KEY: &str = "portfolio.vfs";
+const STORAGE_VERSION: u32 = 3;
+
+pub type Ino = u64;
+
+pub const ROOT_INO: Ino = 2;
+// Built-in inodes are numbered below this by build.rs
+const FIRST_DYNAMIC_INO: Ino = 1_000_000;
+// /proc is generated rather than stored and takes the inodes below those
+pub const PROC_INOS: Range<Ino> = 100..1000;
+pub const PROC_INO: Ino = PROC_INOS.start;
+
+// Bytes the filesystem may hold, counted in whole 1K blocks per inode.
+// Everything ends up in memory and in localStorage, so a visitor can't
+// grow it without bound.
+pub const QUOTA: usize = 4 * 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Timestamps {
+    pub mtime: f64,
+    pub ctime: f64,
+    pub atime: f64,
+}
+
+impl Timestamps {
+    pub fn at(time: f64) -> Self {
+        Self {
+            mtime: time,
+            ctime: time,
+            atime: time,
+        }
+    }
+
+    pub fn now() -> Self {
+        Self::at(system::timestamp())
+    }
+
+    pub fn modified(&mut self) {
+        let now = system::timestamp();
+        self.mtime = now;
+        self.ctime = now;
+    }
+}
+
+// Reading a file only bumps atime, which shouldn't count as a modification
+impl PartialEq for Timestamps {
+    fn eq(&self, other: &Self) -> bool {
+        self.mtime == other.mtime && self.ctime == other.ctime
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FsError {
+    NotFound,
+    NotADirectory,
+    PermissionDenied,
+    NotPermitted,
+    TooManyLinks,
+    IsADirectory,
+    Io,
+    NoSpace,
+    CrossDevice,
+}
+
+impl fmt::Display for FsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            FsError::NotFound => "No such file or directory",
+            FsError::NotADirectory => "Not a directory",
+            FsError::PermissionDenied => "Permission denied",
+            FsError::NotPermitted => "Operation not permitted",
+            FsError::TooManyLinks => "Too many levels of symbolic links",
+            FsError::IsADirectory => "Is a directory",
+            FsError::Io => "Input/output error",
+            FsError::NoSpace => "No space left on device",
+            FsError::CrossDevice => "Invalid cross-device link",
+        };
+        write!(f, "{}", message)
+    }
+}
+
+// What a file holds. Valid UTF-8 is kept as text for the text tools to work
+// on, anything else, including text with NUL bytes, as raw bytes.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Content {
+    Text(String),
+    // Saved as base64, a JSON array of numbers would be far larger
+    Binary {
+        #[serde(rename = "base64", with = "base64")]
+        bytes: Vec<u8>,
+    },
+}
+
+impl Content {
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        match String::from_utf8(bytes) {
+            Ok(text) if !text.contains('\0') => Content::Text(text),
+            Ok(text) => Content::Binary {
+                bytes: text.into_bytes(),
+            },
+            Err(e) => Content::Binary {
+                bytes: e.into_bytes(),
+            },
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Content::Text(text) => text.as_bytes(),
+            Content::Binary { bytes } => bytes,
+        }
+    }
+
+    // Binary content comes out with U+FFFD for every invalid sequence
+    pub fn to_text(&self) -> String {
+        match self {
+            Content::Text(text) => text.clone(),
+            Content::Binary { bytes } => String::from_utf8_lossy(bytes).into_owned(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Node {
     File {
-        content: String,
+        content: Content,
         permissions: u16,
         owner: String,
+        group: String,
         protected: bool,
+        times: Timestamps,
+        // Content lives in a static asset and is fetched on first read
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        url: Option<String>,
     },
     Directory {
-        children: HashMap<String, Node>,
+        children: HashMap<String, Ino>,
         permissions: u16,
         owner: String,
+        group: String,
         protected: bool,
+        times: Timestamps,
     },
     Symlink {
         target: String,
         owner: String,
+        group: String,
+        times: Timestamps,
+    },
+    // Character devices like /dev/null, which make up what they read and
+    // decide for themselves what a write does
+    Device {
+        device: Device,
+        permissions: u16,
+        owner: String,
+        group: String,
+        times: Timestamps,
     },
 }
 
@@ -31,708 +180,2945 @@ impl Node {
         matches!(self, Node::File { .. })
     }
 
-    fn is_protected(&self) -> bool {
+    // Devices can't be removed or replaced either, everything relies on them
+    pub fn is_protected(&self) -> bool {
         match self {
             Node::File { protected, .. } => *protected,
             Node::Directory { protected, .. } => *protected,
             Node::Symlink { .. } => false,
+            Node::Device { .. } => true,
         }
     }
 
-    fn get_owner(&self) -> &str {
+    pub fn content(&self) -> Option<String> {
         match self {
-            Node::File { owner, .. } => owner,
-            Node::Directory { owner, .. } => owner,
-            Node::Symlink { owner, .. } => owner,
+            Node::File { url: Some(url), .. } => fetch::cached(url),
+            Node::File { content, .. } => Some(content.to_text()),
+            Node::Device { device, .. } => {
+                Some(String::from_utf8_lossy(&device.read(devices::BLOCK)).into_owned())
+            }
+            _ => None,
         }
     }
-}
 
-lazy_static! {
-    pub static ref FILESYSTEM: Mutex<Node> = Mutex::new({
-        use Node::*;
-        Directory {
-            permissions: 0o755,
-            owner: "root".to_string(),
-            protected: true,
-            children: HashMap::from([
-                (
-                    "home".into(),
-                    Directory {
-                        permissions: 0o755,
-                        owner: "root".to_string(),
-                        protected: false,
-                        children: HashMap::from([(
-                            "objz".into(),
-                            Directory {
-                                permissions: 0o755,
-                                owner: "objz".to_string(),
-                                protected: false,
-                                children: HashMap::from([
-                                    (
-                                        "projects".into(),
-                                        Directory {
-                                            permissions: 0o755,
-                                            owner: "objz".to_string(),
-                                            protected: true, // Protected!
-                                            children: HashMap::from([(
-                                                "readme.md".into(),
-                                                File {
-                                                    content: "# Projects\n\nThis is the projects folder.\nContains all my development work.".into(),
-                                                    permissions: 0o644,
-                                                    owner: "objz".to_string(),
-                                                    protected: true,
-                                                }
-                                            )]),
-                                        }
-                                    ),
-                                    (
-                                        "about.txt".into(),
-                                        File {
-                                            content: "I'm objz – a developer from Bavaria.\nI love Rust and WebAssembly!".into(),
-                                            permissions: 0o644,
-                                            owner: "objz".to_string(),
-                                            protected: true, // Protected!
-                                        }
-                                    ),
-                                    (
-                                        "contact.txt".into(),
-                                        File {
-                                            content: "Email: me@objz.dev\nGitHub: @objz\nLocation: Bavaria, Germany".into(),
-                                            permissions: 0o644,
-                                            owner: "objz".to_string(),
-                                            protected: true, // Protected!
-                                        }
-                                    ),
-                                    (
-                                        ".bashrc".into(),
-                                        File {
-                                            content: "# ~/.bashrc\nexport PS1='\\u@\\h:\\w\\$ '\nalias ll='ls -la'".into(),
-                                            permissions: 0o644,
-                                            owner: "objz".to_string(),
-                                            protected: false,
-                                        }
-                                    ),
-                                ]),
-                            }
-                        )]),
-                    }
-                ),
-                (
-                    "etc".into(),
-                    Directory {
-                        permissions: 0o755,
-                        owner: "root".to_string(),
-                        protected: true, // Protected!
-                        children: HashMap::from([(
-                            "hostname".into(),
-                            File {
-                                content: "wasm-host".into(),
-                                permissions: 0o644,
-                                owner: "root".to_string(),
-                                protected: true,
-                            }
-                        )]),
-                    }
-                ),
-                (
-                    "tmp".into(),
-                    Directory {
-                        permissions: 0o1777,
-                        owner: "root".to_string(),
-                        protected: false,
-                        children: HashMap::new(),
-                    }
-                ),
-                (
-                    "usr".into(),
-                    Directory {
-                        permissions: 0o755,
-                        owner: "root".to_string(),
-                        protected: true,
-                        children: HashMap::from([(
-                            "bin".into(),
-                            Directory {
-                                permissions: 0o755,
-                                owner: "root".to_string(),
-                                protected: true,
-                                children: HashMap::new(),
-                            }
-                        )]),
-                    }
-                ),
-            ])
+    pub fn bytes(&self) -> Option<Vec<u8>> {
+        match self {
+            Node::File { url: Some(url), .. } => fetch::cached(url).map(String::into_bytes),
+            Node::File { content, .. } => Some(content.as_bytes().to_vec()),
+            Node::Device { device, .. } => Some(device.read(devices::BLOCK)),
+            _ => None,
         }
-    });
-    pub static ref CURRENT_PATH: Mutex<Vec<String>> = Mutex::new(vec!["home".to_string(), "objz".to_string()]);
-    static ref CURRENT_USER: String = "anonym".to_string();
-}
+    }
 
-pub fn get_filesystem_entries(path: &[String], dirs_only: bool) -> Vec<String> {
-    let filesystem = FILESYSTEM.lock().unwrap();
+    pub fn is_binary(&self) -> bool {
+        matches!(
+            self,
+            Node::File {
+                content: Content::Binary { .. },
+                url: None,
+                ..
+            } | Node::Device {
+                device: Device::Random | Device::Urandom,
+                ..
+            }
+        )
+    }
 
-    match get_node_at_path(&filesystem, path) {
-        Some(Node::Directory { children, .. }) => {
-            let mut entries: Vec<String> = children
-                .iter()
-                .filter_map(|(name, node)| {
-                    if dirs_only {
-                        match node {
-                            Node::Directory { .. } => Some(format!("{}/", name)),
-                            _ => None,
-                        }
-                    } else {
-                        match node {
-                            Node::Directory { .. } => Some(format!("{}/", name)),
-                            Node::File { .. } => Some(name.clone()),
-                            Node::Symlink { .. } => Some(format!("{}@", name)),
-                        }
-                    }
-                })
-                .collect();
+    pub fn permissions(&self) -> u16 {
+        match self {
+            Node::File { permissions, .. } => *permissions,
+            Node::Directory { permissions, .. } => *permissions,
+            Node::Symlink { .. } => 0o777,
+            Node::Device { permissions, .. } => *permissions,
+        }
+    }
 
-            entries.sort();
-            entries
+    pub fn set_permissions(&mut self, mode: u16) {
+        match self {
+            Node::File { permissions, .. }
+            | Node::Directory { permissions, .. }
+            | Node::Device { permissions, .. } => *permissions = mode,
+            Node::Symlink { .. } => return,
         }
-        _ => Vec::new(),
+        self.times_mut().ctime = system::timestamp();
     }
-}
 
-pub fn normalize_path(path: &str, current: &[String]) -> Vec<String> {
-    if path.starts_with('/') {
-        let mut result = Vec::new();
-        for part in path.split('/').filter(|s| !s.is_empty()) {
-            match part {
-                "." => continue,
-                ".." => {
-                    result.pop();
+    pub fn set_ownership(&mut self, new_owner: Option<&str>, new_group: Option<&str>) {
+        match self {
+            Node::File { owner, group, .. }
+            | Node::Directory { owner, group, .. }
+            | Node::Symlink { owner, group, .. }
+            | Node::Device { owner, group, .. } => {
+                if let Some(new_owner) = new_owner {
+                    *owner = new_owner.to_string();
                 }
-                _ => result.push(part.to_string()),
-            }
-        }
-        result
-    } else {
-        let mut result = current.to_vec();
-        for part in path.split('/').filter(|s| !s.is_empty()) {
-            match part {
-                "." => continue,
-                ".." => {
-                    result.pop();
+                if let Some(new_group) = new_group {
+                    *group = new_group.to_string();
                 }
-                _ => result.push(part.to_string()),
             }
         }
-        result
+        self.times_mut().ctime = system::timestamp();
     }
-}
 
-pub fn get_node_at_path<'a>(root: &'a Node, path: &[String]) -> Option<&'a Node> {
-    let mut current = root;
-    for part in path {
-        if let Node::Directory { children, .. } = current {
-            current = children.get(part)?;
-        } else {
-            return None;
+    pub fn get_owner(&self) -> &str {
+        match self {
+            Node::File { owner, .. } => owner,
+            Node::Directory { owner, .. } => owner,
+            Node::Symlink { owner, .. } => owner,
+            Node::Device { owner, .. } => owner,
         }
     }
-    Some(current)
-}
 
-fn get_node_at_path_mut<'a>(root: &'a mut Node, path: &[String]) -> Option<&'a mut Node> {
-    let mut current = root;
-    for part in path {
-        if let Node::Directory { children, .. } = current {
-            current = children.get_mut(part)?;
-        } else {
-            return None;
+    pub fn get_group(&self) -> &str {
+        match self {
+            Node::File { group, .. } => group,
+            Node::Directory { group, .. } => group,
+            Node::Symlink { group, .. } => group,
+            Node::Device { group, .. } => group,
         }
     }
-    Some(current)
-}
 
-pub fn ls(args: &[&str]) -> String {
-    let filesystem = FILESYSTEM.lock().unwrap();
-    let current_path = CURRENT_PATH.lock().unwrap();
+    pub fn times(&self) -> &Timestamps {
+        match self {
+            Node::File { times, .. } => times,
+            Node::Directory { times, .. } => times,
+            Node::Symlink { times, .. } => times,
+            Node::Device { times, .. } => times,
+        }
+    }
 
-    let mut show_hidden = false;
-    let mut long_format = false;
-    let mut target_path = None;
+    fn times_mut(&mut self) -> &mut Timestamps {
+        match self {
+            Node::File { times, .. } => times,
+            Node::Directory { times, .. } => times,
+            Node::Symlink { times, .. } => times,
+            Node::Device { times, .. } => times,
+        }
+    }
 
-    for arg in args {
-        if arg.starts_with('-') {
-            for c in arg.chars().skip(1) {
-                match c {
-                    'a' => show_hidden = true,
-                    'l' => long_format = true,
-                    _ => return format!("ls: invalid option -- '{}'", c),
-                }
-            }
-        } else {
-            target_path = Some(*arg);
+    pub fn size(&self) -> usize {
+        match self {
+            Node::Directory { .. } => 4096,
+            Node::File { url: Some(url), .. } => fetch::cached(url).map_or(0, |text| text.len()),
+            Node::File { content, .. } => content.as_bytes().len(),
+            Node::Symlink { target, .. } => target.len(),
+            Node::Device { .. } => 0,
         }
     }
 
-    let path = if let Some(target) = target_path {
-        normalize_path(target, &current_path)
-    } else {
-        current_path.clone()
-    };
+    // Fetched content lives in the asset cache, not in the filesystem
+    fn stored_size(&self) -> usize {
+        match self {
+            Node::File { url: Some(_), .. } => 0,
+            _ => self.size(),
+        }
+    }
+}
 
-    let node = match get_node_at_path(&filesystem, &path) {
-        Some(node) => node,
-        None => return "ls: cannot access: No such file or directory".into(),
+pub fn mode_string(node: &Node) -> String {
+    let (file_type, permissions) = match node {
+        Node::Directory { permissions, .. } => ('d', *permissions),
+        Node::File { permissions, .. } => ('-', *permissions),
+        Node::Symlink { .. } => ('l', 0o777),
+        Node::Device { permissions, .. } => ('c', *permissions),
     };
+    mode_letters(file_type, permissions)
+}
 
-    match node {
-        Node::Directory { children, .. } => {
-            let mut entries: Vec<_> = children.iter().collect();
-            entries.sort_by_key(|(name, _)| name.as_str());
-
-            if long_format {
-                let mut output = String::new();
-                for (name, node) in &entries {
-                    if !show_hidden && name.starts_with('.') {
-                        continue;
-                    }
+// `drwxr-xr-x` and the like, for entries that aren't nodes yet
+pub fn mode_letters(file_type: char, permissions: u16) -> String {
+    let mut mode = String::with_capacity(10);
+    mode.push(file_type);
+    for (shift, special, special_char) in [(6, 0o4000, 's'), (3, 0o2000, 's'), (0, 0o1000, 't')] {
+        let bits = (permissions >> shift) & 0o7;
+        mode.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        mode.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        mode.push(match (bits & 0o1 != 0, permissions & special != 0) {
+            (true, true) => special_char,
+            (false, true) => special_char.to_ascii_uppercase(),
+            (true, false) => 'x',
+            (false, false) => '-',
+        });
+    }
+    mode
+}
 
-                    let (file_type, permissions, size) = match node {
-                        Node::Directory { permissions, .. } => ('d', *permissions, 4096),
-                        Node::File {
-                            permissions,
-                            content,
-                            ..
-                        } => ('-', *permissions, content.len()),
-                        Node::Symlink { .. } => ('l', 0o777, 0),
-                    };
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Inode {
+    pub node: Node,
+    // Number of directory entries pointing here, the inode is freed at zero
+    pub links: usize,
+}
 
-                    output.push_str(&format!(
-                        "{}{:o} 1 objz objz {:>8} {} {}\n",
-                        file_type, permissions, size, "Jan  1 12:00", name
-                    ));
-                }
-                output
-            } else {
-                entries
-                    .iter()
-                    .filter(|(name, _)| show_hidden || !name.starts_with('.'))
-                    .map(|(name, node)| match node {
-                        Node::Directory { .. } => format!("{}/", name),
-                        Node::File { .. } => name.to_string(),
-                        Node::Symlink { .. } => format!("{}@", name),
-                    })
-                    .collect::<Vec<_>>()
-                    .join("  ")
-            }
-        }
-        Node::File { .. } => target_path.unwrap_or(".").to_string(),
-        Node::Symlink { target, .. } => format!("-> {}", target),
-    }
+// Every file, directory and link lives in one table keyed by inode number.
+// Directories only map names to inode numbers, so a file can have several
+// names and renaming never copies anything.
+#[derive(Clone)]
+pub struct Filesystem {
+    inodes: HashMap<Ino, Inode>,
+    next_ino: Ino,
 }
 
-pub fn cd(args: &[&str]) -> String {
-    if args.is_empty() {
-        {
-            let mut path = CURRENT_PATH.lock().unwrap();
-            *path = vec!["home".to_string(), "objz".to_string()];
-        }
-        return String::new();
+impl Filesystem {
+    fn from_nodes(nodes: Vec<(Ino, Node)>) -> Self {
+        let mut filesystem = Self {
+            inodes: nodes
+                .into_iter()
+                .map(|(ino, node)| (ino, Inode { node, links: 0 }))
+                .collect(),
+            next_ino: FIRST_DYNAMIC_INO,
+        };
+        filesystem.count_links();
+        filesystem
     }
 
-    let new_path = {
-        let filesystem = FILESYSTEM.lock().unwrap();
-        let current_path = CURRENT_PATH.lock().unwrap();
-
-        let new_path = normalize_path(args[0], &current_path);
+    // Sets every link count from the directory entries naming each inode
+    fn count_links(&mut self) {
+        for inode in self.inodes.values_mut() {
+            inode.links = 0;
+        }
 
-        match get_node_at_path(&filesystem, &new_path) {
-            Some(Node::Directory { .. }) => new_path,
-            Some(Node::Symlink { target, .. }) => {
-                let symlink_path = normalize_path(target, &current_path);
-                match get_node_at_path(&filesystem, &symlink_path) {
-                    Some(Node::Directory { .. }) => symlink_path,
-                    Some(_) => return format!("cd: {}: Not a directory", args[0]),
-                    None => return format!("cd: {}: No such file or directory", args[0]),
-                }
+        let targets: Vec<Ino> = self
+            .inodes
+            .values()
+            .filter_map(|inode| match &inode.node {
+                Node::Directory { children, .. } => Some(children.values().copied()),
+                _ => None,
+            })
+            .flatten()
+            .chain([ROOT_INO])
+            .collect();
+        for ino in targets {
+            if let Some(inode) = self.inodes.get_mut(&ino) {
+                inode.links += 1;
             }
-            Some(_) => return format!("cd: {}: Not a directory", args[0]),
-            None => return format!("cd: {}: No such file or directory", args[0]),
         }
-    };
-
-    {
-        let mut path = CURRENT_PATH.lock().unwrap();
-        *path = new_path;
     }
-    String::new()
-}
 
-pub fn pwd(_: &[&str]) -> String {
-    let path = CURRENT_PATH.lock().unwrap();
-    if path.is_empty() {
-        "/".into()
-    } else {
-        format!("/{}", path.join("/"))
+    pub fn get(&self, ino: Ino) -> Option<&Node> {
+        self.inodes.get(&ino).map(|inode| &inode.node)
     }
-}
 
-pub fn cat(args: &[&str]) -> String {
-    if args.is_empty() {
-        return "cat: missing file operand".into();
+    pub fn get_mut(&mut self, ino: Ino) -> Option<&mut Node> {
+        self.inodes.get_mut(&ino).map(|inode| &mut inode.node)
     }
 
-    let filesystem = FILESYSTEM.lock().unwrap();
-    let current_path = CURRENT_PATH.lock().unwrap();
+    pub fn entry(&self, dir: Ino, name: &str) -> Option<Ino> {
+        match self.get(dir)? {
+            Node::Directory { children, .. } => children.get(name).copied(),
+            _ => None,
+        }
+    }
 
-    let mut output = String::new();
+    // Walks names to an inode without following links or checking access
+    pub fn ino_at(&self, path: &[String]) -> Option<Ino> {
+        path.iter()
+            .try_fold(ROOT_INO, |dir, name| self.entry(dir, name))
+    }
 
-    for &filename in args {
-        let file_path = normalize_path(filename, &current_path);
+    pub fn node_at(&self, path: &[String]) -> Option<&Node> {
+        self.get(self.ino_at(path)?)
+    }
 
-        match get_node_at_path(&filesystem, &file_path) {
-            Some(Node::File { content, .. }) => {
-                output.push_str(content);
-                if args.len() > 1 && filename != args[args.len() - 1] {
-                    output.push('\n');
-                }
-            }
-            Some(Node::Directory { .. }) => {
-                output.push_str(&format!("cat: {}: Is a directory\n", filename));
-            }
-            Some(Node::Symlink { target, .. }) => {
-                output.push_str(&format!(
-                    "cat: {}: Symbolic link (points to {})\n",
-                    filename, target
-                ));
-            }
-            None => {
-                output.push_str(&format!("cat: {}: No such file or directory\n", filename));
+    // Directories count their own entry, `.` and the `..` of each subdirectory
+    pub fn link_count(&self, ino: Ino) -> usize {
+        match self.inodes.get(&ino) {
+            Some(Inode {
+                node: Node::Directory { children, .. },
+                ..
+            }) => {
+                2 + children
+                    .values()
+                    .filter(|child| matches!(self.get(**child), Some(Node::Directory { .. })))
+                    .count()
             }
+            Some(inode) => inode.links,
+            None => 0,
         }
     }
 
-    output.trim_end().to_string()
-}
+    // Adds a tree made outside of build.rs as `/name`, where `nodes` must
+    // include its root directory `root`
+    fn mount(&mut self, name: &str, root: Ino, nodes: Vec<(Ino, Node)>) {
+        for (ino, node) in nodes {
+            self.inodes.insert(ino, Inode { node, links: 1 });
+        }
 
-pub fn mkdir(args: &[&str]) -> String {
-    if args.is_empty() {
-        return "mkdir: missing operand".into();
+        if let Some(Node::Directory { children, .. }) = self.get_mut(ROOT_INO) {
+            children.insert(name.to_string(), root);
+        }
     }
 
-    let mut filesystem = FILESYSTEM.lock().unwrap();
-    let current_path = CURRENT_PATH.lock().unwrap();
-    let current_user = &*CURRENT_USER;
+    // Replaces everything under /proc with freshly generated `nodes`
+    pub fn mount_proc(&mut self, nodes: Vec<(Ino, Node)>) {
+        self.inodes.retain(|ino, _| !PROC_INOS.contains(ino));
+        self.mount("proc", PROC_INO, nodes);
+    }
 
-    for &dirname in args {
-        let dir_path = normalize_path(dirname, &current_path);
+    // 1K blocks stored, each inode counted once however many names it has
+    // and rounded up on its own, the way du counts
+    pub fn blocks_used(&self) -> usize {
+        self.inodes
+            .iter()
+            .filter(|(ino, _)| !PROC_INOS.contains(ino))
+            .map(|(_, inode)| blocks(inode.node.stored_size()))
+            .sum()
+    }
 
-        if dir_path.is_empty() {
-            return "mkdir: cannot create directory '/': File exists".into();
+    // Fails unless something going from `old` to `new` bytes still fits in
+    // the quota. A full filesystem can't take even an empty file.
+    fn reserve(&self, old: usize, new: usize) -> Result<(), FsError> {
+        let (used, quota) = (self.blocks_used(), blocks(QUOTA));
+        let growth = blocks(new).saturating_sub(blocks(old));
+        if used >= quota || growth > quota - used {
+            return Err(FsError::NoSpace);
         }
+        Ok(())
+    }
 
-        let parent_path = &dir_path[..dir_path.len() - 1];
-        let dir_name = &dir_path[dir_path.len() - 1];
-
-        let parent = match get_node_at_path_mut(&mut filesystem, parent_path) {
-            Some(Node::Directory { children, .. }) => children,
+    // Adds `name` in `dir` for an existing inode, replacing any old entry
+    fn link(&mut self, dir: Ino, name: &str, ino: Ino) {
+        match self.entry(dir, name) {
+            Some(old) if old == ino => return,
             Some(_) => {
-                return format!(
-                    "mkdir: cannot create directory '{}': Not a directory",
-                    dirname
-                )
-            }
-            None => {
-                return format!(
-                    "mkdir: cannot create directory '{}': No such file or directory",
-                    dirname
-                )
+                self.unlink(dir, name);
             }
-        };
-
-        if parent.contains_key(dir_name) {
-            return format!("mkdir: cannot create directory '{}': File exists", dirname);
+            None => {}
         }
 
-        parent.insert(
-            dir_name.clone(),
-            Node::Directory {
-                permissions: 0o755,
-                owner: current_user.clone(),
-                protected: false,
-                children: HashMap::new(),
-            },
-        );
+        if let Some(Node::Directory {
+            children, times, ..
+        }) = self.get_mut(dir)
+        {
+            children.insert(name.to_string(), ino);
+            times.modified();
+        }
+        if let Some(inode) = self.inodes.get_mut(&ino) {
+            inode.links += 1;
+            inode.node.times_mut().ctime = system::timestamp();
+        }
     }
 
-    String::new()
-}
+    fn create(&mut self, dir: Ino, name: &str, node: Node) -> Ino {
+        let mut ino = self.next_ino;
+        while self.inodes.contains_key(&ino) {
+            ino += 1;
+        }
+        self.next_ino = ino + 1;
 
-pub fn touch(args: &[&str]) -> String {
-    if args.is_empty() {
-        return "touch: missing file operand".into();
+        self.inodes.insert(ino, Inode { node, links: 0 });
+        self.link(dir, name, ino);
+        ino
     }
 
-    let mut filesystem = FILESYSTEM.lock().unwrap();
-    let current_path = CURRENT_PATH.lock().unwrap();
-    let current_user = &*CURRENT_USER;
+    // Removes `name` from `dir`, freeing the inode with its last link
+    fn unlink(&mut self, dir: Ino, name: &str) -> Option<Ino> {
+        let ino = match self.get_mut(dir)? {
+            Node::Directory {
+                children, times, ..
+            } => {
+                let ino = children.remove(name)?;
+                times.modified();
+                ino
+            }
+            _ => return None,
+        };
 
-    for &filename in args {
-        let file_path = normalize_path(filename, &current_path);
+        self.release(ino);
+        Some(ino)
+    }
 
-        if file_path.is_empty() {
-            continue;
-        }
+    fn release(&mut self, ino: Ino) {
+        let inode = match self.inodes.get_mut(&ino) {
+            Some(inode) => inode,
+            None => return,
+        };
 
-        let parent_path = &file_path[..file_path.len() - 1];
-        let file_name = &file_path[file_path.len() - 1];
+        inode.links = inode.links.saturating_sub(1);
+        if inode.links > 0 {
+            inode.node.times_mut().ctime = system::timestamp();
+            return;
+        }
 
-        let parent = match get_node_at_path_mut(&mut filesystem, parent_path) {
-            Some(Node::Directory { children, .. }) => children,
-            Some(_) => return format!("touch: cannot touch '{}': Not a directory", filename),
-            None => {
-                return format!(
-                    "touch: cannot touch '{}': No such file or directory",
-                    filename
+        if let Some(Inode {
+            node: Node::Directory { children, .. },
+            ..
+        }) = self.inodes.remove(&ino)
+        {
+            for child in children.into_values() {
+                self.release(child);
+            }
+        }
+    }
+}
+
+// Generated by build.rs from the `content/` directory, along with the
+// devices in /dev. /proc starts out empty and is filled in before each
+// command that may read it.
+fn default_filesystem() -> Filesystem {
+    let mut filesystem: Filesystem = include!(concat!(env!("OUT_DIR"), "/content.rs"));
+    // Devices date from whenever the content was built, so they compare
+    // equal to the pristine filesystem
+    let times = *filesystem.get(ROOT_INO).unwrap().times();
+    filesystem.mount("dev", devices::DEV_INO, devices::nodes(times));
+    filesystem.mount_proc(procfs::empty());
+    filesystem
+}
+
+lazy_static! {
+    pub static ref FILESYSTEM: Mutex<Filesystem> = Mutex::new(default_filesystem());
+    pub static ref CURRENT_PATH: Mutex<Vec<String>> =
+        Mutex::new(vec!["home".to_string(), "objz".to_string()]);
+}
+
+type Change = (Ino, Option<Inode>);
+
+#[derive(Serialize, Deserialize)]
+struct SavedFilesystem {
+    version: u32,
+    next_ino: Ino,
+    changes: Vec<Change>,
+}
+
+// Records only the inodes that differ from the built-in filesystem, so
+// portfolio updates still show up for returning visitors. /proc is never
+// saved.
+fn collect_changes(pristine: &Filesystem, current: &Filesystem) -> Vec<Change> {
+    let mut changes: Vec<Change> = current
+        .inodes
+        .iter()
+        .filter(|(ino, _)| !PROC_INOS.contains(ino))
+        .filter(|(ino, inode)| pristine.inodes.get(ino) != Some(inode))
+        .map(|(ino, inode)| (*ino, Some(inode.clone())))
+        .collect();
+
+    changes.extend(
+        pristine
+            .inodes
+            .keys()
+            .filter(|ino| !PROC_INOS.contains(ino) && !current.inodes.contains_key(ino))
+            .map(|ino| (*ino, None)),
+    );
+
+    changes
+}
+
+pub fn persist() {
+    let filesystem = FILESYSTEM.lock().unwrap();
+    let changes = collect_changes(&default_filesystem(), &filesystem);
+
+    if changes.is_empty() {
+        storage::remove(STORAGE_KEY);
+        return;
+    }
+
+    let saved = SavedFilesystem {
+        version: STORAGE_VERSION,
+        next_ino: filesystem.next_ino,
+        changes,
+    };
+    if let Ok(json) = serde_json::to_string(&saved) {
+        storage::save(STORAGE_KEY, &json);
+    }
+}
+
+pub fn restore() {
+    let saved = match storage::load(STORAGE_KEY)
+        .and_then(|json| serde_json::from_str::<SavedFilesystem>(&json).ok())
+    {
+        Some(saved) if saved.version == STORAGE_VERSION => saved,
+        Some(_) => {
+            storage::remove(STORAGE_KEY);
+            return;
+        }
+        None => return,
+    };
+
+    let mut filesystem = FILESYSTEM.lock().unwrap();
+    filesystem.next_ino = saved.next_ino.max(FIRST_DYNAMIC_INO);
+
+    // Built-in entries the visitor deleted, moved or linked elsewhere
+    let mut placed = HashSet::new();
+    for (ino, inode) in &saved.changes {
+        match inode {
+            Some(Inode {
+                node: Node::Directory { children, .. },
+                ..
+            }) => placed.extend(children.values().copied()),
+            Some(_) => {}
+            None => {
+                placed.insert(*ino);
+            }
+        }
+    }
+
+    for (ino, inode) in saved.changes {
+        match inode {
+            Some(mut inode) => {
+                // A directory saved before a deploy keeps what it added
+                if let (
+                    Some(Node::Directory {
+                        children: built_in, ..
+                    }),
+                    Node::Directory { children, .. },
+                ) = (filesystem.get(ino), &mut inode.node)
+                {
+                    for (name, child) in built_in {
+                        if !placed.contains(child) {
+                            children.entry(name.clone()).or_insert(*child);
+                        }
+                    }
+                }
+                filesystem.inodes.insert(ino, inode);
+            }
+            None => {
+                filesystem.inodes.remove(&ino);
+            }
+        }
+    }
+
+    // Saved counts miss the names a deploy added or the merge kept
+    filesystem.count_links();
+}
+
+// Throws away every change, as if the page had just loaded
+#[cfg(test)]
+pub fn reset_to_default() {
+    *FILESYSTEM.lock().unwrap() = default_filesystem();
+}
+
+fn go_home() {
+    let home = normalize_path(&users::home_dir(), &[]);
+    let mut path = CURRENT_PATH.lock().unwrap();
+    *path = home;
+}
+
+pub fn reset_session() {
+    go_home();
+    permissions::reset_session();
+}
+
+// Reads files like /etc/passwd on behalf of the system itself, so neither
+// the current user's permissions nor the current directory matter
+pub fn read_system_file(path: &str) -> Option<String> {
+    let filesystem = FILESYSTEM.lock().unwrap();
+    match filesystem.node_at(&normalize_path(path, &[]))? {
+        node @ Node::File { .. } => node.content(),
+        _ => None,
+    }
+}
+
+// With `recursive` set, files anywhere below directory arguments count too
+pub fn pending_fetches(args: &[&str], recursive: bool) -> Vec<String> {
+    let filesystem = FILESYSTEM.lock().unwrap();
+    let current_path = CURRENT_PATH.lock().unwrap();
+    let mut urls = Vec::new();
+
+    let mut check = |node: &Node| match node {
+        Node::File { url: Some(url), .. }
+            if fetch::cached(url).is_none() && permissions::can_access(node, READ) =>
+        {
+            urls.push(url.clone());
+        }
+        _ => {}
+    };
+
+    for arg in args.iter().filter(|arg| !arg.starts_with('-')) {
+        let ino = match resolve_ino(&filesystem, &normalize_path(arg, &current_path), true) {
+            Ok(ino) => ino,
+            Err(_) => continue,
+        };
+
+        check(filesystem.get(ino).unwrap());
+        if recursive {
+            walk_tree(&filesystem, ino, &mut |entry| {
+                check(entry.node);
+                matches!(entry.node, Node::Directory { .. })
+            });
+        }
+    }
+
+    urls
+}
+
+pub fn get_filesystem_entries(path: &[String], dirs_only: bool) -> Vec<String> {
+    let filesystem = FILESYSTEM.lock().unwrap();
+
+    match follow(&filesystem, path) {
+        Ok(node @ Node::Directory { children, .. }) if permissions::can_access(node, READ) => {
+            let mut entries: Vec<String> = children
+                .iter()
+                .filter_map(|(name, ino)| {
+                    let node = filesystem.get(*ino)?;
+                    // Links complete like whatever they point at
+                    let node = match node {
+                        Node::Symlink { .. } => {
+                            let link_path = [path, std::slice::from_ref(name)].concat();
+                            follow(&filesystem, &link_path).unwrap_or(node)
+                        }
+                        _ => node,
+                    };
+
+                    match node {
+                        Node::Directory { .. } => Some(format!("{}/", name)),
+                        _ if dirs_only => None,
+                        _ => Some(name.clone()),
+                    }
+                })
+                .collect();
+
+            entries.sort();
+            entries
+        }
+        _ => Vec::new(),
+    }
+}
+
+pub fn normalize_path(path: &str, current: &[String]) -> Vec<String> {
+    if path.starts_with('/') {
+        let mut result = Vec::new();
+        for part in path.split('/').filter(|s| !s.is_empty()) {
+            match part {
+                "." => continue,
+                ".." => {
+                    result.pop();
+                }
+                _ => result.push(part.to_string()),
+            }
+        }
+        result
+    } else {
+        let mut result = current.to_vec();
+        for part in path.split('/').filter(|s| !s.is_empty()) {
+            match part {
+                "." => continue,
+                ".." => {
+                    result.pop();
+                }
+                _ => result.push(part.to_string()),
+            }
+        }
+        result
+    }
+}
+
+// Same limit as Linux before it gives up with ELOOP
+const MAX_SYMLINK_HOPS: usize = 40;
+
+// Turns `path` into the physical path of the node it names, following
+// symlinks on the way. Needs search permission on every directory passed
+// through, the way the kernel resolves paths. `..` is taken physically, from
+// wherever the links led. With `missing_last` the final component doesn't
+// have to exist, like `realpath` allows.
+fn walk(
+    filesystem: &Filesystem,
+    path: &[String],
+    follow_last: bool,
+    missing_last: bool,
+) -> Result<Vec<String>, FsError> {
+    let mut pending: VecDeque<String> = path.iter().cloned().collect();
+    let mut resolved = Vec::new();
+    let mut current = ROOT_INO;
+    let mut hops = 0;
+
+    while let Some(part) = pending.pop_front() {
+        match part.as_str() {
+            "." => continue,
+            ".." => {
+                resolved.pop();
+                current = filesystem.ino_at(&resolved).unwrap_or(ROOT_INO);
+                continue;
+            }
+            _ => {}
+        }
+
+        let dir = filesystem.get(current).ok_or(FsError::NotFound)?;
+        let children = match dir {
+            Node::Directory { children, .. } => children,
+            _ => return Err(FsError::NotADirectory),
+        };
+        permissions::check(dir, EXECUTE)?;
+
+        let is_last = pending.is_empty();
+        let child = children
+            .get(&part)
+            .and_then(|ino| Some((*ino, filesystem.get(*ino)?)));
+
+        match child {
+            Some((_, Node::Symlink { target, .. })) if !is_last || follow_last => {
+                hops += 1;
+                if hops > MAX_SYMLINK_HOPS {
+                    return Err(FsError::TooManyLinks);
+                }
+
+                // Relative targets continue from the directory holding the link
+                if target.starts_with('/') {
+                    resolved.clear();
+                    current = ROOT_INO;
+                }
+                for part in target.split('/').filter(|s| !s.is_empty()).rev() {
+                    pending.push_front(part.to_string());
+                }
+            }
+            Some((ino, _)) => {
+                resolved.push(part);
+                current = ino;
+            }
+            None if is_last && missing_last => resolved.push(part),
+            None => return Err(FsError::NotFound),
+        }
+    }
+
+    Ok(resolved)
+}
+
+pub fn resolve(
+    filesystem: &Filesystem,
+    path: &[String],
+    follow_last: bool,
+) -> Result<Vec<String>, FsError> {
+    walk(filesystem, path, follow_last, false)
+}
+
+pub fn resolve_ino(
+    filesystem: &Filesystem,
+    path: &[String],
+    follow_last: bool,
+) -> Result<Ino, FsError> {
+    let resolved = resolve(filesystem, path, follow_last)?;
+    filesystem.ino_at(&resolved).ok_or(FsError::NotFound)
+}
+
+pub fn canonicalize(filesystem: &Filesystem, path: &[String]) -> Result<Vec<String>, FsError> {
+    walk(filesystem, path, true, true)
+}
+
+// Like lstat, a symlink named by the last component is returned itself
+pub fn lookup<'a>(filesystem: &'a Filesystem, path: &[String]) -> Result<&'a Node, FsError> {
+    let ino = resolve_ino(filesystem, path, false)?;
+    filesystem.get(ino).ok_or(FsError::NotFound)
+}
+
+// Like stat, symlinks are followed all the way to what they point at
+pub fn follow<'a>(filesystem: &'a Filesystem, path: &[String]) -> Result<&'a Node, FsError> {
+    let ino = resolve_ino(filesystem, path, true)?;
+    filesystem.get(ino).ok_or(FsError::NotFound)
+}
+
+pub fn follow_mut<'a>(
+    filesystem: &'a mut Filesystem,
+    path: &[String],
+) -> Result<&'a mut Node, FsError> {
+    let ino = resolve_ino(filesystem, path, true)?;
+    filesystem.get_mut(ino).ok_or(FsError::NotFound)
+}
+
+pub fn ls(args: &[&str]) -> String {
+    let filesystem = FILESYSTEM.lock().unwrap();
+    let current_path = CURRENT_PATH.lock().unwrap();
+
+    let mut show_hidden = false;
+    let mut long_format = false;
+    let mut dereference = false;
+    let mut show_inode = false;
+    let mut target_path = None;
+
+    for arg in args {
+        if arg.starts_with('-') {
+            for c in arg.chars().skip(1) {
+                match c {
+                    'a' => show_hidden = true,
+                    'l' => long_format = true,
+                    'L' => dereference = true,
+                    'i' => show_inode = true,
+                    _ => return stream::error(format!("ls: invalid option -- '{}'", c)),
+                }
+            }
+        } else {
+            target_path = Some(*arg);
+        }
+    }
+
+    let path = if let Some(target) = target_path {
+        normalize_path(target, &current_path)
+    } else {
+        current_path.clone()
+    };
+
+    // A link named on the command line is followed unless -l asks about
+    // the link itself, -L follows links everywhere
+    let ino = match resolve_ino(&filesystem, &path, false) {
+        Ok(link)
+            if matches!(filesystem.get(link), Some(Node::Symlink { .. }))
+                && (dereference || !long_format) =>
+        {
+            match resolve_ino(&filesystem, &path, true) {
+                Ok(ino) => ino,
+                Err(_) if !dereference => link,
+                Err(e) => {
+                    return stream::error(format!(
+                        "ls: cannot access '{}': {}",
+                        target_path.unwrap_or("."),
+                        e
+                    ))
+                }
+            }
+        }
+        Ok(ino) => ino,
+        Err(e) => {
+            return stream::error(format!(
+                "ls: cannot access '{}': {}",
+                target_path.unwrap_or("."),
+                e
+            ))
+        }
+    };
+    let node = filesystem.get(ino).unwrap();
+    let resolved = resolve(&filesystem, &path, true).unwrap_or_default();
+
+    match node {
+        Node::Directory { children, .. } => {
+            if let Err(e) = permissions::check(node, READ) {
+                return stream::error(format!(
+                    "ls: cannot open directory '{}': {}",
+                    target_path.unwrap_or("."),
+                    e
+                ));
+            }
+
+            let mut entries: Vec<(String, Ino)> = children
+                .iter()
+                .filter(|(name, _)| show_hidden || !name.starts_with('.'))
+                .map(|(name, ino)| match filesystem.get(*ino) {
+                    Some(Node::Symlink { .. }) if dereference => {
+                        let child_path = [resolved.as_slice(), std::slice::from_ref(name)].concat();
+                        let target = resolve_ino(&filesystem, &child_path, true).unwrap_or(*ino);
+                        (name.clone(), target)
+                    }
+                    _ => (name.clone(), *ino),
+                })
+                .collect();
+            entries.sort();
+
+            if long_format {
+                let blocks: usize = entries
+                    .iter()
+                    .filter_map(|(_, ino)| filesystem.get(*ino))
+                    .map(|node| node.size().div_ceil(4096) * 4)
+                    .sum();
+
+                format!(
+                    "total {}\n{}",
+                    blocks,
+                    long_listing(&filesystem, &entries, show_inode)
                 )
+            } else {
+                entries
+                    .iter()
+                    .filter_map(|(name, ino)| {
+                        let name = match filesystem.get(*ino)? {
+                            Node::Directory { .. } => format!("{}/", name),
+                            Node::File { .. } | Node::Device { .. } => name.to_string(),
+                            Node::Symlink { .. } => format!("{}@", name),
+                        };
+                        Some(if show_inode {
+                            format!("{} {}", ino, name)
+                        } else {
+                            name
+                        })
+                    })
+                    .collect::<Vec<_>>()
+                    .join("  ")
+            }
+        }
+        _ if long_format => long_listing(
+            &filesystem,
+            &[(target_path.unwrap_or(".").to_string(), ino)],
+            show_inode,
+        ),
+        _ if show_inode => format!("{} {}", ino, target_path.unwrap_or(".")),
+        _ => target_path.unwrap_or(".").to_string(),
+    }
+}
+
+// Devices show their major and minor numbers where files show a size
+fn size_field(node: &Node) -> String {
+    match node {
+        Node::Device { device, .. } => {
+            let (major, minor) = device.numbers();
+            format!("{}, {}", major, minor)
+        }
+        _ => node.size().to_string(),
+    }
+}
+
+fn long_listing(filesystem: &Filesystem, entries: &[(String, Ino)], show_inode: bool) -> String {
+    let entries: Vec<_> = entries
+        .iter()
+        .filter_map(|(name, ino)| Some((name, *ino, filesystem.get(*ino)?)))
+        .collect();
+    let width = |f: &dyn Fn(Ino, &Node) -> String| {
+        entries
+            .iter()
+            .map(|(_, ino, node)| f(*ino, node).len())
+            .max()
+            .unwrap_or(0)
+    };
+    let ino_width = width(&|ino, _| ino.to_string());
+    let links_width = width(&|ino, _| filesystem.link_count(ino).to_string());
+    let owner_width = width(&|_, node| node.get_owner().to_string());
+    let group_width = width(&|_, node| node.get_group().to_string());
+    let size_width = width(&|_, node| size_field(node));
+
+    entries
+        .iter()
+        .map(|(name, ino, node)| {
+            let name = match node {
+                Node::Symlink { target, .. } => format!("{} -> {}", name, target),
+                _ => name.to_string(),
+            };
+            let inode = if show_inode {
+                format!("{:>iw$} ", ino, iw = ino_width)
+            } else {
+                String::new()
+            };
+            format!(
+                "{}{} {:>lw$} {:<ow$} {:<gw$} {:>sw$} {} {}",
+                inode,
+                mode_string(node),
+                filesystem.link_count(*ino),
+                node.get_owner(),
+                node.get_group(),
+                size_field(node),
+                system::format_ls_time(node.times().mtime),
+                name,
+                lw = links_width,
+                ow = owner_width,
+                gw = group_width,
+                sw = size_width,
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub fn cd(args: &[&str]) -> String {
+    if args.is_empty() {
+        go_home();
+        return String::new();
+    }
+
+    let new_path = {
+        let filesystem = FILESYSTEM.lock().unwrap();
+        let current_path = CURRENT_PATH.lock().unwrap();
+
+        let new_path = match resolve(&filesystem, &normalize_path(args[0], &current_path), true) {
+            Ok(path) => path,
+            Err(e) => return stream::error(format!("cd: {}: {}", args[0], e)),
+        };
+
+        match filesystem.node_at(&new_path) {
+            Some(dir @ Node::Directory { .. }) => {
+                if let Err(e) = permissions::check(dir, EXECUTE) {
+                    return stream::error(format!("cd: {}: {}", args[0], e));
+                }
+                new_path
             }
+            _ => return stream::error(format!("cd: {}: Not a directory", args[0])),
+        }
+    };
+
+    {
+        let mut path = CURRENT_PATH.lock().unwrap();
+        *path = new_path;
+    }
+    String::new()
+}
+
+pub fn pwd(_: &[&str]) -> String {
+    let path = CURRENT_PATH.lock().unwrap();
+    if path.is_empty() {
+        "/".into()
+    } else {
+        format!("/{}", path.join("/"))
+    }
+}
+
+fn read_with<T>(name: &str, read: impl Fn(&Node) -> Option<T>) -> Result<T, FsError> {
+    let mut filesystem = FILESYSTEM.lock().unwrap();
+    let current_path = CURRENT_PATH.lock().unwrap();
+
+    match follow_mut(&mut filesystem, &normalize_path(name, &current_path))? {
+        node @ (Node::File { .. } | Node::Device { .. }) => {
+            permissions::check(node, READ)?;
+            let content = read(node).ok_or(FsError::Io)?;
+            node.times_mut().atime = system::timestamp();
+            Ok(content)
+        }
+        _ => Err(FsError::IsADirectory),
+    }
+}
+
+// Reads a file for cat and the text tools, following links. Needs read
+// access and bumps the access time.
+pub fn read_file(name: &str) -> Result<String, FsError> {
+    read_with(name, Node::content)
+}
+
+// Like read_file, for tools that work on the raw bytes
+pub fn read_bytes(name: &str) -> Result<Vec<u8>, FsError> {
+    read_with(name, Node::bytes)
+}
+
+// At most the first `limit` bytes, which is exactly what an endless device
+// like /dev/zero gives. Never more than the quota, whatever is asked for.
+pub fn read_prefix(name: &str, limit: usize) -> Result<Vec<u8>, FsError> {
+    let limit = limit.min(QUOTA);
+    read_with(name, |node| match node {
+        Node::Device { device, .. } => Some(device.read(limit)),
+        _ => node.bytes().map(|mut bytes| {
+            bytes.truncate(limit);
+            bytes
+        }),
+    })
+}
+
+pub fn is_binary(name: &str) -> bool {
+    let filesystem = FILESYSTEM.lock().unwrap();
+    let current_path = CURRENT_PATH.lock().unwrap();
+    follow(&filesystem, &normalize_path(name, &current_path)).is_ok_and(Node::is_binary)
+}
+
+pub fn write_file(name: &str, content: &str) -> Result<(), FsError> {
+    write_bytes(name, content.as_bytes())
+}
+
+// Replaces a file's content, creating the file if it doesn't exist yet.
+// Existing files keep their owner and mode and need write access.
+pub fn write_bytes(name: &str, content: &[u8]) -> Result<(), FsError> {
+    store(name, content, false)
+}
+
+// Adds to the end of a file the way `>>` does, creating it if needed
+pub fn append_bytes(name: &str, content: &[u8]) -> Result<(), FsError> {
+    store(name, content, true)
+}
+
+fn store(name: &str, content: &[u8], append: bool) -> Result<(), FsError> {
+    let mut filesystem = FILESYSTEM.lock().unwrap();
+    let current_path = CURRENT_PATH.lock().unwrap();
+    let path = normalize_path(name, &current_path);
+
+    if let Ok(ino) = resolve_ino(&filesystem, &path, true) {
+        let node = filesystem.get(ino).unwrap();
+        // Devices take the write themselves, whatever they do with it
+        if let Node::Device { device, .. } = node {
+            permissions::check(node, WRITE)?;
+            device.write(content);
+            return Ok(());
+        }
+        if !matches!(node, Node::File { .. }) {
+            return Err(FsError::IsADirectory);
+        }
+        if node.is_protected() {
+            return Err(FsError::NotPermitted);
+        }
+        permissions::check(node, WRITE)?;
+        // Appending to a file that hasn't been fetched yet would lose it
+        let content = if append {
+            let mut bytes = node.bytes().ok_or(FsError::Io)?;
+            bytes.extend_from_slice(content);
+            bytes
+        } else {
+            content.to_vec()
+        };
+        filesystem.reserve(node.stored_size(), content.len())?;
+
+        if let Some(Node::File {
+            content: old,
+            url,
+            times,
+            ..
+        }) = filesystem.get_mut(ino)
+        {
+            *old = Content::from_bytes(content);
+            *url = None;
+            times.modified();
+        }
+        return Ok(());
+    }
+
+    // A dangling link creates the file it points at
+    let path = canonicalize(&filesystem, &path)?;
+    let (parent, file_name) = parent_dir(&filesystem, &path)?;
+    permissions::check(filesystem.get(parent).unwrap(), WRITE | EXECUTE)?;
+    filesystem.reserve(0, content.len())?;
+
+    let owner = permissions::current_user();
+    filesystem.create(
+        parent,
+        file_name,
+        Node::File {
+            content: Content::from_bytes(content.to_vec()),
+            permissions: 0o666 & !permissions::current_umask(),
+            owner: owner.clone(),
+            group: owner,
+            protected: false,
+            times: Timestamps::now(),
+            url: None,
+        },
+    );
+    Ok(())
+}
+
+// An entry as an archive describes it, for tar to recreate
+pub enum Entry {
+    Directory,
+    File(Vec<u8>),
+    Symlink(String),
+    // Another name for a file created earlier, given as its path
+    HardLink(String),
+}
+
+// Creates `name` with an exact mode and mtime, replacing whatever was there.
+// An existing directory is kept and only takes the new mode and mtime, and
+// only if the current user owns it.
+pub fn install(name: &str, entry: Entry, mode: u16, mtime: f64) -> Result<(), FsError> {
+    let mut filesystem = FILESYSTEM.lock().unwrap();
+    let current_path = CURRENT_PATH.lock().unwrap();
+    let path = normalize_path(name, &current_path);
+    let (parent, file_name) = parent_dir(&filesystem, &path)?;
+    let dir = filesystem.get(parent).unwrap();
+    permissions::check(dir, WRITE | EXECUTE)?;
+
+    let mut freed = 0;
+    if let Some(ino) = filesystem.entry(parent, file_name) {
+        let node = filesystem.get(ino).unwrap();
+        if let (Entry::Directory, Node::Directory { .. }) = (&entry, node) {
+            if node.get_owner() == permissions::current_user() || permissions::is_root() {
+                let node = filesystem.get_mut(ino).unwrap();
+                node.set_permissions(mode);
+                node.times_mut().mtime = mtime;
+            }
+            return Ok(());
+        }
+        if let Node::Directory { .. } = node {
+            return Err(FsError::IsADirectory);
+        }
+        if node.is_protected() {
+            return Err(FsError::NotPermitted);
+        }
+        permissions::check_unlink(dir, node)?;
+        if filesystem.link_count(ino) == 1 {
+            freed = node.stored_size();
+        }
+    }
+
+    let owner = permissions::current_user();
+    let now = system::timestamp();
+    let times = Timestamps {
+        mtime,
+        ctime: now,
+        atime: now,
+    };
+    let node = match entry {
+        Entry::Directory => Node::Directory {
+            children: HashMap::new(),
+            permissions: mode,
+            owner: owner.clone(),
+            group: owner,
+            protected: false,
+            times,
+        },
+        Entry::File(bytes) => Node::File {
+            content: Content::from_bytes(bytes),
+            permissions: mode,
+            owner: owner.clone(),
+            group: owner,
+            protected: false,
+            times,
+            url: None,
+        },
+        Entry::Symlink(target) => Node::Symlink {
+            target,
+            owner: owner.clone(),
+            group: owner,
+            times,
+        },
+        Entry::HardLink(target) => {
+            let source = resolve_ino(&filesystem, &normalize_path(&target, &current_path), false)?;
+            if PROC_INOS.contains(&source) {
+                return Err(FsError::CrossDevice);
+            }
+            match filesystem.get(source) {
+                Some(Node::Directory { .. }) => return Err(FsError::NotPermitted),
+                Some(node) if node.is_protected() => return Err(FsError::NotPermitted),
+                _ => {}
+            }
+            filesystem.link(parent, file_name, source);
+            return Ok(());
+        }
+    };
+
+    filesystem.reserve(freed, node.stored_size())?;
+    filesystem.unlink(parent, file_name);
+    filesystem.create(parent, file_name, node);
+    Ok(())
+}
+
+// Removes a file or link the way `rm` without -r would
+pub fn remove_file(name: &str) -> Result<(), FsError> {
+    let mut filesystem = FILESYSTEM.lock().unwrap();
+    let current_path = CURRENT_PATH.lock().unwrap();
+    let path = normalize_path(name, &current_path);
+    let (parent, file_name) = parent_dir(&filesystem, &path)?;
+    let node = child(&filesystem, parent, file_name).ok_or(FsError::NotFound)?;
+
+    if let Node::Directory { .. } = node {
+        return Err(FsError::IsADirectory);
+    }
+    if node.is_protected() {
+        return Err(FsError::NotPermitted);
+    }
+    permissions::check_unlink(filesystem.get(parent).unwrap(), node)?;
+
+    filesystem.unlink(parent, file_name);
+    Ok(())
+}
+
+pub fn cat(args: &[&str], stdin: Option<&[u8]>) -> Output {
+    // Plain `cat` passes its input along, like `cat < file`
+    let args = match (args, stdin) {
+        ([], Some(_)) => &["-"][..],
+        ([], None) => return Output::from(stream::error("cat: missing file operand")),
+        _ => args,
+    };
+
+    let mut output = Output::default();
+
+    for &filename in args {
+        let content = match filename {
+            "-" => Ok(stdin.unwrap_or_default().to_vec()),
+            _ => read_bytes(filename),
+        };
+        match content {
+            // Raw bytes would only garble the terminal
+            Ok(_) if filename != "-" && stream::is_terminal() && is_binary(filename) => {
+                output.line(stream::error(format!(
+                    "cat: {}: binary file not shown, try xxd or hexdump -C",
+                    filename
+                )));
+            }
+            Ok(bytes) => output.bytes(bytes),
+            Err(e) => output.line(stream::error(format!("cat: {}: {}", filename, e))),
+        }
+    }
+
+    output
+}
+
+// Resolves the directory an entry named by `path` lives in. Creating or
+// removing the entry additionally needs write access, checked by the caller.
+// Nobody adds or removes entries in /proc, not even root.
+fn parent_dir<'a>(
+    filesystem: &Filesystem,
+    path: &'a [String],
+) -> Result<(Ino, &'a String), FsError> {
+    let (name, parent_path) = path.split_last().ok_or(FsError::NotPermitted)?;
+    let parent = resolve_ino(filesystem, parent_path, true)?;
+    match filesystem.get(parent) {
+        _ if PROC_INOS.contains(&parent) => Err(FsError::NotPermitted),
+        Some(Node::Directory { .. }) => Ok((parent, name)),
+        _ => Err(FsError::NotADirectory),
+    }
+}
+
+fn child<'a>(filesystem: &'a Filesystem, dir: Ino, name: &str) -> Option<&'a Node> {
+    filesystem.get(filesystem.entry(dir, name)?)
+}
+
+pub fn mkdir(args: &[&str]) -> String {
+    if args.is_empty() {
+        return stream::error("mkdir: missing operand");
+    }
+
+    let mut filesystem = FILESYSTEM.lock().unwrap();
+    let current_path = CURRENT_PATH.lock().unwrap();
+    let current_user = permissions::current_user();
+
+    for &dirname in args {
+        let dir_path = normalize_path(dirname, &current_path);
+
+        if dir_path.is_empty() {
+            return stream::error("mkdir: cannot create directory '/': File exists");
+        }
+
+        let (parent, dir_name) = match parent_dir(&filesystem, &dir_path) {
+            Ok(found) => found,
+            Err(e) => {
+                return stream::error(format!(
+                    "mkdir: cannot create directory '{}': {}",
+                    dirname, e
+                ))
+            }
+        };
+
+        if child(&filesystem, parent, dir_name).is_some() {
+            return stream::error(format!(
+                "mkdir: cannot create directory '{}': File exists",
+                dirname
+            ));
+        }
+
+        if let Err(e) = permissions::check(filesystem.get(parent).unwrap(), WRITE | EXECUTE)
+            .and_then(|_| filesystem.reserve(0, 4096))
+        {
+            return stream::error(format!(
+                "mkdir: cannot create directory '{}': {}",
+                dirname, e
+            ));
+        }
+
+        filesystem.create(
+            parent,
+            dir_name,
+            Node::Directory {
+                permissions: 0o777 & !permissions::current_umask(),
+                owner: current_user.clone(),
+                group: current_user.clone(),
+                protected: false,
+                times: Timestamps::now(),
+                children: HashMap::new(),
+            },
+        );
+    }
+
+    String::new()
+}
+
+pub fn touch(args: &[&str]) -> String {
+    if args.is_empty() {
+        return stream::error("touch: missing file operand");
+    }
+
+    let mut filesystem = FILESYSTEM.lock().unwrap();
+    let current_path = CURRENT_PATH.lock().unwrap();
+    let current_user = permissions::current_user();
+
+    for &filename in args {
+        let file_path = normalize_path(filename, &current_path);
+        // Touching a link updates the file it points at
+        let file_path = resolve(&filesystem, &file_path, true).unwrap_or(file_path);
+
+        if file_path.is_empty() {
+            continue;
+        }
+
+        let (parent, file_name) = match parent_dir(&filesystem, &file_path) {
+            Ok(found) => found,
+            Err(e) => return stream::error(format!("touch: cannot touch '{}': {}", filename, e)),
+        };
+
+        if let Some(ino) = filesystem.entry(parent, file_name) {
+            let node = filesystem.get_mut(ino).unwrap();
+            if node.is_protected() {
+                return stream::error(format!(
+                    "touch: cannot touch '{}': {}",
+                    filename,
+                    FsError::NotPermitted
+                ));
+            }
+            if let Err(e) = permissions::check(node, WRITE) {
+                return stream::error(format!("touch: cannot touch '{}': {}", filename, e));
+            }
+            *node.times_mut() = Timestamps::now();
+            continue;
+        }
+
+        if let Err(e) = permissions::check(filesystem.get(parent).unwrap(), WRITE | EXECUTE)
+            .and_then(|_| filesystem.reserve(0, 0))
+        {
+            return stream::error(format!("touch: cannot touch '{}': {}", filename, e));
+        }
+
+        filesystem.create(
+            parent,
+            file_name,
+            Node::File {
+                content: Content::Text(String::new()),
+                permissions: 0o666 & !permissions::current_umask(),
+                owner: current_user.clone(),
+                group: current_user.clone(),
+                protected: false,
+                times: Timestamps::now(),
+                url: None,
+            },
+        );
+    }
+
+    String::new()
+}
+
+// rm -r needs to empty every directory below, so each of them has to be
+// writable and nothing inside may be protected
+fn check_remove_tree(filesystem: &Filesystem, node: &Node) -> Result<(), FsError> {
+    if node.is_protected() {
+        return Err(FsError::NotPermitted);
+    }
+
+    if let Node::Directory { children, .. } = node {
+        if !children.is_empty() {
+            permissions::check(node, READ | WRITE | EXECUTE)?;
+        }
+        for child in children.values().filter_map(|ino| filesystem.get(*ino)) {
+            check_remove_tree(filesystem, child)?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn rm(args: &[&str]) -> String {
+    if args.is_empty() {
+        return stream::error("rm: missing operand");
+    }
+
+    let mut filesystem = FILESYSTEM.lock().unwrap();
+    let current_path = CURRENT_PATH.lock().unwrap();
+
+    let mut recursive = false;
+    let mut force = false;
+    let mut files = Vec::new();
+
+    for &arg in args {
+        if arg.starts_with('-') {
+            for c in arg.chars().skip(1) {
+                match c {
+                    'r' | 'R' => recursive = true,
+                    'f' => force = true,
+                    _ => return stream::error(format!("rm: invalid option -- '{}'", c)),
+                }
+            }
+        } else {
+            files.push(arg);
+        }
+    }
+
+    for filename in files {
+        let file_path = normalize_path(filename, &current_path);
+
+        if file_path.is_empty() {
+            if !force {
+                return stream::error("rm: cannot remove '/': Permission denied");
+            }
+            continue;
+        }
+
+        let (parent, file_name) = match parent_dir(&filesystem, &file_path) {
+            Ok(found) => found,
+            Err(e) => {
+                if !force {
+                    return stream::error(format!("rm: cannot remove '{}': {}", filename, e));
+                }
+                continue;
+            }
+        };
+
+        let node = match child(&filesystem, parent, file_name) {
+            Some(node) => node,
+            None => {
+                if !force {
+                    return stream::error(format!(
+                        "rm: cannot remove '{}': No such file or directory",
+                        filename
+                    ));
+                }
+                continue;
+            }
+        };
+
+        if node.is_protected() {
+            return stream::error(format!(
+                "rm: cannot remove '{}': Operation not permitted (protected system file)",
+                filename
+            ));
+        }
+
+        if let Node::Directory { .. } = node {
+            if !recursive {
+                if !force {
+                    return stream::error(format!(
+                        "rm: cannot remove '{}': Is a directory",
+                        filename
+                    ));
+                }
+                continue;
+            }
+
+            if let Err(e) = check_remove_tree(&filesystem, node) {
+                return stream::error(format!("rm: cannot remove '{}': {}", filename, e));
+            }
+        }
+
+        if let Err(e) = permissions::check_unlink(filesystem.get(parent).unwrap(), node) {
+            return stream::error(format!("rm: cannot remove '{}': {}", filename, e));
+        }
+
+        filesystem.unlink(parent, file_name);
+    }
+
+    String::new()
+}
+
+// An entry reached by `walk_tree`, `path` is relative to where the walk started
+pub struct Visit<'a> {
+    pub path: &'a [String],
+    pub node: &'a Node,
+    pub depth: usize,
+    pub is_last: bool,
+}
+
+// Depth-first walk below `start` in name order, shared by tree and find.
+// Links are never followed and only searchable directories are entered.
+// `visit` returns whether to descend into the entry.
+pub fn walk_tree(filesystem: &Filesystem, start: Ino, visit: &mut dyn FnMut(&Visit) -> bool) {
+    fn descend(
+        filesystem: &Filesystem,
+        dir: Ino,
+        path: &mut Vec<String>,
+        visit: &mut dyn FnMut(&Visit) -> bool,
+    ) {
+        let children = match filesystem.get(dir) {
+            Some(node @ Node::Directory { children, .. })
+                if permissions::can_access(node, READ | EXECUTE) =>
+            {
+                children
+            }
+            _ => return,
+        };
+
+        let mut entries: Vec<_> = children.iter().collect();
+        entries.sort();
+
+        for (i, (name, ino)) in entries.iter().enumerate() {
+            let node = match filesystem.get(**ino) {
+                Some(node) => node,
+                None => continue,
+            };
+
+            path.push(name.to_string());
+            let enter = visit(&Visit {
+                path,
+                node,
+                depth: path.len(),
+                is_last: i == entries.len() - 1,
+            });
+            if enter {
+                descend(filesystem, **ino, path, visit);
+            }
+            path.pop();
+        }
+    }
+
+    descend(filesystem, start, &mut Vec::new(), visit);
+}
+
+pub fn tree(args: &[&str]) -> String {
+    let filesystem = FILESYSTEM.lock().unwrap();
+    let current_path = CURRENT_PATH.lock().unwrap();
+
+    let start_path = if args.is_empty() {
+        current_path.clone()
+    } else {
+        normalize_path(args[0], &current_path)
+    };
+
+    let start = match resolve_ino(&filesystem, &start_path, true) {
+        Ok(ino) => ino,
+        Err(e) => return stream::error(format!("tree: {}", e)),
+    };
+
+    let tree_name = if start_path.is_empty() {
+        "/".to_string()
+    } else {
+        start_path.last().unwrap_or(&"/".to_string()).clone()
+    };
+
+    let mut output = format!("{}\n", tree_name);
+    // Whether each ancestor was the last entry of its directory
+    let mut last_flags: Vec<bool> = Vec::new();
+
+    walk_tree(&filesystem, start, &mut |entry| {
+        last_flags.truncate(entry.depth - 1);
+        let prefix: String = last_flags
+            .iter()
+            .map(|&last| if last { "    " } else { "│   " })
+            .collect();
+        let connector = if entry.is_last {
+            "└── "
+        } else {
+            "├── "
+        };
+
+        let name = entry.path.last().unwrap();
+        let display_name = match entry.node {
+            Node::Directory { .. } if !permissions::can_access(entry.node, READ | EXECUTE) => {
+                format!("{}/  [error opening dir]", name)
+            }
+            Node::Directory { .. } => format!("{}/", name),
+            Node::File { .. } | Node::Device { .. } => name.to_string(),
+            Node::Symlink { target, .. } => format!("{} -> {}", name, target),
+        };
+
+        output.push_str(&format!("{}{}{}\n", prefix, connector, display_name));
+        last_flags.push(entry.is_last);
+        true
+    });
+
+    output
+}
+
+// Sizes the way `-h` prints them, rounded up: 4.0K, 12K, 1.5M
+pub fn human_size(bytes: usize) -> String {
+    if bytes < 1024 {
+        return bytes.to_string();
+    }
+
+    let mut value = bytes as f64 / 1024.0;
+    let mut units = ['K', 'M', 'G', 'T'].iter().peekable();
+    while value >= 1024.0 && units.len() > 1 {
+        value /= 1024.0;
+        units.next();
+    }
+    let unit = units.next().unwrap();
+
+    let tenths = (value * 10.0).ceil() / 10.0;
+    if tenths < 10.0 {
+        format!("{:.1}{}", tenths, unit)
+    } else {
+        format!("{}{}", value.ceil(), unit)
+    }
+}
+
+fn blocks(bytes: usize) -> usize {
+    bytes.div_ceil(1024)
+}
+
+struct DuOptions {
+    all: bool,
+    human: bool,
+    max_depth: Option<usize>,
+}
+
+// Adds up the 1K blocks of `ino` and everything below it. Entries within
+// the depth limit get a line after their contents, hard links count once.
+fn disk_usage(
+    filesystem: &Filesystem,
+    ino: Ino,
+    name: &str,
+    depth: usize,
+    options: &DuOptions,
+    seen: &mut HashSet<Ino>,
+    output: &mut Vec<String>,
+) -> usize {
+    let node = match filesystem.get(ino) {
+        Some(node) if seen.insert(ino) => node,
+        _ => return 0,
+    };
+    // Like df, only what the filesystem itself stores takes up space
+    let mut total = match PROC_INOS.contains(&ino) {
+        true => 0,
+        false => blocks(node.stored_size()),
+    };
+
+    if let Node::Directory { children, .. } = node {
+        if permissions::can_access(node, READ | EXECUTE) {
+            let mut entries: Vec<_> = children.iter().collect();
+            entries.sort();
+            for (child, child_ino) in entries {
+                let child_name = format!("{}/{}", name.trim_end_matches('/'), child);
+                total += disk_usage(
+                    filesystem,
+                    *child_ino,
+                    &child_name,
+                    depth + 1,
+                    options,
+                    seen,
+                    output,
+                );
+            }
+        } else {
+            output.push(stream::error(format!(
+                "du: cannot read directory '{}': {}",
+                name,
+                FsError::PermissionDenied
+            )));
+        }
+    }
+
+    let within_depth = options.max_depth.is_none_or(|max| depth <= max);
+    let is_dir = matches!(node, Node::Directory { .. });
+    if within_depth && (is_dir || options.all || depth == 0) {
+        let size = if options.human {
+            human_size(total * 1024)
+        } else {
+            total.to_string()
+        };
+        output.push(format!("{}\t{}", size, name));
+    }
+    total
+}
+
+pub fn du(args: &[&str]) -> String {
+    let parsed = match text::parse("du", args, "ash", "d") {
+        Ok(parsed) => parsed,
+        Err(e) => return e,
+    };
+    if parsed.has('s') && parsed.has('a') {
+        return stream::error("du: cannot both summarize and show all entries");
+    }
+    let max_depth = match parsed.value('d') {
+        _ if parsed.has('s') => Some(0),
+        Some(depth) => match depth.parse() {
+            Ok(depth) => Some(depth),
+            Err(_) => return stream::error(format!("du: invalid maximum depth '{}'", depth)),
+        },
+        None => None,
+    };
+    let options = DuOptions {
+        all: parsed.has('a'),
+        human: parsed.has('h'),
+        max_depth,
+    };
+
+    let filesystem = FILESYSTEM.lock().unwrap();
+    let current_path = CURRENT_PATH.lock().unwrap();
+    let operands = if parsed.operands.is_empty() {
+        vec!["."]
+    } else {
+        parsed.operands
+    };
+
+    let mut output = Vec::new();
+    let mut seen = HashSet::new();
+    for name in operands {
+        match resolve_ino(&filesystem, &normalize_path(name, &current_path), false) {
+            Ok(ino) => {
+                disk_usage(&filesystem, ino, name, 0, &options, &mut seen, &mut output);
+            }
+            Err(e) => output.push(stream::error(format!(
+                "du: cannot access '{}': {}",
+                name, e
+            ))),
+        }
+    }
+    output.join("\n")
+}
+
+// There is a single filesystem, whatever the operands name
+pub fn df(args: &[&str]) -> String {
+    let parsed = match text::parse("df", args, "h", "") {
+        Ok(parsed) => parsed,
+        Err(e) => return e,
+    };
+
+    let filesystem = FILESYSTEM.lock().unwrap();
+    let current_path = CURRENT_PATH.lock().unwrap();
+    let mut errors = Vec::new();
+    let mut rows = if parsed.operands.is_empty() { 1 } else { 0 };
+    for name in &parsed.operands {
+        match resolve_ino(&filesystem, &normalize_path(name, &current_path), true) {
+            Ok(_) => rows += 1,
+            Err(e) => errors.push(stream::error(format!("df: {}: {}", name, e))),
+        }
+    }
+    if rows == 0 {
+        return errors.join("\n");
+    }
+
+    let used = filesystem.blocks_used();
+    let available = blocks(QUOTA).saturating_sub(used);
+    let percent = format!("{}%", (used * 100).div_ceil(blocks(QUOTA)));
+
+    let (header, row) = if parsed.has('h') {
+        (
+            "Filesystem      Size  Used Avail Use% Mounted on".to_string(),
+            format!(
+                "{:<15} {:>4} {:>5} {:>5} {:>4} /",
+                "vfs",
+                human_size(QUOTA),
+                human_size(used * 1024),
+                human_size(available * 1024),
+                percent
+            ),
+        )
+    } else {
+        (
+            "Filesystem     1K-blocks  Used Available Use% Mounted on".to_string(),
+            format!(
+                "{:<14} {:>9} {:>5} {:>9} {:>4} /",
+                "vfs",
+                blocks(QUOTA),
+                used,
+                available,
+                percent
+            ),
+        )
+    };
+
+    let mut output = errors;
+    output.push(header);
+    output.extend(std::iter::repeat_n(row, rows));
+    output.join("\n")
+}
+
+pub fn ln(args: &[&str]) -> String {
+    let symbolic = args.first() == Some(&"-s");
+    let operands = if symbolic { &args[1..] } else { args };
+
+    if operands.len() < 2 {
+        return stream::error("ln: missing file operand");
+    }
+
+    let mut filesystem = FILESYSTEM.lock().unwrap();
+    let current_path = CURRENT_PATH.lock().unwrap();
+    let current_user = permissions::current_user();
+
+    let (target, link_name) = (operands[0], operands[1]);
+    let kind = if symbolic { "symbolic" } else { "hard" };
+
+    // As with cp, linking into an existing directory keeps the source name
+    let mut link_path = normalize_path(link_name, &current_path);
+    if let Ok(Node::Directory { .. }) = follow(&filesystem, &link_path) {
+        if let Some(name) = target.trim_end_matches('/').rsplit('/').next() {
+            link_path.push(name.to_string());
+        }
+    }
+
+    if link_path.is_empty() {
+        return stream::error(format!(
+            "ln: failed to create {} link '/': File exists",
+            kind
+        ));
+    }
+
+    let (parent, file_name) = match parent_dir(&filesystem, &link_path) {
+        Ok(found) => found,
+        Err(e) => {
+            return stream::error(format!(
+                "ln: failed to create {} link '{}': {}",
+                kind, link_name, e
+            ))
+        }
+    };
+
+    if child(&filesystem, parent, file_name).is_some() {
+        return stream::error(format!(
+            "ln: failed to create {} link '{}': File exists",
+            kind, link_name
+        ));
+    }
+
+    if let Err(e) = permissions::check(filesystem.get(parent).unwrap(), WRITE | EXECUTE) {
+        return stream::error(format!(
+            "ln: failed to create {} link '{}': {}",
+            kind, link_name, e
+        ));
+    }
+    if symbolic {
+        // Unlike a hard link, a symbolic link stores its target
+        if let Err(e) = filesystem.reserve(0, target.len()) {
+            return stream::error(format!(
+                "ln: failed to create {} link '{}': {}",
+                kind, link_name, e
+            ));
+        }
+        filesystem.create(
+            parent,
+            file_name,
+            Node::Symlink {
+                target: target.to_string(),
+                owner: current_user.clone(),
+                group: current_user,
+                times: Timestamps::now(),
+            },
+        );
+        return String::new();
+    }
+
+    // Hard links name the entry itself, a symlink gets a second name too
+    let source = match resolve_ino(&filesystem, &normalize_path(target, &current_path), false) {
+        Ok(ino) => ino,
+        Err(e) => return stream::error(format!("ln: failed to access '{}': {}", target, e)),
+    };
+    let node = filesystem.get(source).unwrap();
+
+    if let Node::Directory { .. } = node {
+        return stream::error(format!(
+            "ln: {}: hard link not allowed for directory",
+            target
+        ));
+    }
+
+    if PROC_INOS.contains(&source) {
+        return stream::error(format!(
+            "ln: failed to create hard link '{}' => '{}': {}",
+            link_name,
+            target,
+            FsError::CrossDevice
+        ));
+    }
+
+    // Like protected_hardlinks, only owners may link files they cannot write
+    if node.is_protected()
+        || (!permissions::is_root()
+            && node.get_owner() != current_user
+            && !permissions::can_access(node, READ | WRITE))
+    {
+        return stream::error(format!(
+            "ln: failed to create hard link '{}' => '{}': {}",
+            link_name,
+            target,
+            FsError::NotPermitted
+        ));
+    }
+
+    filesystem.link(parent, file_name, source);
+    String::new()
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum TransferKind {
+    Copy,
+    Move,
+}
+
+impl TransferKind {
+    fn command(self) -> &'static str {
+        match self {
+            TransferKind::Copy => "cp",
+            TransferKind::Move => "mv",
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct TransferOptions {
+    kind: TransferKind,
+    recursive: bool,
+    interactive: bool,
+    no_clobber: bool,
+}
+
+// A single cp or mv step. Copying a directory queues one transfer per child,
+// so `-i` can ask about every file that would be overwritten.
+struct Transfer {
+    source: Vec<String>,
+    target: Vec<String>,
+    source_name: String,
+    target_name: String,
+    overwrite: Option<bool>,
+}
+
+enum Step {
+    Done,
+    Confirm,
+}
+
+struct PendingTransfer {
+    options: TransferOptions,
+    queue: VecDeque<Transfer>,
+}
+
+lazy_static! {
+    static ref PENDING_TRANSFER: Mutex<Option<PendingTransfer>> = Mutex::new(None);
+}
+
+pub fn pending_prompt() -> Option<String> {
+    let pending = PENDING_TRANSFER.lock().unwrap();
+    let pending = pending.as_ref()?;
+    let transfer = pending.queue.front()?;
+    Some(format!(
+        "{}: overwrite '{}'? ",
+        pending.options.kind.command(),
+        transfer.target_name
+    ))
+}
+
+pub fn answer_prompt(answer: &str) -> String {
+    let mut pending = match PENDING_TRANSFER.lock().unwrap().take() {
+        Some(pending) => pending,
+        None => return String::new(),
+    };
+
+    let confirmed = matches!(answer.trim(), "y" | "Y" | "yes" | "Yes");
+    if let Some(transfer) = pending.queue.front_mut() {
+        transfer.overwrite = Some(confirmed);
+    }
+
+    run_transfers(pending.options, pending.queue)
+}
+
+fn run_transfers(options: TransferOptions, mut queue: VecDeque<Transfer>) -> String {
+    let mut filesystem = FILESYSTEM.lock().unwrap();
+    let mut errors = Vec::new();
+
+    while let Some(transfer) = queue.pop_front() {
+        let step = match options.kind {
+            TransferKind::Copy => copy_step(&mut filesystem, &options, &transfer, &mut queue),
+            TransferKind::Move => move_step(&mut filesystem, &options, &transfer),
         };
 
-        if !parent.contains_key(file_name) {
-            parent.insert(
-                file_name.clone(),
-                Node::File {
-                    content: String::new(),
-                    permissions: 0o644,
-                    owner: current_user.clone(),
-                    protected: false,
-                },
-            );
+        match step {
+            Ok(Step::Done) => {}
+            Ok(Step::Confirm) => {
+                queue.push_front(transfer);
+                *PENDING_TRANSFER.lock().unwrap() = Some(PendingTransfer { options, queue });
+                break;
+            }
+            Err(e) => errors.push(e),
         }
     }
 
-    String::new()
+    errors.join("\n")
 }
 
-pub fn rm(args: &[&str]) -> String {
-    if args.is_empty() {
-        return "rm: missing operand".into();
+// Decides whether an existing target may be replaced under -n and -i
+fn may_overwrite(options: &TransferOptions, transfer: &Transfer) -> Option<bool> {
+    if options.no_clobber {
+        Some(false)
+    } else if options.interactive {
+        transfer.overwrite
+    } else {
+        Some(true)
     }
+}
 
-    let mut filesystem = FILESYSTEM.lock().unwrap();
-    let current_path = CURRENT_PATH.lock().unwrap();
-    let current_user = &*CURRENT_USER;
+fn copy_of(node: &Node) -> Node {
+    let owner = permissions::current_user();
+    let umask = permissions::current_umask();
 
-    let mut recursive = false;
-    let mut force = false;
-    let mut files = Vec::new();
+    match node {
+        Node::File {
+            content,
+            permissions,
+            url,
+            ..
+        } => Node::File {
+            content: content.clone(),
+            permissions: permissions & 0o777 & !umask,
+            owner: owner.clone(),
+            group: owner,
+            protected: false,
+            times: Timestamps::now(),
+            url: url.clone(),
+        },
+        Node::Directory { permissions, .. } => Node::Directory {
+            children: HashMap::new(),
+            permissions: permissions & 0o1777 & !umask,
+            owner: owner.clone(),
+            group: owner,
+            protected: false,
+            times: Timestamps::now(),
+        },
+        Node::Symlink { target, .. } => Node::Symlink {
+            target: target.clone(),
+            owner: owner.clone(),
+            group: owner,
+            times: Timestamps::now(),
+        },
+        // Only root could make another device, the copy is what it reads
+        Node::Device {
+            device,
+            permissions,
+            ..
+        } => Node::File {
+            content: Content::from_bytes(device.read(devices::BLOCK)),
+            permissions: permissions & 0o777 & !umask,
+            owner: owner.clone(),
+            group: owner,
+            protected: false,
+            times: Timestamps::now(),
+            url: None,
+        },
+    }
+}
 
-    for &arg in args {
-        if arg.starts_with('-') {
-            for c in arg.chars().skip(1) {
-                match c {
-                    'r' | 'R' => recursive = true,
-                    'f' => force = true,
-                    _ => return format!("rm: invalid option -- '{}'", c),
+fn copy_step(
+    filesystem: &mut Filesystem,
+    options: &TransferOptions,
+    transfer: &Transfer,
+    queue: &mut VecDeque<Transfer>,
+) -> Result<Step, String> {
+    let source_name = &transfer.source_name;
+    let target_name = &transfer.target_name;
+
+    // Without -r a link is copied as the file it points at
+    let source_ino = resolve_ino(filesystem, &transfer.source, !options.recursive)
+        .map_err(|e| stream::error(format!("cp: cannot stat '{}': {}", source_name, e)))?;
+    let source = filesystem.get(source_ino).unwrap();
+    let is_dir = matches!(source, Node::Directory { .. });
+
+    // Writing to an existing link writes to the file it points at
+    let existing_ino = resolve_ino(filesystem, &transfer.target, true).ok();
+
+    if transfer.target == transfer.source || existing_ino == Some(source_ino) {
+        return Err(stream::error(format!(
+            "cp: '{}' and '{}' are the same file",
+            source_name, target_name
+        )));
+    }
+
+    if is_dir {
+        if !options.recursive {
+            return Err(stream::error(format!(
+                "cp: -r not specified; omitting directory '{}'",
+                source_name
+            )));
+        }
+        if transfer.target.starts_with(&transfer.source) {
+            return Err(stream::error(format!(
+                "cp: cannot copy a directory, '{}', into itself, '{}'",
+                source_name, target_name
+            )));
+        }
+        permissions::check(source, READ | EXECUTE)
+            .map_err(|e| stream::error(format!("cp: cannot access '{}': {}", source_name, e)))?;
+    } else {
+        permissions::check(source, READ).map_err(|e| {
+            stream::error(format!(
+                "cp: cannot open '{}' for reading: {}",
+                source_name, e
+            ))
+        })?;
+    }
+
+    let copy = copy_of(source);
+    let mut children: Vec<String> = match source {
+        Node::Directory { children, .. } => children.keys().cloned().collect(),
+        _ => Vec::new(),
+    };
+    let kind = if is_dir { "directory" } else { "regular file" };
+
+    match existing_ino.and_then(|ino| filesystem.get(ino)) {
+        Some(Node::Directory { .. }) if is_dir => {}
+        Some(_) if is_dir => {
+            return Err(stream::error(format!(
+                "cp: cannot overwrite non-directory '{}' with directory '{}'",
+                target_name, source_name
+            )))
+        }
+        Some(Node::Directory { .. }) => {
+            return Err(stream::error(format!(
+                "cp: cannot overwrite directory '{}' with non-directory",
+                target_name
+            )))
+        }
+        Some(existing) => {
+            match may_overwrite(options, transfer) {
+                Some(true) => {}
+                Some(false) => return Ok(Step::Done),
+                None => return Ok(Step::Confirm),
+            }
+
+            // Copying onto a device writes to it, like it would on Linux
+            if let Node::Device { device, .. } = existing {
+                permissions::check(existing, WRITE).map_err(|e| {
+                    stream::error(format!(
+                        "cp: cannot create {} '{}': {}",
+                        kind, target_name, e
+                    ))
+                })?;
+                device.write(&copy.bytes().unwrap_or_default());
+                return Ok(Step::Done);
+            }
+
+            if existing.is_protected() {
+                return Err(stream::error(format!(
+                    "cp: cannot create {} '{}': {}",
+                    kind,
+                    target_name,
+                    FsError::NotPermitted
+                )));
+            }
+            permissions::check(existing, WRITE)
+                .and_then(|_| filesystem.reserve(existing.stored_size(), copy.stored_size()))
+                .map_err(|e| {
+                    stream::error(format!(
+                        "cp: cannot create {} '{}': {}",
+                        kind, target_name, e
+                    ))
+                })?;
+
+            // Overwriting keeps the existing file's owner and mode
+            let existing = filesystem.get_mut(existing_ino.unwrap()).unwrap();
+            match (existing, copy) {
+                (
+                    Node::File {
+                        content,
+                        url,
+                        times,
+                        ..
+                    },
+                    Node::File {
+                        content: new_content,
+                        url: new_url,
+                        ..
+                    },
+                ) => {
+                    *content = new_content;
+                    *url = new_url;
+                    times.modified();
                 }
+                (existing, copy) => *existing = copy,
             }
-        } else {
-            files.push(arg);
+        }
+        None => {
+            if let Ok(Node::Symlink { .. }) = lookup(filesystem, &transfer.target) {
+                return Err(stream::error(format!(
+                    "cp: not writing through dangling symlink '{}'",
+                    target_name
+                )));
+            }
+
+            let (parent, name) = parent_dir(filesystem, &transfer.target).map_err(|e| {
+                stream::error(format!(
+                    "cp: cannot create {} '{}': {}",
+                    kind, target_name, e
+                ))
+            })?;
+            permissions::check(filesystem.get(parent).unwrap(), WRITE | EXECUTE)
+                .and_then(|_| filesystem.reserve(0, copy.stored_size()))
+                .map_err(|e| {
+                    stream::error(format!(
+                        "cp: cannot create {} '{}': {}",
+                        kind, target_name, e
+                    ))
+                })?;
+            filesystem.create(parent, name, copy);
         }
     }
 
-    for filename in files {
-        let file_path = normalize_path(filename, &current_path);
+    children.sort();
+    for name in children.into_iter().rev() {
+        queue.push_front(Transfer {
+            source: [transfer.source.as_slice(), std::slice::from_ref(&name)].concat(),
+            target: [transfer.target.as_slice(), std::slice::from_ref(&name)].concat(),
+            source_name: format!("{}/{}", source_name.trim_end_matches('/'), name),
+            target_name: format!("{}/{}", target_name.trim_end_matches('/'), name),
+            overwrite: None,
+        });
+    }
 
-        if file_path.is_empty() {
-            if !force {
-                return "rm: cannot remove '/': Permission denied".into();
-            }
-            continue;
+    Ok(Step::Done)
+}
+
+fn contains_protected(filesystem: &Filesystem, node: &Node) -> bool {
+    match node {
+        Node::Directory { children, .. } => {
+            node.is_protected()
+                || children
+                    .values()
+                    .filter_map(|ino| filesystem.get(*ino))
+                    .any(|child| contains_protected(filesystem, child))
         }
+        _ => node.is_protected(),
+    }
+}
 
-        let parent_path = &file_path[..file_path.len() - 1];
-        let file_name = &file_path[file_path.len() - 1];
+fn move_step(
+    filesystem: &mut Filesystem,
+    options: &TransferOptions,
+    transfer: &Transfer,
+) -> Result<Step, String> {
+    let source_name = &transfer.source_name;
+    let target_name = &transfer.target_name;
+    let cannot_move = |e: FsError| {
+        stream::error(format!(
+            "mv: cannot move '{}' to '{}': {}",
+            source_name, target_name, e
+        ))
+    };
 
-        let parent = match get_node_at_path_mut(&mut filesystem, parent_path) {
-            Some(Node::Directory { children, .. }) => children,
-            Some(_) => {
-                if !force {
-                    return format!("rm: cannot remove '{}': Not a directory", filename);
-                }
-                continue;
+    let source_ino = resolve_ino(filesystem, &transfer.source, false)
+        .map_err(|e| stream::error(format!("mv: cannot stat '{}': {}", source_name, e)))?;
+    let source = filesystem.get(source_ino).unwrap();
+    let is_dir = matches!(source, Node::Directory { .. });
+
+    let (source_entry, source_dir) = transfer
+        .source
+        .split_last()
+        .ok_or_else(|| cannot_move(FsError::NotPermitted))?;
+    let (target_entry, target_dir) = transfer
+        .target
+        .split_last()
+        .ok_or_else(|| cannot_move(FsError::NotPermitted))?;
+
+    // Compare physical paths, so links can't hide a move into the source
+    let source_dir = resolve(filesystem, source_dir, true).map_err(cannot_move)?;
+    let target_dir = resolve(filesystem, target_dir, true).map_err(cannot_move)?;
+    let source_path = [source_dir.as_slice(), std::slice::from_ref(source_entry)].concat();
+    let target_path = [target_dir.as_slice(), std::slice::from_ref(target_entry)].concat();
+    let source_dir = filesystem.ino_at(&source_dir).unwrap();
+    let target_dir = filesystem.ino_at(&target_dir).unwrap();
+
+    if target_path == source_path || filesystem.entry(target_dir, target_entry) == Some(source_ino)
+    {
+        return Err(stream::error(format!(
+            "mv: '{}' and '{}' are the same file",
+            source_name, target_name
+        )));
+    }
+    if target_path.starts_with(&source_path) {
+        return Err(stream::error(format!(
+            "mv: cannot move '{}' to a subdirectory of itself, '{}'",
+            source_name, target_name
+        )));
+    }
+
+    if contains_protected(filesystem, source)
+        || PROC_INOS.contains(&source_dir)
+        || PROC_INOS.contains(&target_dir)
+    {
+        return Err(cannot_move(FsError::NotPermitted));
+    }
+    let source_parent = filesystem.get(source_dir).unwrap();
+    permissions::check_unlink(source_parent, source).map_err(cannot_move)?;
+
+    let target_parent = match filesystem.get(target_dir) {
+        Some(parent @ Node::Directory { .. }) => parent,
+        _ => return Err(cannot_move(FsError::NotADirectory)),
+    };
+    permissions::check(target_parent, WRITE | EXECUTE).map_err(cannot_move)?;
+
+    if let Some(existing) = child(filesystem, target_dir, target_entry) {
+        match (is_dir, existing) {
+            (true, Node::Directory { children, .. }) if !children.is_empty() => {
+                return Err(stream::error(format!(
+                    "mv: cannot move '{}' to '{}': Directory not empty",
+                    source_name, target_name
+                )))
             }
-            None => {
-                if !force {
-                    return format!(
-                        "rm: cannot remove '{}': No such file or directory",
-                        filename
-                    );
-                }
-                continue;
+            (true, Node::Directory { .. }) => {}
+            (true, _) => {
+                return Err(stream::error(format!(
+                    "mv: cannot overwrite non-directory '{}' with directory '{}'",
+                    target_name, source_name
+                )))
             }
-        };
+            (false, Node::Directory { .. }) => {
+                return Err(stream::error(format!(
+                    "mv: cannot overwrite directory '{}' with non-directory",
+                    target_name
+                )))
+            }
+            _ => {}
+        }
 
-        match parent.get(file_name) {
-            Some(node) => {
-                if node.is_protected() {
-                    return format!(
-                        "rm: cannot remove '{}': Operation not permitted (protected system file)",
-                        filename
-                    );
-                }
+        match may_overwrite(options, transfer) {
+            Some(true) => {}
+            Some(false) => return Ok(Step::Done),
+            None => return Ok(Step::Confirm),
+        }
 
-                if node.get_owner() != current_user && current_user != "root" {
-                    return format!(
-                        "rm: cannot remove '{}': Permission denied (not owner)",
-                        filename
-                    );
-                }
+        if existing.is_protected() {
+            return Err(cannot_move(FsError::NotPermitted));
+        }
+        permissions::check_unlink(target_parent, existing).map_err(cannot_move)?;
+    }
 
-                match node {
-                    Node::Directory { .. } => {
-                        if !recursive {
-                            if !force {
-                                return format!("rm: cannot remove '{}': Is a directory", filename);
-                            }
-                            continue;
-                        }
-                        parent.remove(file_name);
+    // Renaming only moves the directory entry, the inode stays put
+    filesystem.link(target_dir, target_entry, source_ino);
+    filesystem.unlink(source_dir, source_entry);
+
+    Ok(Step::Done)
+}
+
+// Shared front end of cp and mv: parses flags and turns the operands into
+// one transfer per source, placed inside the target if it is a directory
+fn transfer(kind: TransferKind, args: &[&str]) -> String {
+    let cmd = kind.command();
+    let mut options = TransferOptions {
+        kind,
+        recursive: false,
+        interactive: false,
+        no_clobber: false,
+    };
+    let mut operands = Vec::new();
+
+    for &arg in args {
+        if arg.starts_with('-') && arg.len() > 1 {
+            for c in arg.chars().skip(1) {
+                match c {
+                    'r' | 'R' if kind == TransferKind::Copy => options.recursive = true,
+                    'i' => {
+                        options.interactive = true;
+                        options.no_clobber = false;
                     }
-                    Node::File { .. } | Node::Symlink { .. } => {
-                        parent.remove(file_name);
+                    'n' => {
+                        options.no_clobber = true;
+                        options.interactive = false;
                     }
+                    'f' => {
+                        options.interactive = false;
+                        options.no_clobber = false;
+                    }
+                    _ => return stream::error(format!("{}: invalid option -- '{}'", cmd, c)),
                 }
             }
-            None => {
-                if !force {
-                    return format!(
-                        "rm: cannot remove '{}': No such file or directory",
-                        filename
-                    );
+        } else {
+            operands.push(arg);
+        }
+    }
+
+    let (destination, sources) = match operands.split_last() {
+        None => return stream::error(format!("{}: missing file operand", cmd)),
+        Some((last, [])) => {
+            return stream::error(format!(
+                "{}: missing destination file operand after '{}'",
+                cmd, last
+            ))
+        }
+        Some((last, sources)) => (*last, sources),
+    };
+
+    let queue = {
+        let filesystem = FILESYSTEM.lock().unwrap();
+        let current_path = CURRENT_PATH.lock().unwrap();
+
+        let destination_path = normalize_path(destination, &current_path);
+        let into_directory = matches!(
+            follow(&filesystem, &destination_path),
+            Ok(Node::Directory { .. })
+        );
+
+        if sources.len() > 1 && !into_directory {
+            return stream::error(format!(
+                "{}: target '{}' is not a directory",
+                cmd, destination
+            ));
+        }
+
+        sources
+            .iter()
+            .map(|&source| {
+                let source_path = normalize_path(source, &current_path);
+                let name = source_path.last().cloned().unwrap_or_default();
+
+                let (target, target_name) = if into_directory && !name.is_empty() {
+                    (
+                        [destination_path.as_slice(), std::slice::from_ref(&name)].concat(),
+                        format!("{}/{}", destination.trim_end_matches('/'), name),
+                    )
+                } else {
+                    (destination_path.clone(), destination.to_string())
+                };
+
+                Transfer {
+                    source: source_path,
+                    target,
+                    source_name: source.to_string(),
+                    target_name,
+                    overwrite: None,
                 }
+            })
+            .collect()
+    };
+
+    run_transfers(options, queue)
+}
+
+pub fn cp(args: &[&str]) -> String {
+    transfer(TransferKind::Copy, args)
+}
+
+pub fn mv(args: &[&str]) -> String {
+    transfer(TransferKind::Move, args)
+}
+
+pub fn display_path(path: &[String]) -> String {
+    format!("/{}", path.join("/"))
+}
+
+// Keeps `..` in place for the resolver, unlike normalize_path which drops
+// it textually before any link is looked at
+fn unresolved_path(path: &str, current: &[String]) -> Vec<String> {
+    let start = if path.starts_with('/') { &[] } else { current };
+    start
+        .iter()
+        .cloned()
+        .chain(
+            path.split('/')
+                .filter(|s| !s.is_empty())
+                .map(str::to_string),
+        )
+        .collect()
+}
+
+pub fn readlink(args: &[&str]) -> String {
+    let filesystem = FILESYSTEM.lock().unwrap();
+    let current_path = CURRENT_PATH.lock().unwrap();
+
+    let mut canonical = false;
+    let mut files = Vec::new();
+
+    for &arg in args {
+        match arg {
+            "-f" => canonical = true,
+            _ if arg.starts_with('-') => {
+                return stream::error(format!("readlink: invalid option -- '{}'", &arg[1..]))
             }
+            _ => files.push(arg),
         }
     }
 
-    String::new()
+    if files.is_empty() {
+        return stream::error("readlink: missing operand");
+    }
+
+    // Like the real readlink, anything that isn't a link prints nothing
+    files
+        .iter()
+        .filter_map(|file| {
+            if canonical {
+                let path = unresolved_path(file, &current_path);
+                canonicalize(&filesystem, &path)
+                    .ok()
+                    .map(|p| display_path(&p))
+            } else {
+                match lookup(&filesystem, &normalize_path(file, &current_path)) {
+                    Ok(Node::Symlink { target, .. }) => Some(target.clone()),
+                    _ => None,
+                }
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
 }
 
-pub fn tree(args: &[&str]) -> String {
+pub fn realpath(args: &[&str]) -> String {
+    if args.is_empty() {
+        return stream::error("realpath: missing operand");
+    }
+
     let filesystem = FILESYSTEM.lock().unwrap();
     let current_path = CURRENT_PATH.lock().unwrap();
 
-    let start_path = if args.is_empty() {
-        current_path.clone()
-    } else {
-        normalize_path(args[0], &current_path)
-    };
+    args.iter()
+        .map(
+            |file| match canonicalize(&filesystem, &unresolved_path(file, &current_path)) {
+                Ok(path) => display_path(&path),
+                Err(e) => stream::error(format!("realpath: {}: {}", file, e)),
+            },
+        )
+        .collect::<Vec<_>>()
+        .join("\n")
+}
 
-    let start_node = match get_node_at_path(&filesystem, &start_path) {
-        Some(node) => node,
-        None => return "tree: No such file or directory".into(),
-    };
+// What `uname` reports, /proc/version is built from the same fields
+pub const SYSNAME: &str = "WASM";
+pub const NODENAME: &str = "wasm-host";
+pub const RELEASE: &str = "1.0.0";
+pub const KERNEL_VERSION: &str = "#1 SMP PREEMPT_DYNAMIC Mon Jan 1 12:00:00 UTC 2024";
+pub const MACHINE: &str = "wasm32";
+
+pub fn uname(args: &[&str]) -> String {
+    if args.is_empty() || args[0] == "-s" {
+        SYSNAME.to_string()
+    } else if args[0] == "-a" {
+        format!(
+            "{} {} {} {} {} GNU/Linux",
+            SYSNAME, NODENAME, RELEASE, KERNEL_VERSION, MACHINE
+        )
+    } else {
+        stream::error("uname: invalid option")
+    }
+}
 
-    fn build_tree(node: &Node, prefix: &str, _is_last: bool) -> String {
-        let mut output = String::new();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::CommandHandler;
+    use crate::testing;
 
-        if let Node::Directory { children, .. } = node {
-            let mut entries: Vec<_> = children.iter().collect();
-            entries.sort_by_key(|(name, _)| name.as_str());
+    fn exists(path: &str) -> bool {
+        let filesystem = FILESYSTEM.lock().unwrap();
+        lookup(&filesystem, &normalize_path(path, &[])).is_ok()
+    }
 
-            for (i, (name, child)) in entries.iter().enumerate() {
-                let is_last_child = i == entries.len() - 1;
-                let connector = if is_last_child {
-                    "└── "
-                } else {
-                    "├── "
-                };
+    #[test]
+    fn persist_and_restore_round_trip() {
+        let _guard = testing::fresh();
+        touch(&["/tmp/note.txt"]);
+        mkdir(&["/tmp/work"]);
+        persist();
+        assert!(storage::load(STORAGE_KEY).is_some());
+
+        reset_to_default();
+        assert!(!exists("/tmp/note.txt"));
+
+        restore();
+        assert!(exists("/tmp/note.txt"));
+        assert!(exists("/tmp/work"));
+        assert!(exists("/home/objz/.bashrc"));
+    }
 
-                let display_name = match child {
-                    Node::Directory { .. } => format!("{}/", name),
-                    Node::File { .. } => name.to_string(),
-                    Node::Symlink { target, .. } => format!("{} -> {}", name, target),
-                };
+    #[test]
+    fn persist_without_changes_clears_the_save() {
+        let _guard = testing::fresh();
+        touch(&["/tmp/note.txt"]);
+        persist();
+        assert!(storage::load(STORAGE_KEY).is_some());
 
-                output.push_str(&format!("{}{}{}\n", prefix, connector, display_name));
+        reset_to_default();
+        persist();
+        assert_eq!(storage::load(STORAGE_KEY), None);
+    }
 
-                let new_prefix =
-                    format!("{}{}", prefix, if is_last_child { "    " } else { "│   " });
+    #[test]
+    fn restore_keeps_entries_added_by_a_later_deploy() {
+        let _guard = testing::fresh();
+        touch(&["/home/objz/note.txt"]);
+        rm(&["/home/objz/.bashrc"]);
+        persist();
 
-                output.push_str(&build_tree(child, &new_prefix, is_last_child));
+        // The next deploy ships a new file next to the visitor's
+        reset_to_default();
+        {
+            let mut filesystem = FILESYSTEM.lock().unwrap();
+            let home = resolve_ino(&filesystem, &normalize_path("/home/objz", &[]), true).unwrap();
+            let node = Node::File {
+                content: Content::Text("new".to_string()),
+                permissions: 0o644,
+                owner: "objz".to_string(),
+                group: "objz".to_string(),
+                protected: false,
+                times: Timestamps::at(0.0),
+                url: None,
+            };
+            filesystem
+                .inodes
+                .insert(FIRST_DYNAMIC_INO - 1, Inode { node, links: 1 });
+            if let Some(Node::Directory { children, .. }) = filesystem.get_mut(home) {
+                children.insert("news.txt".to_string(), FIRST_DYNAMIC_INO - 1);
             }
         }
 
-        output
+        restore();
+        assert!(exists("/home/objz/note.txt"));
+        assert!(exists("/home/objz/news.txt"));
+        assert!(!exists("/home/objz/.bashrc"));
+        assert!(exists("/dev/null"));
     }
 
-    let tree_name = if start_path.is_empty() {
-        "/".to_string()
-    } else {
-        start_path.last().unwrap_or(&"/".to_string()).clone()
-    };
+    #[test]
+    fn cp_r_copies_into_an_existing_directory_or_to_a_new_name() {
+        let _guard = testing::fresh();
+        mkdir(&["/tmp/src", "/tmp/dest"]);
+        write_file("/tmp/src/a.txt", "a\n").unwrap();
 
-    format!("{}\n{}", tree_name, build_tree(start_node, "", true))
-}
+        assert_eq!(
+            cp(&["/tmp/src", "/tmp/dest"]),
+            stream::error("cp: -r not specified; omitting directory '/tmp/src'")
+        );
+        assert_eq!(cp(&["-r", "/tmp/src", "/tmp/dest"]), "");
+        assert_eq!(read_file("/tmp/dest/src/a.txt").unwrap(), "a\n");
 
-pub fn ln(args: &[&str]) -> String {
-    if args.len() < 2 {
-        return "ln: missing file operand".into();
+        assert_eq!(cp(&["-r", "/tmp/src", "/tmp/copy"]), "");
+        assert_eq!(read_file("/tmp/copy/a.txt").unwrap(), "a\n");
+        assert!(!exists("/tmp/copy/src"));
+
+        // Copies are their own files
+        write_file("/tmp/copy/a.txt", "changed\n").unwrap();
+        assert_eq!(read_file("/tmp/src/a.txt").unwrap(), "a\n");
     }
 
-    let mut filesystem = FILESYSTEM.lock().unwrap();
-    let current_path = CURRENT_PATH.lock().unwrap();
-    let current_user = &*CURRENT_USER;
+    #[test]
+    fn mv_refuses_to_move_a_directory_into_itself() {
+        let _guard = testing::fresh();
+        mkdir(&["/tmp/outer", "/tmp/outer/inner"]);
+        assert_eq!(
+            mv(&["/tmp/outer", "/tmp/outer/inner"]),
+            stream::error(
+                "mv: cannot move '/tmp/outer' to a subdirectory of itself, '/tmp/outer/inner/outer'"
+            )
+        );
+        assert!(exists("/tmp/outer/inner"));
 
-    let (target, link_name) = if args[0] == "-s" {
-        if args.len() < 3 {
-            return "ln: missing file operand".into();
-        }
-        (args[1], args[2])
-    } else {
-        return "ln: hard links not supported in this filesystem".into();
-    };
+        assert_eq!(mv(&["/tmp/outer", "/tmp/moved"]), "");
+        assert!(exists("/tmp/moved/inner"));
+        assert!(!exists("/tmp/outer"));
+    }
 
-    let link_path = normalize_path(link_name, &current_path);
+    #[test]
+    fn cp_n_keeps_existing_files() {
+        let _guard = testing::fresh();
+        write_file("/tmp/old", "old\n").unwrap();
+        write_file("/tmp/new", "new\n").unwrap();
 
-    if link_path.is_empty() {
-        return "ln: cannot create link '/': File exists".into();
+        assert_eq!(cp(&["-n", "/tmp/new", "/tmp/old"]), "");
+        assert_eq!(read_file("/tmp/old").unwrap(), "old\n");
+        assert_eq!(cp(&["/tmp/new", "/tmp/old"]), "");
+        assert_eq!(read_file("/tmp/old").unwrap(), "new\n");
     }
 
-    let parent_path = &link_path[..link_path.len() - 1];
-    let file_name = &link_path[link_path.len() - 1];
+    #[test]
+    fn i_asks_before_overwriting() {
+        let _guard = testing::fresh();
+        write_file("/tmp/old", "old\n").unwrap();
+        write_file("/tmp/new", "new\n").unwrap();
 
-    let parent = match get_node_at_path_mut(&mut filesystem, parent_path) {
-        Some(Node::Directory { children, .. }) => children,
-        Some(_) => return format!("ln: cannot create link '{}': Not a directory", link_name),
-        None => {
-            return format!(
-                "ln: cannot create link '{}': No such file or directory",
-                link_name
-            )
+        assert_eq!(cp(&["-i", "/tmp/new", "/tmp/old"]), "");
+        assert_eq!(
+            pending_prompt(),
+            Some("cp: overwrite '/tmp/old'? ".to_string())
+        );
+        assert_eq!(answer_prompt("n"), "");
+        assert_eq!(pending_prompt(), None);
+        assert_eq!(read_file("/tmp/old").unwrap(), "old\n");
+
+        assert_eq!(mv(&["-i", "/tmp/new", "/tmp/old"]), "");
+        assert_eq!(
+            pending_prompt(),
+            Some("mv: overwrite '/tmp/old'? ".to_string())
+        );
+        assert_eq!(answer_prompt("y"), "");
+        assert_eq!(read_file("/tmp/old").unwrap(), "new\n");
+        assert!(!exists("/tmp/new"));
+
+        // Nothing to ask about when the target is free
+        assert_eq!(cp(&["-i", "/tmp/old", "/tmp/free"]), "");
+        assert_eq!(pending_prompt(), None);
+    }
+
+    #[test]
+    fn links_resolve_across_several_hops() {
+        let _guard = testing::fresh();
+        mkdir(&["/tmp/real"]);
+        write_file("/tmp/real/file", "end\n").unwrap();
+        ln(&["-s", "/tmp/real", "/tmp/dir"]);
+        ln(&["-s", "dir/file", "/tmp/one"]);
+        ln(&["-s", "/tmp/one", "/tmp/two"]);
+
+        assert_eq!(read_file("/tmp/two").unwrap(), "end\n");
+        assert_eq!(readlink(&["/tmp/two"]), "/tmp/one");
+        assert_eq!(readlink(&["/tmp/real/file"]), "");
+        assert_eq!(readlink(&["-f", "/tmp/two"]), "/tmp/real/file");
+        // `..` is taken after the link it follows, as the kernel does
+        assert_eq!(realpath(&["/tmp/dir/../two"]), "/tmp/real/file");
+    }
+
+    #[test]
+    fn realpath_of_a_dangling_link() {
+        let _guard = testing::fresh();
+        ln(&["-s", "/tmp/missing", "/tmp/dangling"]);
+
+        assert_eq!(readlink(&["/tmp/dangling"]), "/tmp/missing");
+        assert_eq!(readlink(&["-f", "/tmp/dangling"]), "/tmp/missing");
+        assert_eq!(realpath(&["/tmp/dangling"]), "/tmp/missing");
+        assert!(read_file("/tmp/dangling").is_err());
+    }
+
+    #[test]
+    fn link_loops_stop_with_eloop() {
+        let _guard = testing::fresh();
+        ln(&["-s", "/tmp/ping", "/tmp/pong"]);
+        ln(&["-s", "/tmp/pong", "/tmp/ping"]);
+
+        assert_eq!(
+            realpath(&["/tmp/ping"]),
+            stream::error("realpath: /tmp/ping: Too many levels of symbolic links")
+        );
+        assert_eq!(readlink(&["-f", "/tmp/ping"]), "");
+        assert!(matches!(read_file("/tmp/ping"), Err(FsError::TooManyLinks)));
+    }
+
+    fn ino_of(path: &str) -> Ino {
+        let filesystem = FILESYSTEM.lock().unwrap();
+        resolve_ino(&filesystem, &normalize_path(path, &[]), false).unwrap()
+    }
+
+    fn links_of(path: &str) -> usize {
+        let ino = ino_of(path);
+        FILESYSTEM.lock().unwrap().link_count(ino)
+    }
+
+    #[test]
+    fn link_and_unlink_count_names() {
+        let _guard = testing::fresh();
+        let mut filesystem = default_filesystem();
+        let tmp = filesystem.ino_at(&normalize_path("/tmp", &[])).unwrap();
+        let node = Node::File {
+            content: Content::Text("x".to_string()),
+            permissions: 0o644,
+            owner: "objz".to_string(),
+            group: "objz".to_string(),
+            protected: false,
+            times: Timestamps::at(0.0),
+            url: None,
+        };
+
+        let ino = filesystem.create(tmp, "a", node);
+        assert_eq!(filesystem.link_count(ino), 1);
+        filesystem.link(tmp, "b", ino);
+        filesystem.link(tmp, "b", ino);
+        assert_eq!(filesystem.link_count(ino), 2);
+        assert_eq!(filesystem.entry(tmp, "b"), Some(ino));
+
+        assert_eq!(filesystem.unlink(tmp, "a"), Some(ino));
+        assert_eq!(filesystem.link_count(ino), 1);
+        assert!(filesystem.get(ino).is_some());
+        assert_eq!(filesystem.unlink(tmp, "a"), None);
+
+        assert_eq!(filesystem.unlink(tmp, "b"), Some(ino));
+        assert!(filesystem.get(ino).is_none());
+    }
+
+    #[test]
+    fn unlinking_a_directory_frees_what_only_it_named() {
+        let _guard = testing::fresh();
+        mkdir(&["/tmp/dir"]);
+        write_file("/tmp/dir/only", "1\n").unwrap();
+        write_file("/tmp/dir/shared", "2\n").unwrap();
+        ln(&["/tmp/dir/shared", "/tmp/kept"]);
+        let (only, shared) = (ino_of("/tmp/dir/only"), ino_of("/tmp/dir/shared"));
+
+        let mut filesystem = FILESYSTEM.lock().unwrap();
+        let tmp = filesystem.ino_at(&normalize_path("/tmp", &[])).unwrap();
+        filesystem.unlink(tmp, "dir");
+        assert!(filesystem.get(only).is_none());
+        assert_eq!(filesystem.link_count(shared), 1);
+    }
+
+    #[test]
+    fn hard_links_survive_persist_and_restore() {
+        let _guard = testing::fresh();
+        write_file("/tmp/a", "data\n").unwrap();
+        ln(&["/tmp/a", "/tmp/b"]);
+        let ino = ino_of("/tmp/a");
+        persist();
+
+        reset_to_default();
+        restore();
+        assert_eq!(ls(&["-i", "/tmp"]), format!("{} a  {} b", ino, ino));
+        assert_eq!(links_of("/tmp/a"), 2);
+
+        rm(&["/tmp/a"]);
+        assert_eq!(read_file("/tmp/b").unwrap(), "data\n");
+        assert_eq!(links_of("/tmp/b"), 1);
+    }
+
+    #[test]
+    fn restore_counts_names_a_later_deploy_added() {
+        let _guard = testing::fresh();
+        write_file("/home/objz/.bashrc", "# mine\n").unwrap();
+        persist();
+
+        // The next deploy ships a second name for the same file
+        reset_to_default();
+        let bashrc = ino_of("/home/objz/.bashrc");
+        {
+            let mut filesystem = FILESYSTEM.lock().unwrap();
+            let home = filesystem
+                .ino_at(&normalize_path("/home/objz", &[]))
+                .unwrap();
+            filesystem.link(home, ".profile", bashrc);
         }
-    };
 
-    if parent.contains_key(file_name) {
-        return format!("ln: cannot create link '{}': File exists", link_name);
+        restore();
+        assert_eq!(links_of("/home/objz/.bashrc"), 2);
+        rm(&["/home/objz/.profile"]);
+        assert_eq!(read_file("/home/objz/.bashrc").unwrap(), "# mine\n");
     }
 
-    parent.insert(
-        file_name.clone(),
-        Node::Symlink {
-            target: target.to_string(),
-            owner: current_user.clone(),
-        },
-    );
+    // Used and Use% from df
+    fn df_used() -> (String, String) {
+        let df = df(&[]);
+        let row: Vec<&str> = df.lines().nth(1).unwrap().split_whitespace().collect();
+        (row[2].to_string(), row[4].to_string())
+    }
 
-    String::new()
-}
+    fn free_blocks() -> usize {
+        blocks(QUOTA) - FILESYSTEM.lock().unwrap().blocks_used()
+    }
 
-pub fn uname(args: &[&str]) -> String {
-    if args.is_empty() || args[0] == "-s" {
-        "WASM".to_string()
-    } else if args[0] == "-a" {
-        "WASM wasm-host 1.0.0 #1 SMP PREEMPT_DYNAMIC Mon Jan 1 12:00:00 UTC 2024 wasm32 GNU/Linux"
-            .to_string()
-    } else {
-        "uname: invalid option".to_string()
+    #[test]
+    fn writes_past_the_quota_fail() {
+        let _guard = testing::fresh();
+        let mut handler = CommandHandler::new();
+        let free = free_blocks();
+
+        assert_eq!(
+            handler.handle("head -c 5000000 /dev/zero > /tmp/huge").0,
+            "zsh: no space left on device: /tmp/huge"
+        );
+        // The shell made the file before the write failed
+        assert_eq!(read_bytes("/tmp/huge"), Ok(Vec::new()));
+
+        // Filling up to the last byte works, one more doesn't
+        assert_eq!(write_bytes("/tmp/fill", &vec![b'x'; free * 1024]), Ok(()));
+        assert_eq!(df_used().1, "100%");
+        assert_eq!(append_bytes("/tmp/fill", b"x"), Err(FsError::NoSpace));
+        assert_eq!(
+            handler.handle("touch /tmp/empty").0,
+            "touch: cannot touch '/tmp/empty': No space left on device"
+        );
+        assert_eq!(
+            handler.handle("cp /home/objz/.bashrc /tmp/copy").0,
+            "cp: cannot create regular file '/tmp/copy': No space left on device"
+        );
+        assert_eq!(
+            handler.handle("echo more >> /tmp/fill").0,
+            "zsh: no space left on device: /tmp/fill"
+        );
+
+        // Removing the file gives the space back
+        assert_eq!(handler.handle("rm /tmp/fill").0, "");
+        assert_eq!(free_blocks(), free);
+        assert_eq!(handler.handle("touch /tmp/empty").0, "");
+    }
+
+    #[test]
+    fn du_and_df_agree_on_what_is_used() {
+        let _guard = testing::fresh();
+        let mut handler = CommandHandler::new();
+        let total = |handler: &mut CommandHandler| {
+            let du = handler.handle("sudo du -s /").0;
+            du.split('\t').next().unwrap().to_string()
+        };
+        assert_eq!(total(&mut handler), df_used().0);
+
+        write_bytes("/tmp/data", &vec![0; 10 * 1024 + 1]).unwrap();
+        ln(&["/tmp/data", "/tmp/again"]);
+        assert_eq!(du(&["-s", "/tmp"]), "15\t/tmp");
+        assert_eq!(total(&mut handler), df_used().0);
+
+        rm(&["/tmp/data", "/tmp/again"]);
+        assert_eq!(du(&["-s", "/tmp"]), "4\t/tmp");
+        assert_eq!(total(&mut handler), df_used().0);
+    }
+
+    #[test]
+    fn only_utf8_without_nul_is_text() {
+        assert_eq!(
+            Content::from_bytes("héllo\n".as_bytes().to_vec()),
+            Content::Text("héllo\n".to_string())
+        );
+        assert_eq!(
+            Content::from_bytes(Vec::new()),
+            Content::Text(String::new())
+        );
+        assert_eq!(
+            Content::from_bytes(b"a\0b".to_vec()),
+            Content::Binary {
+                bytes: b"a\0b".to_vec()
+            }
+        );
+        assert_eq!(
+            Content::from_bytes(vec![0xc3, 0x28, 0xff]),
+            Content::Binary {
+                bytes: vec![0xc3, 0x28, 0xff]
+            }
+        );
     }
 }
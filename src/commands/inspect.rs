@@ -0,0 +1,593 @@
+use super::filesystem::{
+    mode_string, normalize_path, resolve_ino, FsError, Ino, Node, Timestamps, CURRENT_PATH,
+    FILESYSTEM,
+};
+use super::permissions::{self, READ};
+use super::{stream, system, text, users};
+use crate::utils::gzip;
+
+// What stat reports about one operand, gathered while the filesystem is
+// locked. Owner ids are looked up afterwards since that reads /etc/passwd.
+struct Stat {
+    name: String,
+    ino: Ino,
+    kind: &'static str,
+    size: usize,
+    links: usize,
+    permissions: u16,
+    mode: String,
+    owner: String,
+    group: String,
+    times: Timestamps,
+    target: Option<String>,
+    // Major and minor numbers of a device
+    device: Option<(u32, u32)>,
+}
+
+fn kind(node: &Node) -> &'static str {
+    match node {
+        Node::Directory { .. } => "directory",
+        Node::Symlink { .. } => "symbolic link",
+        Node::File { .. } if node.size() == 0 => "regular empty file",
+        Node::File { .. } => "regular file",
+        Node::Device { .. } => "character special file",
+    }
+}
+
+fn uid(owner: &str) -> u32 {
+    users::find_user(owner).map_or(65534, |user| user.uid)
+}
+
+fn gid(group: &str) -> u32 {
+    users::find_group(group).map_or(65534, |group| group.gid)
+}
+
+fn seconds(millis: f64) -> i64 {
+    (millis / 1000.0).floor() as i64
+}
+
+// 512-byte blocks, allocated 4K at a time like ls and du count them
+fn blocks(stat: &Stat) -> usize {
+    match stat.kind {
+        "symbolic link" => 0,
+        _ => stat.size.div_ceil(4096) * 8,
+    }
+}
+
+fn quoted_name(stat: &Stat) -> String {
+    match &stat.target {
+        Some(target) => format!("'{}' -> '{}'", stat.name, target),
+        None => format!("'{}'", stat.name),
+    }
+}
+
+fn default_format(stat: &Stat) -> String {
+    let name = match &stat.target {
+        Some(target) => format!("{} -> {}", stat.name, target),
+        None => stat.name.clone(),
+    };
+    let times = stat.times;
+
+    [
+        format!("  File: {}", name),
+        format!(
+            "  Size: {:<10}\tBlocks: {:<10} IO Block: 4096   {}",
+            stat.size,
+            blocks(stat),
+            stat.kind
+        ),
+        match stat.device {
+            Some((major, minor)) => format!(
+                "Device: 0,1\tInode: {:<11} Links: {:<5} Device type: {},{}",
+                stat.ino, stat.links, major, minor
+            ),
+            None => format!("Device: 0,1\tInode: {:<11} Links: {}", stat.ino, stat.links),
+        },
+        format!(
+            "Access: ({:04o}/{})  Uid: ({:>5}/{:>8})   Gid: ({:>5}/{:>8})",
+            stat.permissions,
+            stat.mode,
+            uid(&stat.owner),
+            stat.owner,
+            gid(&stat.group),
+            stat.group
+        ),
+        format!("Access: {}", system::format_stat_time(times.atime)),
+        format!("Modify: {}", system::format_stat_time(times.mtime)),
+        format!("Change: {}", system::format_stat_time(times.ctime)),
+        " Birth: -".to_string(),
+    ]
+    .join("\n")
+}
+
+// The `-c` directives, anything else is copied as is
+fn custom_format(stat: &Stat, format: &str) -> String {
+    let mut out = String::new();
+    let mut chars = format.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        let times = stat.times;
+        match chars.next() {
+            Some('a') => out.push_str(&format!("{:o}", stat.permissions)),
+            Some('A') => out.push_str(&stat.mode),
+            Some('b') => out.push_str(&blocks(stat).to_string()),
+            Some('B') => out.push_str("512"),
+            Some('F') => out.push_str(stat.kind),
+            Some('g') => out.push_str(&gid(&stat.group).to_string()),
+            Some('G') => out.push_str(&stat.group),
+            Some('h') => out.push_str(&stat.links.to_string()),
+            Some('i') => out.push_str(&stat.ino.to_string()),
+            Some('n') => out.push_str(&stat.name),
+            Some('N') => out.push_str(&quoted_name(stat)),
+            Some('s') => out.push_str(&stat.size.to_string()),
+            Some('t') => out.push_str(&format!("{:x}", stat.device.map_or(0, |(major, _)| major))),
+            Some('T') => out.push_str(&format!("{:x}", stat.device.map_or(0, |(_, minor)| minor))),
+            Some('u') => out.push_str(&uid(&stat.owner).to_string()),
+            Some('U') => out.push_str(&stat.owner),
+            Some('x') => out.push_str(&system::format_stat_time(times.atime)),
+            Some('X') => out.push_str(&seconds(times.atime).to_string()),
+            Some('y') => out.push_str(&system::format_stat_time(times.mtime)),
+            Some('Y') => out.push_str(&seconds(times.mtime).to_string()),
+            Some('z') => out.push_str(&system::format_stat_time(times.ctime)),
+            Some('Z') => out.push_str(&seconds(times.ctime).to_string()),
+            Some('%') => out.push('%'),
+            Some(c) => {
+                out.push('?');
+                out.push(c);
+            }
+            None => out.push('%'),
+        }
+    }
+
+    out
+}
+
+pub fn stat(args: &[&str]) -> String {
+    let parsed = match text::parse("stat", args, "L", "c") {
+        Ok(parsed) => parsed,
+        Err(e) => return e,
+    };
+    if parsed.operands.is_empty() {
+        return stream::error("stat: missing operand");
+    }
+
+    let results: Vec<Result<Stat, String>> = {
+        let filesystem = FILESYSTEM.lock().unwrap();
+        let current_path = CURRENT_PATH.lock().unwrap();
+
+        parsed
+            .operands
+            .iter()
+            .map(|&name| {
+                let path = normalize_path(name, &current_path);
+                let ino = resolve_ino(&filesystem, &path, parsed.has('L'))
+                    .map_err(|e| stream::error(format!("stat: cannot stat '{}': {}", name, e)))?;
+                let node = filesystem.get(ino).unwrap();
+                Ok(Stat {
+                    name: name.to_string(),
+                    ino,
+                    kind: kind(node),
+                    size: node.size(),
+                    links: filesystem.link_count(ino),
+                    permissions: node.permissions(),
+                    mode: mode_string(node),
+                    owner: node.get_owner().to_string(),
+                    group: node.get_group().to_string(),
+                    times: *node.times(),
+                    target: match node {
+                        Node::Symlink { target, .. } => Some(target.clone()),
+                        _ => None,
+                    },
+                    device: match node {
+                        Node::Device { device, .. } => Some(device.numbers()),
+                        _ => None,
+                    },
+                })
+            })
+            .collect()
+    };
+
+    results
+        .into_iter()
+        .map(|result| match result {
+            Ok(stat) => match parsed.value('c') {
+                Some(format) => custom_format(&stat, format),
+                None => default_format(&stat),
+            },
+            Err(e) => e,
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn number(bytes: &[u8], range: std::ops::Range<usize>, big_endian: bool) -> Option<u32> {
+    let bytes = bytes.get(range)?;
+    let fold = |value: u32, byte: &u8| value << 8 | *byte as u32;
+    Some(if big_endian {
+        bytes.iter().fold(0, fold)
+    } else {
+        bytes.iter().rev().fold(0, fold)
+    })
+}
+
+// Recognized by their magic bytes
+fn image(bytes: &[u8]) -> Option<String> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        let size = number(bytes, 16..20, true)
+            .zip(number(bytes, 20..24, true))
+            .map_or(String::new(), |(w, h)| format!(", {} x {}", w, h));
+        Some(format!("PNG image data{}", size))
+    } else if bytes.starts_with(b"\xff\xd8\xff") {
+        Some("JPEG image data".to_string())
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        let size = number(bytes, 6..8, false)
+            .zip(number(bytes, 8..10, false))
+            .map_or(String::new(), |(w, h)| format!(", {} x {}", w, h));
+        Some(format!(
+            "GIF image data, version {}{}",
+            String::from_utf8_lossy(&bytes[3..6]),
+            size
+        ))
+    } else if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WEBP") {
+        Some("RIFF (little-endian) data, Web/P image".to_string())
+    } else if bytes.starts_with(b"BM") && bytes.len() > 26 {
+        let size = number(bytes, 18..22, false)
+            .zip(number(bytes, 22..26, false))
+            .map_or(String::new(), |(w, h)| format!(", {} x {}", w, h));
+        Some(format!("PC bitmap{}", size))
+    } else {
+        None
+    }
+}
+
+// gzip members and tar archives, which `file` looks into a little
+fn archive(bytes: &[u8]) -> Option<String> {
+    if gzip::is_gzip(bytes) && bytes.len() >= 18 {
+        let mut description = "gzip compressed data".to_string();
+        let (flags, os) = (bytes[3], bytes[9]);
+        let mut pos = 10;
+        if flags & gzip::FEXTRA != 0 {
+            pos += 2 + number(bytes, pos..pos + 2, false).unwrap_or(0) as usize;
+        }
+        if flags & gzip::FNAME != 0 {
+            let name = bytes.get(pos..).unwrap_or_default();
+            let name = name.split(|&byte| byte == 0).next().unwrap_or_default();
+            description.push_str(&format!(", was \"{}\"", String::from_utf8_lossy(name)));
+        }
+        if os == gzip::OS_UNIX {
+            description.push_str(", from Unix");
+        }
+        if let Some(size) = number(bytes, bytes.len() - 4..bytes.len(), false) {
+            description.push_str(&format!(", original size modulo 2^32 {}", size));
+        }
+        Some(description)
+    } else if bytes.len() >= 512 && bytes.get(257..265) == Some(b"ustar  \0") {
+        Some("POSIX tar archive (GNU)".to_string())
+    } else if bytes.len() >= 512 && bytes.get(257..263) == Some(b"ustar\0") {
+        Some("POSIX tar archive".to_string())
+    } else {
+        None
+    }
+}
+
+fn is_binary(text: &str) -> bool {
+    text.chars()
+        .any(|c| c.is_control() && !matches!(c, '\n' | '\t' | '\r' | '\x0c' | '\x1b'))
+}
+
+fn script(first_line: &str) -> Option<String> {
+    let command = first_line.strip_prefix("#!")?.trim();
+    let mut words = command.split_whitespace();
+    let mut interpreter = words.next()?.rsplit('/').next()?;
+    if interpreter == "env" {
+        interpreter = words.next()?;
+    }
+
+    let name = match interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.') {
+        "sh" | "dash" => "POSIX shell script".to_string(),
+        "bash" => "Bourne-Again shell script".to_string(),
+        "zsh" => "Paul Falstad's zsh script".to_string(),
+        "python" => "Python script".to_string(),
+        "perl" => "Perl script".to_string(),
+        "node" => "Node.js script".to_string(),
+        "awk" => "awk script".to_string(),
+        _ => format!("a {} script", command),
+    };
+    Some(name)
+}
+
+fn classify(name: &str, bytes: &[u8]) -> String {
+    if bytes.is_empty() {
+        return "empty".to_string();
+    }
+    if let Some(known) = image(bytes).or_else(|| archive(bytes)) {
+        return known;
+    }
+    let content = match std::str::from_utf8(bytes) {
+        Ok(content) if !is_binary(content) => content,
+        _ => return "data".to_string(),
+    };
+
+    let mut charset = if content.is_ascii() {
+        "ASCII text".to_string()
+    } else {
+        "Unicode text, UTF-8 text".to_string()
+    };
+    if content.contains("\r\n") {
+        charset.push_str(", with CRLF line terminators");
+    } else if !content.contains('\n') {
+        charset.push_str(", with no line terminators");
+    }
+
+    let trimmed = content.trim_start();
+    let first_line = content.lines().next().unwrap_or_default();
+    // A leading `# ` is as likely a shell comment, so only the name counts
+    let markdown = name.ends_with(".md") || name.ends_with(".markdown");
+
+    if let Some(script) = script(first_line) {
+        format!("{}, {} executable", script, charset)
+    } else if trimmed.starts_with('<')
+        && trimmed.contains("<svg")
+        && trimmed.trim_end().ends_with('>')
+    {
+        format!("SVG Scalable Vector Graphics image, {}", charset)
+    } else if (trimmed.starts_with('{') || trimmed.starts_with('['))
+        && serde_json::from_str::<serde_json::Value>(content).is_ok()
+    {
+        "JSON text data".to_string()
+    } else if markdown {
+        format!("Markdown document, {}", charset)
+    } else {
+        charset
+    }
+}
+
+fn describe(name: &str, follow: bool) -> String {
+    let filesystem = FILESYSTEM.lock().unwrap();
+    let current_path = CURRENT_PATH.lock().unwrap();
+    let path = normalize_path(name, &current_path);
+
+    let node = match resolve_ino(&filesystem, &path, follow) {
+        Ok(ino) => filesystem.get(ino).unwrap(),
+        Err(e) => return format!("cannot open `{}' ({})", name, e),
+    };
+
+    match node {
+        Node::Directory { .. } => "directory".to_string(),
+        Node::Symlink { target, .. } => match resolve_ino(&filesystem, &path, true) {
+            Ok(_) => format!("symbolic link to {}", target),
+            Err(_) => format!("broken symbolic link to {}", target),
+        },
+        Node::File { .. } if !permissions::can_access(node, READ) => {
+            "regular file, no read permission".to_string()
+        }
+        Node::File { .. } => match node.bytes() {
+            Some(bytes) => classify(name, &bytes),
+            None => format!("cannot open `{}' ({})", name, FsError::Io),
+        },
+        Node::Device { device, .. } => {
+            let (major, minor) = device.numbers();
+            format!("character special ({}/{})", major, minor)
+        }
+    }
+}
+
+pub fn file(args: &[&str], stdin: Option<&[u8]>) -> String {
+    let parsed = match text::parse("file", args, "bLh", "") {
+        Ok(parsed) => parsed,
+        Err(e) => return e,
+    };
+    if parsed.operands.is_empty() {
+        return stream::error("Usage: file [-bLh] file ...");
+    }
+    let follow = parsed.has('L') && !parsed.has('h');
+
+    let results: Vec<(&str, String)> = parsed
+        .operands
+        .iter()
+        .map(|&name| match name {
+            "-" => ("/dev/stdin", classify("", stdin.unwrap_or_default())),
+            _ => (name, describe(name, follow)),
+        })
+        .collect();
+
+    // Descriptions line up after the longest name, like file pads them
+    let width = results
+        .iter()
+        .map(|(name, _)| name.chars().count())
+        .max()
+        .unwrap_or(0);
+    results
+        .iter()
+        .map(|(name, description)| {
+            if parsed.has('b') {
+                description.clone()
+            } else {
+                let label = format!("{}:", name);
+                format!("{:<w$} {}", label, description, w = width + 1)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::{filesystem, CommandHandler};
+    use crate::testing;
+
+    fn file_of(name: &str, bytes: &[u8]) -> String {
+        filesystem::write_bytes(name, bytes).unwrap();
+        file(&["-b", name], None)
+    }
+
+    #[test]
+    fn file_recognizes_text() {
+        let _guard = testing::fresh();
+        assert_eq!(file_of("/tmp/empty", b""), "empty");
+        assert_eq!(file_of("/tmp/plain", b"hello\n"), "ASCII text");
+        assert_eq!(
+            file_of("/tmp/dos", b"line\r\nline\r\n"),
+            "ASCII text, with CRLF line terminators"
+        );
+        assert_eq!(
+            file_of("/tmp/bare", b"no newline"),
+            "ASCII text, with no line terminators"
+        );
+        assert_eq!(
+            file_of("/tmp/utf8", "grüße\n".as_bytes()),
+            "Unicode text, UTF-8 text"
+        );
+        assert_eq!(file_of("/tmp/data", b"\x00\x01\x02\x03"), "data");
+    }
+
+    #[test]
+    fn markdown_is_known_by_its_name() {
+        let _guard = testing::fresh();
+        assert_eq!(
+            file_of("/tmp/notes.md", b"# Notes\n\n- one\n"),
+            "Markdown document, ASCII text"
+        );
+        // A comment header doesn't make a shell rc file Markdown
+        assert_eq!(
+            file_of("/tmp/.bashrc", b"# ~/.bashrc\nalias ll='ls -l'\n"),
+            "ASCII text"
+        );
+        assert_eq!(file(&["-b", "/home/objz/.bashrc"], None), "ASCII text");
+    }
+
+    #[test]
+    fn file_recognizes_scripts_and_structured_text() {
+        let _guard = testing::fresh();
+        assert_eq!(
+            file_of("/tmp/run", b"#!/bin/bash\necho hi\n"),
+            "Bourne-Again shell script, ASCII text executable"
+        );
+        assert_eq!(
+            file_of("/tmp/tool", b"#!/usr/bin/env python3\nprint()\n"),
+            "Python script, ASCII text executable"
+        );
+        assert_eq!(
+            file_of("/tmp/data.json", b"{\"a\": [1, 2]}\n"),
+            "JSON text data"
+        );
+        assert_eq!(
+            file_of("/tmp/logo.svg", b"<?xml version=\"1.0\"?>\n<svg></svg>\n"),
+            "SVG Scalable Vector Graphics image, ASCII text"
+        );
+    }
+
+    #[test]
+    fn file_recognizes_images() {
+        let _guard = testing::fresh();
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+        png.extend_from_slice(&[0, 0, 0, 64, 0, 0, 0, 32]);
+        assert_eq!(file_of("/tmp/a.png", &png), "PNG image data, 64 x 32");
+        assert_eq!(
+            file_of("/tmp/a.jpg", b"\xff\xd8\xff\xe0"),
+            "JPEG image data"
+        );
+        assert_eq!(
+            file_of("/tmp/a.gif", b"GIF89a\x10\x00\x08\x00"),
+            "GIF image data, version 89a, 16 x 8"
+        );
+        assert_eq!(
+            file_of("/tmp/a.webp", b"RIFF\0\0\0\0WEBPVP8 "),
+            "RIFF (little-endian) data, Web/P image"
+        );
+    }
+
+    #[test]
+    fn file_recognizes_archives() {
+        let _guard = testing::fresh();
+        let packed = gzip::compress(b"hello\n", Some("hello.txt"), 0);
+        assert_eq!(
+            file_of("/tmp/hello.txt.gz", &packed),
+            "gzip compressed data, was \"hello.txt\", from Unix, original size modulo 2^32 6"
+        );
+        let unnamed = gzip::compress(b"", None, 0);
+        assert_eq!(
+            file_of("/tmp/unnamed.gz", &unnamed),
+            "gzip compressed data, from Unix, original size modulo 2^32 0"
+        );
+
+        let mut handler = CommandHandler::new();
+        filesystem::write_file("/tmp/hello.txt", "hello\n").unwrap();
+        handler.handle("tar -cf /tmp/hello.tar /tmp/hello.txt");
+        assert_eq!(file(&["-b", "/tmp/hello.tar"], None), "POSIX tar archive");
+        handler.handle("gzip /tmp/hello.tar");
+        assert!(file(&["-b", "/tmp/hello.tar.gz"], None)
+            .starts_with("gzip compressed data, was \"hello.tar\""));
+    }
+
+    #[test]
+    fn file_describes_other_nodes() {
+        let _guard = testing::fresh();
+        filesystem::write_file("/tmp/secret", "x\n").unwrap();
+        filesystem::ln(&["-s", "/tmp/secret", "/tmp/link"]);
+        filesystem::ln(&["-s", "/tmp/gone", "/tmp/broken"]);
+        permissions::chmod(&["000", "/tmp/secret"]);
+
+        assert_eq!(
+            file(
+                &[
+                    "/tmp",
+                    "/tmp/link",
+                    "/tmp/broken",
+                    "/dev/null",
+                    "/tmp/secret"
+                ],
+                None
+            ),
+            "/tmp:        directory\n\
+             /tmp/link:   symbolic link to /tmp/secret\n\
+             /tmp/broken: broken symbolic link to /tmp/gone\n\
+             /dev/null:   character special (1/3)\n\
+             /tmp/secret: regular file, no read permission"
+        );
+        assert_eq!(
+            file(&["-L", "/tmp/missing"], None),
+            "/tmp/missing: cannot open `/tmp/missing' (No such file or directory)"
+        );
+        assert_eq!(
+            file(&["-b", "-"], Some(b"#!/bin/sh\n")),
+            "POSIX shell script, ASCII text executable"
+        );
+    }
+
+    #[test]
+    fn stat_reports_each_kind() {
+        let _guard = testing::fresh();
+        filesystem::write_file("/tmp/five", "12345").unwrap();
+        filesystem::write_file("/tmp/empty", "").unwrap();
+        filesystem::ln(&["-s", "five", "/tmp/link"]);
+        filesystem::ln(&["/tmp/five", "/tmp/same"]);
+
+        let format = ["-c", "%n|%F|%s|%h|%a|%b|%U"];
+        let stat_of = |name: &str| stat(&[format[0], format[1], name]);
+        assert_eq!(
+            stat_of("/tmp/five"),
+            "/tmp/five|regular file|5|2|644|8|objz"
+        );
+        assert_eq!(
+            stat_of("/tmp/empty"),
+            "/tmp/empty|regular empty file|0|1|644|0|objz"
+        );
+        assert_eq!(
+            stat_of("/tmp/link"),
+            "/tmp/link|symbolic link|4|1|777|0|objz"
+        );
+        assert_eq!(stat(&["-L", "-c", "%F %s", "/tmp/link"]), "regular file 5");
+        assert_eq!(stat_of("/tmp"), "/tmp|directory|4096|2|1777|8|root");
+        assert_eq!(
+            stat(&["-c", "%F %t,%T", "/dev/null"]),
+            "character special file 1,3"
+        );
+        assert_eq!(stat(&["-c", "%N", "/tmp/link"]), "'/tmp/link' -> 'five'");
+    }
+}
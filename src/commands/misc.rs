@@ -13,20 +13,71 @@ File System:
   pwd         - Print working directory
   cat         - Display file contents
   tree        - Display directory tree
+  du          - Disk usage of files and directories (-s, -h, -d)
+  df          - Free space left in the filesystem (-h)
   mkdir       - Create directory
   touch       - Create empty file
   rm          - Remove files/directories
-  ln          - Create symbolic links
+  ln          - Create hard links (-s for symbolic)
+  readlink    - Print where a symbolic link points
+  realpath    - Print the resolved absolute path
+  stat        - File size, inode, mode, owner and times (-L, -c)
+  file        - Guess what kind of content a file holds
+  find        - Search for files (-name, -type, -exec ...)
+  grep        - Search file contents (-r, -i, -n, -C ...)
+  cp          - Copy files (-r for directories)
+  mv          - Move or rename files
+  chmod       - Change file permissions
+  chown       - Change file owner (root only)
+  chgrp       - Change file group
+  umask       - Default permission mask
+
+Text:
+  head, tail  - First or last lines of a file (-n, -c)
+  wc          - Count lines, words and bytes
+  sort, uniq  - Sort lines, drop or count repeats
+  cut         - Select fields (-d, -f) or characters (-c)
+  tr          - Translate or delete characters
+  nl, rev     - Number lines, reverse lines
+  sed         - Edit streams (s///, d, p, -n, -i)
+  awk         - Pattern scanning and processing (-F, -v)
+  diff        - Compare files or directories (-u, -r)
+  patch       - Apply a unified diff (-p, -R, -i)
+
+Binary:
+  xxd         - Hex dump, -r turns a dump back into bytes
+  hexdump, od - Hex and octal dumps (-C, -t x1, -c ...)
+  base64      - Encode or decode base64 (-d, -w)
+  md5sum, sha1sum, sha256sum
+              - Print or check (-c) checksums
+  tar         - Pack or unpack archives (-c, -x, -t, -z, -f)
+  gzip, gunzip, zcat
+              - Compress or decompress files (-k, -c, -l)
+  download    - Save a file, or a directory as .zip, to your computer
+  upload      - Copy files from your computer into /tmp (or a directory),
+                dropping them onto the terminal works too
+
+Shell:
+  a | b       - Feed one command's output to the next
+  > file      - Write output to a file, >> appends and < reads input
+  /dev/null   - Discards writes, /dev/zero and /dev/urandom never run dry
+
+Users:
+  whoami      - Print the current user
+  id          - Print user and group ids
+  groups      - Print group memberships
+  su          - Switch user (su - for a login shell)
+  sudo        - Run a command as another user
 
 Utilities:
   clear       - Clear screen
   history     - Command history
+  exit        - End the session (also logout, Ctrl+D)
   echo        - Display text
   cowsay      - ASCII cow with message
   sl          - Steam locomotive
   lolcat      - Rainbow text
   calc        - Calculator
-  sudo        - Sudo access
 
 
 Type `ls`, then `cd projects` and `ls` again.  
@@ -34,14 +85,6 @@ Run a project with `./project-name`.""#
         .to_string()
 }
 
-pub fn sudo(args: &[&str]) -> String {
-    if args.len() >= 3 && args[0] == "rm" && args[1] == "-rf" && args[2] == "/" {
-        "SYSTEM_PANIC".to_string()
-    } else {
-        "[sudo] password for objz: \n\nSorry, try again.\n[sudo] password for objz: \n\nSudo access denied for portfolio demo.".to_string()
-    }
-}
-
 pub fn cowsay(args: &[&str]) -> String {
     let message = if args.is_empty() {
         "Hello from WASM!"
@@ -1,6 +1,21 @@
+pub mod archive;
+pub mod awk;
+pub mod checksum;
+pub mod devices;
+pub mod diff;
+pub mod dump;
 pub mod filesystem;
+pub mod inspect;
 pub mod misc;
+pub mod permissions;
 pub mod processor;
+pub mod procfs;
+pub mod search;
+pub mod sed;
+pub mod stream;
 pub mod system;
+pub mod text;
+pub mod transfer;
+pub mod users;
 
 pub use processor::CommandHandler;
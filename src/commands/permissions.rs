@@ -0,0 +1,563 @@
+use super::filesystem::{
+    normalize_path, resolve_ino, Filesystem, FsError, Ino, Node, CURRENT_PATH, FILESYSTEM,
+};
+use super::{stream, users};
+use lazy_static::lazy_static;
+use std::sync::Mutex;
+
+pub const READ: u16 = 0o4;
+pub const WRITE: u16 = 0o2;
+pub const EXECUTE: u16 = 0o1;
+
+const STICKY: u16 = 0o1000;
+const DEFAULT_UMASK: u16 = 0o022;
+
+lazy_static! {
+    static ref UMASK: Mutex<u16> = Mutex::new(DEFAULT_UMASK);
+}
+
+pub fn current_user() -> String {
+    users::current_user()
+}
+
+pub fn current_groups() -> Vec<String> {
+    users::current_groups()
+}
+
+pub fn is_root() -> bool {
+    current_user() == "root"
+}
+
+pub fn current_umask() -> u16 {
+    *UMASK.lock().unwrap()
+}
+
+pub fn reset_session() {
+    *UMASK.lock().unwrap() = DEFAULT_UMASK;
+}
+
+pub fn can_access(node: &Node, access: u16) -> bool {
+    let permissions = node.permissions();
+
+    if is_root() {
+        // root skips every check except running files nobody may execute
+        return access & EXECUTE == 0
+            || matches!(node, Node::Directory { .. })
+            || permissions & 0o111 != 0;
+    }
+
+    let shift = if node.get_owner() == current_user() {
+        6
+    } else if current_groups()
+        .iter()
+        .any(|group| group == node.get_group())
+    {
+        3
+    } else {
+        0
+    };
+
+    (permissions >> shift) & access == access
+}
+
+pub fn check(node: &Node, access: u16) -> Result<(), FsError> {
+    if can_access(node, access) {
+        Ok(())
+    } else {
+        Err(FsError::PermissionDenied)
+    }
+}
+
+// Removing or renaming an entry needs write access to its directory, and in
+// sticky directories like /tmp only the owners of the entry or the directory
+pub fn check_unlink(dir: &Node, entry: &Node) -> Result<(), FsError> {
+    check(dir, WRITE | EXECUTE)?;
+
+    let user = current_user();
+    if dir.permissions() & STICKY != 0
+        && !is_root()
+        && entry.get_owner() != user
+        && dir.get_owner() != user
+    {
+        return Err(FsError::NotPermitted);
+    }
+
+    Ok(())
+}
+
+fn parse_octal(spec: &str) -> Option<u16> {
+    if spec.is_empty() || spec.len() > 4 || !spec.chars().all(|c| ('0'..='7').contains(&c)) {
+        return None;
+    }
+    u16::from_str_radix(spec, 8).ok()
+}
+
+// Symbolic modes as in chmod(1), e.g. `u+x`, `go-w`, `a=r,u+w` or `+t`
+fn parse_symbolic(spec: &str, mut mode: u16, is_dir: bool, umask: u16) -> Option<u16> {
+    for clause in spec.split(',') {
+        let mut chars = clause.chars().peekable();
+        let mut who = 0;
+
+        while let Some(&c) = chars.peek() {
+            who |= match c {
+                'u' => 0o4700,
+                'g' => 0o2070,
+                'o' => 0o1007,
+                'a' => 0o7777,
+                _ => break,
+            };
+            chars.next();
+        }
+
+        let who_given = who != 0;
+        if !who_given {
+            who = 0o7777;
+        }
+
+        chars.peek()?;
+
+        while let Some(op) = chars.next() {
+            if !matches!(op, '+' | '-' | '=') {
+                return None;
+            }
+
+            let mut perm = 0;
+            while let Some(&c) = chars.peek() {
+                perm |= match c {
+                    'r' => 0o444,
+                    'w' => 0o222,
+                    'x' => 0o111,
+                    'X' if is_dir || mode & 0o111 != 0 => 0o111,
+                    'X' => 0,
+                    's' => 0o6000,
+                    't' => STICKY,
+                    'u' => ((mode >> 6) & 0o7) * 0o111,
+                    'g' => ((mode >> 3) & 0o7) * 0o111,
+                    'o' => (mode & 0o7) * 0o111,
+                    _ => break,
+                };
+                chars.next();
+            }
+
+            let mut affected = perm & who;
+            if !who_given {
+                affected &= !umask;
+            }
+
+            match op {
+                '+' => mode |= affected,
+                '-' => mode &= !affected,
+                _ => mode = (mode & !who) | affected,
+            }
+        }
+    }
+
+    Some(mode)
+}
+
+pub fn parse_mode(spec: &str, mode: u16, is_dir: bool) -> Option<u16> {
+    parse_octal(spec).or_else(|| parse_symbolic(spec, mode, is_dir, current_umask()))
+}
+
+fn split_recursive<'a>(args: &[&'a str], cmd: &str) -> Result<(bool, Vec<&'a str>), String> {
+    let mut recursive = false;
+    let mut rest = Vec::new();
+
+    for &arg in args {
+        if arg == "-R" {
+            recursive = true;
+        } else if arg.starts_with('-')
+            && rest.is_empty()
+            && parse_symbolic(arg, 0, false, 0).is_none()
+        {
+            return Err(stream::error(format!(
+                "{}: invalid option -- '{}'",
+                cmd,
+                &arg[1..]
+            )));
+        } else {
+            rest.push(arg);
+        }
+    }
+
+    Ok((recursive, rest))
+}
+
+// Applies `change` to every node named in `files`, descending into
+// directories when `recursive` is set. Errors are collected per file.
+fn change_nodes(
+    files: &[&str],
+    recursive: bool,
+    change: &dyn Fn(&mut Node) -> Result<(), FsError>,
+    describe: &dyn Fn(&str, FsError) -> String,
+) -> String {
+    let mut filesystem = FILESYSTEM.lock().unwrap();
+    let current_path = CURRENT_PATH.lock().unwrap();
+    let mut errors = Vec::new();
+
+    fn apply(
+        filesystem: &mut Filesystem,
+        ino: Ino,
+        name: &str,
+        recursive: bool,
+        change: &dyn Fn(&mut Node) -> Result<(), FsError>,
+        describe: &dyn Fn(&str, FsError) -> String,
+        errors: &mut Vec<String>,
+    ) {
+        let node = filesystem.get_mut(ino).unwrap();
+        if let Err(e) = change(node) {
+            errors.push(describe(name, e));
+        }
+
+        if !recursive {
+            return;
+        }
+
+        let readable = check(node, READ | EXECUTE);
+        if let Node::Directory { children, .. } = node {
+            if let Err(e) = readable {
+                errors.push(describe(name, e));
+                return;
+            }
+
+            let mut entries: Vec<_> = children
+                .iter()
+                .map(|(name, ino)| (name.clone(), *ino))
+                .collect();
+            entries.sort();
+            for (child_name, child) in entries {
+                let child_path = format!("{}/{}", name.trim_end_matches('/'), child_name);
+                apply(
+                    filesystem,
+                    child,
+                    &child_path,
+                    recursive,
+                    change,
+                    describe,
+                    errors,
+                );
+            }
+        }
+    }
+
+    for &file in files {
+        let ino = match resolve_ino(&filesystem, &normalize_path(file, &current_path), true) {
+            Ok(ino) => ino,
+            Err(e) => {
+                errors.push(describe(file, e));
+                continue;
+            }
+        };
+
+        apply(
+            &mut filesystem,
+            ino,
+            file,
+            recursive,
+            change,
+            describe,
+            &mut errors,
+        );
+    }
+
+    errors.join("\n")
+}
+
+fn check_owner(node: &Node) -> Result<(), FsError> {
+    if node.is_protected() || (!is_root() && node.get_owner() != current_user()) {
+        Err(FsError::NotPermitted)
+    } else {
+        Ok(())
+    }
+}
+
+pub fn chmod(args: &[&str]) -> String {
+    let (recursive, rest) = match split_recursive(args, "chmod") {
+        Ok(parsed) => parsed,
+        Err(e) => return e,
+    };
+
+    if rest.is_empty() {
+        return stream::error("chmod: missing operand");
+    }
+    if rest.len() < 2 {
+        return stream::error(format!("chmod: missing operand after '{}'", rest[0]));
+    }
+
+    let spec = rest[0];
+    if parse_mode(spec, 0, false).is_none() {
+        return stream::error(format!("chmod: invalid mode: '{}'", spec));
+    }
+
+    change_nodes(
+        &rest[1..],
+        recursive,
+        &|node| {
+            if let Node::Symlink { .. } = node {
+                return Ok(());
+            }
+            check_owner(node)?;
+
+            let is_dir = matches!(node, Node::Directory { .. });
+            let mode = parse_mode(spec, node.permissions(), is_dir).unwrap_or(node.permissions());
+            node.set_permissions(mode);
+            Ok(())
+        },
+        &|name, e| match e {
+            FsError::NotPermitted => stream::error(format!(
+                "chmod: changing permissions of '{}': Operation not permitted",
+                name
+            )),
+            e => stream::error(format!("chmod: cannot access '{}': {}", name, e)),
+        },
+    )
+}
+
+pub fn chown(args: &[&str]) -> String {
+    let (recursive, rest) = match split_recursive(args, "chown") {
+        Ok(parsed) => parsed,
+        Err(e) => return e,
+    };
+
+    if rest.is_empty() {
+        return stream::error("chown: missing operand");
+    }
+    if rest.len() < 2 {
+        return stream::error(format!("chown: missing operand after '{}'", rest[0]));
+    }
+
+    let (owner, group) = match rest[0].split_once(':') {
+        Some((owner, group)) => (owner, Some(group).filter(|g| !g.is_empty())),
+        None => (rest[0], None),
+    };
+    let owner = Some(owner).filter(|o| !o.is_empty());
+
+    if let Some(owner) = owner.filter(|o| users::find_user(o).is_none()) {
+        return stream::error(format!("chown: invalid user: '{}'", owner));
+    }
+    if let Some(group) = group.filter(|g| users::find_group(g).is_none()) {
+        return stream::error(format!("chown: invalid group: '{}'", group));
+    }
+
+    change_nodes(
+        &rest[1..],
+        recursive,
+        &|node| {
+            if !is_root() || node.is_protected() {
+                return Err(FsError::NotPermitted);
+            }
+            node.set_ownership(owner, group);
+            Ok(())
+        },
+        &|name, e| match e {
+            FsError::NotPermitted => stream::error(format!(
+                "chown: changing ownership of '{}': Operation not permitted",
+                name
+            )),
+            e => stream::error(format!("chown: cannot access '{}': {}", name, e)),
+        },
+    )
+}
+
+pub fn chgrp(args: &[&str]) -> String {
+    let (recursive, rest) = match split_recursive(args, "chgrp") {
+        Ok(parsed) => parsed,
+        Err(e) => return e,
+    };
+
+    if rest.is_empty() {
+        return stream::error("chgrp: missing operand");
+    }
+    if rest.len() < 2 {
+        return stream::error(format!("chgrp: missing operand after '{}'", rest[0]));
+    }
+
+    let group = rest[0];
+    if users::find_group(group).is_none() {
+        return stream::error(format!("chgrp: invalid group: '{}'", group));
+    }
+    let member = is_root() || current_groups().iter().any(|g| g == group);
+
+    change_nodes(
+        &rest[1..],
+        recursive,
+        &|node| {
+            check_owner(node)?;
+            if !member {
+                return Err(FsError::NotPermitted);
+            }
+            node.set_ownership(None, Some(group));
+            Ok(())
+        },
+        &|name, e| match e {
+            FsError::NotPermitted => stream::error(format!(
+                "chgrp: changing group of '{}': Operation not permitted",
+                name
+            )),
+            e => stream::error(format!("chgrp: cannot access '{}': {}", name, e)),
+        },
+    )
+}
+
+pub fn umask(args: &[&str]) -> String {
+    let mut symbolic = false;
+    let mut value = None;
+
+    for &arg in args {
+        match arg {
+            "-S" => symbolic = true,
+            _ if arg.starts_with('-') => {
+                return stream::error(format!("umask: {}: invalid option", arg))
+            }
+            _ => value = Some(arg),
+        }
+    }
+
+    let mut mask = UMASK.lock().unwrap();
+
+    if let Some(value) = value {
+        // `umask u=rwx,g=rx` describes the permissions that stay allowed
+        let new_mask = parse_octal(value)
+            .or_else(|| parse_symbolic(value, 0o777 & !*mask, true, 0).map(|mode| !mode & 0o777));
+
+        return match new_mask {
+            Some(new_mask) => {
+                *mask = new_mask & 0o777;
+                String::new()
+            }
+            None => stream::error(format!("umask: {}: invalid mode", value)),
+        };
+    }
+
+    if symbolic {
+        let allowed = !*mask & 0o777;
+        let class = |shift: u16| {
+            let bits = (allowed >> shift) & 0o7;
+            let mut out = String::new();
+            if bits & READ != 0 {
+                out.push('r');
+            }
+            if bits & WRITE != 0 {
+                out.push('w');
+            }
+            if bits & EXECUTE != 0 {
+                out.push('x');
+            }
+            out
+        };
+        format!("u={},g={},o={}", class(6), class(3), class(0))
+    } else {
+        format!("{:04o}", *mask)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::filesystem::{self, follow};
+    use crate::commands::CommandHandler;
+    use crate::testing;
+
+    fn owner_and_mode(path: &str) -> (String, u16) {
+        let filesystem = FILESYSTEM.lock().unwrap();
+        let node = follow(&filesystem, &normalize_path(path, &[])).unwrap();
+        (node.get_owner().to_string(), node.permissions())
+    }
+
+    fn become_guest(handler: &mut CommandHandler) {
+        handler.handle("su guest");
+        handler.answer("guest");
+        assert_eq!(current_user(), "guest");
+    }
+
+    #[test]
+    fn permission_bits_are_enforced() {
+        let _guard = testing::fresh();
+        let mut handler = CommandHandler::new();
+        assert_eq!(
+            handler.handle("cat /etc/shadow").0,
+            "cat: /etc/shadow: Permission denied"
+        );
+
+        filesystem::write_file("/tmp/note", "secret\n").unwrap();
+        handler.handle("chmod 000 /tmp/note");
+        assert_eq!(
+            handler.handle("cat /tmp/note").0,
+            "cat: /tmp/note: Permission denied"
+        );
+        assert_eq!(handler.handle("sudo cat /tmp/note").0, "secret");
+
+        handler.handle("chmod 444 /tmp/note");
+        assert_eq!(
+            handler.handle("echo more > /tmp/note").0,
+            "zsh: permission denied: /tmp/note"
+        );
+
+        // Without x on the directory nothing inside can be reached
+        handler.handle("mkdir /tmp/dir");
+        filesystem::write_file("/tmp/dir/inside", "hi\n").unwrap();
+        handler.handle("chmod 600 /tmp/dir");
+        assert_eq!(
+            handler.handle("cat /tmp/dir/inside").0,
+            "cat: /tmp/dir/inside: Permission denied"
+        );
+    }
+
+    #[test]
+    fn sticky_tmp_only_lets_owners_delete() {
+        let _guard = testing::fresh();
+        let mut handler = CommandHandler::new();
+        assert_eq!(owner_and_mode("/tmp").1, 0o1777);
+        filesystem::write_file("/tmp/mine", "objz\n").unwrap();
+
+        become_guest(&mut handler);
+        filesystem::write_file("/tmp/theirs", "guest\n").unwrap();
+        assert_eq!(
+            handler.handle("rm /tmp/mine").0,
+            "rm: cannot remove '/tmp/mine': Operation not permitted"
+        );
+        assert!(filesystem::read_file("/tmp/mine").is_ok());
+        assert_eq!(handler.handle("rm /tmp/theirs").0, "");
+        assert!(filesystem::read_file("/tmp/theirs").is_err());
+    }
+
+    #[test]
+    fn umask_masks_new_files_and_directories() {
+        let _guard = testing::fresh();
+        let mut handler = CommandHandler::new();
+        assert_eq!(handler.handle("umask").0, "0022");
+        handler.handle("touch /tmp/open");
+        assert_eq!(owner_and_mode("/tmp/open").1, 0o644);
+
+        handler.handle("umask 077");
+        assert_eq!(handler.handle("umask").0, "0077");
+        assert_eq!(handler.handle("umask -S").0, "u=rwx,g=,o=");
+        handler.handle("touch /tmp/private");
+        handler.handle("mkdir /tmp/closed");
+        assert_eq!(owner_and_mode("/tmp/private").1, 0o600);
+        assert_eq!(owner_and_mode("/tmp/closed").1, 0o700);
+
+        assert_eq!(handler.handle("umask 8").0, "umask: 8: invalid mode");
+    }
+
+    #[test]
+    fn only_root_gives_files_away() {
+        let _guard = testing::fresh();
+        let mut handler = CommandHandler::new();
+        handler.handle("touch /tmp/gift");
+        assert_eq!(
+            handler.handle("chown guest /tmp/gift").0,
+            "chown: changing ownership of '/tmp/gift': Operation not permitted"
+        );
+        assert_eq!(owner_and_mode("/tmp/gift").0, "objz");
+
+        assert_eq!(handler.handle("sudo chown guest /tmp/gift").0, "");
+        assert_eq!(owner_and_mode("/tmp/gift").0, "guest");
+        // Its new owner still can't hand it on
+        become_guest(&mut handler);
+        assert_eq!(
+            handler.handle("chown objz /tmp/gift").0,
+            "chown: changing ownership of '/tmp/gift': Operation not permitted"
+        );
+    }
+}
@@ -1,7 +1,51 @@
-use crate::commands::{filesystem, system};
+use crate::commands::search::{self, Found};
+use crate::commands::stream::{self, Output};
+use crate::commands::users::{self, AuthResult, Sudo};
+use crate::commands::{
+    archive, awk, checksum, diff, dump, filesystem, inspect, permissions, procfs, sed, system,
+    text, transfer,
+};
 
 use super::misc;
 
+// Where `<`, `>`, `>>`, `2>` and `&>` point a command
+#[derive(Clone, Copy, PartialEq)]
+enum Redirect {
+    Input,
+    Output { append: bool },
+    Errors { append: bool },
+    Both { append: bool },
+    // `2>&1` sends errors wherever output goes, `>&2` output wherever
+    // errors go
+    Duplicate { errors: bool },
+}
+
+// Where a command's output or errors end up
+#[derive(Clone, PartialEq)]
+enum Target {
+    Screen,
+    Pipe,
+    File(String),
+}
+
+#[derive(Default)]
+struct Stage {
+    words: Vec<String>,
+    redirects: Vec<(Redirect, String)>,
+}
+
+impl Stage {
+    // A finished word is the target of the operator before it, if any
+    fn push(&mut self, word: Option<String>, operator: &mut Option<Redirect>) {
+        if let Some(word) = word {
+            match operator.take() {
+                Some(redirect) => self.redirects.push((redirect, word)),
+                None => self.words.push(word),
+            }
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct CommandHandler {
     history: Vec<String>,
@@ -18,6 +62,172 @@ impl CommandHandler {
         filesystem::pwd(&[])
     }
 
+    pub fn pending_fetches(&self, input: &str) -> Vec<String> {
+        Self::pipeline(input)
+            .unwrap_or_default()
+            .iter()
+            .flat_map(|stage| {
+                let parts: Vec<&str> = stage.words.iter().map(String::as_str).collect();
+                // `>>` keeps what is already there, so it needs it too
+                let inputs: Vec<&str> = stage
+                    .redirects
+                    .iter()
+                    .filter(|(redirect, _)| {
+                        matches!(
+                            redirect,
+                            Redirect::Input
+                                | Redirect::Output { append: true }
+                                | Redirect::Errors { append: true }
+                                | Redirect::Both { append: true }
+                        )
+                    })
+                    .map(|(_, target)| target.as_str())
+                    .collect();
+                let mut urls = Self::fetches_for(&parts);
+                urls.extend(filesystem::pending_fetches(&inputs, false));
+                urls
+            })
+            .collect()
+    }
+
+    fn fetches_for(parts: &[&str]) -> Vec<String> {
+        match parts.first() {
+            Some(&"cat") | Some(&"head") | Some(&"tail") | Some(&"wc") | Some(&"sort")
+            | Some(&"uniq") | Some(&"cut") | Some(&"nl") | Some(&"rev") | Some(&"sed")
+            | Some(&"awk") | Some(&"stat") | Some(&"file") | Some(&"xxd") | Some(&"hexdump")
+            | Some(&"od") | Some(&"base64") | Some(&"md5sum") | Some(&"sha1sum")
+            | Some(&"sha256sum") => filesystem::pending_fetches(&parts[1..], false),
+            Some(&"diff") => {
+                let recursive = parts[1..]
+                    .iter()
+                    .any(|arg| arg.starts_with('-') && arg.contains('r'));
+                filesystem::pending_fetches(&parts[1..], recursive)
+            }
+            Some(&"patch") | Some(&"gzip") | Some(&"gunzip") | Some(&"zcat") => {
+                filesystem::pending_fetches(&parts[1..], false)
+            }
+            Some(&"tar") | Some(&"download") => filesystem::pending_fetches(&parts[1..], true),
+            Some(&"grep") => {
+                // `grep -r pattern` searches the current directory
+                let recursive = parts[1..]
+                    .iter()
+                    .any(|arg| arg.starts_with('-') && arg.contains(['r', 'R']));
+                let mut args = parts[1..].to_vec();
+                if recursive && args.iter().filter(|arg| !arg.starts_with('-')).count() < 2 {
+                    args.push(".");
+                }
+                filesystem::pending_fetches(&args, recursive)
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    // Splits a command line the way a shell would: quotes and backslashes
+    // keep words together, `|` feeds one command's output to the next and
+    // `>`, `>>`, `<` and friends take the next word as their file
+    fn pipeline(input: &str) -> Result<Vec<Stage>, String> {
+        let mut stages = vec![Stage::default()];
+        let mut word: Option<String> = None;
+        // Quoted or escaped, which keeps the 2 in `'2'>file` an argument
+        let mut literal = false;
+        let mut operator: Option<Redirect> = None;
+        let mut chars = input.chars().peekable();
+        let parse_error = |token: &str| format!("zsh: parse error near `{}'", token);
+
+        while let Some(c) = chars.next() {
+            match c {
+                '\'' => {
+                    let word = word.get_or_insert_with(String::new);
+                    word.extend(chars.by_ref().take_while(|&c| c != '\''));
+                    literal = true;
+                }
+                '"' => {
+                    let word = word.get_or_insert_with(String::new);
+                    while let Some(c) = chars.next() {
+                        match c {
+                            '"' => break,
+                            '\\' => match chars.next() {
+                                Some(c @ ('"' | '\\' | '$' | '`')) => word.push(c),
+                                Some(c) => {
+                                    word.push('\\');
+                                    word.push(c);
+                                }
+                                None => word.push('\\'),
+                            },
+                            c => word.push(c),
+                        }
+                    }
+                    literal = true;
+                }
+                '\\' => {
+                    word.get_or_insert_with(String::new).extend(chars.next());
+                    literal = true;
+                }
+                '|' => {
+                    stages.last_mut().unwrap().push(word.take(), &mut operator);
+                    literal = false;
+                    if operator.is_some() {
+                        return Err(parse_error("|"));
+                    }
+                    stages.push(Stage::default());
+                }
+                '>' | '<' | '&' if c != '&' || chars.peek() == Some(&'>') => {
+                    // A bare 1 or 2 right before `>` picks the stream
+                    let stream = match word.as_deref() {
+                        Some(digit @ ("1" | "2")) if !literal && c == '>' => Some(digit == "2"),
+                        _ => None,
+                    };
+                    if stream.is_some() {
+                        word = None;
+                    }
+                    stages.last_mut().unwrap().push(word.take(), &mut operator);
+                    literal = false;
+                    if operator.is_some() {
+                        return Err(parse_error(&c.to_string()));
+                    }
+                    let both = c == '&' && chars.next().is_some();
+                    let append = c != '<' && chars.next_if_eq(&'>').is_some();
+                    if !both && !append && c == '>' && chars.next_if_eq(&'&').is_some() {
+                        // `>&file` is another way to write `&>file`
+                        match chars.next_if(char::is_ascii_digit) {
+                            Some(fd) => stages.last_mut().unwrap().redirects.push((
+                                Redirect::Duplicate {
+                                    errors: stream == Some(true),
+                                },
+                                fd.to_string(),
+                            )),
+                            None => operator = Some(Redirect::Both { append: false }),
+                        }
+                    } else {
+                        operator = Some(match (c, stream) {
+                            ('<', _) => Redirect::Input,
+                            ('&', _) => Redirect::Both { append },
+                            (_, Some(true)) => Redirect::Errors { append },
+                            _ => Redirect::Output { append },
+                        });
+                    }
+                }
+                c if c.is_whitespace() => {
+                    stages.last_mut().unwrap().push(word.take(), &mut operator);
+                    literal = false;
+                }
+                c => word.get_or_insert_with(String::new).push(c),
+            }
+        }
+        stages.last_mut().unwrap().push(word, &mut operator);
+        if operator.is_some() {
+            return Err(parse_error("\\n"));
+        }
+
+        if stages
+            .iter()
+            .any(|stage| stage.words.is_empty() && stage.redirects.is_empty())
+        {
+            return Err(parse_error("|"));
+        }
+        Ok(stages)
+    }
+
     pub fn handle(&mut self, input: &str) -> (String, bool) {
         let trimmed = input.trim();
         if trimmed.is_empty() {
@@ -25,43 +235,308 @@ impl CommandHandler {
         }
 
         self.history.push(trimmed.to_string());
-        let parts: Vec<&str> = trimmed.split_whitespace().collect();
+        let stages = match Self::pipeline(trimmed) {
+            Ok(stages) => stages,
+            Err(e) => return (e, false),
+        };
+        let directory_changed = stages[0].words.first().map(String::as_str) == Some("cd");
+
+        // Every command but the last feeds the next one, exactly the bytes
+        // a file would get
+        let last = stages.len() - 1;
+        let mut stdin = None;
+        let mut screen = Output::default();
+        for (i, stage) in stages.iter().enumerate() {
+            stdin = Some(self.run(stage, stdin.take(), i == last, &mut screen));
+        }
+
+        (screen.into_text(), directory_changed)
+    }
+
+    // Runs one stage of a pipeline with its redirections, applied left to
+    // right. Every file is opened first, so nothing runs when one of them
+    // can't be. Returns what the stage feeds the next one.
+    fn run(
+        &mut self,
+        stage: &Stage,
+        mut stdin: Option<Vec<u8>>,
+        last: bool,
+        screen: &mut Output,
+    ) -> Vec<u8> {
+        let output = if last { Target::Screen } else { Target::Pipe };
+        let mut targets = [output, Target::Screen];
+        let files: Vec<&str> = stage
+            .redirects
+            .iter()
+            .filter(|(redirect, _)| !matches!(redirect, Redirect::Duplicate { .. }))
+            .map(|(_, target)| target.as_str())
+            .collect();
+        procfs::refresh_for(&files);
+        for (redirect, target) in &stage.redirects {
+            let file = Target::File(target.clone());
+            let opened = match *redirect {
+                Redirect::Input => filesystem::read_bytes(target).map(|bytes| stdin = Some(bytes)),
+                Redirect::Duplicate { errors } => {
+                    let source = match target.as_str() {
+                        "1" => targets[0].clone(),
+                        "2" => targets[1].clone(),
+                        _ => {
+                            let message = format!("zsh: {}: bad file descriptor", target);
+                            screen.line(stream::error(message));
+                            return Vec::new();
+                        }
+                    };
+                    targets[errors as usize] = source;
+                    Ok(())
+                }
+                Redirect::Output { append }
+                | Redirect::Errors { append }
+                | Redirect::Both { append } => {
+                    if let Redirect::Output { .. } | Redirect::Both { .. } = redirect {
+                        targets[0] = file.clone();
+                    }
+                    if let Redirect::Errors { .. } | Redirect::Both { .. } = redirect {
+                        targets[1] = file;
+                    }
+                    if append {
+                        filesystem::append_bytes(target, b"")
+                    } else {
+                        filesystem::write_bytes(target, b"")
+                    }
+                }
+            };
+            if let Err(e) = opened {
+                screen.line(Self::redirect_error(e, target));
+                return Vec::new();
+            }
+        }
+
+        let output = if stage.words.is_empty() {
+            Output::default()
+        } else {
+            let parts: Vec<&str> = stage.words.iter().map(String::as_str).collect();
+            stream::set_terminal(targets[0] == Target::Screen);
+            let output = self.execute(&parts, stdin.as_deref());
+            stream::set_terminal(true);
+            output
+        };
+
+        // Both streams going to one place keep the order they came in
+        let routed = if targets[0] == targets[1] {
+            vec![(output, &targets[0])]
+        } else {
+            let (output, errors) = output.split();
+            vec![(output, &targets[0]), (errors, &targets[1])]
+        };
+        let mut pipe = Vec::new();
+        let mut written = false;
+        for (output, target) in routed {
+            match target {
+                Target::Screen => screen.append(output),
+                Target::Pipe => pipe.extend(output.into_bytes()),
+                Target::File(path) => {
+                    if let Err(e) = filesystem::append_bytes(path, &output.into_bytes()) {
+                        screen.line(Self::redirect_error(e, path));
+                    }
+                    written = true;
+                }
+            }
+        }
+        if written {
+            filesystem::persist();
+        }
+        pipe
+    }
+
+    // zsh's wording, like `zsh: permission denied: /etc/shadow`
+    fn redirect_error(e: filesystem::FsError, target: &str) -> String {
+        let message = e.to_string();
+        let mut chars = message.chars();
+        let first = chars.next().map(|c| c.to_ascii_lowercase());
+        stream::error(format!(
+            "zsh: {}{}: {}",
+            first.unwrap_or_default(),
+            chars.as_str(),
+            target
+        ))
+    }
+
+    // Questions asked by a running command, like su's password prompt or
+    // `cp -i` asking before it overwrites a file
+    pub fn pending_prompt() -> Option<String> {
+        users::pending_prompt().or_else(filesystem::pending_prompt)
+    }
+
+    pub fn is_prompt_masked() -> bool {
+        users::pending_prompt().is_some()
+    }
+
+    pub fn answer(&mut self, input: &str) -> String {
+        if users::pending_prompt().is_some() {
+            return self.authenticate(input).into_text();
+        }
+
+        let output = filesystem::answer_prompt(input);
+        filesystem::persist();
+        Output::from(output).into_text()
+    }
+
+    fn authenticate(&mut self, password: &str) -> Output {
+        match users::submit_password(password) {
+            AuthResult::Granted(sudo) => self.finish_sudo(sudo, None),
+            AuthResult::Retry(message) | AuthResult::Failed(message) => message.into(),
+        }
+    }
+
+    fn finish_sudo(&mut self, sudo: Sudo, stdin: Option<&[u8]>) -> Output {
+        match sudo {
+            Sudo::Run { user, command } => {
+                let parts: Vec<&str> = command.iter().map(String::as_str).collect();
+                users::run_as(&user, || self.execute(&parts, stdin))
+            }
+            Sudo::Done(output) => output.into(),
+        }
+    }
+
+    fn find(&mut self, args: &[&str]) -> Output {
+        let mut output = Output::default();
+        for found in search::find(args) {
+            match found {
+                Found::Line(line) => output.line(line),
+                Found::Exec(command) => {
+                    let parts: Vec<&str> = command.iter().map(String::as_str).collect();
+                    output.append(self.execute(&parts, None));
+                }
+            }
+        }
+        output
+    }
+
+    fn execute(&mut self, parts: &[&str], stdin: Option<&[u8]>) -> Output {
         let cmd = parts[0];
         let args = &parts[1..];
-
-        let directory_changed = cmd == "cd";
+        procfs::refresh_for(args);
 
         let output = match cmd {
+            // These hand over exactly the bytes they read or decoded
+            "cat" => filesystem::cat(args, stdin),
+            "head" => text::head(args, stdin),
+            "tail" => text::tail(args, stdin),
+            "xxd" => dump::xxd(args, stdin),
+            "base64" => dump::base64(args, stdin),
+            "gzip" => archive::gzip(args, stdin),
+            "gunzip" => archive::gunzip(args, stdin),
+            "zcat" => archive::zcat(args, stdin),
+            "find" => self.find(args),
+            "sudo" => match users::sudo(args) {
+                Ok(sudo) => self.finish_sudo(sudo, stdin),
+                Err(e) => e.into(),
+            },
+            _ => self.execute_lines(cmd, args, stdin).into(),
+        };
+
+        if Self::is_mutating(cmd) {
+            filesystem::persist();
+        }
+
+        output
+    }
+
+    // Commands whose output is lines of text. The text tools read stdin as
+    // text, the byte tools as it is.
+    fn execute_lines(&mut self, cmd: &str, args: &[&str], bytes: Option<&[u8]>) -> String {
+        let text = bytes.map(|bytes| {
+            let text = String::from_utf8_lossy(bytes);
+            text.strip_suffix('\n').unwrap_or(&text).to_string()
+        });
+        let stdin = text.as_deref();
+
+        match cmd {
             "clear" => system::clear(args),
             "history" => self.show_history(args),
             "echo" => system::echo(args),
             "date" => system::date(args),
             "uptime" => system::uptime(args),
             "neofetch" => system::neofetch(args),
+            "exit" | "logout" => system::logout(args),
 
             "ls" => filesystem::ls(args),
             "cd" => filesystem::cd(args),
-            "cat" => filesystem::cat(args),
             "pwd" => filesystem::pwd(args),
             "tree" => filesystem::tree(args),
+            "du" => filesystem::du(args),
+            "df" => filesystem::df(args),
             "mkdir" => filesystem::mkdir(args),
             "touch" => filesystem::touch(args),
             "rm" => filesystem::rm(args),
             "uname" => filesystem::uname(args),
             "ln" => filesystem::ln(args),
+            "cp" => filesystem::cp(args),
+            "mv" => filesystem::mv(args),
+            "readlink" => filesystem::readlink(args),
+            "realpath" => filesystem::realpath(args),
+            "stat" => inspect::stat(args),
+            "file" => inspect::file(args, bytes),
+            "grep" => search::grep(args, stdin),
+
+            "wc" => text::wc(args, bytes),
+            "sort" => text::sort(args, stdin),
+            "uniq" => text::uniq(args, stdin),
+            "cut" => text::cut(args, stdin),
+            "tr" => text::tr(args, stdin),
+            "nl" => text::nl(args, stdin),
+            "rev" => text::rev(args, stdin),
+            "sed" => sed::sed(args, stdin),
+            "awk" => awk::awk(args, stdin),
+            "diff" => diff::diff(args, stdin),
+            "patch" => diff::patch(args, stdin),
+            "hexdump" => dump::hexdump(args, bytes),
+            "od" => dump::od(args, bytes),
+            "md5sum" => checksum::md5sum(args, bytes),
+            "sha1sum" => checksum::sha1sum(args, bytes),
+            "sha256sum" => checksum::sha256sum(args, bytes),
+            "tar" => archive::tar(args, bytes),
+            "download" => transfer::download(args),
+            "upload" => transfer::upload(args),
             "ll" => filesystem::ls(&["-la"]),
+            "chmod" => permissions::chmod(args),
+            "chown" => permissions::chown(args),
+            "chgrp" => permissions::chgrp(args),
+            "umask" => permissions::umask(args),
 
+            "whoami" => users::whoami(args),
+            "id" => users::id(args),
+            "groups" => users::groups(args),
+            "su" => users::su(args),
             "help" => misc::help(args),
-            "sudo" => misc::sudo(args),
             "cowsay" => misc::cowsay(args),
             "sl" => misc::sl(args),
             "lolcat" => misc::lolcat(args),
             "calc" => misc::calc(args),
 
-            _ => format!("zsh: {}: command not found", cmd),
-        };
+            _ => stream::error(format!("zsh: {}: command not found", cmd)),
+        }
+    }
 
-        (output, directory_changed)
+    fn is_mutating(cmd: &str) -> bool {
+        matches!(
+            cmd,
+            "mkdir"
+                | "touch"
+                | "rm"
+                | "ln"
+                | "cp"
+                | "mv"
+                | "chmod"
+                | "chown"
+                | "chgrp"
+                | "sed"
+                | "patch"
+                | "xxd"
+                | "tar"
+                | "gzip"
+                | "gunzip"
+        )
     }
 
     fn show_history(&self, _args: &[&str]) -> String {
@@ -77,3 +552,99 @@ impl CommandHandler {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn quotes_and_backslashes_keep_words_together() {
+        let _guard = testing::fresh();
+        let mut handler = CommandHandler::new();
+        assert_eq!(
+            handler.handle(r#"echo 'a  b' "c\"d" e\ f"#).0,
+            r#"a  b c"d e f"#
+        );
+        assert_eq!(handler.handle("echo 'x | y' | wc -w").0.trim(), "3");
+    }
+
+    #[test]
+    fn redirects_open_their_files() {
+        let _guard = testing::fresh();
+        let mut handler = CommandHandler::new();
+        assert_eq!(handler.handle("echo one > /tmp/out").0, "");
+        assert_eq!(handler.handle("echo two >> /tmp/out").0, "");
+        assert_eq!(handler.handle("cat < /tmp/out").0, "one\ntwo");
+        assert_eq!(handler.handle("echo gone > /dev/null").0, "");
+
+        assert_eq!(handler.handle("echo x 2> /tmp/errors").0, "x");
+        assert_eq!(filesystem::read_file("/tmp/errors").unwrap(), "");
+        assert_eq!(
+            handler.handle("echo x > /etc/shadow").0,
+            "zsh: operation not permitted: /etc/shadow"
+        );
+        assert_eq!(handler.handle("echo x >").0, "zsh: parse error near `\\n'");
+    }
+
+    #[test]
+    fn redirects_write_exactly_the_bytes() {
+        let _guard = testing::fresh();
+        let mut handler = CommandHandler::new();
+        handler.handle("head -c 8 /dev/zero > /tmp/zero");
+        assert_eq!(filesystem::read_bytes("/tmp/zero").unwrap(), vec![0; 8]);
+        handler.handle("echo hi > /tmp/hi");
+        assert_eq!(filesystem::read_bytes("/tmp/hi").unwrap(), b"hi\n");
+    }
+
+    #[test]
+    fn pipes_carry_bytes() {
+        let _guard = testing::fresh();
+        let mut handler = CommandHandler::new();
+        assert_eq!(handler.handle("tail -c 4 /dev/zero | wc -c").0, "4");
+        assert_eq!(
+            handler.handle("cat /dev/urandom | head -c 3 | wc -c").0,
+            "3"
+        );
+        assert_eq!(
+            handler.handle("head -c 8 /dev/zero | xxd").0,
+            "00000000: 0000 0000 0000 0000                      ........"
+        );
+
+        handler.handle("head -c 16 /dev/urandom > /tmp/random");
+        let random = filesystem::read_bytes("/tmp/random").unwrap();
+        let hex: String = random.iter().map(|byte| format!("{:02x}", byte)).collect();
+        assert_eq!(handler.handle("cat /tmp/random | xxd -p").0, hex);
+    }
+
+    #[test]
+    fn errors_go_where_2_points() {
+        let _guard = testing::fresh();
+        let mut handler = CommandHandler::new();
+        assert_eq!(handler.handle("ls nonexistent 2>/dev/null").0, "");
+        assert_eq!(
+            handler.handle("cat nofile /etc/hostname 2>/dev/null").0,
+            "wasm-host"
+        );
+
+        handler.handle("cat nofile /etc/hostname 2> /tmp/errors > /tmp/output");
+        let error = "cat: nofile: No such file or directory\n";
+        assert_eq!(filesystem::read_file("/tmp/errors").unwrap(), error);
+        assert_eq!(filesystem::read_file("/tmp/output").unwrap(), "wasm-host\n");
+
+        handler.handle("cat nofile /etc/hostname > /tmp/both 2>&1");
+        let both = format!("{}wasm-host\n", error);
+        assert_eq!(filesystem::read_file("/tmp/both").unwrap(), both);
+    }
+
+    #[test]
+    fn errors_skip_the_pipe() {
+        let _guard = testing::fresh();
+        let mut handler = CommandHandler::new();
+        assert_eq!(
+            handler.handle("ls nonexistent | wc -l").0,
+            "ls: cannot access 'nonexistent': No such file or directory\n0"
+        );
+        assert_eq!(handler.handle("ls nonexistent 2>&1 | wc -l").0, "1");
+    }
+}
@@ -0,0 +1,387 @@
+use super::filesystem::{
+    self, canonicalize, display_path, normalize_path, resolve, Content, Ino, Node, Timestamps,
+    CURRENT_PATH, FILESYSTEM, PROC_INO, PROC_INOS,
+};
+use super::system;
+use super::users::{self, Process};
+use std::collections::HashMap;
+use wasm_bindgen::JsCast;
+
+// Every process takes its directory and the handful of entries inside, so
+// this many always fit in PROC_INOS
+const MAX_PROCESSES: usize = 100;
+// All of the address space a wasm32 module can grow into
+const MEMORY_LIMIT: u64 = 4 * 1024 * 1024 * 1024;
+// What runs in each process
+const SHELL: &str = "zsh";
+
+fn cpu_count() -> usize {
+    if cfg!(target_arch = "wasm32") {
+        web_sys::window()
+            .map(|window| window.navigator().hardware_concurrency() as usize)
+            .unwrap_or(1)
+            .max(1)
+    } else {
+        std::thread::available_parallelism().map_or(1, |count| count.get())
+    }
+}
+
+// Bytes of linear memory the module has grown to so far
+fn memory_used() -> u64 {
+    if cfg!(target_arch = "wasm32") {
+        wasm_bindgen::memory()
+            .unchecked_into::<js_sys::WebAssembly::Memory>()
+            .buffer()
+            .unchecked_into::<js_sys::ArrayBuffer>()
+            .byte_length() as u64
+    } else {
+        0
+    }
+}
+
+fn uptime() -> String {
+    let seconds = ((system::timestamp() - system::started()) / 1000.0).max(0.0);
+    // The second field sums the time every CPU spent idle, which in a
+    // terminal waiting for keypresses is nearly all of it
+    format!("{:.2} {:.2}\n", seconds, seconds * cpu_count() as f64)
+}
+
+fn version() -> String {
+    format!(
+        "{} version {} (root@{}) {}\n",
+        filesystem::SYSNAME,
+        filesystem::RELEASE,
+        filesystem::NODENAME,
+        filesystem::KERNEL_VERSION
+    )
+}
+
+fn cpuinfo() -> String {
+    let count = cpu_count();
+    (0..count)
+        .map(|cpu| {
+            format!(
+                "processor\t: {}\n\
+                 vendor_id\t: WebAssembly\n\
+                 model name\t: {}\n\
+                 physical id\t: 0\n\
+                 siblings\t: {}\n\
+                 core id\t\t: {}\n\
+                 cpu cores\t: {}\n\
+                 flags\t\t: bulk-memory mutable-globals sign-ext\n\n",
+                cpu,
+                filesystem::MACHINE,
+                count,
+                cpu,
+                count
+            )
+        })
+        .collect()
+}
+
+fn meminfo() -> String {
+    let total = MEMORY_LIMIT / 1024;
+    let free = total.saturating_sub(memory_used() / 1024);
+    [
+        ("MemTotal", total),
+        ("MemFree", free),
+        ("MemAvailable", free),
+        ("Buffers", 0),
+        ("Cached", 0),
+        ("SwapTotal", 0),
+        ("SwapFree", 0),
+    ]
+    .iter()
+    .map(|(name, kb)| format!("{:<15} {:>8} kB\n", format!("{}:", name), kb))
+    .collect()
+}
+
+fn status(process: &Process, running: bool) -> String {
+    let groups: Vec<String> = process
+        .groups
+        .iter()
+        .map(|group| group.gid.to_string())
+        .collect();
+    format!(
+        "Name:\t{}\n\
+         State:\t{}\n\
+         Tgid:\t{}\n\
+         Pid:\t{}\n\
+         PPid:\t{}\n\
+         Uid:\t{uid}\t{uid}\t{uid}\t{uid}\n\
+         Gid:\t{gid}\t{gid}\t{gid}\t{gid}\n\
+         Groups:\t{}\n\
+         Threads:\t1\n",
+        SHELL,
+        if running {
+            "R (running)"
+        } else {
+            "S (sleeping)"
+        },
+        process.pid,
+        process.pid,
+        process.ppid,
+        groups.join(" "),
+        uid = process.user.uid,
+        gid = process.user.gid,
+    )
+}
+
+// Hands out inodes in PROC_INOS. /proc itself always gets the first.
+struct Tree {
+    nodes: Vec<(Ino, Node)>,
+    next: Ino,
+    owner: String,
+    times: Timestamps,
+}
+
+impl Tree {
+    fn new(times: Timestamps) -> Self {
+        Self {
+            nodes: Vec::new(),
+            next: PROC_INO + 1,
+            owner: "root".to_string(),
+            times,
+        }
+    }
+
+    fn add(&mut self, node: Node) -> Ino {
+        let ino = self.next;
+        self.next += 1;
+        self.nodes.push((ino, node));
+        ino
+    }
+
+    fn file(&mut self, content: Content, permissions: u16) -> Ino {
+        self.add(Node::File {
+            content,
+            permissions,
+            owner: self.owner.clone(),
+            group: self.owner.clone(),
+            protected: true,
+            times: self.times,
+            url: None,
+        })
+    }
+
+    fn text(&mut self, text: String) -> Ino {
+        self.file(Content::Text(text), 0o444)
+    }
+
+    fn symlink(&mut self, target: String) -> Ino {
+        self.add(Node::Symlink {
+            target,
+            owner: self.owner.clone(),
+            group: self.owner.clone(),
+            times: self.times,
+        })
+    }
+
+    fn directory(&self, children: HashMap<String, Ino>) -> Node {
+        Node::Directory {
+            children,
+            permissions: 0o555,
+            owner: self.owner.clone(),
+            group: self.owner.clone(),
+            protected: true,
+            times: self.times,
+        }
+    }
+
+    // A process's entries belong to whoever runs it
+    fn process(&mut self, process: &Process, running: bool) -> Ino {
+        self.owner = process.user.name.clone();
+        // Arguments end in NUL rather than newline
+        let cmdline = Content::from_bytes(format!("{}\0", SHELL).into_bytes());
+        let comm = Content::Text(format!("{}\n", SHELL));
+        let children = HashMap::from([
+            ("cmdline".to_string(), self.file(cmdline, 0o444)),
+            ("comm".to_string(), self.file(comm, 0o644)),
+            ("status".to_string(), self.text(status(process, running))),
+            ("cwd".to_string(), self.symlink(display_path(&process.cwd))),
+        ]);
+        let dir = self.directory(children);
+        let ino = self.add(dir);
+        self.owner = "root".to_string();
+        ino
+    }
+}
+
+// /proc with nothing in it, for the built-in filesystem
+pub fn empty() -> Vec<(Ino, Node)> {
+    let tree = Tree::new(Timestamps::at(0.0));
+    vec![(PROC_INO, tree.directory(HashMap::new()))]
+}
+
+fn generate() -> Vec<(Ino, Node)> {
+    let mut tree = Tree::new(Timestamps::at(system::started()));
+
+    let mut children = HashMap::from([
+        ("uptime".to_string(), tree.text(uptime())),
+        ("version".to_string(), tree.text(version())),
+        ("cpuinfo".to_string(), tree.text(cpuinfo())),
+        ("meminfo".to_string(), tree.text(meminfo())),
+    ]);
+
+    // The most recent shells are the ones worth listing
+    let processes = users::processes();
+    let skipped = processes.len().saturating_sub(MAX_PROCESSES);
+    for (i, process) in processes.iter().enumerate().skip(skipped) {
+        let running = i + 1 == processes.len();
+        let ino = tree.process(process, running);
+        children.insert(process.pid.to_string(), ino);
+        if running {
+            let link = tree.symlink(process.pid.to_string());
+            children.insert("self".to_string(), link);
+        }
+    }
+    debug_assert!(tree.next <= PROC_INOS.end);
+
+    let root = tree.directory(children);
+    tree.nodes.push((PROC_INO, root));
+    tree.nodes
+}
+
+// Regenerates /proc so whatever reads it next sees the current state
+fn refresh() {
+    let nodes = generate();
+    FILESYSTEM.lock().unwrap().mount_proc(nodes);
+}
+
+// /proc itself, somewhere inside it, or / above it, which a recursive walk
+// goes down into
+fn reaches(path: &[String]) -> bool {
+    path.first().is_none_or(|first| first == "proc")
+}
+
+/// Refreshes /proc only when it may be read: the current directory or one
+/// of `names` leads into it, directly or through a link. Anything else
+/// leaves the last snapshot in place rather than rebuilding it each time.
+pub fn refresh_for(names: &[&str]) {
+    let current = CURRENT_PATH.lock().unwrap().clone();
+    let touched = reaches(&current)
+        || names.iter().any(|name| {
+            // Also without following the last link, since the snapshot's
+            // own links, like /proc/self/cwd, lead back out of it
+            let path = normalize_path(name, &current);
+            let filesystem = FILESYSTEM.lock().unwrap();
+            let resolved = [
+                resolve(&filesystem, &path, false),
+                canonicalize(&filesystem, &path),
+            ];
+            reaches(&path) || resolved.iter().flatten().any(|real| reaches(real))
+        });
+    if touched {
+        refresh();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::CommandHandler;
+    use crate::testing;
+
+    fn generated(path: &str) -> bool {
+        let filesystem = FILESYSTEM.lock().unwrap();
+        filesystem::lookup(&filesystem, &normalize_path(path, &[])).is_ok()
+    }
+
+    #[test]
+    fn self_cwd_follows_cd() {
+        let _guard = testing::fresh();
+        let mut handler = CommandHandler::new();
+        assert_eq!(handler.handle("readlink /proc/self/cwd").0, "/home/objz");
+        handler.handle("cd /tmp");
+        assert_eq!(handler.handle("readlink /proc/self/cwd").0, "/tmp");
+
+        // Through a link into /proc, and from inside it
+        handler.handle("ln -s /proc/self /tmp/me");
+        handler.handle("cd /etc");
+        assert_eq!(handler.handle("readlink /tmp/me/cwd").0, "/etc");
+        handler.handle("cd /proc/self");
+        assert_eq!(handler.handle("readlink cwd").0, "/proc/1");
+    }
+
+    #[test]
+    fn su_adds_a_process_until_exit() {
+        let _guard = testing::fresh();
+        let mut handler = CommandHandler::new();
+        assert_eq!(
+            handler.handle("ls /proc").0,
+            "1/  cpuinfo  meminfo  self@  uptime  version"
+        );
+
+        handler.handle("su guest");
+        handler.answer("guest");
+        // Nothing is generated until something looks
+        handler.handle("cd /tmp");
+        assert!(!generated("/proc/2"));
+        assert_eq!(
+            handler.handle("ls /proc").0,
+            "1/  2/  cpuinfo  meminfo  self@  uptime  version"
+        );
+        assert_eq!(handler.handle("readlink /proc/self").0, "2");
+        assert_eq!(
+            handler
+                .handle("stat -c '%U %n' /proc/1/status /proc/2/cwd")
+                .0,
+            "objz /proc/1/status\nguest /proc/2/cwd"
+        );
+        assert_eq!(
+            handler.handle("grep Uid /proc/self/status | cat").0,
+            "Uid:\t1001\t1001\t1001\t1001"
+        );
+
+        handler.handle("exit");
+        assert_eq!(handler.handle("readlink /proc/self").0, "1");
+        assert!(!generated("/proc/2"));
+    }
+
+    #[test]
+    fn nothing_under_proc_can_be_changed() {
+        let _guard = testing::fresh();
+        let mut handler = CommandHandler::new();
+        let refused = [
+            (
+                "echo hi > /proc/self/comm",
+                "zsh: operation not permitted: /proc/self/comm",
+            ),
+            (
+                "rm /proc/uptime",
+                "rm: cannot remove '/proc/uptime': Operation not permitted",
+            ),
+            (
+                "rm -r /proc/1",
+                "rm: cannot remove '/proc/1': Operation not permitted",
+            ),
+            (
+                "sudo rm /proc/version",
+                "rm: cannot remove '/proc/version': Operation not permitted",
+            ),
+            (
+                "touch /proc/new",
+                "touch: cannot touch '/proc/new': Operation not permitted",
+            ),
+            (
+                "mkdir /proc/new",
+                "mkdir: cannot create directory '/proc/new': Operation not permitted",
+            ),
+            (
+                "mv /proc/uptime /tmp",
+                "mv: cannot move '/proc/uptime' to '/tmp/uptime': Operation not permitted",
+            ),
+        ];
+        for (command, error) in &refused {
+            assert_eq!(handler.handle(command).0, *error, "{}", command);
+        }
+        assert_eq!(
+            handler.handle("ls /proc").0,
+            "1/  cpuinfo  meminfo  self@  uptime  version"
+        );
+        assert!(handler
+            .handle("cat /proc/version")
+            .0
+            .starts_with("WASM version"));
+    }
+}
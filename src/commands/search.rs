@@ -0,0 +1,975 @@
+use super::filesystem::{normalize_path, resolve_ino, walk_tree, Node, CURRENT_PATH, FILESYSTEM};
+use super::permissions::{self, EXECUTE, READ};
+use super::{stream, system, users};
+use regex_lite::{Regex, RegexBuilder};
+
+const DAY: f64 = 1000.0 * 60.0 * 60.0 * 24.0;
+
+// What find produces, in order. Commands from -exec run once the walk is
+// over, so they can change the filesystem without disturbing the walk.
+pub enum Found {
+    Line(String),
+    Exec(Vec<String>),
+}
+
+// Numeric find arguments: `+n` is more than n, `-n` less, `n` exactly
+#[derive(Clone, Copy)]
+enum Compare {
+    Less,
+    Exactly,
+    More,
+}
+
+impl Compare {
+    fn split(spec: &str) -> (Self, &str) {
+        if let Some(rest) = spec.strip_prefix('+') {
+            (Compare::More, rest)
+        } else if let Some(rest) = spec.strip_prefix('-') {
+            (Compare::Less, rest)
+        } else {
+            (Compare::Exactly, spec)
+        }
+    }
+
+    fn matches(self, value: u64, n: u64) -> bool {
+        match self {
+            Compare::Less => value < n,
+            Compare::Exactly => value == n,
+            Compare::More => value > n,
+        }
+    }
+}
+
+enum PermMatch {
+    Exact(u16),
+    All(u16),
+    Any(u16),
+}
+
+enum Expr {
+    Name { pattern: String, ignore_case: bool },
+    Type(char),
+    Size(Compare, u64, u64),
+    Mtime(Compare, u64),
+    User(String),
+    Perm(PermMatch),
+    Print,
+    Exec(Vec<String>),
+    True,
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+struct Parser<'a> {
+    args: &'a [&'a str],
+    pos: usize,
+    min_depth: usize,
+    max_depth: usize,
+    has_action: bool,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a str> {
+        self.args.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<&'a str> {
+        let arg = self.peek();
+        self.pos += 1;
+        arg
+    }
+
+    fn argument(&mut self, predicate: &str) -> Result<&'a str, String> {
+        self.next()
+            .ok_or_else(|| stream::error(format!("find: missing argument to `{}'", predicate)))
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.parse_and()?;
+        while matches!(self.peek(), Some("-o") | Some("-or")) {
+            self.next();
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    // Predicates next to each other are joined with an implicit -and
+    fn parse_and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.parse_unary()?;
+        loop {
+            match self.peek() {
+                None | Some("-o") | Some("-or") | Some(")") => return Ok(expr),
+                Some("-a") | Some("-and") => {
+                    self.next();
+                }
+                _ => {}
+            }
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_unary()?));
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some("!") | Some("-not") => Ok(Expr::Not(Box::new(self.parse_unary()?))),
+            Some("(") => {
+                let expr = self.parse_or()?;
+                if self.next() != Some(")") {
+                    return Err(stream::error("find: invalid expression; I was expecting to find a ')' somewhere but did not see one."));
+                }
+                Ok(expr)
+            }
+            Some(predicate) => self.parse_primary(predicate),
+            None => Err(stream::error("find: invalid expression")),
+        }
+    }
+
+    fn parse_primary(&mut self, predicate: &'a str) -> Result<Expr, String> {
+        match predicate {
+            "-name" | "-iname" => Ok(Expr::Name {
+                pattern: self.argument(predicate)?.to_string(),
+                ignore_case: predicate == "-iname",
+            }),
+            "-type" => match self.argument(predicate)? {
+                kind @ ("f" | "d" | "l" | "c") => Ok(Expr::Type(kind.chars().next().unwrap())),
+                kind => Err(stream::error(format!(
+                    "find: Unknown argument to -type: {}",
+                    kind
+                ))),
+            },
+            "-size" => {
+                let spec = self.argument(predicate)?;
+                let (compare, rest) = Compare::split(spec);
+                let (number, unit) = match rest.chars().last() {
+                    Some('c') => (&rest[..rest.len() - 1], 1),
+                    Some('w') => (&rest[..rest.len() - 1], 2),
+                    Some('b') => (&rest[..rest.len() - 1], 512),
+                    Some('k') => (&rest[..rest.len() - 1], 1024),
+                    Some('M') => (&rest[..rest.len() - 1], 1024 * 1024),
+                    Some('G') => (&rest[..rest.len() - 1], 1024 * 1024 * 1024),
+                    _ => (rest, 512),
+                };
+                match number.parse() {
+                    Ok(n) => Ok(Expr::Size(compare, n, unit)),
+                    Err(_) => Err(stream::error(format!(
+                        "find: invalid -size argument `{}'",
+                        spec
+                    ))),
+                }
+            }
+            "-mtime" => {
+                let spec = self.argument(predicate)?;
+                let (compare, rest) = Compare::split(spec);
+                match rest.parse() {
+                    Ok(days) => Ok(Expr::Mtime(compare, days)),
+                    Err(_) => Err(stream::error(format!(
+                        "find: invalid argument `{}' to `{}'",
+                        spec, predicate
+                    ))),
+                }
+            }
+            "-user" => {
+                let user = self.argument(predicate)?;
+                if users::find_user(user).is_none() {
+                    return Err(stream::error(format!(
+                        "find: '{}' is not the name of a known user",
+                        user
+                    )));
+                }
+                Ok(Expr::User(user.to_string()))
+            }
+            "-perm" => {
+                let spec = self.argument(predicate)?;
+                let (kind, mode): (fn(u16) -> PermMatch, &str) =
+                    if let Some(mode) = spec.strip_prefix('-') {
+                        (PermMatch::All, mode)
+                    } else if let Some(mode) = spec.strip_prefix('/') {
+                        (PermMatch::Any, mode)
+                    } else {
+                        (PermMatch::Exact, spec)
+                    };
+                match permissions::parse_mode(mode, 0, false) {
+                    Some(mode) => Ok(Expr::Perm(kind(mode))),
+                    None => Err(stream::error(format!("find: invalid mode '{}'", spec))),
+                }
+            }
+            "-maxdepth" | "-mindepth" => {
+                let value = self.argument(predicate)?;
+                let depth = value.parse().map_err(|_| {
+                    stream::error(format!(
+                        "find: Expected a positive decimal integer argument to {}, but got `{}'",
+                        predicate, value
+                    ))
+                })?;
+                if predicate == "-maxdepth" {
+                    self.max_depth = depth;
+                } else {
+                    self.min_depth = depth;
+                }
+                Ok(Expr::True)
+            }
+            "-print" => {
+                self.has_action = true;
+                Ok(Expr::Print)
+            }
+            "-exec" => {
+                let mut command = Vec::new();
+                loop {
+                    match self.next() {
+                        Some(";") | Some("\\;") if !command.is_empty() => break,
+                        Some(";") | Some("\\;") | None => {
+                            return Err(stream::error("find: missing argument to `-exec'"))
+                        }
+                        Some(arg) => command.push(arg.to_string()),
+                    }
+                }
+                self.has_action = true;
+                Ok(Expr::Exec(command))
+            }
+            _ if predicate.starts_with('-') => Err(stream::error(format!(
+                "find: unknown predicate `{}'",
+                predicate
+            ))),
+            _ => Err(stream::error(format!(
+                "find: paths must precede expression: `{}'",
+                predicate
+            ))),
+        }
+    }
+}
+
+// Shell wildcards as used by -name: `*`, `?`, `[a-z]`, `[!x]` and `\` escapes
+fn glob_match(pattern: &[char], text: &[char]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some(('*', rest)) => (0..=text.len()).any(|skip| glob_match(rest, &text[skip..])),
+        Some(('?', rest)) => !text.is_empty() && glob_match(rest, &text[1..]),
+        Some(('[', rest)) if rest.iter().skip(1).any(|&c| c == ']') => {
+            let (negated, class) = match rest.first() {
+                Some('!') | Some('^') => (true, &rest[1..]),
+                _ => (false, rest),
+            };
+            // A `]` right after the opening bracket is part of the class
+            let end = 1 + class[1..].iter().position(|&c| c == ']').unwrap();
+            let (class, rest) = (&class[..end], &class[end + 1..]);
+
+            let c = match text.first() {
+                Some(c) => *c,
+                None => return false,
+            };
+            let mut matched = false;
+            let mut i = 0;
+            while i < class.len() {
+                if i + 2 < class.len() && class[i + 1] == '-' {
+                    matched |= (class[i]..=class[i + 2]).contains(&c);
+                    i += 3;
+                } else {
+                    matched |= class[i] == c;
+                    i += 1;
+                }
+            }
+
+            matched != negated && glob_match(rest, &text[1..])
+        }
+        Some(('\\', [escaped, rest @ ..])) => {
+            text.first() == Some(escaped) && glob_match(rest, &text[1..])
+        }
+        Some((c, rest)) => text.first() == Some(c) && glob_match(rest, &text[1..]),
+    }
+}
+
+struct Candidate<'a> {
+    display: &'a str,
+    name: &'a str,
+    node: &'a Node,
+    now: f64,
+}
+
+fn evaluate(expr: &Expr, candidate: &Candidate, found: &mut Vec<Found>) -> bool {
+    let node = candidate.node;
+
+    match expr {
+        Expr::Name {
+            pattern,
+            ignore_case,
+        } => {
+            let (pattern, name) = if *ignore_case {
+                (pattern.to_lowercase(), candidate.name.to_lowercase())
+            } else {
+                (pattern.clone(), candidate.name.to_string())
+            };
+            let pattern: Vec<char> = pattern.chars().collect();
+            let name: Vec<char> = name.chars().collect();
+            glob_match(&pattern, &name)
+        }
+        Expr::Type(kind) => matches!(
+            (kind, node),
+            ('f', Node::File { .. })
+                | ('d', Node::Directory { .. })
+                | ('l', Node::Symlink { .. })
+                | ('c', Node::Device { .. })
+        ),
+        Expr::Size(compare, n, unit) => {
+            let size = node.size() as u64;
+            compare.matches(size.div_ceil(*unit), *n)
+        }
+        Expr::Mtime(compare, days) => {
+            let age = ((candidate.now - node.times().mtime) / DAY).max(0.0) as u64;
+            compare.matches(age, *days)
+        }
+        Expr::User(user) => node.get_owner() == user,
+        Expr::Perm(perm) => {
+            let mode = node.permissions() & 0o7777;
+            match perm {
+                PermMatch::Exact(bits) => mode == *bits,
+                PermMatch::All(bits) => mode & bits == *bits,
+                PermMatch::Any(bits) => *bits == 0 || mode & bits != 0,
+            }
+        }
+        Expr::Print => {
+            found.push(Found::Line(candidate.display.to_string()));
+            true
+        }
+        Expr::Exec(command) => {
+            let command = command
+                .iter()
+                .map(|arg| arg.replace("{}", candidate.display))
+                .collect();
+            found.push(Found::Exec(command));
+            true
+        }
+        Expr::True => true,
+        Expr::Not(expr) => !evaluate(expr, candidate, found),
+        Expr::And(left, right) => {
+            evaluate(left, candidate, found) && evaluate(right, candidate, found)
+        }
+        Expr::Or(left, right) => {
+            evaluate(left, candidate, found) || evaluate(right, candidate, found)
+        }
+    }
+}
+
+pub fn find(args: &[&str]) -> Vec<Found> {
+    let is_expression = |arg: &&str| arg.starts_with('-') || *arg == "!" || *arg == "(";
+    let paths_end = args.iter().position(is_expression).unwrap_or(args.len());
+    let (paths, expression) = args.split_at(paths_end);
+    let paths = if paths.is_empty() { &["."][..] } else { paths };
+
+    let mut parser = Parser {
+        args: expression,
+        pos: 0,
+        min_depth: 0,
+        max_depth: usize::MAX,
+        has_action: false,
+    };
+    let mut expr = if expression.is_empty() {
+        Expr::True
+    } else {
+        match parser.parse_or() {
+            Ok(expr) => expr,
+            Err(e) => return vec![Found::Line(e)],
+        }
+    };
+    if let Some(extra) = parser.peek() {
+        return vec![Found::Line(stream::error(format!(
+            "find: unexpected argument `{}'",
+            extra
+        )))];
+    }
+    if !parser.has_action {
+        expr = Expr::And(Box::new(expr), Box::new(Expr::Print));
+    }
+
+    let filesystem = FILESYSTEM.lock().unwrap();
+    let current_path = CURRENT_PATH.lock().unwrap();
+    let now = system::timestamp();
+    let mut found = Vec::new();
+
+    for &start in paths {
+        // Like find -P, links are never followed, not even on the command line
+        let start_ino = match resolve_ino(&filesystem, &normalize_path(start, &current_path), false)
+        {
+            Ok(ino) => ino,
+            Err(e) => {
+                found.push(Found::Line(stream::error(format!(
+                    "find: '{}': {}",
+                    start, e
+                ))));
+                continue;
+            }
+        };
+        let node = filesystem.get(start_ino).unwrap();
+
+        if parser.min_depth == 0 {
+            let name = start.trim_end_matches('/').rsplit('/').next().unwrap();
+            let candidate = Candidate {
+                display: start,
+                name: if name.is_empty() { "/" } else { name },
+                node,
+                now,
+            };
+            evaluate(&expr, &candidate, &mut found);
+        }
+
+        if !matches!(node, Node::Directory { .. }) || parser.max_depth == 0 {
+            continue;
+        }
+        if !permissions::can_access(node, READ | EXECUTE) {
+            found.push(Found::Line(stream::error(format!(
+                "find: '{}': Permission denied",
+                start
+            ))));
+            continue;
+        }
+
+        let prefix = start.trim_end_matches('/');
+        walk_tree(&filesystem, start_ino, &mut |entry| {
+            let display = format!("{}/{}", prefix, entry.path.join("/"));
+
+            if entry.depth >= parser.min_depth {
+                let candidate = Candidate {
+                    display: &display,
+                    name: entry.path.last().unwrap(),
+                    node: entry.node,
+                    now,
+                };
+                evaluate(&expr, &candidate, &mut found);
+            }
+
+            match entry.node {
+                Node::Directory { .. } if entry.depth < parser.max_depth => {
+                    if permissions::can_access(entry.node, READ | EXECUTE) {
+                        true
+                    } else {
+                        found.push(Found::Line(stream::error(format!(
+                            "find: '{}': Permission denied",
+                            display
+                        ))));
+                        false
+                    }
+                }
+                _ => false,
+            }
+        });
+    }
+
+    found
+}
+
+const GREP_USAGE: &str = "Usage: grep [OPTION]... PATTERNS [FILE]...";
+
+// GNU grep's colors, used only when the output goes to the screen
+const MATCH_COLOR: &str = "\x1b[31m";
+const NAME_COLOR: &str = "\x1b[35m";
+const NUMBER_COLOR: &str = "\x1b[32m";
+const SEPARATOR_COLOR: &str = "\x1b[36m";
+const RESET: &str = "\x1b[0m";
+
+#[derive(Default)]
+struct GrepOptions {
+    ignore_case: bool,
+    invert: bool,
+    line_numbers: bool,
+    count: bool,
+    files_with_matches: bool,
+    recursive: bool,
+    word: bool,
+    extended: bool,
+    fixed: bool,
+    before: usize,
+    after: usize,
+}
+
+// Basic regular expressions swap the meaning of escaped and plain `( ) { } | + ?`
+pub fn translate_basic(pattern: &str) -> String {
+    let mut out = String::new();
+    let mut chars = pattern.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(c @ ('(' | ')' | '{' | '}' | '|' | '+' | '?')) => out.push(c),
+                Some('<') | Some('>') => out.push_str("\\b"),
+                Some(c) => {
+                    out.push('\\');
+                    out.push(c);
+                }
+                None => out.push_str("\\\\"),
+            },
+            '(' | ')' | '{' | '}' | '|' | '+' | '?' => {
+                out.push('\\');
+                out.push(c);
+            }
+            // A leading `*` has nothing to repeat and matches itself
+            '*' if out.is_empty() || out.ends_with('(') || out.ends_with('|') => {
+                out.push_str("\\*")
+            }
+            // Bracket expressions are copied as they are
+            '[' => {
+                out.push('[');
+                if chars.peek() == Some(&'^') {
+                    out.push(chars.next().unwrap());
+                }
+                if chars.peek() == Some(&']') {
+                    out.push_str("\\]");
+                    chars.next();
+                }
+                while let Some(c) = chars.next() {
+                    match c {
+                        '[' if chars.peek() == Some(&':') => {
+                            out.push('[');
+                            for c in chars.by_ref() {
+                                out.push(c);
+                                if c == ']' {
+                                    break;
+                                }
+                            }
+                        }
+                        ']' => {
+                            out.push(']');
+                            break;
+                        }
+                        '\\' => out.push_str("\\\\"),
+                        c => out.push(c),
+                    }
+                }
+            }
+            c => out.push(c),
+        }
+    }
+
+    out
+}
+
+fn grep_regex(pattern: &str, options: &GrepOptions) -> Result<Regex, String> {
+    let mut pattern = if options.fixed {
+        regex_lite::escape(pattern)
+    } else if options.extended {
+        pattern.to_string()
+    } else {
+        translate_basic(pattern)
+    };
+    if options.word {
+        pattern = format!("\\b(?:{})\\b", pattern);
+    }
+
+    RegexBuilder::new(&pattern)
+        .case_insensitive(options.ignore_case)
+        .build()
+        .map_err(|_| stream::error("grep: Invalid regular expression"))
+}
+
+fn paint(color: &str, text: &str) -> String {
+    format!("{}{}{}", color, text, RESET)
+}
+
+// Colors every match in `line`, empty ones have nothing to show
+fn highlight(regex: &Regex, line: &str) -> String {
+    let mut out = String::new();
+    let mut last = 0;
+    for found in regex.find_iter(line).filter(|found| !found.is_empty()) {
+        out.push_str(&line[last..found.start()]);
+        out.push_str(&paint(MATCH_COLOR, found.as_str()));
+        last = found.end();
+    }
+    out.push_str(&line[last..]);
+    out
+}
+
+// Reads the files grep was given, descending into directories with -r.
+// Errors are kept in order so they show up between the matches.
+fn grep_inputs(files: &[&str], recursive: bool) -> Vec<Result<(String, String), String>> {
+    let filesystem = FILESYSTEM.lock().unwrap();
+    let current_path = CURRENT_PATH.lock().unwrap();
+    let mut inputs = Vec::new();
+
+    // A device named on the command line gives one block, which is all of
+    // an endless one like /dev/zero that grep ever looks at
+    let read = |name: &str, node: &Node| match node {
+        Node::File { .. } | Node::Device { .. } => {
+            permissions::check(node, READ)
+                .map_err(|e| stream::error(format!("grep: {}: {}", name, e)))?;
+            node.content()
+                .ok_or_else(|| stream::error(format!("grep: {}: Input/output error", name)))
+        }
+        _ => Err(stream::error(format!("grep: {}: Is a directory", name))),
+    };
+
+    for &file in files {
+        let path = normalize_path(file, &current_path);
+        let ino = match resolve_ino(&filesystem, &path, true) {
+            Ok(ino) => ino,
+            Err(e) => {
+                inputs.push(Err(stream::error(format!("grep: {}: {}", file, e))));
+                continue;
+            }
+        };
+        let node = filesystem.get(ino).unwrap();
+
+        if !recursive || !matches!(node, Node::Directory { .. }) {
+            inputs.push(read(file, node).map(|content| (file.to_string(), content)));
+            continue;
+        }
+
+        if let Err(e) = permissions::check(node, READ | EXECUTE) {
+            inputs.push(Err(stream::error(format!("grep: {}: {}", file, e))));
+            continue;
+        }
+
+        // Without a file operand -r searches `.` and names files relative to it
+        let prefix = if file == "." && files.len() == 1 {
+            String::new()
+        } else {
+            format!("{}/", file.trim_end_matches('/'))
+        };
+        walk_tree(&filesystem, ino, &mut |entry| {
+            let name = format!("{}{}", prefix, entry.path.join("/"));
+            match entry.node {
+                Node::Directory { .. } => {
+                    let readable = permissions::check(entry.node, READ | EXECUTE);
+                    if let Err(e) = &readable {
+                        inputs.push(Err(stream::error(format!("grep: {}: {}", name, e))));
+                    }
+                    readable.is_ok()
+                }
+                Node::File { .. } => {
+                    inputs.push(read(&name, entry.node).map(|content| (name, content)));
+                    false
+                }
+                // Like GNU grep, devices met while recursing are skipped
+                Node::Symlink { .. } | Node::Device { .. } => false,
+            }
+        });
+    }
+
+    inputs
+}
+
+pub fn grep(args: &[&str], stdin: Option<&str>) -> String {
+    let mut options = GrepOptions::default();
+    let mut operands = Vec::new();
+    let mut args = args.iter();
+
+    while let Some(&arg) = args.next() {
+        if arg == "--" {
+            operands.extend(args.by_ref());
+            break;
+        }
+        if !arg.starts_with('-') || arg.len() == 1 {
+            operands.push(arg);
+            continue;
+        }
+
+        for (i, c) in arg.char_indices().skip(1) {
+            match c {
+                'i' => options.ignore_case = true,
+                'v' => options.invert = true,
+                'n' => options.line_numbers = true,
+                'c' => options.count = true,
+                'l' => options.files_with_matches = true,
+                'r' | 'R' => options.recursive = true,
+                'w' => options.word = true,
+                'E' => options.extended = true,
+                'F' => options.fixed = true,
+                'A' | 'B' | 'C' => {
+                    // The length may follow directly, as in -A2, or separately
+                    let value = match &arg[i + 1..] {
+                        "" => args.next().copied().unwrap_or(""),
+                        rest => rest,
+                    };
+                    let length = match value.parse() {
+                        Ok(length) => length,
+                        Err(_) => {
+                            return stream::error(format!(
+                                "grep: {}: invalid context length argument",
+                                value
+                            ))
+                        }
+                    };
+                    if c != 'A' {
+                        options.before = length;
+                    }
+                    if c != 'B' {
+                        options.after = length;
+                    }
+                    break;
+                }
+                _ => {
+                    return stream::error(format!(
+                        "grep: invalid option -- '{}'\n{}",
+                        c, GREP_USAGE
+                    ))
+                }
+            }
+        }
+    }
+
+    let (pattern, files) = match operands.split_first() {
+        Some((pattern, files)) => (*pattern, files),
+        None => return stream::error(GREP_USAGE),
+    };
+    let regex = match grep_regex(pattern, &options) {
+        Ok(regex) => regex,
+        Err(e) => return e,
+    };
+
+    let inputs = if !files.is_empty() {
+        grep_inputs(files, options.recursive)
+    } else if options.recursive {
+        grep_inputs(&["."], true)
+    } else {
+        vec![Ok((
+            "(standard input)".to_string(),
+            stdin.unwrap_or_default().to_string(),
+        ))]
+    };
+    let show_names = files.len() > 1 || options.recursive;
+    let context = options.before > 0 || options.after > 0;
+    let colored = stream::is_terminal();
+    let group_separator = match colored {
+        true => paint(SEPARATOR_COLOR, "--"),
+        false => "--".to_string(),
+    };
+
+    let mut output = Vec::new();
+    for input in inputs {
+        let (name, content) = match input {
+            Ok(input) => input,
+            Err(e) => {
+                output.push(e);
+                continue;
+            }
+        };
+
+        let lines: Vec<&str> = content.lines().collect();
+        let matched: Vec<bool> = lines
+            .iter()
+            .map(|line| regex.is_match(line) != options.invert)
+            .collect();
+        let count = matched.iter().filter(|&&m| m).count();
+
+        if options.files_with_matches {
+            if count > 0 {
+                output.push(name);
+            }
+            continue;
+        }
+        if options.count {
+            output.push(if show_names {
+                format!("{}:{}", name, count)
+            } else {
+                count.to_string()
+            });
+            continue;
+        }
+        // Like GNU grep, a NUL or bytes that aren't text make the input
+        // binary, and only the fact that it matched is shown
+        if content.contains(['\0', char::REPLACEMENT_CHARACTER]) {
+            if count > 0 {
+                output.push(format!("grep: {}: binary file matches", name));
+            }
+            continue;
+        }
+
+        // Matches use `:` after the name and number, context lines `-`
+        let format_line = |index: usize, separator: char| {
+            let separator = separator.to_string();
+            let number = (index + 1).to_string();
+            let mut fields = Vec::new();
+            if show_names {
+                fields.push((NAME_COLOR, name.as_str()));
+            }
+            if options.line_numbers {
+                fields.push((NUMBER_COLOR, number.as_str()));
+            }
+
+            let mut line = String::new();
+            for (color, field) in fields {
+                if colored {
+                    line.push_str(&paint(color, field));
+                    line.push_str(&paint(SEPARATOR_COLOR, &separator));
+                } else {
+                    line.push_str(field);
+                    line.push_str(&separator);
+                }
+            }
+            if colored && separator == ":" && !options.invert {
+                line.push_str(&highlight(&regex, lines[index]));
+            } else {
+                line.push_str(lines[index]);
+            }
+            line
+        };
+
+        let mut next_unprinted = 0;
+        let mut after_remaining = 0;
+        for (index, &is_match) in matched.iter().enumerate() {
+            if is_match {
+                let first = index.saturating_sub(options.before).max(next_unprinted);
+                if context && !output.is_empty() && (first > next_unprinted || next_unprinted == 0)
+                {
+                    output.push(group_separator.clone());
+                }
+                for before in first..index {
+                    output.push(format_line(before, '-'));
+                }
+                output.push(format_line(index, ':'));
+                next_unprinted = index + 1;
+                after_remaining = options.after;
+            } else if after_remaining > 0 {
+                output.push(format_line(index, '-'));
+                next_unprinted = index + 1;
+                after_remaining -= 1;
+            }
+        }
+    }
+
+    output.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::{filesystem, stream, CommandHandler};
+    use crate::testing;
+
+    const LINES: &str = "one\ntwo\nthree\nfour\nfive\nsix\nseven\neight\n";
+
+    // What grep shows on the screen, without its colors
+    fn grep(handler: &mut CommandHandler, command: &str) -> String {
+        stream::strip_colors(&handler.handle(command).0)
+    }
+
+    #[test]
+    fn grep_reads_a_block_of_a_device() {
+        let _guard = testing::fresh();
+        let mut handler = CommandHandler::new();
+        assert_eq!(handler.handle("grep x /dev/zero").0, "");
+        assert_eq!(handler.handle("grep -c x /dev/null").0, "0");
+        assert_eq!(
+            handler.handle("grep -v x /dev/zero").0,
+            "grep: /dev/zero: binary file matches"
+        );
+    }
+
+    #[test]
+    fn find_selects_devices_by_type() {
+        let _guard = testing::fresh();
+        let mut handler = CommandHandler::new();
+        let devices = handler.handle("find /dev -type c").0;
+        assert!(devices.lines().any(|line| line == "/dev/null"));
+        assert!(devices.lines().all(|line| line.starts_with("/dev/")));
+        assert_eq!(handler.handle("find /dev -type f").0, "");
+    }
+
+    #[test]
+    fn context_groups_are_separated() {
+        let _guard = testing::fresh();
+        let mut handler = CommandHandler::new();
+        filesystem::write_file("/tmp/lines", LINES).unwrap();
+
+        assert_eq!(
+            grep(&mut handler, "grep -A1 -n '^[ts]' /tmp/lines"),
+            "2:two\n3:three\n4-four\n--\n6:six\n7:seven\n8-eight"
+        );
+        assert_eq!(
+            grep(&mut handler, "grep -B 1 -E 'two|six' /tmp/lines"),
+            "one\ntwo\n--\nfive\nsix"
+        );
+        // Groups that touch or overlap are joined
+        assert_eq!(
+            grep(&mut handler, "grep -B1 e$ /tmp/lines"),
+            "one\ntwo\nthree\nfour\nfive"
+        );
+        assert_eq!(
+            grep(&mut handler, "grep -C1 -E 'two|four' /tmp/lines"),
+            "one\ntwo\nthree\nfour\nfive"
+        );
+        assert_eq!(
+            grep(&mut handler, "grep -C1 -E 'one|eight' /tmp/lines"),
+            "one\ntwo\n--\nseven\neight"
+        );
+    }
+
+    #[test]
+    fn recursive_search_lists_matching_files() {
+        let _guard = testing::fresh();
+        let mut handler = CommandHandler::new();
+        handler.handle("mkdir /tmp/tree");
+        handler.handle("mkdir /tmp/tree/sub");
+        filesystem::write_file("/tmp/tree/a", "needle\n").unwrap();
+        filesystem::write_file("/tmp/tree/b", "hay\n").unwrap();
+        filesystem::write_file("/tmp/tree/sub/c", "hay\nneedle\n").unwrap();
+
+        assert_eq!(
+            grep(&mut handler, "grep -rl needle /tmp/tree"),
+            "/tmp/tree/a\n/tmp/tree/sub/c"
+        );
+        assert_eq!(
+            grep(&mut handler, "grep -r needle /tmp/tree"),
+            "/tmp/tree/a:needle\n/tmp/tree/sub/c:needle"
+        );
+        handler.handle("cd /tmp/tree");
+        assert_eq!(grep(&mut handler, "grep -rl needle"), "a\nsub/c");
+    }
+
+    #[test]
+    fn words_counts_and_inverted_counts() {
+        let _guard = testing::fresh();
+        let mut handler = CommandHandler::new();
+        filesystem::write_file("/tmp/words", "cat\ncatalog\nthe cat sat\nconcat\n").unwrap();
+
+        assert_eq!(
+            grep(&mut handler, "grep -w cat /tmp/words"),
+            "cat\nthe cat sat"
+        );
+        assert_eq!(grep(&mut handler, "grep -c cat /tmp/words"), "4");
+        assert_eq!(grep(&mut handler, "grep -cw cat /tmp/words"), "2");
+        assert_eq!(grep(&mut handler, "grep -c -v -w cat /tmp/words"), "2");
+        assert_eq!(grep(&mut handler, "grep -vc cat /tmp/words"), "0");
+    }
+
+    #[test]
+    fn stdin_is_searched_without_files() {
+        let _guard = testing::fresh();
+        let mut handler = CommandHandler::new();
+        filesystem::write_file("/tmp/lines", LINES).unwrap();
+
+        assert_eq!(
+            grep(&mut handler, "cat /tmp/lines | grep -n ve"),
+            "5:five\n7:seven"
+        );
+        assert_eq!(grep(&mut handler, "echo hello | grep -c x"), "0");
+        assert_eq!(
+            grep(&mut handler, "cat /tmp/lines | grep -l one"),
+            "(standard input)"
+        );
+    }
+
+    #[test]
+    fn matches_are_colored_only_on_the_screen() {
+        let _guard = testing::fresh();
+        let mut handler = CommandHandler::new();
+        filesystem::write_file("/tmp/words", "a cat and a cat\ndog\n").unwrap();
+
+        assert_eq!(
+            handler.handle("grep cat /tmp/words").0,
+            "a \x1b[31mcat\x1b[0m and a \x1b[31mcat\x1b[0m"
+        );
+        assert_eq!(
+            handler.handle("grep -n dog /tmp/words").0,
+            "\x1b[32m2\x1b[0m\x1b[36m:\x1b[0m\x1b[31mdog\x1b[0m"
+        );
+        // Lines picked by -v have no match to show
+        assert_eq!(handler.handle("grep -v cat /tmp/words").0, "dog");
+
+        handler.handle("grep cat /tmp/words > /tmp/found");
+        assert_eq!(
+            filesystem::read_file("/tmp/found").unwrap(),
+            "a cat and a cat\n"
+        );
+        assert_eq!(
+            handler.handle("grep cat /tmp/words | cat").0,
+            "a cat and a cat"
+        );
+    }
+}
@@ -0,0 +1,529 @@
+use super::filesystem;
+use super::search::translate_basic;
+use super::stream;
+use super::text;
+use regex_lite::{Captures, Regex, RegexBuilder};
+
+const USAGE: &str = "Usage: sed [OPTION]... {script-only-if-no-other-script} [input-file]...";
+
+enum Address {
+    Line(usize),
+    Last,
+    Pattern(Regex),
+}
+
+enum Action {
+    Delete,
+    Print,
+    Substitute {
+        regex: Regex,
+        replacement: String,
+        global: bool,
+        occurrence: usize,
+        print: bool,
+    },
+}
+
+struct Command {
+    start: Option<Address>,
+    end: Option<Address>,
+    negated: bool,
+    action: Action,
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    extended: bool,
+}
+
+impl Parser {
+    fn error(&self, message: &str) -> String {
+        stream::error(format!(
+            "sed: -e expression #1, char {}: {}",
+            self.pos, message
+        ))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.pos += 1;
+        c
+    }
+
+    fn skip_blanks(&mut self) {
+        while matches!(self.peek(), Some(' ') | Some('\t')) {
+            self.pos += 1;
+        }
+    }
+
+    // Reads up to an unescaped `delimiter`, `\delimiter` stands for itself
+    fn delimited(&mut self, delimiter: char, unterminated: &str) -> Result<String, String> {
+        let mut text = String::new();
+        loop {
+            match self.next() {
+                Some(c) if c == delimiter => return Ok(text),
+                Some('\\') => match self.next() {
+                    Some(c) if c == delimiter => text.push(c),
+                    Some(c) => {
+                        text.push('\\');
+                        text.push(c);
+                    }
+                    None => return Err(self.error(unterminated)),
+                },
+                Some('\n') | None => return Err(self.error(unterminated)),
+                Some(c) => text.push(c),
+            }
+        }
+    }
+
+    fn regex(&self, pattern: &str, ignore_case: bool) -> Result<Regex, String> {
+        let pattern = if self.extended {
+            pattern.to_string()
+        } else {
+            translate_basic(pattern)
+        };
+        RegexBuilder::new(&pattern)
+            .case_insensitive(ignore_case)
+            .build()
+            .map_err(|_| self.error("Invalid regular expression"))
+    }
+
+    fn address(&mut self) -> Result<Option<Address>, String> {
+        match self.peek() {
+            Some(c) if c.is_ascii_digit() => {
+                let start = self.pos;
+                while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                    self.pos += 1;
+                }
+                let digits: String = self.chars[start..self.pos].iter().collect();
+                match digits.parse() {
+                    Ok(0) | Err(_) => Err(self.error("invalid usage of line address 0")),
+                    Ok(line) => Ok(Some(Address::Line(line))),
+                }
+            }
+            Some('$') => {
+                self.pos += 1;
+                Ok(Some(Address::Last))
+            }
+            // `/re/`, or `\cREc` for any other delimiter c
+            Some('/') | Some('\\') => {
+                let delimiter = match self.next() {
+                    Some('\\') => self
+                        .next()
+                        .ok_or_else(|| self.error("unexpected end of expression"))?,
+                    _ => '/',
+                };
+                let pattern = self.delimited(delimiter, "unterminated address regex")?;
+                Ok(Some(Address::Pattern(self.regex(&pattern, false)?)))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn substitute(&mut self) -> Result<Action, String> {
+        let delimiter = match self.next() {
+            Some(c) if c != '\n' && c != '\\' => c,
+            _ => return Err(self.error("unterminated `s' command")),
+        };
+        let pattern = self.delimited(delimiter, "unterminated `s' command")?;
+        let replacement = self.delimited(delimiter, "unterminated `s' command")?;
+
+        let mut global = false;
+        let mut print = false;
+        let mut ignore_case = false;
+        let mut occurrence = None;
+
+        while let Some(c) = self.peek() {
+            match c {
+                'g' => global = true,
+                'p' => print = true,
+                'i' | 'I' => ignore_case = true,
+                c if c.is_ascii_digit() => {
+                    let digit = c.to_digit(10).unwrap() as usize;
+                    let value = occurrence.unwrap_or(0) * 10 + digit;
+                    if value == 0 {
+                        return Err(self.error("number option to `s' command may not be zero"));
+                    }
+                    occurrence = Some(value);
+                }
+                ';' | '\n' | ' ' | '\t' => break,
+                _ => return Err(self.error("unknown option to `s'")),
+            }
+            self.pos += 1;
+        }
+
+        Ok(Action::Substitute {
+            regex: self.regex(&pattern, ignore_case)?,
+            replacement,
+            global,
+            occurrence: occurrence.unwrap_or(1),
+            print,
+        })
+    }
+
+    fn commands(&mut self) -> Result<Vec<Command>, String> {
+        let mut commands = Vec::new();
+
+        loop {
+            while matches!(self.peek(), Some(' ' | '\t' | '\n' | ';')) {
+                self.pos += 1;
+            }
+            if self.peek().is_none() {
+                return Ok(commands);
+            }
+
+            let start = self.address()?;
+            let end = if start.is_some() && self.peek() == Some(',') {
+                self.pos += 1;
+                self.skip_blanks();
+                Some(
+                    self.address()?
+                        .ok_or_else(|| self.error("unexpected `,'"))?,
+                )
+            } else {
+                None
+            };
+
+            self.skip_blanks();
+            let negated = self.peek() == Some('!');
+            if negated {
+                self.pos += 1;
+                self.skip_blanks();
+            }
+
+            let action = match self.next() {
+                Some('d') => Action::Delete,
+                Some('p') => Action::Print,
+                Some('s') => self.substitute()?,
+                Some(c) => return Err(self.error(&format!("unknown command: `{}'", c))),
+                None => return Err(self.error("missing command")),
+            };
+
+            self.skip_blanks();
+            if !matches!(self.peek(), None | Some(';') | Some('\n')) {
+                return Err(self.error("extra characters after command"));
+            }
+
+            commands.push(Command {
+                start,
+                end,
+                negated,
+                action,
+            });
+        }
+    }
+}
+
+// `&` is the whole match, `\1`..`\9` the groups and `\n` a newline
+fn expand(replacement: &str, captures: &Captures) -> String {
+    let mut out = String::new();
+    let mut chars = replacement.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '&' => out.push_str(&captures[0]),
+            '\\' => match chars.next() {
+                Some(digit @ '1'..='9') => {
+                    let group = digit.to_digit(10).unwrap() as usize;
+                    out.push_str(captures.get(group).map_or("", |m| m.as_str()));
+                }
+                Some('n') => out.push('\n'),
+                Some(c) => out.push(c),
+                None => out.push('\\'),
+            },
+            c => out.push(c),
+        }
+    }
+
+    out
+}
+
+fn substitute(
+    space: &str,
+    regex: &Regex,
+    replacement: &str,
+    global: bool,
+    occurrence: usize,
+) -> Option<String> {
+    let mut result = String::new();
+    let mut copied = 0;
+    let mut replaced = false;
+
+    for (count, captures) in regex.captures_iter(space).enumerate() {
+        let count = count + 1;
+        if count < occurrence {
+            continue;
+        }
+
+        let matched = captures.get(0).unwrap();
+        result.push_str(&space[copied..matched.start()]);
+        result.push_str(&expand(replacement, &captures));
+        copied = matched.end();
+        replaced = true;
+
+        if !global {
+            break;
+        }
+    }
+
+    if replaced {
+        result.push_str(&space[copied..]);
+        Some(result)
+    } else {
+        None
+    }
+}
+
+// Ranges stay active from a line matching the start to one matching the
+// end, `active` keeps that state for each command between lines
+fn selects(command: &Command, active: &mut bool, line: usize, is_last: bool, text: &str) -> bool {
+    let hit = |address: &Address| match address {
+        Address::Line(n) => line == *n,
+        Address::Last => is_last,
+        Address::Pattern(regex) => regex.is_match(text),
+    };
+
+    let selected = match (&command.start, &command.end) {
+        (None, _) => true,
+        (Some(start), None) => hit(start),
+        (Some(_), Some(end)) if *active => {
+            let done = match end {
+                Address::Line(n) => line >= *n,
+                _ => hit(end),
+            };
+            *active = !done;
+            true
+        }
+        (Some(start), Some(end)) if hit(start) => {
+            // A line number already passed ends the range right away
+            *active = match end {
+                Address::Line(n) => *n > line,
+                Address::Last => !is_last,
+                Address::Pattern(_) => true,
+            };
+            true
+        }
+        _ => false,
+    };
+
+    selected != command.negated
+}
+
+fn run(commands: &[Command], lines: &[&str], quiet: bool) -> Vec<String> {
+    let mut active = vec![false; commands.len()];
+    let mut output = Vec::new();
+
+    for (index, line) in lines.iter().enumerate() {
+        let is_last = index + 1 == lines.len();
+        let mut space = line.to_string();
+        let mut deleted = false;
+
+        for (command, active) in commands.iter().zip(active.iter_mut()) {
+            if !selects(command, active, index + 1, is_last, &space) {
+                continue;
+            }
+
+            match &command.action {
+                Action::Delete => {
+                    deleted = true;
+                    break;
+                }
+                Action::Print => output.push(space.clone()),
+                Action::Substitute {
+                    regex,
+                    replacement,
+                    global,
+                    occurrence,
+                    print,
+                } => {
+                    if let Some(result) =
+                        substitute(&space, regex, replacement, *global, *occurrence)
+                    {
+                        space = result;
+                        if *print {
+                            output.push(space.clone());
+                        }
+                    }
+                }
+            }
+        }
+
+        if !deleted && !quiet {
+            output.push(space);
+        }
+    }
+
+    output
+}
+
+pub fn sed(args: &[&str], stdin: Option<&str>) -> String {
+    let parsed = match text::parse("sed", args, "niEr", "e") {
+        Ok(parsed) => parsed,
+        Err(e) => return stream::error(format!("{}\n{}", e, USAGE)),
+    };
+
+    let scripts = parsed.values('e');
+    let (script, files) = if !scripts.is_empty() {
+        (scripts.join("\n"), &parsed.operands[..])
+    } else {
+        match parsed.operands.split_first() {
+            Some((script, files)) => (script.to_string(), files),
+            None => return stream::error(USAGE),
+        }
+    };
+
+    let mut parser = Parser {
+        chars: script.chars().collect(),
+        pos: 0,
+        extended: parsed.has('E') || parsed.has('r'),
+    };
+    let commands = match parser.commands() {
+        Ok(commands) => commands,
+        Err(e) => return e,
+    };
+    let quiet = parsed.has('n');
+
+    // -i edits every file on its own and writes the result back
+    if parsed.has('i') {
+        if files.is_empty() {
+            return stream::error("sed: no input files");
+        }
+
+        let mut output = Vec::new();
+        for &file in files {
+            let content = match filesystem::read_file(file) {
+                Ok(content) => content,
+                Err(e) => {
+                    output.push(stream::error(format!("sed: can't read {}: {}", file, e)));
+                    continue;
+                }
+            };
+
+            let lines: Vec<&str> = content.lines().collect();
+            let mut edited = run(&commands, &lines, quiet).join("\n");
+            if content.ends_with('\n') && !edited.is_empty() {
+                edited.push('\n');
+            }
+            if let Err(e) = filesystem::write_file(file, &edited) {
+                output.push(stream::error(format!("sed: couldn't edit {}: {}", file, e)));
+            }
+        }
+        return output.join("\n");
+    }
+
+    // Otherwise all inputs form one stream, so `$` is the very last line
+    let mut errors = Vec::new();
+    let mut contents = Vec::new();
+    for input in text::read_inputs(files, stdin) {
+        match input.content {
+            Ok(content) => contents.push(content),
+            Err(e) => errors.push(stream::error(format!(
+                "sed: can't read {}: {}",
+                input.name.as_deref().unwrap_or("-"),
+                e
+            ))),
+        }
+    }
+    let lines: Vec<&str> = contents
+        .iter()
+        .flat_map(|content| content.lines())
+        .collect();
+
+    errors
+        .into_iter()
+        .chain(run(&commands, &lines, quiet))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    const LINES: &str = "one\ntwo\nthree\nfour\nfive\n";
+
+    #[test]
+    fn substitute_with_the_match_and_groups() {
+        let _guard = testing::fresh();
+        assert_eq!(sed(&["s/o/0/"], Some("foo boo\n")), "f0o boo");
+        assert_eq!(sed(&["s/o/0/g"], Some("foo boo\n")), "f00 b00");
+        assert_eq!(sed(&["s/[a-z]*/<&>/"], Some("word rest\n")), "<word> rest");
+        assert_eq!(
+            sed(&["s/\\(.*\\)@\\(.*\\)/\\2 at \\1/"], Some("me@host\n")),
+            "host at me"
+        );
+        assert_eq!(
+            sed(
+                &["-E", "s/([0-9]+)-([0-9]+)/\\2-\\1/g"],
+                Some("1-2 34-56\n")
+            ),
+            "2-1 56-34"
+        );
+        assert_eq!(sed(&["s|/|:|g"], Some("/usr/bin\n")), ":usr:bin");
+    }
+
+    #[test]
+    fn quiet_prints_only_what_p_asks_for() {
+        let _guard = testing::fresh();
+        assert_eq!(sed(&["-n", "2p"], Some(LINES)), "two");
+        assert_eq!(sed(&["-n", "$p"], Some(LINES)), "five");
+        assert_eq!(sed(&["-n", "/^t/p"], Some(LINES)), "two\nthree");
+        assert_eq!(sed(&["2p"], Some("a\nb\nc\n")), "a\nb\nb\nc");
+        assert_eq!(sed(&["-n", "s/e$/E/p"], Some(LINES)), "onE\nthreE\nfivE");
+    }
+
+    #[test]
+    fn ranges_and_delete() {
+        let _guard = testing::fresh();
+        assert_eq!(sed(&["2,4d"], Some(LINES)), "one\nfive");
+        assert_eq!(sed(&["/two/,/four/d"], Some(LINES)), "one\nfive");
+        assert_eq!(sed(&["3,$d"], Some(LINES)), "one\ntwo");
+        assert_eq!(sed(&["-n", "/three/,$p"], Some(LINES)), "three\nfour\nfive");
+        assert_eq!(sed(&["/o/d"], Some(LINES)), "three\nfive");
+        assert_eq!(
+            sed(&["2,3s/^/> /"], Some(LINES)),
+            "one\n> two\n> three\nfour\nfive"
+        );
+    }
+
+    #[test]
+    fn e_chains_scripts() {
+        let _guard = testing::fresh();
+        assert_eq!(
+            sed(
+                &["-e", "s/one/1/", "-e", "s/two/2/", "-e", "4,$d"],
+                Some(LINES)
+            ),
+            "1\n2\nthree"
+        );
+        assert_eq!(sed(&["s/a/b/;s/b/c/"], Some("a\n")), "c");
+    }
+
+    #[test]
+    fn i_edits_files_in_place() {
+        let _guard = testing::fresh();
+        filesystem::write_file("/tmp/a", "hello world\n").unwrap();
+        filesystem::write_file("/tmp/b", "world\nbye\n").unwrap();
+
+        assert_eq!(sed(&["-i", "s/world/there/", "/tmp/a", "/tmp/b"], None), "");
+        assert_eq!(filesystem::read_file("/tmp/a").unwrap(), "hello there\n");
+        assert_eq!(filesystem::read_file("/tmp/b").unwrap(), "there\nbye\n");
+
+        assert_eq!(sed(&["-i", "/bye/d", "/tmp/b"], None), "");
+        assert_eq!(filesystem::read_file("/tmp/b").unwrap(), "there\n");
+        assert_eq!(
+            sed(&["-i", "p", "/tmp/missing"], None),
+            stream::error("sed: can't read /tmp/missing: No such file or directory")
+        );
+        assert_eq!(
+            sed(&["-i", "p"], None),
+            stream::error("sed: no input files")
+        );
+    }
+}
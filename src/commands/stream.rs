@@ -0,0 +1,137 @@
+use std::cell::Cell;
+
+// Marks error lines in the text a command returns, so the shell can send
+// them to stderr. Private use, real text never has it.
+const ERROR_MARK: char = '\u{e000}';
+
+thread_local! {
+    static TERMINAL: Cell<bool> = const { Cell::new(true) };
+}
+
+// Whether the running command prints straight to the screen. Commands that
+// move data around only hand over raw bytes when it doesn't.
+pub fn is_terminal() -> bool {
+    TERMINAL.with(Cell::get)
+}
+
+pub fn set_terminal(terminal: bool) {
+    TERMINAL.with(|current| current.set(terminal));
+}
+
+/// Marks every line of `message` as an error, which shows up on the screen
+/// or where `2>` points rather than in a pipe or a `>` file.
+pub fn error(message: impl AsRef<str>) -> String {
+    message
+        .as_ref()
+        .lines()
+        .map(|line| format!("{}{}", ERROR_MARK, line))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub fn strip_colors(text: &str) -> String {
+    let mut plain = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("\x1b[") {
+        plain.push_str(&rest[..start]);
+        rest = &rest[start + 2..];
+        if let Some(end) = rest.find('m') {
+            rest = &rest[end + 1..];
+        }
+    }
+    plain.push_str(rest);
+    plain
+}
+
+enum Chunk {
+    // A line of text for the screen, possibly colored
+    Line(String),
+    // Exactly what a file or the next command gets
+    Bytes(Vec<u8>),
+    Error(String),
+}
+
+/// What a command produced, in the order it did.
+#[derive(Default)]
+pub struct Output {
+    chunks: Vec<Chunk>,
+}
+
+// Plain command output is lines of text, some marked as errors
+impl From<String> for Output {
+    fn from(text: String) -> Self {
+        let mut output = Output::default();
+        for line in text.lines() {
+            output.line(line);
+        }
+        output
+    }
+}
+
+impl Output {
+    pub fn bytes(&mut self, bytes: Vec<u8>) {
+        if !bytes.is_empty() {
+            self.chunks.push(Chunk::Bytes(bytes));
+        }
+    }
+
+    // Messages built from an error can end up marked anywhere in the line
+    pub fn line(&mut self, line: impl Into<String>) {
+        let line = line.into();
+        if line.contains(ERROR_MARK) {
+            self.chunks.push(Chunk::Error(line.replace(ERROR_MARK, "")));
+        } else {
+            self.chunks.push(Chunk::Line(line));
+        }
+    }
+
+    pub fn append(&mut self, other: Output) {
+        self.chunks.extend(other.chunks);
+    }
+
+    /// What goes to stdout and what to stderr
+    pub fn split(self) -> (Output, Output) {
+        let (errors, output) = self
+            .chunks
+            .into_iter()
+            .partition(|chunk| matches!(chunk, Chunk::Error(_)));
+        (Output { chunks: output }, Output { chunks: errors })
+    }
+
+    /// The data for a file or the next command. Lines get back the newline
+    /// plain output leaves off and lose their colors, bytes stay as they are.
+    pub fn into_bytes(self) -> Vec<u8> {
+        let mut data = Vec::new();
+        for chunk in self.chunks {
+            match chunk {
+                Chunk::Line(line) | Chunk::Error(line) => {
+                    data.extend(strip_colors(&line).into_bytes());
+                    data.push(b'\n');
+                }
+                Chunk::Bytes(bytes) => data.extend(bytes),
+            }
+        }
+        data
+    }
+
+    /// Everything as the screen shows it
+    pub fn into_text(self) -> String {
+        let mut text = String::new();
+        for chunk in self.chunks {
+            match chunk {
+                Chunk::Line(line) | Chunk::Error(line) => {
+                    if !text.is_empty() && !text.ends_with('\n') {
+                        text.push('\n');
+                    }
+                    text.push_str(&line);
+                    text.push('\n');
+                }
+                Chunk::Bytes(bytes) => text.push_str(&String::from_utf8_lossy(&bytes)),
+            }
+        }
+        match text.strip_suffix('\n') {
+            Some(text) => text.to_string(),
+            None => text,
+        }
+    }
+}
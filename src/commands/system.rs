@@ -1,5 +1,6 @@
 use crate::ascii::AsciiArt;
-use std::sync::OnceLock;
+use crate::commands::users;
+use std::sync::{Mutex, OnceLock};
 use wasm_bindgen::prelude::*;
 
 #[wasm_bindgen]
@@ -12,10 +13,100 @@ extern "C" {
 }
 
 static START_TIME: OnceLock<f64> = OnceLock::new();
+static LAST_LOGIN: Mutex<Option<f64>> = Mutex::new(None);
+
 pub fn init() {
     START_TIME.set(now()).ok();
 }
 
+// When the terminal booted, which /proc treats as the system's boot
+pub fn started() -> f64 {
+    START_TIME.get().copied().unwrap_or_else(timestamp)
+}
+
+// Leaves a shell opened with `su`, the last one ends the session
+pub fn logout(_args: &[&str]) -> String {
+    if users::exit_shell() {
+        String::new()
+    } else {
+        "LOGOUT".to_string()
+    }
+}
+
+pub fn record_login() -> String {
+    let previous = LAST_LOGIN.lock().unwrap().replace(now());
+    let previous = previous.unwrap_or(*START_TIME.get().unwrap_or(&now()));
+    format_login_time(previous)
+}
+
+const DAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+pub fn timestamp() -> f64 {
+    if cfg!(target_arch = "wasm32") {
+        now()
+    } else {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0.0, |elapsed| elapsed.as_millis() as f64)
+    }
+}
+
+// Same layout as `ls -l`: the time for recent files, the year otherwise
+pub fn format_ls_time(millis: f64) -> String {
+    const SIX_MONTHS: f64 = 1000.0 * 60.0 * 60.0 * 24.0 * 182.0;
+
+    let date = js_sys::Date::new(&JsValue::from_f64(millis));
+    let month = MONTHS[date.get_month() as usize];
+
+    if (timestamp() - millis).abs() < SIX_MONTHS {
+        format!(
+            "{} {:>2} {:02}:{:02}",
+            month,
+            date.get_date(),
+            date.get_hours(),
+            date.get_minutes()
+        )
+    } else {
+        format!("{} {:>2}  {}", month, date.get_date(), date.get_full_year())
+    }
+}
+
+// Same layout as `stat`: local time to the nanosecond and the UTC offset
+pub fn format_stat_time(millis: f64) -> String {
+    let date = js_sys::Date::new(&JsValue::from_f64(millis));
+    let offset = -date.get_timezone_offset() as i64;
+    format!(
+        "{}-{:02}-{:02} {:02}:{:02}:{:02}.{:09} {}{:02}{:02}",
+        date.get_full_year(),
+        date.get_month() + 1,
+        date.get_date(),
+        date.get_hours(),
+        date.get_minutes(),
+        date.get_seconds(),
+        date.get_milliseconds() * 1_000_000,
+        if offset < 0 { '-' } else { '+' },
+        offset.abs() / 60,
+        offset.abs() % 60
+    )
+}
+
+fn format_login_time(millis: f64) -> String {
+    let date = js_sys::Date::new(&JsValue::from_f64(millis));
+    format!(
+        "{} {} {} {:02}:{:02}:{:02} {}",
+        DAYS[date.get_day() as usize],
+        MONTHS[date.get_month() as usize],
+        date.get_date(),
+        date.get_hours(),
+        date.get_minutes(),
+        date.get_seconds(),
+        date.get_full_year()
+    )
+}
+
 pub fn clear(_args: &[&str]) -> String {
     "CLEAR_SCREEN".to_string()
 }
@@ -37,8 +128,7 @@ pub fn date(_args: &[&str]) -> String {
 }
 
 pub fn uptime(_args: &[&str]) -> String {
-    let start = *START_TIME.get().unwrap_or(&now());
-    let elapsed = now() - start;
+    let elapsed = now() - started();
 
     let total_secs = (elapsed / 1000.0) as u64;
     let hours = total_secs / 3600;
@@ -0,0 +1,847 @@
+use super::filesystem::{self, FsError};
+use super::stream::{self, Output};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+// getopt-style arguments: grouped flags like `-rn`, values attached as in
+// `-n5` or separate as in `-n 5`, and `--` ending the options
+pub struct Parsed<'a> {
+    flags: Vec<char>,
+    values: Vec<(char, &'a str)>,
+    pub operands: Vec<&'a str>,
+}
+
+impl Parsed<'_> {
+    pub fn has(&self, flag: char) -> bool {
+        self.flags.contains(&flag)
+    }
+
+    pub fn values(&self, flag: char) -> Vec<&str> {
+        self.values
+            .iter()
+            .filter(|(name, _)| *name == flag)
+            .map(|(_, value)| *value)
+            .collect()
+    }
+
+    pub fn value(&self, flag: char) -> Option<&str> {
+        self.values
+            .iter()
+            .rev()
+            .find(|(name, _)| *name == flag)
+            .map(|(_, value)| *value)
+    }
+}
+
+pub fn parse<'a>(
+    cmd: &str,
+    args: &[&'a str],
+    flags: &str,
+    valued: &str,
+) -> Result<Parsed<'a>, String> {
+    let mut parsed = Parsed {
+        flags: Vec::new(),
+        values: Vec::new(),
+        operands: Vec::new(),
+    };
+    let mut args = args.iter();
+
+    while let Some(&arg) = args.next() {
+        if arg == "--" {
+            parsed.operands.extend(args.by_ref());
+            break;
+        }
+        if !arg.starts_with('-') || arg.len() == 1 {
+            parsed.operands.push(arg);
+            continue;
+        }
+
+        for (i, c) in arg.char_indices().skip(1) {
+            if valued.contains(c) {
+                let value = match &arg[i + c.len_utf8()..] {
+                    "" => *args.next().ok_or_else(|| {
+                        stream::error(format!("{}: option requires an argument -- '{}'", cmd, c))
+                    })?,
+                    rest => rest,
+                };
+                parsed.values.push((c, value));
+                break;
+            } else if flags.contains(c) {
+                parsed.flags.push(c);
+            } else {
+                return Err(stream::error(format!("{}: invalid option -- '{}'", cmd, c)));
+            }
+        }
+    }
+
+    Ok(parsed)
+}
+
+pub struct Input<T = String> {
+    // None for stdin
+    pub name: Option<String>,
+    pub content: Result<T, FsError>,
+}
+
+impl<T> Input<T> {
+    pub fn display_name(&self) -> &str {
+        self.name.as_deref().unwrap_or("-")
+    }
+}
+
+// Reads every operand, `-` or no operand at all reads stdin. Command output
+// loses its final newline, so it is put back to count like a file would.
+pub fn read_inputs(files: &[&str], stdin: Option<&str>) -> Vec<Input> {
+    let stdin = || {
+        let mut text = stdin.unwrap_or_default().to_string();
+        if !text.is_empty() && !text.ends_with('\n') {
+            text.push('\n');
+        }
+        text
+    };
+    read_inputs_with(files, stdin, filesystem::read_file)
+}
+
+// Like read_inputs for tools that count or pass on bytes, which read stdin
+// exactly as it came and files with `read`
+fn read_byte_inputs(
+    files: &[&str],
+    stdin: Option<&[u8]>,
+    read: impl Fn(&str) -> Result<Vec<u8>, FsError>,
+) -> Vec<Input<Vec<u8>>> {
+    read_inputs_with(files, || stdin.unwrap_or_default().to_vec(), read)
+}
+
+fn read_inputs_with<T>(
+    files: &[&str],
+    stdin: impl Fn() -> T,
+    read: impl Fn(&str) -> Result<T, FsError>,
+) -> Vec<Input<T>> {
+    let stdin = || Input {
+        name: None,
+        content: Ok(stdin()),
+    };
+
+    if files.is_empty() {
+        return vec![stdin()];
+    }
+
+    files
+        .iter()
+        .map(|&file| match file {
+            "-" => stdin(),
+            _ => Input {
+                name: Some(file.to_string()),
+                content: read(file),
+            },
+        })
+        .collect()
+}
+
+// The lines of all inputs together, or the first error as `cmd: file: error`
+fn read_lines(cmd: &str, files: &[&str], stdin: Option<&str>) -> Result<Vec<String>, String> {
+    let mut lines = Vec::new();
+    for input in read_inputs(files, stdin) {
+        match &input.content {
+            Ok(content) => lines.extend(content.lines().map(str::to_string)),
+            Err(e) => {
+                return Err(stream::error(format!(
+                    "{}: {}: {}",
+                    cmd,
+                    input.display_name(),
+                    e
+                )))
+            }
+        }
+    }
+    Ok(lines)
+}
+
+// `-5` is an old way of writing `-n 5` that head and tail still accept
+// as their first argument
+fn legacy_count(args: &[&str]) -> Vec<String> {
+    let mut args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    if let Some(first) = args.first_mut() {
+        match first.strip_prefix('-') {
+            Some(count) if !count.is_empty() && count.chars().all(|c| c.is_ascii_digit()) => {
+                *first = format!("-n{}", count);
+            }
+            _ => {}
+        }
+    }
+    args
+}
+
+fn head_or_tail(cmd: &str, args: &[&str], stdin: Option<&[u8]>) -> Output {
+    let args = legacy_count(args);
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let parsed = match parse(cmd, &args, "", "nc") {
+        Ok(parsed) => parsed,
+        Err(e) => return e.into(),
+    };
+
+    let (spec, unit) = match (parsed.value('c'), parsed.value('n')) {
+        (Some(bytes), _) => (bytes, "bytes"),
+        (None, Some(lines)) => (lines, "lines"),
+        (None, None) => ("10", "lines"),
+    };
+    // head -n -N drops the last N, tail -n +N starts at the Nth
+    let (sign, digits) = match spec.chars().next() {
+        Some(sign @ ('+' | '-')) => (Some(sign), &spec[1..]),
+        _ => (None, spec),
+    };
+    let count: usize = match digits.parse() {
+        Ok(count) => count,
+        Err(_) => {
+            return stream::error(format!("{}: invalid number of {}: '{}'", cmd, unit, spec)).into()
+        }
+    };
+
+    let select = |len: usize| match (cmd, sign) {
+        ("head", Some('-')) => 0..len.saturating_sub(count),
+        ("head", _) => 0..count.min(len),
+        (_, Some('+')) => count.saturating_sub(1).min(len)..len,
+        _ => len.saturating_sub(count)..len,
+    };
+
+    // The first bytes are all head -c needs, which keeps it from reading
+    // an endless device like /dev/zero forever
+    let inputs = if cmd == "head" && unit == "bytes" && sign.is_none() {
+        read_byte_inputs(&parsed.operands, stdin, |file| {
+            filesystem::read_prefix(file, count)
+        })
+    } else {
+        read_byte_inputs(&parsed.operands, stdin, filesystem::read_bytes)
+    };
+    let headers = inputs.len() > 1;
+    let mut output = Output::default();
+
+    for (i, input) in inputs.iter().enumerate() {
+        let content = match &input.content {
+            Ok(content) => content,
+            Err(e) => {
+                output.line(stream::error(format!(
+                    "{}: cannot open '{}' for reading: {}",
+                    cmd,
+                    input.display_name(),
+                    e
+                )));
+                continue;
+            }
+        };
+
+        if headers {
+            if i > 0 {
+                output.line("");
+            }
+            let name = input.name.as_deref().unwrap_or("standard input");
+            output.line(format!("==> {} <==", name));
+        }
+        // Lines keep their newlines, so the bytes come out as they went in
+        if unit == "bytes" {
+            output.bytes(content[select(content.len())].to_vec());
+        } else {
+            let lines: Vec<&[u8]> = content.split_inclusive(|&byte| byte == b'\n').collect();
+            output.bytes(lines[select(lines.len())].concat());
+        }
+    }
+
+    output
+}
+
+pub fn head(args: &[&str], stdin: Option<&[u8]>) -> Output {
+    head_or_tail("head", args, stdin)
+}
+
+pub fn tail(args: &[&str], stdin: Option<&[u8]>) -> Output {
+    head_or_tail("tail", args, stdin)
+}
+
+pub fn wc(args: &[&str], stdin: Option<&[u8]>) -> String {
+    let parsed = match parse("wc", args, "lwc", "") {
+        Ok(parsed) => parsed,
+        Err(e) => return e,
+    };
+    let shown: Vec<bool> = if ['l', 'w', 'c'].iter().any(|&c| parsed.has(c)) {
+        ['l', 'w', 'c'].iter().map(|&c| parsed.has(c)).collect()
+    } else {
+        vec![true; 3]
+    };
+
+    let inputs = read_byte_inputs(&parsed.operands, stdin, filesystem::read_bytes);
+    let mut rows = Vec::new();
+    let mut errors = Vec::new();
+    let mut total = [0; 3];
+
+    for input in &inputs {
+        match &input.content {
+            Ok(content) => {
+                let counts = [
+                    content.iter().filter(|&&byte| byte == b'\n').count(),
+                    content
+                        .split(u8::is_ascii_whitespace)
+                        .filter(|word| !word.is_empty())
+                        .count(),
+                    content.len(),
+                ];
+                for (sum, count) in total.iter_mut().zip(counts) {
+                    *sum += count;
+                }
+                rows.push((counts, input.name.clone()));
+            }
+            Err(e) => errors.push(stream::error(format!(
+                "wc: {}: {}",
+                input.display_name(),
+                e
+            ))),
+        }
+    }
+    if inputs.len() > 1 {
+        rows.push((total, Some("total".to_string())));
+    }
+
+    // Like coreutils, columns are as wide as the total byte count, or 7
+    // when reading stdin whose size isn't known up front
+    let columns = shown.iter().filter(|&&shown| shown).count();
+    let width = if columns == 1 && rows.len() == 1 {
+        1
+    } else if inputs.iter().any(|input| input.name.is_none()) {
+        7
+    } else {
+        total[2].to_string().len()
+    };
+
+    let lines = rows.into_iter().map(|(counts, name)| {
+        let mut fields: Vec<String> = counts
+            .iter()
+            .zip(&shown)
+            .filter(|(_, &shown)| shown)
+            .map(|(count, _)| format!("{:>w$}", count, w = width))
+            .collect();
+        fields.extend(name);
+        fields.join(" ")
+    });
+
+    errors
+        .into_iter()
+        .chain(lines)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Leading number of a line as `sort -n` reads it, anything else is 0
+fn numeric_prefix(text: &str) -> f64 {
+    let text = text.trim_start();
+    let end = text
+        .char_indices()
+        .find(|&(i, c)| !(c.is_ascii_digit() || c == '.' || (i == 0 && c == '-')))
+        .map_or(text.len(), |(i, _)| i);
+    text[..end].parse().unwrap_or(0.0)
+}
+
+pub fn sort(args: &[&str], stdin: Option<&str>) -> String {
+    let parsed = match parse("sort", args, "rnu", "kt") {
+        Ok(parsed) => parsed,
+        Err(e) => return e,
+    };
+
+    let separator = match parsed.value('t') {
+        Some(tab) if tab.chars().count() == 1 => tab.chars().next(),
+        Some(tab) => return stream::error(format!("sort: multi-character tab '{}'", tab)),
+        None => None,
+    };
+
+    // -k START[,END] compares fields START to END, counted from 1
+    let key = match parsed.value('k') {
+        Some(spec) => {
+            let (start, end) = spec.split_once(',').unwrap_or((spec, ""));
+            let field = |value: &str| value.parse::<usize>().ok().filter(|&n| n > 0);
+            match (field(start), end) {
+                (Some(start), "") => Some((start, None)),
+                (Some(start), end) => match field(end) {
+                    Some(end) => Some((start, Some(end))),
+                    None => {
+                        return stream::error(format!(
+                            "sort: invalid number after ',': invalid count at start of '{}'",
+                            end
+                        ))
+                    }
+                },
+                (None, _) => {
+                    return stream::error(format!(
+                        "sort: invalid number at field start: invalid count at start of '{}'",
+                        start
+                    ))
+                }
+            }
+        }
+        None => None,
+    };
+
+    let mut lines = match read_lines("sort", &parsed.operands, stdin) {
+        Ok(lines) => lines,
+        Err(e) => return e.replacen("sort: ", "sort: cannot read: ", 1),
+    };
+
+    let key_of = |line: &str| -> String {
+        let (start, end) = match key {
+            Some(key) => key,
+            None => return line.to_string(),
+        };
+        let fields: Vec<&str> = match separator {
+            Some(separator) => line.split(separator).collect(),
+            None => line.split_whitespace().collect(),
+        };
+        let end = end.unwrap_or(fields.len()).min(fields.len());
+        if start > end {
+            return String::new();
+        }
+        fields[start - 1..end].join(&separator.unwrap_or(' ').to_string())
+    };
+
+    let compare_keys = |a: &str, b: &str| {
+        let (a, b) = (key_of(a), key_of(b));
+        if parsed.has('n') {
+            numeric_prefix(&a)
+                .partial_cmp(&numeric_prefix(&b))
+                .unwrap_or(Ordering::Equal)
+        } else {
+            a.cmp(&b)
+        }
+    };
+
+    // Equal keys fall back to comparing whole lines, except under -u
+    lines.sort_by(|a, b| {
+        let ordering = compare_keys(a, b);
+        let ordering = if parsed.has('u') {
+            ordering
+        } else {
+            ordering.then_with(|| a.cmp(b))
+        };
+        if parsed.has('r') {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    });
+    if parsed.has('u') {
+        lines.dedup_by(|a, b| compare_keys(a, b) == Ordering::Equal);
+    }
+
+    lines.join("\n")
+}
+
+pub fn uniq(args: &[&str], stdin: Option<&str>) -> String {
+    let parsed = match parse("uniq", args, "cd", "") {
+        Ok(parsed) => parsed,
+        Err(e) => return e,
+    };
+    if let Some(extra) = parsed.operands.get(1) {
+        return stream::error(format!("uniq: extra operand '{}'", extra));
+    }
+
+    let lines = match read_lines("uniq", &parsed.operands, stdin) {
+        Ok(lines) => lines,
+        Err(e) => return e,
+    };
+
+    let mut groups: Vec<(usize, &str)> = Vec::new();
+    for line in &lines {
+        match groups.last_mut() {
+            Some((count, last)) if *last == line => *count += 1,
+            _ => groups.push((1, line)),
+        }
+    }
+
+    groups
+        .into_iter()
+        .filter(|(count, _)| !parsed.has('d') || *count > 1)
+        .map(|(count, line)| {
+            if parsed.has('c') {
+                format!("{:>7} {}", count, line)
+            } else {
+                line.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Lists like `1,3-5,7-` as inclusive ranges counted from 1
+fn parse_list(list: &str) -> Result<Vec<(usize, usize)>, String> {
+    list.split(',')
+        .map(|range| {
+            let (start, end) = match range.split_once('-') {
+                Some((start, end)) => (start, end),
+                None => (range, range),
+            };
+            let bound = |value: &str, default: usize| match value {
+                "" => Ok(default),
+                value => value
+                    .parse::<usize>()
+                    .map_err(|_| stream::error(format!("cut: invalid field value '{}'", range))),
+            };
+
+            let (start, end) = (bound(start, 1)?, bound(end, usize::MAX)?);
+            if start == 0 || end == 0 {
+                return Err(stream::error(
+                    "cut: fields and positions are numbered from 1",
+                ));
+            }
+            if start > end {
+                return Err(stream::error("cut: invalid decreasing range"));
+            }
+            Ok((start, end))
+        })
+        .collect()
+}
+
+pub fn cut(args: &[&str], stdin: Option<&str>) -> String {
+    let parsed = match parse("cut", args, "", "dfc") {
+        Ok(parsed) => parsed,
+        Err(e) => return e,
+    };
+
+    let (list, by_fields) = match (parsed.value('f'), parsed.value('c')) {
+        (Some(_), Some(_)) => return stream::error("cut: only one type of list may be specified"),
+        (Some(fields), None) => (fields, true),
+        (None, Some(chars)) => (chars, false),
+        (None, None) => {
+            return stream::error("cut: you must specify a list of bytes, characters, or fields")
+        }
+    };
+    let delimiter = match parsed.value('d') {
+        Some(_) if !by_fields => {
+            return stream::error(
+                "cut: an input delimiter may be specified only when operating on fields",
+            )
+        }
+        Some(delimiter) if delimiter.chars().count() == 1 => delimiter.chars().next().unwrap(),
+        Some(_) => return stream::error("cut: the delimiter must be a single character"),
+        None => '\t',
+    };
+    let ranges = match parse_list(list) {
+        Ok(ranges) => ranges,
+        Err(e) => return e,
+    };
+    let selected = |index: usize| {
+        ranges
+            .iter()
+            .any(|&(start, end)| (start..=end).contains(&(index + 1)))
+    };
+
+    let lines = match read_lines("cut", &parsed.operands, stdin) {
+        Ok(lines) => lines,
+        Err(e) => return e,
+    };
+
+    lines
+        .iter()
+        .map(|line| {
+            if !by_fields {
+                line.chars()
+                    .enumerate()
+                    .filter(|(i, _)| selected(*i))
+                    .map(|(_, c)| c)
+                    .collect()
+            } else if !line.contains(delimiter) {
+                // Lines without any delimiter are passed through whole
+                line.to_string()
+            } else {
+                line.split(delimiter)
+                    .enumerate()
+                    .filter(|(i, _)| selected(*i))
+                    .map(|(_, field)| field)
+                    .collect::<Vec<_>>()
+                    .join(&delimiter.to_string())
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Expands a tr set with ranges, `\n` style escapes and `[:class:]` names
+fn expand_set(set: &str) -> Result<Vec<char>, String> {
+    const CLASSES: &[(&str, &str)] = &[
+        ("upper", "ABCDEFGHIJKLMNOPQRSTUVWXYZ"),
+        ("lower", "abcdefghijklmnopqrstuvwxyz"),
+        ("digit", "0123456789"),
+        ("space", " \t\n\r\x0b\x0c"),
+        ("blank", " \t"),
+        ("punct", "!\"#$%&'()*+,-./:;<=>?@[\\]^_`{|}~"),
+    ];
+    let class = |name: &str| -> Option<String> {
+        match name {
+            "alpha" => Some(format!("{}{}", CLASSES[0].1, CLASSES[1].1)),
+            "alnum" => Some(format!("{}{}{}", CLASSES[2].1, CLASSES[0].1, CLASSES[1].1)),
+            _ => CLASSES
+                .iter()
+                .find(|(class, _)| *class == name)
+                .map(|(_, chars)| chars.to_string()),
+        }
+    };
+
+    let chars: Vec<char> = set.chars().collect();
+    let mut out = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        if chars[i] == '[' && chars.get(i + 1) == Some(&':') {
+            let rest: String = chars[i + 2..].iter().collect();
+            if let Some(end) = rest.find(":]") {
+                let name = &rest[..end];
+                let expanded = class(name).ok_or_else(|| {
+                    stream::error(format!("tr: invalid character class '{}'", name))
+                })?;
+                out.extend(expanded.chars());
+                i += 2 + name.chars().count() + 2;
+                continue;
+            }
+        }
+
+        let (c, len) = match chars[i] {
+            '\\' => match chars.get(i + 1) {
+                Some('n') => ('\n', 2),
+                Some('t') => ('\t', 2),
+                Some('r') => ('\r', 2),
+                Some(&c) => (c, 2),
+                None => ('\\', 1),
+            },
+            c => (c, 1),
+        };
+        i += len;
+
+        if chars.get(i) == Some(&'-') && i + 1 < chars.len() {
+            let end = chars[i + 1];
+            if end < c {
+                return Err(stream::error(format!(
+                    "tr: range-endpoints of '{}-{}' are in reverse collating sequence order",
+                    c, end
+                )));
+            }
+            out.extend(c..=end);
+            i += 2;
+        } else {
+            out.push(c);
+        }
+    }
+
+    Ok(out)
+}
+
+pub fn tr(args: &[&str], stdin: Option<&str>) -> String {
+    let parsed = match parse("tr", args, "ds", "") {
+        Ok(parsed) => parsed,
+        Err(e) => return e,
+    };
+    let (delete, squeeze) = (parsed.has('d'), parsed.has('s'));
+    let sets = &parsed.operands;
+
+    let expected = if delete == squeeze {
+        2
+    } else if delete {
+        1
+    } else {
+        sets.len().clamp(1, 2)
+    };
+    match sets.len() {
+        0 => return stream::error("tr: missing operand"),
+        n if n < expected => {
+            return stream::error(format!(
+                "tr: missing operand after '{}'\nTwo strings must be given when {}.",
+                sets[n - 1],
+                if delete {
+                    "both deleting and squeezing repeats"
+                } else {
+                    "translating"
+                }
+            ))
+        }
+        n if n > expected => {
+            let hint = if delete && !squeeze {
+                "\nOnly one string may be given when deleting without squeezing repeats."
+            } else {
+                ""
+            };
+            return stream::error(format!("tr: extra operand '{}'{}", sets[expected], hint));
+        }
+        _ => {}
+    }
+
+    let set1 = match expand_set(sets[0]) {
+        Ok(set) => set,
+        Err(e) => return e,
+    };
+    let set2 = match sets.get(1).map(|set| expand_set(set)).transpose() {
+        Ok(set) => set,
+        Err(e) => return e,
+    };
+
+    // A shorter second set is padded with its last character
+    let mut translation = HashMap::new();
+    if let (false, Some(set2)) = (delete, &set2) {
+        if set2.is_empty() {
+            return stream::error("tr: when not truncating set1, string2 must be non-empty");
+        }
+        for (i, c) in set1.iter().enumerate() {
+            translation.insert(*c, set2[i.min(set2.len() - 1)]);
+        }
+    }
+    let squeeze_set = set2.as_ref().unwrap_or(&set1);
+
+    let mut output = String::new();
+    for c in stdin.unwrap_or_default().chars() {
+        if delete && set1.contains(&c) {
+            continue;
+        }
+        let c = translation.get(&c).copied().unwrap_or(c);
+        if squeeze && output.ends_with(c) && squeeze_set.contains(&c) {
+            continue;
+        }
+        output.push(c);
+    }
+
+    output
+}
+
+pub fn nl(args: &[&str], stdin: Option<&str>) -> String {
+    let parsed = match parse("nl", args, "", "") {
+        Ok(parsed) => parsed,
+        Err(e) => return e,
+    };
+    let lines = match read_lines("nl", &parsed.operands, stdin) {
+        Ok(lines) => lines,
+        Err(e) => return e,
+    };
+
+    // Only non-empty lines are numbered, empty ones keep the indentation
+    let mut number = 0;
+    lines
+        .iter()
+        .map(|line| {
+            if line.is_empty() {
+                " ".repeat(7)
+            } else {
+                number += 1;
+                format!("{:>6}\t{}", number, line)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub fn rev(args: &[&str], stdin: Option<&str>) -> String {
+    let parsed = match parse("rev", args, "", "") {
+        Ok(parsed) => parsed,
+        Err(e) => return e,
+    };
+
+    match read_lines("rev", &parsed.operands, stdin) {
+        Ok(lines) => lines
+            .iter()
+            .map(|line| line.chars().rev().collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        Err(e) => e,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    const NUMBERS: &str = "10\n9\n100\n9\n-1\n";
+
+    fn lines(output: Output) -> String {
+        String::from_utf8(output.into_bytes()).unwrap()
+    }
+
+    #[test]
+    fn sort_numeric_reverse_and_unique() {
+        let _guard = testing::fresh();
+        assert_eq!(sort(&[], Some(NUMBERS)), "-1\n10\n100\n9\n9");
+        assert_eq!(sort(&["-n"], Some(NUMBERS)), "-1\n9\n9\n10\n100");
+        assert_eq!(sort(&["-rn"], Some(NUMBERS)), "100\n10\n9\n9\n-1");
+        assert_eq!(sort(&["-n", "-u"], Some(NUMBERS)), "-1\n9\n10\n100");
+        assert_eq!(
+            sort(&["-t", ":", "-k", "2", "-n"], Some("a:3\nb:1\nc:2\n")),
+            "b:1\nc:2\na:3"
+        );
+    }
+
+    #[test]
+    fn uniq_counts_adjacent_lines() {
+        let _guard = testing::fresh();
+        let input = "a\na\nb\na\nc\nc\nc\n";
+        assert_eq!(uniq(&[], Some(input)), "a\nb\na\nc");
+        assert_eq!(
+            uniq(&["-c"], Some(input)),
+            "      2 a\n      1 b\n      1 a\n      3 c"
+        );
+        assert_eq!(uniq(&["-d"], Some(input)), "a\nc");
+    }
+
+    #[test]
+    fn cut_selects_field_and_character_ranges() {
+        let _guard = testing::fresh();
+        let input = "a:b:c:d\nno delimiter\n";
+        assert_eq!(cut(&["-d:", "-f2"], Some(input)), "b\nno delimiter");
+        assert_eq!(
+            cut(&["-d", ":", "-f", "1,3-"], Some(input)),
+            "a:c:d\nno delimiter"
+        );
+        assert_eq!(cut(&["-d:", "-f-2"], Some(input)), "a:b\nno delimiter");
+        assert_eq!(cut(&["-c", "2-4"], Some("abcdef\n")), "bcd");
+        assert_eq!(
+            cut(&["-d:", "-f3-2"], Some(input)),
+            stream::error("cut: invalid decreasing range")
+        );
+        assert_eq!(
+            cut(&["-f0"], Some(input)),
+            stream::error("cut: fields and positions are numbered from 1")
+        );
+    }
+
+    #[test]
+    fn tr_deletes_squeezes_and_knows_classes() {
+        let _guard = testing::fresh();
+        assert_eq!(tr(&["a-z", "A-Z"], Some("hello\n")), "HELLO\n");
+        assert_eq!(
+            tr(&["[:lower:]", "[:upper:]"], Some("mixed Case\n")),
+            "MIXED CASE\n"
+        );
+        assert_eq!(tr(&["-d", "[:digit:]"], Some("a1b22c333\n")), "abc\n");
+        assert_eq!(tr(&["-s", " "], Some("a   b  c\n")), "a b c\n");
+        assert_eq!(tr(&["-s", "[:space:]", " "], Some("a \n\n b\n")), "a b ");
+        assert_eq!(tr(&["-ds", "l", "o"], Some("hollow loop\n")), "how op\n");
+        assert_eq!(
+            tr(&["[:nope:]", "x"], Some("")),
+            stream::error("tr: invalid character class 'nope'")
+        );
+    }
+
+    #[test]
+    fn head_counts_bytes_or_lines() {
+        let _guard = testing::fresh();
+        let input = b"one\ntwo\nthree\n";
+        assert_eq!(lines(head(&["-n", "2"], Some(input))), "one\ntwo\n");
+        assert_eq!(lines(head(&["-c", "5"], Some(input))), "one\nt");
+        assert_eq!(lines(head(&["-n", "-1"], Some(input))), "one\ntwo\n");
+        assert_eq!(lines(head(&["-2"], Some(input))), "one\ntwo\n");
+    }
+
+    #[test]
+    fn tail_starts_at_a_line_with_plus() {
+        let _guard = testing::fresh();
+        let input = b"one\ntwo\nthree\n";
+        assert_eq!(lines(tail(&["-n", "1"], Some(input))), "three\n");
+        assert_eq!(lines(tail(&["-n", "+2"], Some(input))), "two\nthree\n");
+        assert_eq!(lines(tail(&["-n", "+1"], Some(input))), "one\ntwo\nthree\n");
+        assert_eq!(lines(tail(&["-c", "3"], Some(input))), "ee\n");
+        assert_eq!(
+            lines(tail(&["-n", "x"], Some(input))),
+            "tail: invalid number of lines: 'x'\n"
+        );
+    }
+}
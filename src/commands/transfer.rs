@@ -0,0 +1,397 @@
+use super::archive::{self, Member};
+use super::filesystem::{
+    self, canonicalize, follow, human_size, normalize_path, Entry, FsError, Node, CURRENT_PATH,
+    FILESYSTEM, QUOTA,
+};
+use super::permissions::{self, EXECUTE, WRITE};
+use super::stream;
+use crate::utils::host::{self, HostFile, PickFuture};
+use crate::utils::zip;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::future::Future;
+
+// Where uploads and dropped files go unless told otherwise
+pub const UPLOAD_DIR: &str = "/tmp";
+
+thread_local! {
+    // Files being chosen for `upload`, and the directory they go to
+    static PENDING_UPLOAD: RefCell<Option<(String, PickFuture)>> = const { RefCell::new(None) };
+}
+
+// Enough for the browser to pick an application, anything else is bytes
+fn mime_type(name: &str) -> &'static str {
+    let extension = match name.rsplit_once('.') {
+        Some((_, extension)) => extension.to_ascii_lowercase(),
+        None => return "application/octet-stream",
+    };
+    match extension.as_str() {
+        "txt" | "md" | "rs" | "sh" | "toml" | "conf" | "log" => "text/plain",
+        "html" | "htm" => "text/html",
+        "css" => "text/css",
+        "js" => "text/javascript",
+        "json" => "application/json",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" | "tgz" => "application/gzip",
+        "tar" => "application/x-tar",
+        _ => "application/octet-stream",
+    }
+}
+
+// Zip entries for a gathered tree. Zip has no hard links, so later names
+// of a file carry their own copy.
+fn zip_entries(members: Vec<Member>) -> Vec<zip::Entry> {
+    let mut contents: HashMap<String, Vec<u8>> = HashMap::new();
+
+    members
+        .into_iter()
+        .map(|member| {
+            let mode = member.mode as u32;
+            let (data, mode) = match member.entry {
+                Entry::Directory => (Vec::new(), zip::DIRECTORY | mode),
+                Entry::Symlink(target) => (target.into_bytes(), zip::SYMLINK | 0o777),
+                Entry::File(bytes) => {
+                    contents.insert(member.name.clone(), bytes.clone());
+                    (bytes, zip::FILE | mode)
+                }
+                Entry::HardLink(first) => {
+                    let bytes = contents.get(&first).cloned().unwrap_or_default();
+                    (bytes, zip::FILE | mode)
+                }
+            };
+            zip::Entry {
+                name: member.name,
+                data,
+                mode,
+                mtime: member.mtime,
+            }
+        })
+        .collect()
+}
+
+// A file as it is, a directory as `<name>.zip`, along with the errors met
+// on the way for files that had to be left out
+fn package(path: &str) -> Result<(String, Vec<u8>, Vec<String>), String> {
+    let canonical = {
+        let filesystem = FILESYSTEM.lock().unwrap();
+        let current_path = CURRENT_PATH.lock().unwrap();
+        let normalized = normalize_path(path, &current_path);
+        match follow(&filesystem, &normalized) {
+            Ok(Node::Directory { .. }) => canonicalize(&filesystem, &normalized).ok(),
+            Ok(_) => None,
+            Err(e) => return Err(stream::error(format!("download: {}: {}", path, e))),
+        }
+    };
+
+    let canonical = match canonical {
+        Some(canonical) => canonical,
+        None => {
+            let bytes = filesystem::read_bytes(path)
+                .map_err(|e| stream::error(format!("download: {}: {}", path, e)))?;
+            let name = path
+                .trim_end_matches('/')
+                .rsplit('/')
+                .next()
+                .unwrap_or(path);
+            return Ok((name.to_string(), bytes, Vec::new()));
+        }
+    };
+
+    // Names inside the zip start at the directory itself
+    let (found, name) = match canonical.split_last() {
+        Some((name, parent)) => {
+            let parent = format!("/{}", parent.join("/"));
+            (archive::gather(&[name], Some(&parent), None), name.clone())
+        }
+        None => (archive::gather(&["/"], None, None), "root".to_string()),
+    };
+    let errors = found
+        .errors
+        .iter()
+        .map(|e| stream::error(format!("download: {}", e)))
+        .collect();
+    let bytes = zip::write(&zip_entries(found.members));
+    Ok((format!("{}.zip", name), bytes, errors))
+}
+
+pub fn download(args: &[&str]) -> String {
+    if args.is_empty() {
+        return stream::error("download: missing operand");
+    }
+
+    let mut output = Vec::new();
+    for &arg in args {
+        let (name, bytes, mut errors) = match package(arg) {
+            Ok(packaged) => packaged,
+            Err(e) => {
+                output.push(e);
+                continue;
+            }
+        };
+        output.append(&mut errors);
+
+        let size = human_size(bytes.len());
+        match host::download(&name, mime_type(&name), bytes) {
+            Ok(()) => output.push(format!("Downloading {} ({})", name, size)),
+            Err(e) => output.push(stream::error(format!("download: {}: {}", arg, e))),
+        }
+    }
+    output.join("\n")
+}
+
+fn check_directory(dir: &str) -> Result<(), FsError> {
+    let filesystem = FILESYSTEM.lock().unwrap();
+    let current_path = CURRENT_PATH.lock().unwrap();
+    match follow(&filesystem, &normalize_path(dir, &current_path))? {
+        node @ Node::Directory { .. } => permissions::check(node, WRITE | EXECUTE),
+        _ => Err(FsError::NotADirectory),
+    }
+}
+
+// Browsers only give the base name, but nothing else should slip through
+fn upload_name(name: &str) -> Option<&str> {
+    match name.rsplit(['/', '\\']).next()? {
+        "" | "." | ".." => None,
+        name => Some(name),
+    }
+}
+
+/// Writes files from the host into `dir`, owned by the current user and
+/// counted against the quota like anything else.
+pub fn receive(dir: &str, files: Vec<HostFile>) -> String {
+    let mode = 0o666 & !permissions::current_umask();
+    let mut output = Vec::new();
+
+    for file in files {
+        let name = match upload_name(&file.name) {
+            Some(name) => name,
+            None => {
+                output.push(format!("upload: '{}': Invalid file name", file.name));
+                continue;
+            }
+        };
+        let path = match dir.ends_with('/') {
+            true => format!("{}{}", dir, name),
+            false => format!("{}/{}", dir, name),
+        };
+
+        let written = match file.bytes {
+            Some(bytes) => filesystem::install(&path, Entry::File(bytes), mode, file.mtime),
+            None if file.size > QUOTA => Err(FsError::NoSpace),
+            None => Err(FsError::Io),
+        };
+        match written {
+            Ok(()) => output.push(format!("Uploaded {} ({})", path, human_size(file.size))),
+            Err(e) => output.push(format!("upload: {}: {}", path, e)),
+        }
+    }
+
+    filesystem::persist();
+    output.join("\n")
+}
+
+// Opens the host's file picker. It has to happen while the keypress that
+// ran the command is still being handled, the rest waits for the choice.
+pub fn upload(args: &[&str]) -> String {
+    let dir = match args {
+        [] => UPLOAD_DIR,
+        [dir] => dir,
+        _ => return stream::error("upload: too many arguments"),
+    };
+    if let Err(e) = check_directory(dir) {
+        return stream::error(format!("upload: cannot upload to '{}': {}", dir, e));
+    }
+
+    let files = host::pick_files(QUOTA);
+    PENDING_UPLOAD.with(|pending| *pending.borrow_mut() = Some((dir.to_string(), files)));
+    "UPLOAD".into()
+}
+
+/// The upload started by the last `upload` command, resolving to its
+/// output once the chosen files are in the VFS.
+pub fn pending_upload() -> Option<impl Future<Output = String>> {
+    let (dir, files) = PENDING_UPLOAD.with(|pending| pending.borrow_mut().take())?;
+    Some(async move {
+        let files = files.await;
+        if files.is_empty() {
+            return "upload: no files selected".to_string();
+        }
+        receive(&dir, files)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{follow, normalize_path, FILESYSTEM, QUOTA};
+    use crate::commands::{filesystem, CommandHandler};
+    use crate::testing;
+    use crate::utils::deflate::{crc32, inflate};
+    use crate::utils::host::{self, HostFile, MemoryHost};
+
+    fn u16_at(bytes: &[u8], at: usize) -> usize {
+        u16::from_le_bytes([bytes[at], bytes[at + 1]]) as usize
+    }
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+    }
+
+    // Every entry the central directory lists, checked against its local
+    // header and its CRC
+    fn unzip(zip: &[u8]) -> Vec<(String, Vec<u8>)> {
+        let end = zip.len() - 22;
+        assert_eq!(u32_at(zip, end), 0x0605_4b50);
+        let count = u16_at(zip, end + 10);
+        let size = u32_at(zip, end + 12) as usize;
+        let mut at = u32_at(zip, end + 16) as usize;
+        assert_eq!(at + size, end);
+
+        let mut entries = Vec::new();
+        for _ in 0..count {
+            assert_eq!(u32_at(zip, at), 0x0201_4b50);
+            let method = u16_at(zip, at + 10);
+            let crc = u32_at(zip, at + 16);
+            let compressed = u32_at(zip, at + 20) as usize;
+            let length = u32_at(zip, at + 24) as usize;
+            let name_length = u16_at(zip, at + 28);
+            let extra_length = u16_at(zip, at + 30);
+            let comment_length = u16_at(zip, at + 32);
+            let offset = u32_at(zip, at + 42) as usize;
+            let name = &zip[at + 46..at + 46 + name_length];
+
+            assert_eq!(u32_at(zip, offset), 0x0403_4b50);
+            assert_eq!(zip[offset + 4..offset + 30], zip[at + 6..at + 32]);
+            let start = offset + 30 + name_length + u16_at(zip, offset + 28);
+            let body = &zip[start..start + compressed];
+            let data = match method {
+                0 => body.to_vec(),
+                8 => inflate(body).unwrap().0,
+                method => panic!("unexpected method {}", method),
+            };
+            assert_eq!(data.len(), length);
+            assert_eq!(crc32(&data), crc);
+
+            entries.push((String::from_utf8(name.to_vec()).unwrap(), data));
+            at += 46 + name_length + extra_length + comment_length;
+        }
+        entries
+    }
+
+    #[test]
+    fn download_hands_over_a_file_as_it_is() {
+        let _guard = testing::fresh();
+        let host = MemoryHost::default();
+        host::set_host(Box::new(host.clone()));
+        let mut handler = CommandHandler::new();
+        let random = vec![0x1f, 0x8b, 0xff, 0x00, 0x80, 0x0a, 0xc3, 0x28];
+        filesystem::write_bytes("/tmp/random", &random).unwrap();
+        filesystem::write_file("/tmp/note.txt", "hello\n").unwrap();
+
+        handler.handle("download /tmp/note.txt /tmp/random");
+        let downloads = host.downloads();
+        assert_eq!(downloads.len(), 2);
+        assert_eq!(downloads[0].name, "note.txt");
+        assert_eq!(downloads[0].mime, "text/plain");
+        assert_eq!(downloads[0].bytes, b"hello\n");
+        assert_eq!(downloads[1].mime, "application/octet-stream");
+        assert_eq!(downloads[1].bytes, random);
+    }
+
+    #[test]
+    fn download_zips_a_directory() {
+        let _guard = testing::fresh();
+        let host = MemoryHost::default();
+        host::set_host(Box::new(host.clone()));
+        let mut handler = CommandHandler::new();
+        handler.handle("mkdir /tmp/pack");
+        filesystem::write_file("/tmp/pack/one.txt", "one\n").unwrap();
+        filesystem::write_bytes("/tmp/pack/zeros", &[0; 1000]).unwrap();
+
+        handler.handle("download /tmp/pack");
+        let downloads = host.downloads();
+        assert_eq!(downloads.len(), 1);
+        assert_eq!(downloads[0].name, "pack.zip");
+        assert_eq!(downloads[0].mime, "application/zip");
+
+        let mut entries = unzip(&downloads[0].bytes);
+        entries.sort();
+        assert_eq!(
+            entries,
+            vec![
+                ("pack/".to_string(), Vec::new()),
+                ("pack/one.txt".to_string(), b"one\n".to_vec()),
+                ("pack/zeros".to_string(), vec![0; 1000]),
+            ]
+        );
+    }
+
+    fn host_file(name: &str, bytes: Vec<u8>) -> HostFile {
+        HostFile {
+            name: name.to_string(),
+            size: bytes.len(),
+            mtime: 0.0,
+            bytes: Some(bytes),
+        }
+    }
+
+    // Runs an upload command and waits for the files the host hands over
+    fn upload(handler: &mut CommandHandler, command: &str) -> String {
+        assert_eq!(handler.handle(command).0, "UPLOAD");
+        testing::block_on(super::pending_upload().unwrap())
+    }
+
+    fn owner_and_mode(path: &str) -> (String, u16) {
+        let filesystem = FILESYSTEM.lock().unwrap();
+        let node = follow(&filesystem, &normalize_path(path, &[])).unwrap();
+        (node.get_owner().to_string(), node.permissions())
+    }
+
+    #[test]
+    fn uploads_belong_to_whoever_uploads_them() {
+        let _guard = testing::fresh();
+        let host = MemoryHost::default();
+        host::set_host(Box::new(host.clone()));
+        let mut handler = CommandHandler::new();
+
+        host.queue_upload(host_file("notes.txt", b"hi\n".to_vec()));
+        let output = upload(&mut handler, "upload");
+        assert!(output.starts_with("Uploaded /tmp/notes.txt"), "{}", output);
+        assert_eq!(filesystem::read_bytes("/tmp/notes.txt").unwrap(), b"hi\n");
+        assert_eq!(
+            owner_and_mode("/tmp/notes.txt"),
+            ("objz".to_string(), 0o644)
+        );
+
+        handler.handle("su guest");
+        handler.answer("guest");
+        host.queue_upload(host_file("theirs.txt", b"guest\n".to_vec()));
+        upload(&mut handler, "upload /tmp");
+        assert_eq!(owner_and_mode("/tmp/theirs.txt").0, "guest");
+
+        assert_eq!(
+            handler.handle("upload /etc").0,
+            "upload: cannot upload to '/etc': Permission denied"
+        );
+    }
+
+    #[test]
+    fn uploads_over_the_quota_are_rejected() {
+        let _guard = testing::fresh();
+        let host = MemoryHost::default();
+        host::set_host(Box::new(host.clone()));
+        let mut handler = CommandHandler::new();
+
+        host.queue_upload(host_file("big.bin", vec![0; QUOTA + 1]));
+        assert_eq!(
+            upload(&mut handler, "upload"),
+            "upload: /tmp/big.bin: No space left on device"
+        );
+        assert!(filesystem::read_bytes("/tmp/big.bin").is_err());
+    }
+}
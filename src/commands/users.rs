@@ -0,0 +1,613 @@
+use super::filesystem::{self, CURRENT_PATH};
+use super::{permissions, stream};
+use crate::utils::digest;
+use lazy_static::lazy_static;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+
+const DEFAULT_USER: &str = "objz";
+const MAX_ATTEMPTS: usize = 3;
+
+#[derive(Debug, Clone)]
+pub struct PasswdEntry {
+    pub name: String,
+    pub uid: u32,
+    pub gid: u32,
+    pub home: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct GroupEntry {
+    pub name: String,
+    pub gid: u32,
+    pub members: Vec<String>,
+}
+
+// Credentials of a running shell. `su` and `sudo` push a new one and
+// restore the previous shell's directory when it exits.
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub pid: u32,
+    pub user: PasswdEntry,
+    pub groups: Vec<GroupEntry>,
+    saved_path: Vec<String>,
+}
+
+// A running shell as /proc shows it
+pub struct Process {
+    pub pid: u32,
+    // 0 for the login shell, which nothing else started
+    pub ppid: u32,
+    pub user: PasswdEntry,
+    pub groups: Vec<GroupEntry>,
+    pub cwd: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+enum AuthAction {
+    Su { user: String, login: bool },
+    Sudo { user: String, command: Vec<String> },
+}
+
+#[derive(Debug, Clone)]
+struct PendingAuth {
+    user: String,
+    prompt: String,
+    attempts: usize,
+    action: AuthAction,
+}
+
+pub enum Sudo {
+    Run { user: String, command: Vec<String> },
+    Done(String),
+}
+
+lazy_static! {
+    static ref SESSIONS: Mutex<Vec<Session>> = Mutex::new(Vec::new());
+    static ref PENDING_AUTH: Mutex<Option<PendingAuth>> = Mutex::new(None);
+    static ref SUDO_AUTHENTICATED: Mutex<bool> = Mutex::new(false);
+}
+
+// Shells are numbered in the order they start, the first login shell is 1
+static NEXT_PID: AtomicU32 = AtomicU32::new(1);
+
+fn database(path: &str) -> Vec<Vec<String>> {
+    filesystem::read_system_file(path)
+        .unwrap_or_default()
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.split(':').map(str::to_string).collect())
+        .collect()
+}
+
+pub fn passwd_entries() -> Vec<PasswdEntry> {
+    database("/etc/passwd")
+        .into_iter()
+        .filter(|fields| fields.len() >= 6)
+        .map(|fields| PasswdEntry {
+            name: fields[0].clone(),
+            uid: fields[2].parse().unwrap_or(65534),
+            gid: fields[3].parse().unwrap_or(65534),
+            home: fields[5].clone(),
+        })
+        .collect()
+}
+
+pub fn group_entries() -> Vec<GroupEntry> {
+    database("/etc/group")
+        .into_iter()
+        .filter(|fields| fields.len() >= 3)
+        .map(|fields| GroupEntry {
+            name: fields[0].clone(),
+            gid: fields[2].parse().unwrap_or(65534),
+            members: fields
+                .get(3)
+                .map(|members| {
+                    members
+                        .split(',')
+                        .filter(|m| !m.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                })
+                .unwrap_or_default(),
+        })
+        .collect()
+}
+
+pub fn find_user(name: &str) -> Option<PasswdEntry> {
+    passwd_entries()
+        .into_iter()
+        .find(|entry| entry.name == name)
+}
+
+pub fn find_group(name: &str) -> Option<GroupEntry> {
+    group_entries().into_iter().find(|entry| entry.name == name)
+}
+
+fn groups_of(user: &PasswdEntry) -> Vec<GroupEntry> {
+    let mut groups: Vec<GroupEntry> = group_entries()
+        .into_iter()
+        .filter(|group| group.gid == user.gid || group.members.contains(&user.name))
+        .collect();
+    // The primary group always comes first, like in `id`
+    groups.sort_by_key(|group| group.gid != user.gid);
+    groups
+}
+
+fn session_for(name: &str) -> Option<Session> {
+    let user = find_user(name)?;
+    let groups = groups_of(&user);
+    Some(Session {
+        pid: NEXT_PID.fetch_add(1, Ordering::Relaxed),
+        user,
+        groups,
+        saved_path: CURRENT_PATH.lock().unwrap().clone(),
+    })
+}
+
+fn password_field(user: &str) -> Option<String> {
+    database("/etc/shadow")
+        .into_iter()
+        .find(|fields| fields.first().map(String::as_str) == Some(user))
+        .and_then(|fields| fields.get(1).cloned())
+}
+
+// `$sha256$<salt>$<digest>` with the digest taken over the salt followed by
+// the password, in hex
+fn hash_password(salt: &str, password: &str) -> String {
+    let digest = digest::sha256(format!("{}{}", salt, password).as_bytes());
+    format!("$sha256${}${}", salt, digest::hex(&digest))
+}
+
+// An empty field is an account without a password, like the portfolio's
+// own objz account visitors land in: it logs in, su's to and sudo's
+// without being asked for one. Anything that isn't a hash, like `!` or `*`,
+// never matches.
+pub fn has_password(user: &str) -> bool {
+    password_field(user).is_none_or(|stored| !stored.is_empty())
+}
+
+pub fn check_password(user: &str, password: &str) -> bool {
+    match password_field(user) {
+        Some(stored) if stored.is_empty() => password.is_empty(),
+        Some(stored) => stored
+            .strip_prefix("$sha256$")
+            .and_then(|rest| rest.split_once('$'))
+            .is_some_and(|(salt, _)| hash_password(salt, password) == stored),
+        None => false,
+    }
+}
+
+pub fn current() -> Option<Session> {
+    SESSIONS.lock().unwrap().last().cloned()
+}
+
+// Every running shell, from the login shell to the current one
+pub fn processes() -> Vec<Process> {
+    let sessions = SESSIONS.lock().unwrap();
+    let current_path = CURRENT_PATH.lock().unwrap();
+
+    sessions
+        .iter()
+        .enumerate()
+        .map(|(i, session)| Process {
+            pid: session.pid,
+            ppid: i.checked_sub(1).map_or(0, |parent| sessions[parent].pid),
+            user: session.user.clone(),
+            groups: session.groups.clone(),
+            // A shell's directory is saved by the one it starts
+            cwd: sessions
+                .get(i + 1)
+                .map_or_else(|| current_path.clone(), |child| child.saved_path.clone()),
+        })
+        .collect()
+}
+
+pub fn current_user() -> String {
+    current().map_or(DEFAULT_USER.to_string(), |session| session.user.name)
+}
+
+pub fn current_groups() -> Vec<String> {
+    match current() {
+        Some(session) => session.groups.into_iter().map(|g| g.name).collect(),
+        None => vec![DEFAULT_USER.to_string()],
+    }
+}
+
+pub fn home_dir() -> String {
+    current().map_or(format!("/home/{}", DEFAULT_USER), |session| {
+        session.user.home
+    })
+}
+
+pub fn prompt_symbol() -> char {
+    if current_user() == "root" {
+        '#'
+    } else {
+        '$'
+    }
+}
+
+pub fn init() {
+    // Booting starts the numbering over
+    NEXT_PID.store(1, Ordering::Relaxed);
+    login(DEFAULT_USER);
+}
+
+// Replaces every running shell with a fresh login shell for `name`
+pub fn login(name: &str) -> bool {
+    let session = match session_for(name) {
+        Some(session) => session,
+        None => return false,
+    };
+
+    *SESSIONS.lock().unwrap() = vec![session];
+    *PENDING_AUTH.lock().unwrap() = None;
+    *SUDO_AUTHENTICATED.lock().unwrap() = false;
+    true
+}
+
+pub fn authenticate(name: &str, password: &str) -> bool {
+    find_user(name).is_some() && check_password(name, password)
+}
+
+fn push_session(name: &str, login_shell: bool) -> Option<usize> {
+    let session = session_for(name)?;
+    let home = session.user.home.clone();
+
+    let depth = {
+        let mut sessions = SESSIONS.lock().unwrap();
+        sessions.push(session);
+        sessions.len() - 1
+    };
+
+    if login_shell {
+        *CURRENT_PATH.lock().unwrap() = filesystem::normalize_path(&home, &[]);
+        permissions::reset_session();
+    }
+
+    Some(depth)
+}
+
+fn remove_session(depth: usize) {
+    let session = {
+        let mut sessions = SESSIONS.lock().unwrap();
+        if depth >= sessions.len() || sessions.len() == 1 {
+            return;
+        }
+        sessions.remove(depth)
+    };
+
+    if depth == SESSIONS.lock().unwrap().len() {
+        *CURRENT_PATH.lock().unwrap() = session.saved_path;
+    }
+}
+
+// `exit` leaves a shell started by `su`, or logs out of the last one
+pub fn exit_shell() -> bool {
+    let depth = SESSIONS.lock().unwrap().len();
+    if depth > 1 {
+        remove_session(depth - 1);
+        true
+    } else {
+        false
+    }
+}
+
+pub fn pending_prompt() -> Option<String> {
+    PENDING_AUTH
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|auth| auth.prompt.clone())
+}
+
+fn request_password(user: &str, prompt: String, action: AuthAction) -> String {
+    *PENDING_AUTH.lock().unwrap() = Some(PendingAuth {
+        user: user.to_string(),
+        prompt,
+        attempts: 0,
+        action,
+    });
+    String::new()
+}
+
+pub enum AuthResult {
+    Granted(Sudo),
+    Retry(String),
+    Failed(String),
+}
+
+pub fn submit_password(password: &str) -> AuthResult {
+    let mut auth = match PENDING_AUTH.lock().unwrap().take() {
+        Some(auth) => auth,
+        None => return AuthResult::Failed(String::new()),
+    };
+
+    if !check_password(&auth.user, password) {
+        auth.attempts += 1;
+        let sudo = matches!(auth.action, AuthAction::Sudo { .. });
+
+        if sudo && auth.attempts < MAX_ATTEMPTS {
+            *PENDING_AUTH.lock().unwrap() = Some(auth);
+            return AuthResult::Retry("Sorry, try again.".to_string());
+        }
+
+        return AuthResult::Failed(if sudo {
+            stream::error(format!(
+                "sudo: {} incorrect password attempts",
+                auth.attempts
+            ))
+        } else {
+            stream::error("su: Authentication failure")
+        });
+    }
+
+    match auth.action {
+        AuthAction::Su { user, login } => {
+            push_session(&user, login);
+            AuthResult::Granted(Sudo::Done(String::new()))
+        }
+        AuthAction::Sudo { user, command } => {
+            *SUDO_AUTHENTICATED.lock().unwrap() = true;
+            AuthResult::Granted(Sudo::Run { user, command })
+        }
+    }
+}
+
+pub fn whoami(_args: &[&str]) -> String {
+    current_user()
+}
+
+fn describe_user(user: &PasswdEntry, groups: &[GroupEntry]) -> String {
+    let primary = groups
+        .iter()
+        .find(|group| group.gid == user.gid)
+        .map_or(user.gid.to_string(), |group| {
+            format!("{}({})", group.gid, group.name)
+        });
+
+    format!(
+        "uid={}({}) gid={} groups={}",
+        user.uid,
+        user.name,
+        primary,
+        groups
+            .iter()
+            .map(|group| format!("{}({})", group.gid, group.name))
+            .collect::<Vec<_>>()
+            .join(",")
+    )
+}
+
+pub fn id(args: &[&str]) -> String {
+    match args.first() {
+        Some(name) => match find_user(name) {
+            Some(user) => {
+                let groups = groups_of(&user);
+                describe_user(&user, &groups)
+            }
+            None => stream::error(format!("id: '{}': no such user", name)),
+        },
+        None => match current() {
+            Some(session) => describe_user(&session.user, &session.groups),
+            None => stream::error(format!("id: cannot find name for user '{}'", DEFAULT_USER)),
+        },
+    }
+}
+
+pub fn groups(args: &[&str]) -> String {
+    match args.first() {
+        Some(name) => match find_user(name) {
+            Some(user) => format!(
+                "{} : {}",
+                name,
+                groups_of(&user)
+                    .into_iter()
+                    .map(|group| group.name)
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            ),
+            None => stream::error(format!("groups: '{}': no such user", name)),
+        },
+        None => current_groups().join(" "),
+    }
+}
+
+pub fn su(args: &[&str]) -> String {
+    let mut login = false;
+    let mut target = None;
+
+    for &arg in args {
+        match arg {
+            "-" | "-l" | "--login" => login = true,
+            _ if arg.starts_with('-') => {
+                return stream::error(format!("su: invalid option -- '{}'", &arg[1..]))
+            }
+            _ => target = Some(arg),
+        }
+    }
+
+    let target = target.unwrap_or("root");
+    if find_user(target).is_none() {
+        return stream::error(format!(
+            "su: user {} does not exist or the user entry does not contain all the required fields",
+            target
+        ));
+    }
+
+    let action = AuthAction::Su {
+        user: target.to_string(),
+        login,
+    };
+
+    if current_user() == "root" || !has_password(target) {
+        push_session(target, login);
+        return String::new();
+    }
+
+    request_password(target, "Password: ".to_string(), action)
+}
+
+fn sudo_rule(user: &str, groups: &[String]) -> Option<bool> {
+    database("/etc/sudoers").into_iter().find_map(|fields| {
+        // sudoers lines don't use ':' as a separator, join them back
+        let line = fields.join(":");
+        let mut parts = line.split_whitespace();
+        let subject = parts.next()?;
+        let spec = parts.collect::<Vec<_>>().join(" ");
+
+        let applies = match subject.strip_prefix('%') {
+            Some(group) => groups.iter().any(|g| g == group),
+            None => subject == user,
+        };
+
+        if applies {
+            Some(!spec.contains("NOPASSWD:"))
+        } else {
+            None
+        }
+    })
+}
+
+pub fn sudo(args: &[&str]) -> Result<Sudo, String> {
+    if args.len() >= 3 && args[0] == "rm" && args[1] == "-rf" && args[2] == "/" {
+        return Ok(Sudo::Done("SYSTEM_PANIC".to_string()));
+    }
+
+    let mut target = "root".to_string();
+    let mut rest = args;
+
+    while let Some(&arg) = rest.first() {
+        match arg {
+            "-u" => {
+                target = match rest.get(1) {
+                    Some(user) => user.to_string(),
+                    None => return Err(stream::error("sudo: option requires an argument -- 'u'")),
+                };
+                rest = &rest[2..];
+            }
+            "-k" => {
+                *SUDO_AUTHENTICATED.lock().unwrap() = false;
+                rest = &rest[1..];
+            }
+            _ if arg.starts_with('-') => {
+                return Err(stream::error(format!(
+                    "sudo: invalid option -- '{}'",
+                    &arg[1..]
+                )))
+            }
+            _ => break,
+        }
+    }
+
+    if rest.is_empty() {
+        if args.contains(&"-k") {
+            return Ok(Sudo::Done(String::new()));
+        }
+        return Err(stream::error(
+            "usage: sudo [-k] [-u user] command [arg ...]",
+        ));
+    }
+
+    if find_user(&target).is_none() {
+        return Err(stream::error(format!("sudo: unknown user {}", target)));
+    }
+
+    let user = current_user();
+    let command: Vec<String> = rest.iter().map(|s| s.to_string()).collect();
+
+    if user == "root" {
+        return Ok(Sudo::Run {
+            user: target,
+            command,
+        });
+    }
+
+    let needs_password = match sudo_rule(&user, &current_groups()) {
+        Some(needs_password) => needs_password,
+        None => {
+            return Err(format!(
+                "{} is not in the sudoers file.  This incident will be reported.",
+                user
+            ))
+        }
+    };
+
+    if !needs_password || !has_password(&user) || *SUDO_AUTHENTICATED.lock().unwrap() {
+        return Ok(Sudo::Run {
+            user: target,
+            command,
+        });
+    }
+
+    let prompt = format!("[sudo] password for {}: ", user);
+    Ok(Sudo::Done(request_password(
+        &user,
+        prompt,
+        AuthAction::Sudo {
+            user: target,
+            command,
+        },
+    )))
+}
+
+// Runs `run` with the credentials of `user`, like a child process would
+pub fn run_as<T: From<String>, F: FnOnce() -> T>(user: &str, run: F) -> T {
+    let depth = match push_session(user, false) {
+        Some(depth) => depth,
+        None => return stream::error(format!("sudo: unknown user {}", user)).into(),
+    };
+
+    let output = run();
+    remove_session(depth);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::CommandHandler;
+    use crate::testing;
+
+    #[test]
+    fn passwords_are_checked_against_hashes() {
+        let _guard = testing::fresh();
+        assert!(check_password("root", "toor"));
+        assert!(!check_password("root", "wrong"));
+        assert!(!check_password("root", &password_field("root").unwrap()));
+        assert!(check_password("guest", "guest"));
+        assert!(!check_password("nobody", ""));
+    }
+
+    #[test]
+    fn empty_field_means_no_password() {
+        let _guard = testing::fresh();
+        assert!(!has_password("objz"));
+        assert!(has_password("root"));
+        assert!(check_password("objz", ""));
+        assert!(!check_password("objz", "anything"));
+
+        let mut handler = CommandHandler::new();
+        assert_eq!(handler.handle("sudo whoami").0, "root");
+        assert_eq!(pending_prompt(), None);
+    }
+
+    #[test]
+    fn su_asks_for_the_target_password() {
+        let _guard = testing::fresh();
+        let mut handler = CommandHandler::new();
+        handler.handle("su guest");
+        assert_eq!(pending_prompt(), Some("Password: ".to_string()));
+        assert_eq!(handler.answer("objz"), "su: Authentication failure");
+        assert_eq!(current_user(), "objz");
+
+        handler.handle("su guest");
+        handler.answer("guest");
+        assert_eq!(current_user(), "guest");
+        // Back to objz without a password
+        handler.handle("su objz");
+        assert_eq!(current_user(), "objz");
+    }
+}
@@ -0,0 +1,106 @@
+use crate::commands::{filesystem, users, CommandHandler};
+use crate::input::history::CommandHistory;
+use std::cell::RefCell;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoginStage {
+    Username,
+    Password { user: String },
+}
+
+thread_local! {
+    static LOGIN_STAGE: RefCell<Option<LoginStage>> = const { RefCell::new(None) };
+}
+
+pub fn begin() {
+    LOGIN_STAGE.with(|stage| *stage.borrow_mut() = Some(LoginStage::Username));
+}
+
+pub fn finish() {
+    LOGIN_STAGE.with(|stage| *stage.borrow_mut() = None);
+}
+
+// A new login starts over: the user's own shell in their home directory,
+// the default umask and no history
+pub fn start(user: &str, history: &mut CommandHistory, processor: &mut CommandHandler) {
+    finish();
+    users::login(user);
+    *history = CommandHistory::new();
+    *processor = CommandHandler::new();
+    filesystem::reset_session();
+}
+
+pub fn stage() -> Option<LoginStage> {
+    LOGIN_STAGE.with(|stage| stage.borrow().clone())
+}
+
+pub fn set_stage(new_stage: LoginStage) {
+    LOGIN_STAGE.with(|stage| *stage.borrow_mut() = Some(new_stage));
+}
+
+pub fn is_active() -> bool {
+    stage().is_some()
+}
+
+pub fn prompt() -> Option<String> {
+    match stage()? {
+        LoginStage::Username => Some("login: ".to_string()),
+        LoginStage::Password { .. } => Some("password: ".to_string()),
+    }
+}
+
+pub fn is_masked() -> bool {
+    matches!(stage(), Some(LoginStage::Password { .. }))
+}
+
+// Unknown names are asked for a password too, so the prompt doesn't give
+// away which accounts exist
+pub fn needs_password(user: &str) -> bool {
+    users::find_user(user).is_none() || users::has_password(user)
+}
+
+pub fn authenticate(user: &str, password: &str) -> bool {
+    users::authenticate(user, password)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn exit_leaves_su_shells_before_logging_out() {
+        let _guard = testing::fresh();
+        let mut handler = CommandHandler::new();
+        handler.handle("su guest");
+        handler.answer("guest");
+        assert_eq!(users::current_user(), "guest");
+
+        assert_eq!(handler.handle("exit").0, "");
+        assert_eq!(users::current_user(), "objz");
+        assert_eq!(handler.handle("logout").0, "LOGOUT");
+    }
+
+    #[test]
+    fn login_starts_a_fresh_session() {
+        let _guard = testing::fresh();
+        let mut history = CommandHistory::new();
+        let mut handler = CommandHandler::new();
+        for command in &["cd /tmp", "umask 077"] {
+            history.add(command.to_string());
+            handler.handle(command);
+        }
+        assert_eq!(handler.handle("exit").0, "LOGOUT");
+
+        begin();
+        assert!(needs_password("guest"));
+        assert!(authenticate("guest", "guest"));
+        start("guest", &mut history, &mut handler);
+        assert!(!is_active());
+        assert_eq!(users::current_user(), "guest");
+        assert_eq!(history.prev(), None);
+        assert_eq!(handler.handle("history").0, "  1  history");
+        assert_eq!(handler.handle("pwd").0, "/home/guest");
+        assert_eq!(handler.handle("umask").0, "0022");
+    }
+}
@@ -1,17 +1,20 @@
-use crate::commands::CommandHandler;
+use crate::boot::boot;
+use crate::commands::{filesystem, system, transfer, CommandHandler};
 use crate::input::history::CommandHistory;
+use crate::input::login::{self, LoginStage};
 use crate::terminal::autocomplete::{find_common_prefix, AutoComplete, CompletionResult};
-use crate::terminal::buffer::{self, InputMode};
+use crate::terminal::buffer::{self, InputMode, LineType};
 use crate::terminal::Terminal;
-use crate::utils::panic;
-use std::cell::RefCell;
+use crate::utils::{fetch, host, panic};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen_futures::spawn_local;
-use web_sys::{window, HtmlInputElement, KeyboardEvent};
+use web_sys::{window, DragEvent, HtmlInputElement, KeyboardEvent};
 
 thread_local! {
-    static CURRENT_INPUT: RefCell<String> = RefCell::new(String::new());
-    static IS_FOCUSED: RefCell<bool> = RefCell::new(false);
+    static CURRENT_INPUT: RefCell<String> = const { RefCell::new(String::new()) };
+    static IS_FOCUSED: RefCell<bool> = const { RefCell::new(false) };
     static AUTOCOMPLETE: RefCell<AutoComplete> = RefCell::new(AutoComplete::new());
 }
 
@@ -54,18 +57,52 @@ impl InputHandler {
             let terminal = terminal_clone.clone();
             let hidden_input = hidden_input_clone.clone();
             let history = RefCell::new(history);
-            let processor = RefCell::new(processor);
+            let processor = Rc::new(RefCell::new(processor));
 
             Closure::wrap(Box::new(move |event: KeyboardEvent| {
                 let current_input = CURRENT_INPUT.with(|input| input.borrow().clone());
 
+                if event.ctrl_key() && event.key() == "d" {
+                    let state = buffer::get_terminal_state();
+                    if current_input.is_empty()
+                        && state.input_mode == InputMode::Normal
+                        && !login::is_active()
+                        && CommandHandler::pending_prompt().is_none()
+                    {
+                        event.prevent_default();
+                        event.stop_propagation();
+                        buffer::add_command_line(&terminal.get_current_prompt(), "");
+                        Self::show_result(&system::logout(&[]), &terminal, &hidden_input);
+                    }
+                    return;
+                }
+
                 match event.key().as_str() {
+                    "Enter" if login::is_active() => {
+                        event.prevent_default();
+                        Self::handle_login(
+                            &current_input,
+                            &mut history.borrow_mut(),
+                            &mut processor.borrow_mut(),
+                            &terminal,
+                            &hidden_input,
+                        );
+                    }
+                    "Enter" if CommandHandler::pending_prompt().is_some() => {
+                        event.prevent_default();
+                        Self::handle_prompt(&current_input, &processor, &terminal, &hidden_input);
+                    }
+                    "ArrowUp" | "ArrowDown" | "Tab"
+                        if login::is_active() || CommandHandler::pending_prompt().is_some() =>
+                    {
+                        event.prevent_default();
+                    }
                     "Enter" => {
                         event.prevent_default();
                         Self::handle_enter(
                             &current_input,
                             &mut history.borrow_mut(),
-                            &mut processor.borrow_mut(),
+                            &processor,
                             &terminal,
                             &hidden_input,
                         );
@@ -161,6 +198,7 @@ impl InputHandler {
         Self::setup_cursor_blink(&terminal_clone);
         Self::setup_custom_listeners(&terminal_clone);
         Self::setup_scroll_listeners(&terminal_clone);
+        Self::setup_drop_listeners(&terminal_clone);
 
         terminal.prepare_for_input();
         let _ = hidden_input.focus();
@@ -300,10 +338,58 @@ impl InputHandler {
         scroll_to_bottom_callback.forget();
     }
 
+    // Files dropped onto the terminal are uploaded to /tmp
+    fn setup_drop_listeners(terminal: &Terminal) {
+        let canvas = &terminal.renderer.canvas;
+
+        // Without this the browser opens the dropped file itself
+        let dragover_callback = Closure::wrap(Box::new(move |event: DragEvent| {
+            event.prevent_default();
+        }) as Box<dyn FnMut(_)>);
+
+        canvas
+            .add_event_listener_with_callback(
+                "dragover",
+                dragover_callback.as_ref().unchecked_ref(),
+            )
+            .unwrap();
+        dragover_callback.forget();
+
+        let drop_callback = {
+            let terminal = terminal.clone();
+            Closure::wrap(Box::new(move |event: DragEvent| {
+                event.prevent_default();
+
+                let state = buffer::get_terminal_state();
+                if state.input_mode != InputMode::Normal || login::is_active() {
+                    return;
+                }
+                let files = match event.data_transfer().and_then(|data| data.files()) {
+                    Some(files) if files.length() > 0 => files,
+                    _ => return,
+                };
+
+                let terminal = terminal.clone();
+                spawn_local(async move {
+                    let files = host::read_files(&files, filesystem::QUOTA).await;
+                    let result = transfer::receive(transfer::UPLOAD_DIR, files);
+                    buffer::add_output_lines(&result, None);
+                    buffer::auto_scroll_to_bottom();
+                    terminal.render();
+                });
+            }) as Box<dyn FnMut(_)>)
+        };
+
+        canvas
+            .add_event_listener_with_callback("drop", drop_callback.as_ref().unchecked_ref())
+            .unwrap();
+        drop_callback.forget();
+    }
+
     fn handle_enter(
         current_input: &str,
         history: &mut CommandHistory,
-        processor: &mut CommandHandler,
+        processor: &Rc<RefCell<CommandHandler>>,
         terminal: &Terminal,
         hidden_input: &HtmlInputElement,
     ) {
@@ -340,36 +426,208 @@ impl InputHandler {
         buffer::set_input_mode(InputMode::Processing);
 
         if !trimmed_input.is_empty() {
-            let (result, _directory_changed) = processor.handle(trimmed_input);
+            let pending = processor.borrow().pending_fetches(trimmed_input);
+            if !pending.is_empty() {
+                let processor = Rc::clone(processor);
+                let input = trimmed_input.to_string();
+                let terminal_clone = terminal.clone();
+                let hidden_input_clone = hidden_input.clone();
+                spawn_local(async move {
+                    Self::fetch_with_spinner(&terminal_clone, pending).await;
+                    let (result, _directory_changed) = processor.borrow_mut().handle(&input);
+                    Self::show_result(&result, &terminal_clone, &hidden_input_clone);
+                });
+                return;
+            }
 
-            match result.as_str() {
-                "CLEAR_SCREEN" => {
-                    buffer::clear_buffer();
-                    Self::prepare_input(terminal, hidden_input);
-                }
-                "SYSTEM_PANIC" => {
+            let (result, _directory_changed) = processor.borrow_mut().handle(trimmed_input);
+            Self::show_result(&result, terminal, hidden_input);
+        } else {
+            Self::prepare_input(terminal, hidden_input);
+        }
+    }
+
+    fn show_result(result: &str, terminal: &Terminal, hidden_input: &HtmlInputElement) {
+        match result {
+            "CLEAR_SCREEN" => {
+                buffer::clear_buffer();
+                Self::prepare_input(terminal, hidden_input);
+            }
+            "LOGOUT" => {
+                Self::logout(terminal, hidden_input);
+            }
+            "UPLOAD" => match transfer::pending_upload() {
+                Some(upload) => {
                     let terminal_clone = terminal.clone();
                     let hidden_input_clone = hidden_input.clone();
                     spawn_local(async move {
-                        panic::trigger(&terminal_clone).await;
-                        Self::prepare_input(&terminal_clone, &hidden_input_clone);
+                        let result = upload.await;
+                        Self::show_result(&result, &terminal_clone, &hidden_input_clone);
                     });
                 }
-                _ => {
-                    if !result.is_empty() {
-                        buffer::add_output_lines(&result, None);
-                    }
+                None => Self::prepare_input(terminal, hidden_input),
+            },
+            "SYSTEM_PANIC" => {
+                let terminal_clone = terminal.clone();
+                let hidden_input_clone = hidden_input.clone();
+                spawn_local(async move {
+                    panic::trigger(&terminal_clone).await;
+                    Self::prepare_input(&terminal_clone, &hidden_input_clone);
+                });
+            }
+            _ => {
+                if !result.is_empty() {
+                    buffer::add_output_lines(result, None);
+                }
+                Self::prepare_input(terminal, hidden_input);
+            }
+        }
+    }
+
+    async fn fetch_with_spinner(terminal: &Terminal, urls: Vec<String>) {
+        let spinner = ["⠋", "⠙", "⠹", "⠸"];
+        let remaining = Rc::new(Cell::new(urls.len()));
+        let failures = Rc::new(RefCell::new(Vec::new()));
+
+        for url in &urls {
+            let url = url.clone();
+            let remaining = Rc::clone(&remaining);
+            let failures = Rc::clone(&failures);
+            spawn_local(async move {
+                if let Err(e) = fetch::load(&url).await {
+                    failures.borrow_mut().push(format!("fetch: {}: {}", url, e));
+                }
+                remaining.set(remaining.get() - 1);
+            });
+        }
+
+        let label = format!("Fetching {}", urls.join(" "));
+        buffer::add_line(format!("{} {}", label, spinner[0]), LineType::System, None);
+
+        let mut frame = 0;
+        while remaining.get() > 0 {
+            frame += 1;
+            buffer::set_last_line(format!("{} {}", label, spinner[frame % spinner.len()]));
+            terminal.render();
+            terminal.sleep(60).await;
+        }
+
+        buffer::pop_line();
+        // The command still runs and reports the unreadable file itself
+        for failure in failures.borrow().iter() {
+            buffer::add_line(failure.clone(), LineType::System, None);
+        }
+        terminal.render();
+    }
+
+    fn logout(terminal: &Terminal, hidden_input: &HtmlInputElement) {
+        buffer::set_input_mode(InputMode::Disabled);
+
+        let terminal_clone = terminal.clone();
+        let hidden_input_clone = hidden_input.clone();
+        spawn_local(async move {
+            terminal_clone.sleep(300).await;
+            buffer::clear_buffer();
+            terminal_clone.render();
+
+            boot::login_banner(&terminal_clone).await;
+            login::begin();
+            Self::prepare_input(&terminal_clone, &hidden_input_clone);
+        });
+    }
+
+    fn handle_prompt(
+        current_input: &str,
+        processor: &Rc<RefCell<CommandHandler>>,
+        terminal: &Terminal,
+        hidden_input: &HtmlInputElement,
+    ) {
+        if let Some(prompt) = CommandHandler::pending_prompt() {
+            if CommandHandler::is_prompt_masked() {
+                buffer::add_line(prompt, LineType::Normal, None);
+            } else {
+                buffer::add_command_line(&prompt, current_input);
+            }
+        }
+
+        hidden_input.set_value("");
+        CURRENT_INPUT.with(|input| input.borrow_mut().clear());
+        buffer::update_input_state(String::new(), 0);
+        buffer::set_input_mode(InputMode::Processing);
+
+        let result = processor.borrow_mut().answer(current_input);
+        Self::show_result(&result, terminal, hidden_input);
+    }
+
+    fn handle_login(
+        current_input: &str,
+        history: &mut CommandHistory,
+        processor: &mut CommandHandler,
+        terminal: &Terminal,
+        hidden_input: &HtmlInputElement,
+    ) {
+        let entered = current_input.trim().to_string();
+
+        hidden_input.set_value("");
+        CURRENT_INPUT.with(|input| input.borrow_mut().clear());
+        buffer::update_input_state(String::new(), 0);
+
+        match login::stage() {
+            Some(LoginStage::Username) => {
+                buffer::add_line(format!("login: {}", entered), LineType::Normal, None);
+                if entered.is_empty() {
+                    Self::prepare_input(terminal, hidden_input);
+                } else if login::needs_password(&entered) {
+                    login::set_stage(LoginStage::Password { user: entered });
                     Self::prepare_input(terminal, hidden_input);
+                } else {
+                    Self::start_session(&entered, history, processor, terminal, hidden_input);
                 }
             }
-        } else {
-            Self::prepare_input(terminal, hidden_input);
+            Some(LoginStage::Password { user }) => {
+                buffer::add_line("password: ••••••••".to_string(), LineType::Normal, None);
+
+                if !login::authenticate(&user, current_input) {
+                    buffer::add_line(String::new(), LineType::Normal, None);
+                    buffer::add_line("Login incorrect".to_string(), LineType::Normal, None);
+                    login::begin();
+                    Self::prepare_input(terminal, hidden_input);
+                    return;
+                }
+
+                Self::start_session(&user, history, processor, terminal, hidden_input);
+            }
+            None => Self::prepare_input(terminal, hidden_input),
         }
     }
 
+    fn start_session(
+        user: &str,
+        history: &mut CommandHistory,
+        processor: &mut CommandHandler,
+        terminal: &Terminal,
+        hidden_input: &HtmlInputElement,
+    ) {
+        login::start(user, history, processor);
+
+        buffer::set_input_mode(InputMode::Disabled);
+        terminal.render();
+
+        let last_login = system::record_login();
+        let terminal_clone = terminal.clone();
+        let hidden_input_clone = hidden_input.clone();
+        spawn_local(async move {
+            boot::motd(&terminal_clone, &last_login).await;
+            Self::prepare_input(&terminal_clone, &hidden_input_clone);
+        });
+    }
+
     fn prepare_input(terminal: &Terminal, hidden_input: &HtmlInputElement) {
-        let prompt = terminal.get_current_prompt();
+        let prompt = login::prompt()
+            .or_else(CommandHandler::pending_prompt)
+            .unwrap_or_else(|| terminal.get_current_prompt());
         buffer::set_current_prompt(prompt);
+        buffer::set_input_masked(login::is_masked() || CommandHandler::is_prompt_masked());
         buffer::set_input_mode(InputMode::Normal);
         buffer::auto_scroll_to_bottom();
 
@@ -386,20 +644,17 @@ impl InputHandler {
         let trimmed = current_input.trim();
         let parts: Vec<&str> = trimmed.split_whitespace().collect();
 
-        let (command_prefix, completion_target) = if parts.is_empty() {
-            ("", current_input)
-        } else if parts.len() == 1 && !trimmed.ends_with(' ') {
-            ("", current_input)
-        } else {
-            if parts.len() == 1 {
+        let (command_prefix, completion_target) =
+            if parts.is_empty() || (parts.len() == 1 && !trimmed.ends_with(' ')) {
+                ("", current_input)
+            } else if parts.len() == 1 {
                 (trimmed, "")
             } else {
                 let last_space_idx = current_input.rfind(' ').unwrap_or(0);
                 let prefix = &current_input[..=last_space_idx];
                 let target = &current_input[last_space_idx + 1..];
                 (prefix, target)
-            }
-        };
+            };
 
         let completion_result = AUTOCOMPLETE.with(|autocomplete| {
             autocomplete
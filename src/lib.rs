@@ -11,6 +11,8 @@ mod boot;
 mod commands;
 mod input;
 mod terminal;
+#[cfg(test)]
+mod testing;
 mod utils;
 
 use terminal::Terminal;
@@ -26,6 +28,8 @@ pub fn main() {
     let document = window.document().expect("should have a document on window");
 
     commands::system::init();
+    commands::filesystem::restore();
+    commands::users::init();
 
     let terminal = Terminal::new(&document);
 
@@ -5,9 +5,75 @@ pub struct AutoComplete {
 impl AutoComplete {
     pub fn new() -> Self {
         let commands = vec![
-            "help", "clear", "history", "echo", "date", "uptime", "neofetch", "uname", "ls", "ll",
-            "cd", "cat", "pwd", "tree", "mkdir", "touch", "rm", "ln", "sudo", "cowsay", "sl",
-            "lolcat", "calc",
+            "help",
+            "clear",
+            "history",
+            "echo",
+            "date",
+            "uptime",
+            "neofetch",
+            "uname",
+            "ls",
+            "ll",
+            "cd",
+            "cat",
+            "pwd",
+            "tree",
+            "du",
+            "df",
+            "mkdir",
+            "touch",
+            "rm",
+            "ln",
+            "cp",
+            "mv",
+            "readlink",
+            "realpath",
+            "stat",
+            "file",
+            "find",
+            "grep",
+            "head",
+            "tail",
+            "wc",
+            "sort",
+            "uniq",
+            "cut",
+            "tr",
+            "nl",
+            "rev",
+            "sed",
+            "awk",
+            "diff",
+            "patch",
+            "xxd",
+            "hexdump",
+            "od",
+            "base64",
+            "md5sum",
+            "sha1sum",
+            "sha256sum",
+            "tar",
+            "gzip",
+            "gunzip",
+            "zcat",
+            "download",
+            "upload",
+            "sudo",
+            "cowsay",
+            "sl",
+            "lolcat",
+            "calc",
+            "exit",
+            "logout",
+            "chmod",
+            "chown",
+            "chgrp",
+            "umask",
+            "whoami",
+            "id",
+            "groups",
+            "su",
         ]
         .into_iter()
         .map(|s| s.to_string())
@@ -25,13 +91,18 @@ impl AutoComplete {
         let parts: Vec<&str> = trimmed.split_whitespace().collect();
 
         if parts.len() == 1 {
-            self.complete_command(&parts[0])
+            self.complete_command(parts[0])
         } else {
             let command = parts[0];
             let partial_path = parts.last().map_or("", |v| v);
 
             match command {
-                "cd" | "ls" | "cat" | "tree" | "rm" | "mkdir" | "touch" => {
+                "cd" | "ls" | "cat" | "tree" | "du" | "df" | "rm" | "mkdir" | "touch" | "cp"
+                | "mv" | "ln" | "readlink" | "realpath" | "stat" | "file" | "find" | "grep"
+                | "head" | "tail" | "wc" | "sort" | "uniq" | "cut" | "nl" | "rev" | "sed"
+                | "awk" | "diff" | "patch" | "xxd" | "hexdump" | "od" | "base64" | "md5sum"
+                | "sha1sum" | "sha256sum" | "tar" | "gzip" | "gunzip" | "zcat" | "download"
+                | "upload" | "chmod" | "chown" | "chgrp" => {
                     self.complete_path(partial_path, current_path, command == "cd")
                 }
                 _ => CompletionResult::None,
@@ -61,6 +132,7 @@ impl AutoComplete {
         dirs_only: bool,
     ) -> CompletionResult {
         use crate::commands::filesystem::{get_filesystem_entries, normalize_path};
+        use crate::commands::procfs;
 
         let (dir_path, filename_prefix) = if partial.contains('/') {
             let last_slash = partial.rfind('/').unwrap();
@@ -75,6 +147,7 @@ impl AutoComplete {
             normalize_path(dir_path, current_path)
         };
 
+        procfs::refresh_for(&[dir_path]);
         let entries = get_filesystem_entries(&search_path, dirs_only);
 
         let matches: Vec<String> = entries
@@ -12,13 +12,24 @@ pub enum LineType {
     System,  // System messages
 }
 
+// Characters `start..end` of a line drawn in their own color
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub color: String,
+}
+
 #[derive(Debug, Clone)]
 pub struct BufferLine {
     pub content: String,
     pub line_type: LineType,
     pub color: Option<String>,
+    pub spans: Vec<Span>,
     pub _timestamp: f64,
     pub wrapped_lines: Vec<String>,
+    // Where in `content` each wrapped line starts, in characters
+    pub wrap_starts: Vec<usize>,
 }
 
 impl BufferLine {
@@ -27,18 +38,22 @@ impl BufferLine {
             content,
             line_type,
             color,
+            spans: Vec::new(),
             _timestamp: js_sys::Date::now(),
             wrapped_lines: Vec::new(),
+            wrap_starts: Vec::new(),
         }
     }
 
     pub fn calculate_wrapping(&mut self, max_width: usize) {
         self.wrapped_lines.clear();
+        self.wrap_starts.clear();
 
         let chars: Vec<char> = self.content.chars().collect();
 
         if chars.len() <= max_width {
             self.wrapped_lines.push(self.content.clone());
+            self.wrap_starts.push(0);
             return;
         }
 
@@ -49,6 +64,7 @@ impl BufferLine {
             if end >= chars.len() {
                 let chunk: String = chars[start..].iter().collect();
                 self.wrapped_lines.push(chunk);
+                self.wrap_starts.push(start);
                 break;
             }
 
@@ -62,6 +78,7 @@ impl BufferLine {
 
             let chunk: String = chars[start..break_point].iter().collect();
             self.wrapped_lines.push(chunk);
+            self.wrap_starts.push(start);
 
             start = if break_point < end && chars[break_point] == ' ' {
                 break_point + 1
@@ -87,6 +104,7 @@ pub struct TerminalState {
     pub prompt: String,
     pub input_mode: InputMode,
     pub scroll_offset: usize,
+    pub masked: bool,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -104,6 +122,7 @@ impl Default for TerminalState {
             prompt: "objz@objz:~$ ".to_string(),
             input_mode: InputMode::Normal,
             scroll_offset: 0,
+            masked: false,
         }
     }
 }
@@ -141,7 +160,10 @@ impl LineBuffer {
     }
 
     pub fn add_line(&self, content: String, line_type: LineType, color: Option<String>) {
-        let mut line = BufferLine::new(content, line_type, color);
+        self.push_line(BufferLine::new(content, line_type, color));
+    }
+
+    fn push_line(&self, mut line: BufferLine) {
         let width = *self.terminal_width.borrow();
         line.calculate_wrapping(width);
 
@@ -158,10 +180,25 @@ impl LineBuffer {
 
     pub fn add_lines(&self, content: &str, line_type: LineType, color: Option<String>) {
         for line in content.lines() {
-            self.add_line(line.to_string(), line_type.clone(), color.clone());
+            let (text, line_color, spans) = split_colors(line);
+            let mut line = BufferLine::new(text, line_type.clone(), color.clone().or(line_color));
+            line.spans = spans;
+            self.push_line(line);
+        }
+    }
+
+    pub fn set_last_line(&self, content: String) {
+        let width = *self.terminal_width.borrow();
+        if let Some(line) = self.buffer.borrow_mut().back_mut() {
+            line.content = content;
+            line.calculate_wrapping(width);
         }
     }
 
+    pub fn pop_line(&self) {
+        self.buffer.borrow_mut().pop_back();
+    }
+
     pub fn add_command(&self, prompt: &str, input: &str) {
         let full_command = format!("{}{}", prompt, input);
         self.add_line(full_command, LineType::Command, Some("cyan".to_string()));
@@ -201,6 +238,10 @@ impl LineBuffer {
         self.state.borrow_mut().prompt = prompt;
     }
 
+    pub fn set_masked(&self, masked: bool) {
+        self.state.borrow_mut().masked = masked;
+    }
+
     fn get_state(&self) -> TerminalState {
         self.state.borrow().clone()
     }
@@ -269,10 +310,82 @@ thread_local! {
     pub static LINE_BUFFER: LineBuffer = LineBuffer::new();
 }
 
+fn sgr_color(code: &str) -> Option<&'static str> {
+    Some(match code {
+        "31" => "red",
+        "32" => "green",
+        "33" => "yellow",
+        "34" => "blue",
+        "35" => "magenta",
+        "36" => "cyan",
+        "37" => "white",
+        "90" => "gray",
+        _ => return None,
+    })
+}
+
+// Commands color text with SGR codes, a whole line like
+// `\x1b[31m-removed\x1b[0m` or just parts of it like a grep match. A line
+// that is one color throughout gets that as its color, anything else is
+// plain text with spans. Codes the terminal doesn't know show no color.
+fn split_colors(line: &str) -> (String, Option<String>, Vec<Span>) {
+    let mut text = String::new();
+    let mut spans: Vec<Span> = Vec::new();
+    let mut color = None;
+    let mut length = 0;
+    let mut rest = line;
+
+    loop {
+        let (plain, code) = match rest.find("\x1b[") {
+            Some(start) => match rest[start + 2..].split_once('m') {
+                Some((code, after)) => {
+                    let plain = &rest[..start];
+                    rest = after;
+                    (plain, Some(code))
+                }
+                None => (std::mem::take(&mut rest), None),
+            },
+            None => (std::mem::take(&mut rest), None),
+        };
+
+        let count = plain.chars().count();
+        text.push_str(plain);
+        if let (Some(color), true) = (color, count > 0) {
+            spans.push(Span {
+                start: length,
+                end: length + count,
+                color: String::from(color),
+            });
+        }
+        length += count;
+
+        match code {
+            Some(code) => color = sgr_color(code),
+            None => break,
+        }
+    }
+
+    match spans.as_slice() {
+        [span] if span.start == 0 && span.end == length => {
+            let color = span.color.clone();
+            (text, Some(color), Vec::new())
+        }
+        _ => (text, None, spans),
+    }
+}
+
 pub fn add_line(content: String, line_type: LineType, color: Option<String>) {
     LINE_BUFFER.with(|buffer| buffer.add_line(content, line_type, color));
 }
 
+pub fn set_last_line(content: String) {
+    LINE_BUFFER.with(|buffer| buffer.set_last_line(content));
+}
+
+pub fn pop_line() {
+    LINE_BUFFER.with(|buffer| buffer.pop_line());
+}
+
 pub fn add_command_line(prompt: &str, input: &str) {
     LINE_BUFFER.with(|buffer| buffer.add_command(prompt, input));
 }
@@ -301,6 +414,10 @@ pub fn set_current_prompt(prompt: String) {
     LINE_BUFFER.with(|buffer| buffer.set_prompt(prompt));
 }
 
+pub fn set_input_masked(masked: bool) {
+    LINE_BUFFER.with(|buffer| buffer.set_masked(masked));
+}
+
 pub fn get_terminal_state() -> TerminalState {
     LINE_BUFFER.with(|buffer| buffer.get_state())
 }
@@ -328,3 +445,32 @@ pub fn _get_scroll_offset() -> usize {
 pub fn reset_scroll() {
     LINE_BUFFER.with(|buffer| buffer.reset_scroll());
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors_cover_a_line_or_parts_of_it() {
+        assert_eq!(
+            split_colors("\x1b[31m-removed\x1b[0m"),
+            ("-removed".to_string(), Some("red".to_string()), Vec::new())
+        );
+        assert_eq!(
+            split_colors("plain"),
+            ("plain".to_string(), None, Vec::new())
+        );
+
+        let (text, color, spans) = split_colors("a \x1b[31mmatch\x1b[0m and \x1b[1mbold\x1b[0m");
+        assert_eq!(text, "a match and bold");
+        assert_eq!(color, None);
+        assert_eq!(
+            spans,
+            vec![Span {
+                start: 2,
+                end: 7,
+                color: "red".to_string()
+            }]
+        );
+    }
+}
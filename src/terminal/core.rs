@@ -1,6 +1,6 @@
 use super::buffer;
 use super::renderer::{LineOptions, TerminalRenderer};
-use crate::commands::CommandHandler;
+use crate::commands::{users, CommandHandler};
 use js_sys::Promise;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen_futures::JsFuture;
@@ -36,7 +36,7 @@ impl Terminal {
 
         let renderer = TerminalRenderer::new(canvas, context);
         let command_handler = CommandHandler::new();
-        let base_prompt = "objz@objz".to_string();
+        let base_prompt = "objz".to_string();
 
         buffer::set_terminal_dimensions(
             renderer.max_chars_per_line(),
@@ -52,15 +52,22 @@ impl Terminal {
 
     pub fn get_current_prompt(&self) -> String {
         let cwd = self.command_handler.get_current_directory();
-        let display_path = if cwd == "/home/objz" {
+        let home = users::home_dir();
+        let display_path = if cwd == home {
             "~".to_string()
-        } else if cwd.starts_with("/home/objz/") {
-            format!("~{}", &cwd["/home/objz".len()..])
+        } else if cwd.starts_with(&format!("{}/", home)) {
+            format!("~{}", &cwd[home.len()..])
         } else {
             cwd
         };
 
-        format!("{}:{}$ ", self.base_prompt, display_path)
+        format!(
+            "{}@{}:{}{} ",
+            users::current_user(),
+            self.base_prompt,
+            display_path,
+            users::prompt_symbol()
+        )
     }
 
     pub async fn sleep(&self, ms: i32) {
@@ -1,4 +1,4 @@
-use super::buffer::{self, BufferLine, InputMode, LineType, TerminalState};
+use super::buffer::{self, BufferLine, InputMode, LineType, Span, TerminalState};
 use js_sys::Promise;
 use std::cell::Cell;
 use wasm_bindgen::prelude::*;
@@ -174,12 +174,12 @@ impl TerminalRenderer {
             let color = self.get_color(&line.line_type, line.color.as_deref());
 
             if line.wrapped_lines.is_empty() {
-                self.draw_text(&line.content, 10.0, y, Some(&color));
+                self.draw_row(&line.content, 0, y, &color, &line.spans);
                 self.line_height
             } else {
                 let mut current_y = y;
-                for wrapped_line in &line.wrapped_lines {
-                    self.draw_text(wrapped_line, 10.0, current_y, Some(&color));
+                for (wrapped_line, start) in line.wrapped_lines.iter().zip(&line.wrap_starts) {
+                    self.draw_row(wrapped_line, *start, current_y, &color, &line.spans);
                     current_y += self.line_height;
                 }
                 self.line_height * line.wrapped_lines.len() as f64
@@ -187,6 +187,41 @@ impl TerminalRenderer {
         }
     }
 
+    // Draws the part of a line starting at character `start`, in `color`
+    // except where a span says otherwise
+    fn draw_row(&self, row: &str, start: usize, y: f64, color: &str, spans: &[Span]) {
+        if spans.is_empty() {
+            self.draw_text(row, 10.0, y, Some(color));
+            return;
+        }
+
+        let chars: Vec<char> = row.chars().collect();
+        let mut at = 0;
+        while at < chars.len() {
+            let column = start + at;
+            let (end, paint) = match spans
+                .iter()
+                .find(|span| span.start <= column && column < span.end)
+            {
+                Some(span) => (span.end - start, span.color.as_str()),
+                None => {
+                    let next = spans
+                        .iter()
+                        .map(|span| span.start)
+                        .filter(|&span_start| span_start > column)
+                        .min();
+                    (next.map_or(chars.len(), |next| next - start), color)
+                }
+            };
+            let end = end.min(chars.len());
+
+            let text: String = chars[at..end].iter().collect();
+            let x = 10.0 + at as f64 * self.char_width;
+            self.draw_text(&text, x, y, Some(paint));
+            at = end;
+        }
+    }
+
     fn render_input_line(&self, state: &TerminalState, y: f64) {
         self.clear_line_at_y(y);
         self.draw_text(&state.prompt, 10.0, y, Some("#00ffff"));
@@ -195,7 +230,12 @@ impl TerminalRenderer {
         let input_x = 10.0 + prompt_width;
 
         if !state.current_input.is_empty() {
-            self.draw_text(&state.current_input, input_x, y, Some("#ffffff"));
+            let shown = if state.masked {
+                "•".repeat(state.current_input.chars().count())
+            } else {
+                state.current_input.clone()
+            };
+            self.draw_text(&shown, input_x, y, Some("#ffffff"));
         }
 
         if self.cursor_blink_state.get() {
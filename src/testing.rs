@@ -0,0 +1,34 @@
+//! Shared setup for the native tests. Commands work on global state, so
+//! tests that touch it take turns.
+
+use crate::commands::{filesystem, users};
+use crate::utils::storage::{self, MemoryStorage};
+use std::future::Future;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll, Waker};
+
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// Waits for other tests to finish, then starts over as objz in the
+/// built-in filesystem with nothing saved.
+pub fn fresh() -> MutexGuard<'static, ()> {
+    let guard = LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+    storage::set_storage(Box::new(MemoryStorage::default()));
+    filesystem::reset_to_default();
+    users::init();
+    filesystem::reset_session();
+    guard
+}
+
+/// Runs a future that never has to wait, which is all the in-memory
+/// stand-ins hand out.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = std::pin::pin!(future);
+    match future
+        .as_mut()
+        .poll(&mut Context::from_waker(Waker::noop()))
+    {
+        Poll::Ready(output) => output,
+        Poll::Pending => panic!("future is waiting on something"),
+    }
+}
@@ -0,0 +1,67 @@
+use serde::{Deserialize, Deserializer, Serializer};
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Standard base64 with `=` padding and no line breaks.
+pub fn encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+
+    for chunk in bytes.chunks(3) {
+        let value = chunk.iter().enumerate().fold(0u32, |value, (i, &byte)| {
+            value | (byte as u32) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(value >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+
+    out
+}
+
+/// Decodes base64, skipping whitespace. Returns `None` on any other
+/// character outside the alphabet or on a truncated final group.
+pub fn decode(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() / 4 * 3);
+    let mut value = 0u32;
+    let mut bits = 0;
+    let mut padding = 0;
+
+    for c in text.bytes().filter(|c| !c.is_ascii_whitespace()) {
+        if c == b'=' {
+            padding += 1;
+            continue;
+        }
+        // Nothing may follow the padding
+        if padding > 0 {
+            return None;
+        }
+        let digit = ALPHABET.iter().position(|&a| a == c)? as u32;
+        value = value << 6 | digit;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((value >> bits) as u8);
+            value &= (1 << bits) - 1;
+        }
+    }
+
+    // A final group of one character can't hold a byte
+    if bits >= 6 || padding > 2 {
+        return None;
+    }
+    Some(out)
+}
+
+/// For `#[serde(with = "base64")]` on byte fields.
+pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&encode(bytes))
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    decode(&text).ok_or_else(|| serde::de::Error::custom("invalid base64"))
+}
@@ -0,0 +1,462 @@
+// DEFLATE (RFC 1951) as used by gzip. Compression finds repeats with a hash
+// chain and writes one block with the fixed Huffman codes, which any
+// decoder reads. Decompression handles all three block types.
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// Order the code length code lengths are stored in dynamic blocks
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+const WINDOW: usize = 32 * 1024;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+// How many earlier positions to try per match, more is slower but smaller
+const MAX_CHAIN: usize = 128;
+
+struct BitWriter {
+    out: Vec<u8>,
+    buffer: u32,
+    count: u32,
+}
+
+impl BitWriter {
+    // Values go in least significant bit first
+    fn bits(&mut self, value: u32, count: u32) {
+        self.buffer |= value << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.out.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    // Huffman codes go in most significant bit first
+    fn code(&mut self, code: u32, length: u32) {
+        let reversed = code.reverse_bits() >> (32 - length);
+        self.bits(reversed, length);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.buffer as u8);
+        }
+        self.out
+    }
+}
+
+fn fixed_literal(writer: &mut BitWriter, symbol: u32) {
+    match symbol {
+        0..=143 => writer.code(0x30 + symbol, 8),
+        144..=255 => writer.code(0x190 + symbol - 144, 9),
+        256..=279 => writer.code(symbol - 256, 7),
+        _ => writer.code(0xc0 + symbol - 280, 8),
+    }
+}
+
+fn write_match(writer: &mut BitWriter, length: usize, distance: usize) {
+    let index = LENGTH_BASE
+        .iter()
+        .rposition(|&base| base as usize <= length)
+        .unwrap();
+    fixed_literal(writer, 257 + index as u32);
+    writer.bits(
+        (length - LENGTH_BASE[index] as usize) as u32,
+        LENGTH_EXTRA[index] as u32,
+    );
+
+    let index = DISTANCE_BASE
+        .iter()
+        .rposition(|&base| base as usize <= distance)
+        .unwrap();
+    writer.code(index as u32, 5);
+    writer.bits(
+        (distance - DISTANCE_BASE[index] as usize) as u32,
+        DISTANCE_EXTRA[index] as u32,
+    );
+}
+
+fn hash(data: &[u8], pos: usize) -> usize {
+    let value = (data[pos] as usize) << 10 ^ (data[pos + 1] as usize) << 5 ^ data[pos + 2] as usize;
+    value & 0x7fff
+}
+
+// Remembers `pos` as the latest place its next three bytes were seen
+fn insert(data: &[u8], pos: usize, head: &mut [usize], previous: &mut [usize]) {
+    if pos + MIN_MATCH <= data.len() {
+        let h = hash(data, pos);
+        previous[pos] = head[h];
+        head[h] = pos;
+    }
+}
+
+// Uncompressed blocks of up to 64K each, for data that doesn't shrink
+fn stored(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + data.len() / 0xffff * 5 + 5);
+    let mut blocks = data.chunks(0xffff).peekable();
+    if blocks.peek().is_none() {
+        return vec![1, 0, 0, 0xff, 0xff];
+    }
+    while let Some(block) = blocks.next() {
+        out.push(blocks.peek().is_none() as u8);
+        let length = block.len() as u16;
+        out.extend_from_slice(&length.to_le_bytes());
+        out.extend_from_slice(&(!length).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out
+}
+
+pub fn deflate(data: &[u8]) -> Vec<u8> {
+    let compressed = compress(data);
+    if compressed.len() > data.len() + data.len() / 0xffff * 5 + 5 {
+        stored(data)
+    } else {
+        compressed
+    }
+}
+
+fn compress(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter {
+        out: Vec::new(),
+        buffer: 0,
+        count: 0,
+    };
+    // A single final block with fixed codes
+    writer.bits(1, 1);
+    writer.bits(1, 2);
+
+    // Most recent position for each hash, and the one before each position
+    let mut head = vec![usize::MAX; 0x8000];
+    let mut previous = vec![usize::MAX; data.len()];
+
+    let mut pos = 0;
+    while pos < data.len() {
+        let mut best = (0, 0);
+        if pos + MIN_MATCH <= data.len() {
+            let mut candidate = head[hash(data, pos)];
+            let limit = (data.len() - pos).min(MAX_MATCH);
+            let mut tries = 0;
+            while candidate != usize::MAX && pos - candidate <= WINDOW && tries < MAX_CHAIN {
+                let length = data[candidate..]
+                    .iter()
+                    .zip(&data[pos..pos + limit])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if length > best.0 {
+                    best = (length, pos - candidate);
+                    if length == limit {
+                        break;
+                    }
+                }
+                candidate = previous[candidate];
+                tries += 1;
+            }
+        }
+
+        if best.0 >= MIN_MATCH {
+            write_match(&mut writer, best.0, best.1);
+            for at in pos..pos + best.0 {
+                insert(data, at, &mut head, &mut previous);
+            }
+            pos += best.0;
+        } else {
+            fixed_literal(&mut writer, data[pos] as u32);
+            insert(data, pos, &mut head, &mut previous);
+            pos += 1;
+        }
+    }
+
+    fixed_literal(&mut writer, 256);
+    writer.finish()
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bit: u32,
+}
+
+impl BitReader<'_> {
+    fn bit(&mut self) -> Result<u32, &'static str> {
+        let byte = *self.data.get(self.pos).ok_or("unexpected end of file")?;
+        let value = (byte >> self.bit) as u32 & 1;
+        self.bit += 1;
+        if self.bit == 8 {
+            self.bit = 0;
+            self.pos += 1;
+        }
+        Ok(value)
+    }
+
+    fn bits(&mut self, count: u32) -> Result<u32, &'static str> {
+        (0..count).try_fold(0, |value, i| Ok(value | self.bit()? << i))
+    }
+
+    fn align(&mut self) {
+        if self.bit > 0 {
+            self.bit = 0;
+            self.pos += 1;
+        }
+    }
+}
+
+// Canonical Huffman code, decoded one bit at a time: codes of each length
+// are consecutive, so counting them per length is enough
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut symbols: Vec<(u8, u16)> = lengths
+            .iter()
+            .enumerate()
+            .filter(|(_, &length)| length > 0)
+            .map(|(symbol, &length)| (length, symbol as u16))
+            .collect();
+        symbols.sort();
+
+        Self {
+            counts,
+            symbols: symbols.into_iter().map(|(_, symbol)| symbol).collect(),
+        }
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, &'static str> {
+        let mut code = 0i32;
+        let mut first = 0i32;
+        let mut index = 0i32;
+        for &count in &self.counts[1..] {
+            code |= reader.bit()? as i32;
+            let count = count as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("invalid code")
+    }
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [8u8; 288];
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+}
+
+fn dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman), &'static str> {
+    let literals = reader.bits(5)? as usize + 257;
+    let distances = reader.bits(5)? as usize + 1;
+    let code_lengths = reader.bits(4)? as usize + 4;
+
+    let mut lengths = [0u8; 19];
+    for &index in &CODE_LENGTH_ORDER[..code_lengths] {
+        lengths[index] = reader.bits(3)? as u8;
+    }
+    let code_length_code = Huffman::new(&lengths);
+
+    let mut lengths = Vec::with_capacity(literals + distances);
+    while lengths.len() < literals + distances {
+        let (value, repeat) = match code_length_code.decode(reader)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => (
+                *lengths.last().ok_or("repeat with no first length")?,
+                3 + reader.bits(2)?,
+            ),
+            17 => (0, 3 + reader.bits(3)?),
+            _ => (0, 11 + reader.bits(7)?),
+        };
+        lengths.extend(std::iter::repeat_n(value, repeat as usize));
+    }
+    if lengths.len() > literals + distances {
+        return Err("too many length or distance symbols");
+    }
+
+    Ok((
+        Huffman::new(&lengths[..literals]),
+        Huffman::new(&lengths[literals..]),
+    ))
+}
+
+/// Decompresses a raw DEFLATE stream, returning the data and the number of
+/// input bytes used, so whatever follows the stream can be read.
+pub fn inflate(data: &[u8]) -> Result<(Vec<u8>, usize), &'static str> {
+    let mut reader = BitReader {
+        data,
+        pos: 0,
+        bit: 0,
+    };
+    let mut out = Vec::new();
+
+    loop {
+        let last = reader.bit()? == 1;
+        match reader.bits(2)? {
+            0 => {
+                reader.align();
+                let header = data
+                    .get(reader.pos..reader.pos + 4)
+                    .ok_or("unexpected end of file")?;
+                let length = u16::from_le_bytes([header[0], header[1]]) as usize;
+                let complement = u16::from_le_bytes([header[2], header[3]]) as usize;
+                if length != !complement & 0xffff {
+                    return Err("invalid stored block lengths");
+                }
+                reader.pos += 4;
+                let stored = data
+                    .get(reader.pos..reader.pos + length)
+                    .ok_or("unexpected end of file")?;
+                out.extend_from_slice(stored);
+                reader.pos += length;
+            }
+            kind @ (1 | 2) => {
+                let (literals, distances) = if kind == 1 {
+                    fixed_codes()
+                } else {
+                    dynamic_codes(&mut reader)?
+                };
+                loop {
+                    let symbol = literals.decode(&mut reader)? as usize;
+                    if symbol < 256 {
+                        out.push(symbol as u8);
+                        continue;
+                    }
+                    if symbol == 256 {
+                        break;
+                    }
+
+                    let index = symbol - 257;
+                    if index >= LENGTH_BASE.len() {
+                        return Err("invalid literal/length code");
+                    }
+                    let length = LENGTH_BASE[index] as usize
+                        + reader.bits(LENGTH_EXTRA[index] as u32)? as usize;
+                    let index = distances.decode(&mut reader)? as usize;
+                    if index >= DISTANCE_BASE.len() {
+                        return Err("invalid distance code");
+                    }
+                    let distance = DISTANCE_BASE[index] as usize
+                        + reader.bits(DISTANCE_EXTRA[index] as u32)? as usize;
+                    if distance > out.len() {
+                        return Err("invalid distance too far back");
+                    }
+
+                    // Copied byte by byte since a match may overlap itself
+                    let start = out.len() - distance;
+                    for i in 0..length {
+                        out.push(out[start + i]);
+                    }
+                }
+            }
+            _ => return Err("invalid block type"),
+        }
+
+        if last {
+            reader.align();
+            return Ok((out, reader.pos));
+        }
+    }
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let table: Vec<u32> = (0..256)
+        .map(|n| {
+            (0..8).fold(n as u32, |c, _| {
+                if c & 1 != 0 {
+                    0xedb88320 ^ (c >> 1)
+                } else {
+                    c >> 1
+                }
+            })
+        })
+        .collect();
+
+    !data.iter().fold(!0u32, |crc, &byte| {
+        table[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // xorshift, so the "random" data is the same on every run
+    fn noise(length: usize) -> Vec<u8> {
+        let mut state = 0x2545_f491_u32;
+        (0..length)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    fn round_trip(data: &[u8]) -> Vec<u8> {
+        let compressed = deflate(data);
+        let (out, used) = inflate(&compressed).unwrap();
+        assert_eq!(used, compressed.len());
+        out
+    }
+
+    #[test]
+    fn random_data_spans_several_stored_blocks() {
+        let data = noise(3 * 0xffff + 100);
+        let compressed = deflate(&data);
+        assert_eq!(compressed.len(), data.len() + 4 * 5);
+        assert_eq!(round_trip(&data), data);
+        assert_eq!(round_trip(b""), b"");
+    }
+
+    #[test]
+    fn long_matches_and_overlapping_runs_survive() {
+        // Noise repeated 21K back, near the end of the 32K window, with
+        // runs in between that copy from themselves
+        let chunk = noise(20_000);
+        let mut data = Vec::new();
+        for round in 0..6 {
+            data.extend_from_slice(&chunk);
+            data.extend(std::iter::repeat_n(round as u8, 1000));
+        }
+        let compressed = deflate(&data);
+        assert!(compressed.len() < data.len() / 2);
+        assert_eq!(round_trip(&data), data);
+    }
+
+    #[test]
+    fn damaged_streams_are_rejected() {
+        let compressed = deflate(&noise(1000));
+        assert_eq!(
+            inflate(&compressed[..compressed.len() - 1]),
+            Err("unexpected end of file")
+        );
+        // Block type 3 doesn't exist
+        assert_eq!(inflate(&[0x07]), Err("invalid block type"));
+    }
+}
@@ -0,0 +1,170 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::future::Future;
+use std::iter::FromIterator;
+use std::pin::Pin;
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::Response;
+
+pub type FetchFuture = Pin<Box<dyn Future<Output = Result<String, String>>>>;
+
+pub trait Fetcher {
+    fn fetch(&self, url: &str) -> FetchFuture;
+}
+
+/// Loads static assets over HTTP with `window.fetch`.
+pub struct HttpFetcher;
+
+impl Fetcher for HttpFetcher {
+    fn fetch(&self, url: &str) -> FetchFuture {
+        let url = url.to_string();
+        Box::pin(async move {
+            let window = web_sys::window().ok_or("no window")?;
+            let response = JsFuture::from(window.fetch_with_str(&url))
+                .await
+                .map_err(|_| "Network error".to_string())?;
+            let response: Response = response
+                .dyn_into()
+                .map_err(|_| "Invalid response".to_string())?;
+
+            if !response.ok() {
+                return Err(format!("{} {}", response.status(), response.status_text()));
+            }
+
+            let text = response
+                .text()
+                .map_err(|_| "Invalid response body".to_string())?;
+            JsFuture::from(text)
+                .await
+                .ok()
+                .and_then(|text| text.as_string())
+                .ok_or_else(|| "Invalid response body".to_string())
+        })
+    }
+}
+
+/// In-memory stand-in used outside the browser.
+#[derive(Default)]
+pub struct MemoryFetcher {
+    files: HashMap<String, String>,
+}
+
+impl FromIterator<(String, String)> for MemoryFetcher {
+    fn from_iter<I: IntoIterator<Item = (String, String)>>(iter: I) -> Self {
+        Self {
+            files: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl Fetcher for MemoryFetcher {
+    fn fetch(&self, url: &str) -> FetchFuture {
+        let result = self
+            .files
+            .get(url)
+            .cloned()
+            .ok_or_else(|| "404 Not Found".to_string());
+        Box::pin(async move { result })
+    }
+}
+
+thread_local! {
+    static FETCHER: RefCell<Rc<dyn Fetcher>> = RefCell::new(if cfg!(target_arch = "wasm32") {
+        Rc::new(HttpFetcher)
+    } else {
+        Rc::new(MemoryFetcher::default())
+    });
+    static CACHE: RefCell<HashMap<String, String>> = RefCell::new(HashMap::new());
+}
+
+// Only native tests swap the fetcher
+#[allow(dead_code)]
+pub fn set_fetcher(fetcher: Rc<dyn Fetcher>) {
+    FETCHER.with(|current| *current.borrow_mut() = fetcher);
+}
+
+pub fn cached(url: &str) -> Option<String> {
+    CACHE.with(|cache| cache.borrow().get(url).cloned())
+}
+
+pub async fn load(url: &str) -> Result<(), String> {
+    if cached(url).is_some() {
+        return Ok(());
+    }
+
+    let fetcher = FETCHER.with(|fetcher| Rc::clone(&fetcher.borrow()));
+    let content = fetcher.fetch(url).await?;
+    CACHE.with(|cache| cache.borrow_mut().insert(url.to_string(), content));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::CommandHandler;
+    use crate::testing;
+
+    const URL: &str = "assets/projects/portfolio.md";
+    const FILE: &str = "/home/objz/projects/portfolio.md";
+
+    #[test]
+    fn first_read_fetches_and_later_reads_use_the_cache() {
+        let _guard = testing::fresh();
+        let fetcher: MemoryFetcher = vec![(URL.to_string(), "# Portfolio\n".to_string())]
+            .into_iter()
+            .collect();
+        set_fetcher(Rc::new(fetcher));
+        let mut handler = CommandHandler::new();
+        let command = format!("cat {}", FILE);
+
+        assert_eq!(handler.pending_fetches(&command), vec![URL.to_string()]);
+        assert_eq!(testing::block_on(load(URL)), Ok(()));
+        assert_eq!(handler.handle(&command).0, "# Portfolio");
+
+        // Nothing is fetched again, even once the asset is gone
+        set_fetcher(Rc::new(MemoryFetcher::default()));
+        assert!(handler.pending_fetches(&command).is_empty());
+        assert_eq!(testing::block_on(load(URL)), Ok(()));
+        assert_eq!(handler.handle(&command).0, "# Portfolio");
+    }
+
+    #[test]
+    fn failed_fetch_is_reported() {
+        let _guard = testing::fresh();
+        CACHE.with(|cache| cache.borrow_mut().clear());
+        set_fetcher(Rc::new(MemoryFetcher::default()));
+        let mut handler = CommandHandler::new();
+
+        assert_eq!(
+            testing::block_on(load(URL)),
+            Err("404 Not Found".to_string())
+        );
+        assert_eq!(cached(URL), None);
+        assert_eq!(
+            handler.handle(&format!("cat {}", FILE)).0,
+            format!("cat: {}: Input/output error", FILE)
+        );
+    }
+
+    #[test]
+    fn appending_keeps_the_fetched_content() {
+        let _guard = testing::fresh();
+        CACHE.with(|cache| cache.borrow_mut().clear());
+        let fetcher: MemoryFetcher = vec![(URL.to_string(), "# Portfolio\n".to_string())]
+            .into_iter()
+            .collect();
+        set_fetcher(Rc::new(fetcher));
+        let mut handler = CommandHandler::new();
+        handler.handle(&format!("cp {} /tmp/p", FILE));
+
+        // Nothing is written until the content is there to append to
+        let command = "echo extra >> /tmp/p";
+        assert_eq!(handler.handle(command).0, "zsh: input/output error: /tmp/p");
+        assert_eq!(handler.pending_fetches(command), vec![URL.to_string()]);
+        assert_eq!(testing::block_on(load(URL)), Ok(()));
+        assert_eq!(handler.handle(command).0, "");
+        assert_eq!(handler.handle("cat /tmp/p").0, "# Portfolio\nextra");
+    }
+}
@@ -0,0 +1,184 @@
+use super::deflate::{crc32, deflate, inflate};
+
+// Header flags, RFC 1952
+const FHCRC: u8 = 0x02;
+pub const FEXTRA: u8 = 0x04;
+pub const FNAME: u8 = 0x08;
+const FCOMMENT: u8 = 0x10;
+// Written as the creating OS, like gzip on Linux does
+pub const OS_UNIX: u8 = 3;
+
+pub fn is_gzip(data: &[u8]) -> bool {
+    data.starts_with(&[0x1f, 0x8b])
+}
+
+/// Wraps `data` in a gzip member, recording the original name and mtime.
+pub fn compress(data: &[u8], name: Option<&str>, mtime: u32) -> Vec<u8> {
+    let mut out = vec![0x1f, 0x8b, 8, 0];
+    out.extend_from_slice(&mtime.to_le_bytes());
+    out.extend_from_slice(&[0, OS_UNIX]);
+    if let Some(name) = name {
+        out[3] |= FNAME;
+        out.extend(name.bytes().filter(|&byte| byte != 0));
+        out.push(0);
+    }
+
+    out.extend(deflate(data));
+    out.extend_from_slice(&crc32(data).to_le_bytes());
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out
+}
+
+pub struct Member {
+    pub data: Vec<u8>,
+    pub name: Option<String>,
+    pub mtime: u32,
+}
+
+fn zero_terminated(data: &[u8], pos: &mut usize) -> Result<String, &'static str> {
+    let rest = data.get(*pos..).ok_or("unexpected end of file")?;
+    let end = rest
+        .iter()
+        .position(|&byte| byte == 0)
+        .ok_or("unexpected end of file")?;
+    *pos += end + 1;
+    Ok(String::from_utf8_lossy(&rest[..end]).into_owned())
+}
+
+/// Decompresses every member in `data` back to back. The name and mtime
+/// are the first member's. Errors read like gzip's own messages.
+pub fn decompress(data: &[u8]) -> Result<Member, &'static str> {
+    if !is_gzip(data) {
+        return Err("not in gzip format");
+    }
+
+    let mut member = Member {
+        data: Vec::new(),
+        name: None,
+        mtime: 0,
+    };
+    let mut pos = 0;
+    let mut first = true;
+
+    // Trailing zeros are padding, like tape blocks
+    while pos < data.len() && data[pos..].iter().any(|&byte| byte != 0) {
+        let header = data.get(pos..pos + 10).ok_or("unexpected end of file")?;
+        if !is_gzip(header) {
+            return Err("trailing garbage ignored");
+        }
+        if header[2] != 8 {
+            return Err("unknown method -- not supported");
+        }
+        let flags = header[3];
+        let mtime = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        pos += 10;
+
+        if flags & FEXTRA != 0 {
+            let length = data.get(pos..pos + 2).ok_or("unexpected end of file")?;
+            pos += 2 + u16::from_le_bytes([length[0], length[1]]) as usize;
+        }
+        let name = if flags & FNAME != 0 {
+            Some(zero_terminated(data, &mut pos)?)
+        } else {
+            None
+        };
+        if flags & FCOMMENT != 0 {
+            zero_terminated(data, &mut pos)?;
+        }
+        if flags & FHCRC != 0 {
+            pos += 2;
+        }
+
+        let (content, used) =
+            inflate(data.get(pos..).ok_or("unexpected end of file")?).map_err(|e| match e {
+                "unexpected end of file" => e,
+                _ => "invalid compressed data--format violated",
+            })?;
+        pos += used;
+
+        let trailer = data.get(pos..pos + 8).ok_or("unexpected end of file")?;
+        let crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+        let size = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+        if crc != crc32(&content) {
+            return Err("invalid compressed data--crc error");
+        }
+        if size != content.len() as u32 {
+            return Err("invalid compressed data--length error");
+        }
+        pos += 8;
+
+        if first {
+            member.name = name;
+            member.mtime = mtime;
+            first = false;
+        }
+        member.data.extend(content);
+    }
+
+    Ok(member)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // fox() as written by zlib (Python's gzip module, name "fox.txt",
+    // mtime 0), in a single block with dynamic Huffman codes
+    const FOX_GZ: [u8; 115] = [
+        0x1f, 0x8b, 0x08, 0x08, 0x00, 0x00, 0x00, 0x00, 0x02, 0xff, 0x66, 0x6f, 0x78, 0x2e, 0x74,
+        0x78, 0x74, 0x00, 0x9d, 0xd2, 0xb7, 0x11, 0x80, 0x30, 0x00, 0x43, 0xd1, 0x9e, 0x29, 0x34,
+        0x02, 0x39, 0x6d, 0x43, 0x30, 0x60, 0x30, 0x36, 0xc9, 0xa4, 0xe9, 0x39, 0xd8, 0x00, 0xd5,
+        0xba, 0x57, 0xe9, 0x2b, 0xa9, 0x05, 0xdc, 0x1c, 0x5b, 0x27, 0x30, 0x5b, 0x59, 0x0d, 0x28,
+        0x17, 0x73, 0x68, 0x34, 0xe6, 0x44, 0x6f, 0xc7, 0x69, 0x85, 0xd9, 0xc5, 0xf2, 0xcd, 0xaa,
+        0xb8, 0x2f, 0xd4, 0xa6, 0x75, 0xd4, 0x6b, 0x3c, 0xc2, 0xf8, 0x84, 0x09, 0x08, 0x13, 0x12,
+        0x26, 0x22, 0x4c, 0x4c, 0x98, 0x84, 0x30, 0x29, 0x61, 0x32, 0xe6, 0x53, 0x2a, 0x84, 0x9f,
+        0x25, 0x3c, 0x36, 0xc2, 0x31, 0x25, 0x72, 0x02, 0x00, 0x00,
+    ];
+
+    fn fox() -> Vec<u8> {
+        (0..12)
+            .flat_map(|i| {
+                format!("line {}: the quick brown fox jumps over the lazy dog\n", i).into_bytes()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn inflates_dynamic_huffman_blocks() {
+        assert_eq!(FOX_GZ[18] >> 1 & 3, 2);
+        let member = decompress(&FOX_GZ).unwrap();
+        assert_eq!(member.data, fox());
+        assert_eq!(member.name.as_deref(), Some("fox.txt"));
+        assert_eq!(member.mtime, 0);
+    }
+
+    #[test]
+    fn members_read_back_and_concatenate() {
+        let mut data = compress(b"hello ", Some("a"), 7);
+        data.extend(compress(b"world", None, 9));
+        let member = decompress(&data).unwrap();
+        assert_eq!(member.data, b"hello world");
+        assert_eq!(member.name.as_deref(), Some("a"));
+        assert_eq!(member.mtime, 7);
+    }
+
+    #[test]
+    fn damage_is_caught_by_the_trailer() {
+        let mut crc = FOX_GZ.to_vec();
+        crc[FOX_GZ.len() - 8] ^= 1;
+        assert_eq!(
+            decompress(&crc).err(),
+            Some("invalid compressed data--crc error")
+        );
+        let mut size = FOX_GZ.to_vec();
+        size[FOX_GZ.len() - 4] ^= 1;
+        assert_eq!(
+            decompress(&size).err(),
+            Some("invalid compressed data--length error")
+        );
+        assert_eq!(
+            decompress(&FOX_GZ[..50]).err(),
+            Some("unexpected end of file")
+        );
+    }
+}
@@ -0,0 +1,192 @@
+use lazy_static::lazy_static;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Download {
+    pub name: String,
+    pub mime: String,
+    pub bytes: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HostFile {
+    pub name: String,
+    pub size: usize,
+    // Milliseconds since the epoch
+    pub mtime: f64,
+    // None if the file was over the size limit or couldn't be read
+    pub bytes: Option<Vec<u8>>,
+}
+
+pub type PickFuture = Pin<Box<dyn Future<Output = Vec<HostFile>>>>;
+
+/// Whatever the terminal exchanges files with outside the VFS.
+pub trait Host: Send {
+    fn download(&mut self, download: Download) -> Result<(), String>;
+
+    /// Asks for files to upload, resolving once the choice is made. Files
+    /// over `limit` bytes are left unread.
+    fn pick_files(&mut self, limit: usize) -> PickFuture;
+}
+
+/// Reads files handed over by the browser, from a picker or a drop.
+pub async fn read_files(files: &web_sys::FileList, limit: usize) -> Vec<HostFile> {
+    let mut read = Vec::new();
+
+    for file in (0..files.length()).filter_map(|i| files.get(i)) {
+        let size = file.size() as usize;
+        let bytes = if size > limit {
+            None
+        } else {
+            JsFuture::from(file.array_buffer())
+                .await
+                .ok()
+                .map(|buffer| js_sys::Uint8Array::new(&buffer).to_vec())
+        };
+        read.push(HostFile {
+            name: file.name(),
+            size,
+            mtime: file.last_modified(),
+            bytes,
+        });
+    }
+
+    read
+}
+
+/// The page the terminal runs in, files are saved through a Blob URL.
+pub struct BrowserHost;
+
+impl Host for BrowserHost {
+    fn download(&mut self, download: Download) -> Result<(), String> {
+        let window = web_sys::window().ok_or("no window")?;
+        let document = window.document().ok_or("no document")?;
+        let body = document.body().ok_or("no document body")?;
+
+        let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(download.bytes.as_slice()));
+        let options = web_sys::BlobPropertyBag::new();
+        options.set_type(&download.mime);
+        let blob = web_sys::Blob::new_with_u8_array_sequence_and_options(&parts, &options)
+            .map_err(|_| "cannot create blob")?;
+        let url =
+            web_sys::Url::create_object_url_with_blob(&blob).map_err(|_| "cannot create URL")?;
+
+        // A click on a detached link is ignored by some browsers
+        let link: web_sys::HtmlAnchorElement = document
+            .create_element("a")
+            .map_err(|_| "cannot create link")?
+            .dyn_into()
+            .map_err(|_| "cannot create link")?;
+        link.set_href(&url);
+        link.set_download(&download.name);
+        let _ = body.append_child(&link);
+        link.click();
+        let _ = body.remove_child(&link);
+
+        let _ = web_sys::Url::revoke_object_url(&url);
+        Ok(())
+    }
+
+    fn pick_files(&mut self, limit: usize) -> PickFuture {
+        let input = web_sys::window()
+            .and_then(|window| window.document())
+            .and_then(|document| document.create_element("input").ok())
+            .and_then(|element| element.dyn_into::<web_sys::HtmlInputElement>().ok());
+        let input = match input {
+            Some(input) => input,
+            None => return Box::pin(async { Vec::new() }),
+        };
+        input.set_type("file");
+        input.set_multiple(true);
+
+        // Settles when the picker closes, whether or not files were chosen
+        let closed = js_sys::Promise::new(&mut |resolve, _reject| {
+            let _ = input.add_event_listener_with_callback("change", &resolve);
+            let _ = input.add_event_listener_with_callback("cancel", &resolve);
+        });
+        // Only opens while the keypress that ran the command is being handled
+        input.click();
+
+        Box::pin(async move {
+            let _ = JsFuture::from(closed).await;
+            match input.files() {
+                Some(files) => read_files(&files, limit).await,
+                None => Vec::new(),
+            }
+        })
+    }
+}
+
+/// In-memory stand-in used outside the browser. Clones share what was
+/// downloaded and what is queued for upload, so a test can keep one and
+/// install the other.
+#[derive(Clone, Default)]
+pub struct MemoryHost {
+    downloads: Arc<Mutex<Vec<Download>>>,
+    uploads: Arc<Mutex<Vec<HostFile>>>,
+}
+
+// Only native tests look at what was downloaded or queue uploads
+#[allow(dead_code)]
+impl MemoryHost {
+    pub fn downloads(&self) -> Vec<Download> {
+        self.downloads.lock().unwrap().clone()
+    }
+
+    pub fn queue_upload(&self, file: HostFile) {
+        self.uploads.lock().unwrap().push(file);
+    }
+}
+
+impl Host for MemoryHost {
+    fn download(&mut self, download: Download) -> Result<(), String> {
+        self.downloads.lock().unwrap().push(download);
+        Ok(())
+    }
+
+    fn pick_files(&mut self, limit: usize) -> PickFuture {
+        let files: Vec<HostFile> = self
+            .uploads
+            .lock()
+            .unwrap()
+            .drain(..)
+            .map(|file| {
+                let fits = file.size <= limit;
+                HostFile {
+                    bytes: file.bytes.filter(|_| fits),
+                    ..file
+                }
+            })
+            .collect();
+        Box::pin(async move { files })
+    }
+}
+
+lazy_static! {
+    static ref HOST: Mutex<Box<dyn Host>> = Mutex::new(if cfg!(target_arch = "wasm32") {
+        Box::new(BrowserHost)
+    } else {
+        Box::new(MemoryHost::default())
+    });
+}
+
+#[allow(dead_code)]
+pub fn set_host(host: Box<dyn Host>) {
+    *HOST.lock().unwrap() = host;
+}
+
+pub fn download(name: &str, mime: &str, bytes: Vec<u8>) -> Result<(), String> {
+    HOST.lock().unwrap().download(Download {
+        name: name.to_string(),
+        mime: mime.to_string(),
+        bytes,
+    })
+}
+
+pub fn pick_files(limit: usize) -> PickFuture {
+    HOST.lock().unwrap().pick_files(limit)
+}
@@ -1 +1,9 @@
+pub mod base64;
+pub mod deflate;
+pub mod digest;
+pub mod fetch;
+pub mod gzip;
+pub mod host;
 pub mod panic;
+pub mod storage;
+pub mod zip;
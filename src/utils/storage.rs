@@ -0,0 +1,83 @@
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+pub trait Storage: Send {
+    fn load(&self, key: &str) -> Option<String>;
+    fn save(&mut self, key: &str, value: &str);
+    fn remove(&mut self, key: &str);
+}
+
+/// Browser `localStorage`, survives reloads of the page.
+pub struct LocalStorage;
+
+impl LocalStorage {
+    fn backend() -> Option<web_sys::Storage> {
+        web_sys::window()?.local_storage().ok()?
+    }
+}
+
+impl Storage for LocalStorage {
+    fn load(&self, key: &str) -> Option<String> {
+        Self::backend()?.get_item(key).ok()?
+    }
+
+    fn save(&mut self, key: &str, value: &str) {
+        if let Some(storage) = Self::backend() {
+            // Quota errors are not fatal, the session simply stays unsaved
+            let _ = storage.set_item(key, value);
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(storage) = Self::backend() {
+            let _ = storage.remove_item(key);
+        }
+    }
+}
+
+/// In-memory stand-in used outside the browser.
+#[derive(Default)]
+pub struct MemoryStorage {
+    entries: HashMap<String, String>,
+}
+
+impl Storage for MemoryStorage {
+    fn load(&self, key: &str) -> Option<String> {
+        self.entries.get(key).cloned()
+    }
+
+    fn save(&mut self, key: &str, value: &str) {
+        self.entries.insert(key.to_string(), value.to_string());
+    }
+
+    fn remove(&mut self, key: &str) {
+        self.entries.remove(key);
+    }
+}
+
+lazy_static! {
+    static ref STORAGE: Mutex<Box<dyn Storage>> = Mutex::new(if cfg!(target_arch = "wasm32") {
+        Box::new(LocalStorage)
+    } else {
+        Box::new(MemoryStorage::default())
+    });
+}
+
+// Only native tests swap the backend
+#[allow(dead_code)]
+pub fn set_storage(storage: Box<dyn Storage>) {
+    *STORAGE.lock().unwrap() = storage;
+}
+
+pub fn load(key: &str) -> Option<String> {
+    STORAGE.lock().unwrap().load(key)
+}
+
+pub fn save(key: &str, value: &str) {
+    STORAGE.lock().unwrap().save(key, value);
+}
+
+pub fn remove(key: &str) {
+    STORAGE.lock().unwrap().remove(key);
+}
@@ -0,0 +1,132 @@
+use super::deflate::{crc32, deflate};
+
+// File type bits of a Unix mode, kept in the high half of the external
+// attributes so unzip restores permissions and links
+pub const DIRECTORY: u32 = 0o040000;
+pub const FILE: u32 = 0o100000;
+pub const SYMLINK: u32 = 0o120000;
+
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+// Bit 11: names are UTF-8
+const UTF8_NAMES: u16 = 0x0800;
+// Made by Unix, spec version 2.0
+const MADE_BY: u16 = 3 << 8 | 20;
+const VERSION_NEEDED: u16 = 20;
+// Info-ZIP's extended timestamp, the exact Unix mtime next to the DOS one
+const EXTENDED_TIME: u16 = 0x5455;
+
+pub struct Entry {
+    // Directories end with '/'
+    pub name: String,
+    // What a symlink points to for links, empty for directories
+    pub data: Vec<u8>,
+    // Permissions along with one of the file type bits above
+    pub mode: u32,
+    // Milliseconds since the epoch
+    pub mtime: f64,
+}
+
+// DOS time and date fields. They have no time zone, so this is UTC, and
+// can't go before 1980.
+fn dos_time(seconds: i64) -> (u16, u16) {
+    let seconds = seconds.max(315_532_800);
+    let days = seconds.div_euclid(86_400);
+    let time = seconds.rem_euclid(86_400);
+
+    // Civil date from days since the epoch, after Howard Hinnant
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    let dos_time = (time / 3600) << 11 | (time % 3600 / 60) << 5 | ((time % 60) / 2);
+    let dos_date = (year - 1980) << 9 | month << 5 | day;
+    (dos_time as u16, dos_date as u16)
+}
+
+fn push16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn push32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+/// Packs `entries` into a zip archive, deflating whatever gets smaller.
+pub fn write(entries: &[Entry]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut directory = Vec::new();
+
+    for entry in entries {
+        let offset = out.len() as u32;
+        let crc = crc32(&entry.data);
+        let compressed = deflate(&entry.data);
+        let (method, body) = if compressed.len() < entry.data.len() {
+            (DEFLATED, compressed.as_slice())
+        } else {
+            (STORED, entry.data.as_slice())
+        };
+        let seconds = (entry.mtime / 1000.0).floor() as i64;
+        let (time, date) = dos_time(seconds);
+        let mut extra = Vec::new();
+        push16(&mut extra, EXTENDED_TIME);
+        push16(&mut extra, 5);
+        extra.push(1);
+        push32(&mut extra, seconds.clamp(0, u32::MAX as i64) as u32);
+
+        // Fields both headers share, from the version needed onwards
+        let mut common = Vec::new();
+        push16(&mut common, VERSION_NEEDED);
+        push16(&mut common, UTF8_NAMES);
+        push16(&mut common, method);
+        push16(&mut common, time);
+        push16(&mut common, date);
+        push32(&mut common, crc);
+        push32(&mut common, body.len() as u32);
+        push32(&mut common, entry.data.len() as u32);
+        push16(&mut common, entry.name.len() as u16);
+        push16(&mut common, extra.len() as u16);
+
+        push32(&mut out, 0x0403_4b50);
+        out.extend_from_slice(&common);
+        out.extend_from_slice(entry.name.as_bytes());
+        out.extend_from_slice(&extra);
+        out.extend_from_slice(body);
+
+        // MS-DOS directory attribute in the low byte, the Unix mode above
+        let attributes = entry.mode << 16 | if entry.mode & DIRECTORY != 0 { 0x10 } else { 0 };
+        push32(&mut directory, 0x0201_4b50);
+        push16(&mut directory, MADE_BY);
+        directory.extend_from_slice(&common);
+        push16(&mut directory, 0);
+        push16(&mut directory, 0);
+        push16(&mut directory, 0);
+        push32(&mut directory, attributes);
+        push32(&mut directory, offset);
+        directory.extend_from_slice(entry.name.as_bytes());
+        directory.extend_from_slice(&extra);
+    }
+
+    let offset = out.len() as u32;
+    out.extend_from_slice(&directory);
+    push32(&mut out, 0x0605_4b50);
+    push16(&mut out, 0);
+    push16(&mut out, 0);
+    push16(&mut out, entries.len() as u16);
+    push16(&mut out, entries.len() as u16);
+    push32(&mut out, directory.len() as u32);
+    push32(&mut out, offset);
+    push16(&mut out, 0);
+    out
+}